@@ -0,0 +1,227 @@
+// Copyright 2023 Pelikan Foundation LLC.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Sampling hotkey detection. This is a port of the `hotkey` module from the
+//! legacy C implementation.
+//!
+//! One in every `rate` keys is sampled into a fixed-size window of the most
+//! recently sampled keys, and the number of times each key appears in the
+//! window is tracked. A key which accounts for at least the threshold ratio of
+//! the window is reported as a hotkey. All detectors in the process share a
+//! single set of reported hotkeys, which can be read with [`hotkeys`].
+
+use metriken::*;
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[metric(name = "hotkey_sample", description = "number of keys sampled")]
+pub static HOTKEY_SAMPLE: Counter = Counter::new();
+
+#[metric(
+    name = "hotkey_current",
+    description = "current number of keys detected as hotkeys"
+)]
+pub static HOTKEY_CURRENT: Gauge = Gauge::new();
+
+/// Hotkeys along with the number of times each appears in the sample window.
+type HotCounts = HashMap<Box<[u8]>, Arc<AtomicU32>>;
+
+static HOTKEYS: Lazy<Mutex<HotCounts>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Returns the currently detected hotkeys along with the number of times each
+/// key appears in the sample window, ordered from hottest to coldest.
+pub fn hotkeys() -> Vec<(Box<[u8]>, u32)> {
+    let mut hotkeys: Vec<(Box<[u8]>, u32)> = HOTKEYS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(key, count)| (key.clone(), count.load(Ordering::Relaxed)))
+        .collect();
+    hotkeys.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    hotkeys
+}
+
+/// Records a key which became hot, or removes it once it has cooled down. The
+/// count is shared with the detector, which keeps it up to date without
+/// taking the lock.
+fn publish(key: &[u8], count: Option<Arc<AtomicU32>>) {
+    let mut hotkeys = HOTKEYS.lock().unwrap();
+    if let Some(count) = count {
+        hotkeys.insert(key.into(), count);
+    } else {
+        hotkeys.remove(key);
+    }
+    HOTKEY_CURRENT.set(hotkeys.len() as _);
+}
+
+/// A key which crossed the hotkey threshold, along with the number of times
+/// it appears in the window.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Crossing {
+    /// The key became a hotkey.
+    Hot(Box<[u8]>, u32),
+    /// The key is no longer a hotkey.
+    Cold(Box<[u8]>, u32),
+}
+
+/// Tracks the keys sampled from a stream of requests.
+pub struct Hotkey {
+    counter: u64,
+    rate: u64,
+    threshold: u32,
+    window_size: usize,
+    window: VecDeque<Box<[u8]>>,
+    counts: HashMap<Box<[u8]>, u32>,
+    hot: HotCounts,
+}
+
+impl Hotkey {
+    /// Create a new detector which keeps a window of `window_size` sampled
+    /// keys, samples one in every `rate` keys, and reports keys which make up
+    /// at least `threshold_ratio` of the window.
+    pub fn new(window_size: usize, rate: usize, threshold_ratio: f64) -> Self {
+        let window_size = window_size.max(1);
+        let threshold = ((threshold_ratio * window_size as f64) as u32).max(1);
+
+        Self {
+            counter: 0,
+            rate: rate.max(1) as u64,
+            threshold,
+            window_size,
+            window: VecDeque::with_capacity(window_size),
+            counts: HashMap::with_capacity(window_size),
+            hot: HashMap::new(),
+        }
+    }
+
+    /// Offer a key to the detector. Returns the keys which crossed the
+    /// threshold in either direction as a result. This is empty unless the
+    /// key was sampled, and the sampled key is only returned when it first
+    /// becomes hot.
+    pub fn sample(&mut self, key: &[u8]) -> Vec<Crossing> {
+        let mut crossings = Vec::new();
+
+        self.counter += 1;
+        if !self.counter.is_multiple_of(self.rate) {
+            return crossings;
+        }
+
+        HOTKEY_SAMPLE.increment();
+
+        if self.window.len() == self.window_size {
+            if let Some(popped) = self.window.pop_front() {
+                if *popped == *key {
+                    // the key replaces itself, so its count is unchanged
+                    self.window.push_back(popped);
+                    return crossings;
+                }
+                crossings.extend(self.decr(popped));
+            }
+        }
+
+        self.window.push_back(key.into());
+
+        let count = self.counts.entry(key.into()).or_insert(0);
+        *count += 1;
+        let count = *count;
+
+        if let Some(shared) = self.hot.get(key) {
+            shared.store(count, Ordering::Relaxed);
+        } else if count >= self.threshold {
+            let shared = Arc::new(AtomicU32::new(count));
+            publish(key, Some(shared.clone()));
+            self.hot.insert(key.into(), shared);
+            crossings.push(Crossing::Hot(key.into(), count));
+        }
+
+        crossings
+    }
+
+    /// Decrement the count for a key which has left the window. Returns the
+    /// crossing if the key was hot and has now cooled down.
+    fn decr(&mut self, key: Box<[u8]>) -> Option<Crossing> {
+        let count = self.counts.get_mut(&key)?;
+        *count -= 1;
+        let count = *count;
+
+        if count == 0 {
+            self.counts.remove(&key);
+        }
+
+        let shared = self.hot.get(&key)?;
+        if count >= self.threshold {
+            shared.store(count, Ordering::Relaxed);
+            return None;
+        }
+
+        self.hot.remove(&key);
+        publish(&key, None);
+        Some(Crossing::Cold(key, count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect() {
+        // sample every key, report keys which make up 25% of the window
+        let mut hotkey = Hotkey::new(8, 1, 0.25);
+
+        assert_eq!(hotkey.sample(b"hotkey::hot"), vec![]);
+        assert_eq!(
+            hotkey.sample(b"hotkey::hot"),
+            vec![Crossing::Hot(b"hotkey::hot".to_vec().into(), 2)]
+        );
+
+        // the key is only reported when it crosses the threshold, but its
+        // published count is kept up to date
+        assert_eq!(hotkey.sample(b"hotkey::hot"), vec![]);
+        assert!(hotkeys().contains(&(b"hotkey::hot".to_vec().into(), 3)));
+
+        // cycle the hot key out of the window, it cools down once it drops
+        // below the threshold
+        let mut crossings = Vec::new();
+        for i in 0..8 {
+            crossings.extend(hotkey.sample(format!("hotkey::{i}").as_bytes()));
+        }
+        assert_eq!(
+            crossings,
+            vec![Crossing::Cold(b"hotkey::hot".to_vec().into(), 1)]
+        );
+        assert!(!hotkeys().iter().any(|(key, _)| &**key == b"hotkey::hot"));
+    }
+
+    #[test]
+    fn rate() {
+        // only every fourth key is sampled
+        let mut hotkey = Hotkey::new(8, 4, 0.25);
+
+        for _ in 0..7 {
+            assert_eq!(hotkey.sample(b"hotkey::rate"), vec![]);
+        }
+        assert_eq!(
+            hotkey.sample(b"hotkey::rate"),
+            vec![Crossing::Hot(b"hotkey::rate".to_vec().into(), 2)]
+        );
+    }
+
+    #[test]
+    fn replace() {
+        // a full window of a single key stays hot without being reported again
+        let mut hotkey = Hotkey::new(2, 1, 0.5);
+
+        assert_eq!(
+            hotkey.sample(b"hotkey::replace"),
+            vec![Crossing::Hot(b"hotkey::replace".to_vec().into(), 1)]
+        );
+        for _ in 0..4 {
+            assert_eq!(hotkey.sample(b"hotkey::replace"), vec![]);
+        }
+        assert!(hotkeys().contains(&(b"hotkey::replace".to_vec().into(), 2)));
+    }
+}
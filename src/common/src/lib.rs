@@ -4,8 +4,10 @@
 
 pub mod bytes;
 pub mod expiry;
+pub mod hotkey;
 pub mod metrics;
 pub mod signal;
 #[cfg(feature = "boringssl")]
 pub mod ssl;
+pub mod topkeys;
 pub mod traits;
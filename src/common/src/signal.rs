@@ -5,5 +5,9 @@
 #[derive(Clone)]
 pub enum Signal {
     FlushAll,
+    /// Resize the storage heap to the given number of bytes
+    HeapSize(usize),
+    /// Remove every item with a key matching the glob pattern
+    DeleteMatching(Vec<u8>),
     Shutdown,
 }
@@ -0,0 +1,89 @@
+// Copyright 2023 Pelikan Foundation LLC.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! A process-wide registry of the keys with the most hits. Each storage shard
+//! tracks its own top keys and periodically publishes them here, where they
+//! can be read across all shards with [`top_keys`].
+
+use metriken::*;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// A key along with its estimated number of hits and its size in bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TopKey {
+    pub key: Box<[u8]>,
+    pub hits: u64,
+    pub size: usize,
+}
+
+static TOP_KEYS: Lazy<Mutex<HashMap<usize, Vec<TopKey>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Replaces the top keys which were published for a shard.
+pub fn publish(shard: usize, keys: Vec<TopKey>) {
+    TOP_KEYS.lock().unwrap().insert(shard, keys);
+}
+
+/// Returns up to `k` of the keys with the most hits across all shards, from
+/// most to fewest hits. The hits for a key which was published by more than
+/// one shard are summed, and the largest size is reported.
+pub fn top_keys(k: usize) -> Vec<TopKey> {
+    let mut merged: HashMap<Box<[u8]>, TopKey> = HashMap::new();
+
+    for key in TOP_KEYS.lock().unwrap().values().flatten() {
+        merged
+            .entry(key.key.clone())
+            .and_modify(|merged| {
+                merged.hits += key.hits;
+                merged.size = merged.size.max(key.size);
+            })
+            .or_insert_with(|| key.clone());
+    }
+
+    let mut keys: Vec<TopKey> = merged.into_values().collect();
+    keys.sort_by(|a, b| b.hits.cmp(&a.hits).then_with(|| a.key.cmp(&b.key)));
+    keys.truncate(k);
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: &[u8], hits: u64, size: usize) -> TopKey {
+        TopKey {
+            key: key.into(),
+            hits,
+            size,
+        }
+    }
+
+    #[test]
+    fn merge() {
+        publish(
+            usize::MAX - 1,
+            vec![key(b"topkeys::a", 1000, 10), key(b"topkeys::b", 900, 20)],
+        );
+        publish(
+            usize::MAX,
+            vec![key(b"topkeys::b", 200, 30), key(b"topkeys::c", 950, 40)],
+        );
+
+        let top = top_keys(3);
+        assert_eq!(
+            top,
+            vec![
+                key(b"topkeys::b", 1100, 30),
+                key(b"topkeys::a", 1000, 10),
+                key(b"topkeys::c", 950, 40),
+            ]
+        );
+
+        // publishing again replaces the keys for the shard
+        publish(usize::MAX, Vec::new());
+        assert_eq!(top_keys(1), vec![key(b"topkeys::a", 1000, 10)]);
+    }
+}
@@ -0,0 +1,101 @@
+// Copyright 2023 Pelikan Foundation LLC.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use serde::{Deserialize, Serialize};
+
+////////////////////////////////////////////////////////////////////////////////
+// constants to define default values
+////////////////////////////////////////////////////////////////////////////////
+
+// hotkey detection is disabled by default
+const ENABLED: bool = false;
+
+// keep the last N sampled keys
+const SAMPLE_SIZE: usize = 10_000;
+
+// sample 1 in every N keys
+const SAMPLE_RATE: usize = 100;
+
+// signal a hotkey if it makes up at least this fraction of the sampled keys
+const THRESHOLD_RATIO: f64 = 0.01;
+
+////////////////////////////////////////////////////////////////////////////////
+// helper functions
+////////////////////////////////////////////////////////////////////////////////
+
+fn enabled() -> bool {
+    ENABLED
+}
+
+fn sample_size() -> usize {
+    SAMPLE_SIZE
+}
+
+fn sample_rate() -> usize {
+    SAMPLE_RATE
+}
+
+fn threshold_ratio() -> f64 {
+    THRESHOLD_RATIO
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// struct definitions
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Hotkey {
+    #[serde(default = "enabled")]
+    enabled: bool,
+    #[serde(default = "sample_size")]
+    sample_size: usize,
+    #[serde(default = "sample_rate")]
+    sample_rate: usize,
+    #[serde(default = "threshold_ratio")]
+    threshold_ratio: f64,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// implementation
+////////////////////////////////////////////////////////////////////////////////
+
+impl Hotkey {
+    /// Is hotkey detection enabled?
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The number of sampled keys which are kept in the window.
+    pub fn sample_size(&self) -> usize {
+        self.sample_size
+    }
+
+    /// One in every `sample_rate` keys is sampled.
+    pub fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    /// The fraction of the sampled keys which a single key must account for
+    /// to be reported as a hotkey.
+    pub fn threshold_ratio(&self) -> f64 {
+        self.threshold_ratio
+    }
+}
+
+// trait implementations
+impl Default for Hotkey {
+    fn default() -> Self {
+        Self {
+            enabled: enabled(),
+            sample_size: sample_size(),
+            sample_rate: sample_rate(),
+            threshold_ratio: threshold_ratio(),
+        }
+    }
+}
+
+// trait definitions
+pub trait HotkeyConfig {
+    fn hotkey(&self) -> &Hotkey;
+}
@@ -10,6 +10,7 @@ mod array;
 mod buf;
 mod dbuf;
 mod debug;
+mod hotkey;
 mod klog;
 pub mod momento_proxy;
 mod pingproxy;
@@ -33,6 +34,7 @@ pub use array::ArrayConfig;
 pub use buf::{Buf, BufConfig};
 pub use dbuf::DbufConfig;
 pub use debug::{Debug, DebugConfig};
+pub use hotkey::{Hotkey, HotkeyConfig};
 pub use klog::{Klog, KlogConfig};
 pub use momento_proxy::MomentoProxyConfig;
 pub use pingproxy::PingproxyConfig;
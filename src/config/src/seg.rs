@@ -11,6 +11,7 @@ const MB: usize = 1024 * 1024;
 // defaults for hashtable
 const HASH_POWER: u8 = 16;
 const OVERFLOW_FACTOR: f64 = 1.0;
+const HASH_EXPAND_THRESHOLD: Option<f64> = None;
 
 // default heap/segment sizing
 const HEAP_SIZE: usize = 64 * MB;
@@ -26,6 +27,43 @@ const MERGE_MAX: usize = 8;
 
 // datapool
 const DATAPOOL_PATH: Option<&str> = None;
+const DATAPOOL_KEY_PATH: Option<&str> = None;
+
+// value compression
+const COMPRESSION: Compression = Compression::None;
+const COMPRESSION_LEVEL: i32 = 3;
+const COMPRESSION_THRESHOLD: usize = 1024;
+
+// admission filter
+const ADMISSION: Admission = Admission::None;
+const ADMISSION_COUNTERS: usize = 1024 * 1024;
+const ADMISSION_THRESHOLD: u8 = 2;
+
+// miss ratio curve estimation
+const MRC: Mrc = Mrc::None;
+const MRC_SAMPLE_RATE: f64 = 0.001;
+const MRC_MAX_KEYS: usize = 65536;
+
+// top keys tracking
+const TOP_KEYS: usize = 0;
+
+// expiration budget
+const EXPIRE_BUDGET: ExpireBudget = ExpireBudget::Unbounded;
+const EXPIRE_BUDGET_SEGMENTS: usize = 64;
+const EXPIRE_BUDGET_TIME_US: u64 = 1000;
+
+// stale grace period
+const STALE_GRACE: u32 = 0;
+
+// lease limits
+const LEASE_MAX_DURATION: u32 = 60;
+const LEASE_MAX_OUTSTANDING: usize = 65536;
+
+// disk tier
+const DISK_PATH: Option<&str> = None;
+const DISK_SIZE: usize = 1024 * MB;
+const DISK_SEGMENT_SIZE: i32 = MB as i32;
+const DISK_EVICTION: DiskEviction = DiskEviction::Fifo;
 
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum Eviction {
@@ -38,6 +76,62 @@ pub enum Eviction {
     Merge,
 }
 
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum Compression {
+    None,
+    Zstd,
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum Admission {
+    None,
+    TinyLfu,
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum Mrc {
+    None,
+    Shards,
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum ExpireBudget {
+    Unbounded,
+    Segments,
+    Time,
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum DiskEviction {
+    Fifo,
+    Random,
+}
+
+/// A namespace which is given a share of the heap. Keys which start with the
+/// prefix are stored in the namespace.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Namespace {
+    name: String,
+    #[serde(default)]
+    prefix: Option<String>,
+    #[serde(default)]
+    share: f64,
+}
+
+impl Namespace {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+
+    pub fn share(&self) -> f64 {
+        self.share
+    }
+}
+
 // helper functions for default values
 fn hash_power() -> u8 {
     HASH_POWER
@@ -47,6 +141,10 @@ fn overflow_factor() -> f64 {
     OVERFLOW_FACTOR
 }
 
+fn hash_expand_threshold() -> Option<f64> {
+    HASH_EXPAND_THRESHOLD
+}
+
 fn heap_size() -> usize {
     HEAP_SIZE
 }
@@ -75,6 +173,90 @@ fn datapool_path() -> Option<String> {
     DATAPOOL_PATH.map(|v| v.to_string())
 }
 
+fn datapool_key_path() -> Option<String> {
+    DATAPOOL_KEY_PATH.map(|v| v.to_string())
+}
+
+fn compression() -> Compression {
+    COMPRESSION
+}
+
+fn compression_level() -> i32 {
+    COMPRESSION_LEVEL
+}
+
+fn compression_threshold() -> usize {
+    COMPRESSION_THRESHOLD
+}
+
+fn admission() -> Admission {
+    ADMISSION
+}
+
+fn admission_counters() -> usize {
+    ADMISSION_COUNTERS
+}
+
+fn admission_threshold() -> u8 {
+    ADMISSION_THRESHOLD
+}
+
+fn mrc() -> Mrc {
+    MRC
+}
+
+fn mrc_sample_rate() -> f64 {
+    MRC_SAMPLE_RATE
+}
+
+fn mrc_max_keys() -> usize {
+    MRC_MAX_KEYS
+}
+
+fn top_keys() -> usize {
+    TOP_KEYS
+}
+
+fn expire_budget() -> ExpireBudget {
+    EXPIRE_BUDGET
+}
+
+fn expire_budget_segments() -> usize {
+    EXPIRE_BUDGET_SEGMENTS
+}
+
+fn expire_budget_time_us() -> u64 {
+    EXPIRE_BUDGET_TIME_US
+}
+
+fn stale_grace() -> u32 {
+    STALE_GRACE
+}
+
+fn lease_max_duration() -> u32 {
+    LEASE_MAX_DURATION
+}
+
+fn lease_max_outstanding() -> usize {
+    LEASE_MAX_OUTSTANDING
+}
+
+fn disk_path() -> Option<String> {
+    DISK_PATH.map(|v| v.to_string())
+}
+
+fn disk_size() -> usize {
+    DISK_SIZE
+}
+
+fn disk_segment_size() -> i32 {
+    DISK_SEGMENT_SIZE
+}
+
+fn disk_eviction() -> DiskEviction {
+    DISK_EVICTION
+}
+
 // definitions
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Seg {
@@ -82,6 +264,8 @@ pub struct Seg {
     hash_power: u8,
     #[serde(default = "overflow_factor")]
     overflow_factor: f64,
+    #[serde(default = "hash_expand_threshold")]
+    hash_expand_threshold: Option<f64>,
     #[serde(default = "heap_size")]
     heap_size: usize,
     #[serde(default = "segment_size")]
@@ -96,6 +280,50 @@ pub struct Seg {
     compact_target: usize,
     #[serde(default = "datapool_path")]
     datapool_path: Option<String>,
+    #[serde(default = "datapool_key_path")]
+    datapool_key_path: Option<String>,
+    #[serde(default = "compression")]
+    compression: Compression,
+    #[serde(default = "compression_level")]
+    compression_level: i32,
+    #[serde(default = "compression_threshold")]
+    compression_threshold: usize,
+    #[serde(default = "admission")]
+    admission: Admission,
+    #[serde(default = "admission_counters")]
+    admission_counters: usize,
+    #[serde(default = "admission_threshold")]
+    admission_threshold: u8,
+    #[serde(default = "mrc")]
+    mrc: Mrc,
+    #[serde(default = "mrc_sample_rate")]
+    mrc_sample_rate: f64,
+    #[serde(default = "mrc_max_keys")]
+    mrc_max_keys: usize,
+    #[serde(default = "top_keys")]
+    top_keys: usize,
+    #[serde(default = "expire_budget")]
+    expire_budget: ExpireBudget,
+    #[serde(default = "expire_budget_segments")]
+    expire_budget_segments: usize,
+    #[serde(default = "expire_budget_time_us")]
+    expire_budget_time_us: u64,
+    #[serde(default = "stale_grace")]
+    stale_grace: u32,
+    #[serde(default = "lease_max_duration")]
+    lease_max_duration: u32,
+    #[serde(default = "lease_max_outstanding")]
+    lease_max_outstanding: usize,
+    #[serde(default = "disk_path")]
+    disk_path: Option<String>,
+    #[serde(default = "disk_size")]
+    disk_size: usize,
+    #[serde(default = "disk_segment_size")]
+    disk_segment_size: i32,
+    #[serde(default = "disk_eviction")]
+    disk_eviction: DiskEviction,
+    #[serde(default)]
+    namespaces: Vec<Namespace>,
 }
 
 impl Default for Seg {
@@ -103,6 +331,7 @@ impl Default for Seg {
         Self {
             hash_power: hash_power(),
             overflow_factor: overflow_factor(),
+            hash_expand_threshold: hash_expand_threshold(),
             heap_size: heap_size(),
             segment_size: segment_size(),
             eviction: eviction(),
@@ -110,6 +339,28 @@ impl Default for Seg {
             merge_max: merge_max(),
             compact_target: compact_target(),
             datapool_path: datapool_path(),
+            datapool_key_path: datapool_key_path(),
+            compression: compression(),
+            compression_level: compression_level(),
+            compression_threshold: compression_threshold(),
+            admission: admission(),
+            admission_counters: admission_counters(),
+            admission_threshold: admission_threshold(),
+            mrc: mrc(),
+            mrc_sample_rate: mrc_sample_rate(),
+            mrc_max_keys: mrc_max_keys(),
+            top_keys: top_keys(),
+            expire_budget: expire_budget(),
+            expire_budget_segments: expire_budget_segments(),
+            expire_budget_time_us: expire_budget_time_us(),
+            stale_grace: stale_grace(),
+            lease_max_duration: lease_max_duration(),
+            lease_max_outstanding: lease_max_outstanding(),
+            disk_path: disk_path(),
+            disk_size: disk_size(),
+            disk_segment_size: disk_segment_size(),
+            disk_eviction: disk_eviction(),
+            namespaces: Vec::new(),
         }
     }
 }
@@ -124,6 +375,10 @@ impl Seg {
         self.overflow_factor
     }
 
+    pub fn hash_expand_threshold(&self) -> Option<f64> {
+        self.hash_expand_threshold
+    }
+
     pub fn heap_size(&self) -> usize {
         self.heap_size
     }
@@ -151,6 +406,108 @@ impl Seg {
     pub fn datapool_path(&self) -> Option<PathBuf> {
         self.datapool_path.as_ref().map(|v| Path::new(v).to_owned())
     }
+
+    /// The key file used to encrypt the datapool. The datapool is stored
+    /// unencrypted if this is not set.
+    pub fn datapool_key_path(&self) -> Option<PathBuf> {
+        self.datapool_key_path
+            .as_ref()
+            .map(|v| Path::new(v).to_owned())
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    pub fn compression_level(&self) -> i32 {
+        self.compression_level
+    }
+
+    pub fn compression_threshold(&self) -> usize {
+        self.compression_threshold
+    }
+
+    pub fn admission(&self) -> Admission {
+        self.admission
+    }
+
+    pub fn admission_counters(&self) -> usize {
+        self.admission_counters
+    }
+
+    pub fn admission_threshold(&self) -> u8 {
+        self.admission_threshold
+    }
+
+    pub fn mrc(&self) -> Mrc {
+        self.mrc
+    }
+
+    pub fn mrc_sample_rate(&self) -> f64 {
+        self.mrc_sample_rate
+    }
+
+    pub fn mrc_max_keys(&self) -> usize {
+        self.mrc_max_keys
+    }
+
+    pub fn top_keys(&self) -> usize {
+        self.top_keys
+    }
+
+    pub fn expire_budget(&self) -> ExpireBudget {
+        self.expire_budget
+    }
+
+    pub fn expire_budget_segments(&self) -> usize {
+        self.expire_budget_segments
+    }
+
+    pub fn expire_budget_time_us(&self) -> u64 {
+        self.expire_budget_time_us
+    }
+
+    pub fn stale_grace(&self) -> u32 {
+        self.stale_grace
+    }
+
+    pub fn lease_max_duration(&self) -> u32 {
+        self.lease_max_duration
+    }
+
+    pub fn lease_max_outstanding(&self) -> usize {
+        self.lease_max_outstanding
+    }
+
+    pub fn disk_path(&self) -> Option<PathBuf> {
+        self.disk_path.as_ref().map(|v| Path::new(v).to_owned())
+    }
+
+    pub fn disk_size(&self) -> usize {
+        self.disk_size
+    }
+
+    pub fn disk_segment_size(&self) -> i32 {
+        self.disk_segment_size
+    }
+
+    pub fn disk_eviction(&self) -> DiskEviction {
+        self.disk_eviction
+    }
+
+    pub fn namespaces(&self) -> &[Namespace] {
+        &self.namespaces
+    }
+
+    /// The checkpoint is stored alongside the datapool, using the datapool
+    /// path with an additional `.checkpoint` extension.
+    pub fn checkpoint_path(&self) -> Option<PathBuf> {
+        self.datapool_path.as_ref().map(|v| {
+            let mut path = v.clone();
+            path.push_str(".checkpoint");
+            PathBuf::from(path)
+        })
+    }
 }
 
 // trait definitions
@@ -51,6 +51,8 @@ pub struct SegcacheConfig {
     tls: Tls,
     #[serde(default)]
     seg: Seg,
+    #[serde(default)]
+    hotkey: Hotkey,
 
     // ccommon
     #[serde(default)]
@@ -125,6 +127,12 @@ impl DebugConfig for SegcacheConfig {
     }
 }
 
+impl HotkeyConfig for SegcacheConfig {
+    fn hotkey(&self) -> &Hotkey {
+        &self.hotkey
+    }
+}
+
 impl KlogConfig for SegcacheConfig {
     fn klog(&self) -> &Klog {
         &self.klog
@@ -191,6 +199,7 @@ impl Default for SegcacheConfig {
             worker: Default::default(),
             time: Default::default(),
             seg: Default::default(),
+            hotkey: Default::default(),
 
             buf: Default::default(),
             debug: Default::default(),
@@ -8,6 +8,7 @@ use serde::{Deserialize, Serialize};
 const WORKER_TIMEOUT: usize = 100;
 const WORKER_NEVENT: usize = 1024;
 const WORKER_THREADS: usize = 1;
+const WORKER_STORAGE_THREADS: usize = 1;
 
 // helper functions
 fn timeout() -> usize {
@@ -22,6 +23,10 @@ fn threads() -> usize {
     WORKER_THREADS
 }
 
+fn storage_threads() -> usize {
+    WORKER_STORAGE_THREADS
+}
+
 // definitions
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Worker {
@@ -31,6 +36,8 @@ pub struct Worker {
     nevent: usize,
     #[serde(default = "threads")]
     threads: usize,
+    #[serde(default = "storage_threads")]
+    storage_threads: usize,
 }
 
 // implementation
@@ -50,6 +57,16 @@ impl Worker {
     pub fn set_threads(&mut self, threads: usize) {
         self.threads = threads
     }
+
+    /// The number of storage threads. The storage is partitioned into one
+    /// shard per storage thread and requests are routed by key.
+    pub fn storage_threads(&self) -> usize {
+        self.storage_threads
+    }
+
+    pub fn set_storage_threads(&mut self, threads: usize) {
+        self.storage_threads = threads
+    }
 }
 
 // trait implementations
@@ -59,6 +76,7 @@ impl Default for Worker {
             timeout: timeout(),
             nevent: nevent(),
             threads: threads(),
+            storage_threads: storage_threads(),
         }
     }
 }
@@ -368,16 +368,38 @@ impl Admin {
 
                 // do some request handling
                 match request {
+                    AdminRequest::DeleteMatching(pattern) => {
+                        let _ = self
+                            .signal_queue_tx
+                            .try_send_all(Signal::DeleteMatching(pattern));
+                        session.send(AdminResponse::Ok)?;
+                    }
+                    AdminRequest::DeleteMatchingProgress => {
+                        session.send(AdminResponse::DeleteMatching)?;
+                    }
                     AdminRequest::FlushAll => {
                         let _ = self.signal_queue_tx.try_send_all(Signal::FlushAll);
                         session.send(AdminResponse::Ok)?;
                     }
+                    AdminRequest::HeapSize(bytes) => {
+                        let _ = self.signal_queue_tx.try_send_all(Signal::HeapSize(bytes));
+                        session.send(AdminResponse::Ok)?;
+                    }
+                    AdminRequest::Hotkeys => {
+                        session.send(AdminResponse::Hotkeys)?;
+                    }
+                    AdminRequest::Mrc => {
+                        session.send(AdminResponse::Mrc)?;
+                    }
                     AdminRequest::Quit => {
                         return Err(Error::new(ErrorKind::Other, "should hangup"));
                     }
                     AdminRequest::Stats => {
                         session.send(AdminResponse::Stats)?;
                     }
+                    AdminRequest::TopKeys(count) => {
+                        session.send(AdminResponse::top_keys(count))?;
+                    }
                     AdminRequest::Version => {
                         session.send(AdminResponse::version(self.version.clone()))?;
                     }
@@ -589,7 +611,7 @@ impl Admin {
             // handle all signals
             while let Ok(signal) = self.signal_queue_rx.try_recv() {
                 match signal {
-                    Signal::FlushAll => {}
+                    Signal::FlushAll | Signal::HeapSize(_) | Signal::DeleteMatching(_) => {}
                     Signal::Shutdown => {
                         // if a shutdown is received from any
                         // thread, we will broadcast it to all
@@ -87,6 +87,13 @@
 //! execute requests. The storage thread will receive requests from a worker
 //! over a queue, execute the request, and returns the result back to the worker
 //! thread.
+//!
+//! The storage may also be partitioned into multiple shards, each of which is
+//! owned by its own storage thread. Workers route each request to the shard
+//! which owns the key by hashing it. Requests with keys on multiple shards,
+//! such as a multi-key get, are split and the responses are reassembled by the
+//! worker before being returned to the client in the order the requests were
+//! received.
 
 #[macro_use]
 extern crate logger;
@@ -103,7 +110,7 @@ use logger::{Drain, Klog};
 use metriken::*;
 use pelikan_net::event::{Event, Source};
 use pelikan_net::*;
-use protocol_common::{Compose, Execute, Parse};
+use protocol_common::{Compose, Execute, Parse, Route, Shard};
 use session::{Buf, ServerSession, Session};
 use slab::Slab;
 use std::io::{Error, ErrorKind, Result};
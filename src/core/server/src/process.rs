@@ -18,7 +18,7 @@ pub struct ProcessBuilder<Parser, Request, Response, Storage> {
 impl<Parser, Request, Response, Storage> ProcessBuilder<Parser, Request, Response, Storage>
 where
     Parser: 'static + Parse<Request> + Clone + Send,
-    Request: 'static + Klog + Klog<Response = Response> + Send,
+    Request: 'static + Klog + Klog<Response = Response> + Shard<Response> + Send,
     Response: 'static + Compose + Send,
     Storage: 'static + Execute<Request, Response> + EntryStore + Send,
 {
@@ -27,6 +27,18 @@ where
         log_drain: Box<dyn Drain>,
         parser: Parser,
         storage: Storage,
+    ) -> Result<Self> {
+        Self::sharded(config, log_drain, parser, vec![storage])
+    }
+
+    /// Create a process with storage which is partitioned into multiple
+    /// shards. Each shard is owned by its own storage thread and requests are
+    /// routed to the shards by key.
+    pub fn sharded<T: AdminConfig + ServerConfig + TlsConfig + WorkerConfig>(
+        config: &T,
+        log_drain: Box<dyn Drain>,
+        parser: Parser,
+        storage: Vec<Storage>,
     ) -> Result<Self> {
         let admin = AdminBuilder::new(config)?;
         let listener = ListenerBuilder::new(config)?;
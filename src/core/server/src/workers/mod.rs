@@ -63,6 +63,14 @@ fn map_result(result: Result<usize>) -> Result<()> {
     }
 }
 
+/// Identifies a request, or part of a request which was split across storage
+/// shards, so that the response can be matched to the pending request.
+#[derive(Clone, Copy, Debug)]
+pub struct Tag {
+    pending: usize,
+    fragment: usize,
+}
+
 // NOTE: as it is expected to have very few instances of this enum
 // we suppress the warning about the large variant
 #[allow(clippy::large_enum_variant)]
@@ -72,14 +80,14 @@ pub enum Workers<Parser, Request, Response, Storage> {
     },
     Multi {
         workers: Vec<MultiWorker<Parser, Request, Response>>,
-        storage: StorageWorker<Request, Response, Storage, Token>,
+        storage: Vec<StorageWorker<Request, Response, Storage, Tag>>,
     },
 }
 
 impl<Parser, Request, Response, Storage> Workers<Parser, Request, Response, Storage>
 where
     Parser: 'static + Parse<Request> + Clone + Send,
-    Request: 'static + Klog + Klog<Response = Response> + Send,
+    Request: 'static + Klog + Klog<Response = Response> + Shard<Response> + Send,
     Response: 'static + Compose + Send,
     Storage: 'static + EntryStore + Execute<Request, Response> + Send,
 {
@@ -95,10 +103,16 @@ where
                 mut workers,
                 mut storage,
             } => {
-                let mut join_handles = vec![std::thread::Builder::new()
-                    .name(format!("{THREAD_PREFIX}_storage"))
-                    .spawn(move || storage.run())
-                    .unwrap()];
+                let mut join_handles = Vec::new();
+
+                for (id, mut storage) in storage.drain(..).enumerate() {
+                    join_handles.push(
+                        std::thread::Builder::new()
+                            .name(format!("{THREAD_PREFIX}_storage_{id}"))
+                            .spawn(move || storage.run())
+                            .unwrap(),
+                    )
+                }
 
                 for (id, mut worker) in workers.drain(..).enumerate() {
                     join_handles.push(
@@ -121,7 +135,7 @@ pub enum WorkersBuilder<Parser, Request, Response, Storage> {
     },
     Multi {
         workers: Vec<MultiWorkerBuilder<Parser, Request, Response>>,
-        storage: StorageWorkerBuilder<Request, Response, Storage>,
+        storage: Vec<StorageWorkerBuilder<Request, Response, Storage>>,
     },
 }
 
@@ -131,22 +145,36 @@ where
     Response: Compose,
     Storage: Execute<Request, Response> + EntryStore,
 {
-    pub fn new<T: WorkerConfig>(config: &T, parser: Parser, storage: Storage) -> Result<Self> {
+    /// Create the workers for the provided storage. If there is more than one
+    /// storage instance, each is used as a shard which is owned by its own
+    /// storage thread.
+    pub fn new<T: WorkerConfig>(
+        config: &T,
+        parser: Parser,
+        mut storage: Vec<Storage>,
+    ) -> Result<Self> {
+        assert!(
+            !storage.is_empty(),
+            "at least one storage shard is required"
+        );
+
         let threads = config.worker().threads();
 
-        if threads > 1 {
+        if threads > 1 || storage.len() > 1 {
             let mut workers = vec![];
             for _ in 0..threads {
                 workers.push(MultiWorkerBuilder::new(config, parser.clone())?)
             }
 
-            Ok(Self::Multi {
-                workers,
-                storage: StorageWorkerBuilder::new(config, storage)?,
-            })
+            let storage = storage
+                .drain(..)
+                .map(|storage| StorageWorkerBuilder::new(config, storage))
+                .collect::<Result<Vec<_>>>()?;
+
+            Ok(Self::Multi { workers, storage })
         } else {
             Ok(Self::Single {
-                worker: SingleWorkerBuilder::new(config, parser, storage)?,
+                worker: SingleWorkerBuilder::new(config, parser, storage.remove(0))?,
             })
         }
     }
@@ -169,7 +197,7 @@ where
                 vec![worker.waker()]
             }
             Self::Multi { workers, storage } => {
-                let mut wakers = vec![storage.waker()];
+                let mut wakers: Vec<Arc<Waker>> = storage.iter().map(|s| s.waker()).collect();
                 for worker in workers {
                     wakers.push(worker.waker());
                 }
@@ -187,26 +215,34 @@ where
         let mut session_queues = session_queues;
         match self {
             Self::Multi {
-                storage,
+                mut storage,
                 mut workers,
             } => {
-                let storage_wakers = vec![storage.waker()];
+                let storage_wakers: Vec<Arc<Waker>> = storage.iter().map(|v| v.waker()).collect();
                 let worker_wakers: Vec<Arc<Waker>> = workers.iter().map(|v| v.waker()).collect();
+                let shards = storage_wakers.len();
                 let (mut worker_data_queues, mut storage_data_queues) =
                     Queues::new(worker_wakers, storage_wakers, QUEUE_CAPACITY);
 
-                // The storage thread precedes the worker threads in the set of
-                // wakers, so its signal queue is the first element of
-                // `signal_queues`. Its request queue is also the first (and
-                // only) element of `request_queues`. We remove these and build
-                // the storage so we can loop through the remaining signal
-                // queues when launching the worker threads.
-                let s = storage.build(storage_data_queues.remove(0), signal_queues.remove(0));
+                // The storage threads precede the worker threads in the set of
+                // wakers, so their signal queues are the first elements of
+                // `signal_queues`. Each storage thread has one request queue
+                // and each worker has a queue to every storage thread. We
+                // remove these and build the storage so we can loop through the
+                // remaining signal queues when launching the worker threads.
+                let mut s = Vec::new();
+                for storage_builder in storage.drain(..) {
+                    s.push(
+                        storage_builder
+                            .build(storage_data_queues.remove(0), signal_queues.remove(0)),
+                    );
+                }
 
                 let mut w = Vec::new();
                 for worker_builder in workers.drain(..) {
                     w.push(worker_builder.build(
                         worker_data_queues.remove(0),
+                        shards,
                         session_queues.remove(0),
                         signal_queues.remove(0),
                     ));
@@ -3,12 +3,15 @@
 // http://www.apache.org/licenses/LICENSE-2.0
 
 use super::*;
+use ahash::RandomState;
+use core::hash::{BuildHasher, Hasher};
+use std::collections::VecDeque;
 
 pub struct MultiWorkerBuilder<Parser, Request, Response> {
     nevent: usize,
     parser: Parser,
     poll: Poll,
-    sessions: Slab<ServerSession<Parser, Response, Request>>,
+    sessions: Slab<WorkerSession<Parser, Request, Response>>,
     timeout: Duration,
     waker: Arc<Waker>,
 }
@@ -40,19 +43,36 @@ impl<Parser, Request, Response> MultiWorkerBuilder<Parser, Request, Response> {
         self.waker.clone()
     }
 
+    /// Build the worker. The `data_queue` must have one peer for each storage
+    /// shard.
     pub fn build(
         self,
-        data_queue: Queues<(Request, Token), (Request, Response, Token)>,
+        data_queue: Queues<(Request, Tag), (Request, Response, Tag)>,
+        shards: usize,
         session_queue: Queues<Session, Session>,
         signal_queue: Queues<(), Signal>,
     ) -> MultiWorker<Parser, Request, Response> {
+        // the hash must be the same across all workers and across restarts so
+        // that keys are always routed to the same shard. The seeds differ from
+        // those used by the storage hashtables, otherwise the keys held by a
+        // shard would all share the same hash bits and collide in its buckets
+        let hash_builder = RandomState::with_seeds(
+            0xe6f4275503d73c09,
+            0x63a122ea8859a1ad,
+            0x74670bb41db161ba,
+            0xf95b624c01b1d01b,
+        );
+
         MultiWorker {
             data_queue,
+            hash_builder,
             nevent: self.nevent,
             parser: self.parser,
+            pending: Slab::new(),
             poll: self.poll,
             session_queue,
             sessions: self.sessions,
+            shards,
             signal_queue,
             timeout: self.timeout,
             waker: self.waker,
@@ -60,13 +80,37 @@ impl<Parser, Request, Response> MultiWorkerBuilder<Parser, Request, Response> {
     }
 }
 
+/// A session along with the requests which have been sent to storage and are
+/// waiting for a response, in the order they were received.
+pub struct WorkerSession<Parser, Request, Response> {
+    session: ServerSession<Parser, Response, Request>,
+    pending: VecDeque<usize>,
+}
+
+/// A request which has been sent to storage and is waiting for responses from
+/// one or more shards.
+pub struct Pending<Request, Response> {
+    /// The session which sent the request, or `None` if it has been closed
+    token: Option<Token>,
+    /// The request, which is held here if it was split across shards and is
+    /// otherwise returned by the storage along with the response
+    request: Option<Request>,
+    /// The response from each shard, in the order the request was split
+    responses: Vec<Option<Response>>,
+    /// The number of responses which have not been received
+    remaining: usize,
+}
+
 pub struct MultiWorker<Parser, Request, Response> {
-    data_queue: Queues<(Request, Token), (Request, Response, Token)>,
+    data_queue: Queues<(Request, Tag), (Request, Response, Tag)>,
+    hash_builder: RandomState,
     nevent: usize,
     parser: Parser,
+    pending: Slab<Pending<Request, Response>>,
     poll: Poll,
     session_queue: Queues<Session, Session>,
-    sessions: Slab<ServerSession<Parser, Response, Request>>,
+    sessions: Slab<WorkerSession<Parser, Request, Response>>,
+    shards: usize,
     signal_queue: Queues<(), Signal>,
     timeout: Duration,
     waker: Arc<Waker>,
@@ -75,19 +119,37 @@ pub struct MultiWorker<Parser, Request, Response> {
 impl<Parser, Request, Response> MultiWorker<Parser, Request, Response>
 where
     Parser: Parse<Request> + Clone,
-    Request: Klog + Klog<Response = Response>,
+    Request: Klog + Klog<Response = Response> + Shard<Response>,
     Response: Compose,
 {
     /// Return the `Session` to the `Listener` to handle flush/close
     fn close(&mut self, token: Token) {
         if self.sessions.contains(token.0) {
-            let mut session = self.sessions.remove(token.0).into_inner();
+            let session = self.sessions.remove(token.0);
+
+            // any responses which are still outstanding will be dropped
+            for id in session.pending {
+                if self.pending[id].remaining == 0 {
+                    self.pending.remove(id);
+                } else {
+                    self.pending[id].token = None;
+                }
+            }
+
+            let mut session = session.session.into_inner();
             let _ = session.deregister(self.poll.registry());
             let _ = self.session_queue.try_send_any(session);
             let _ = self.session_queue.wake();
         }
     }
 
+    /// Returns the index of the storage shard which owns the key
+    fn shard(&self, key: &[u8]) -> usize {
+        let mut hasher = self.hash_builder.build_hasher();
+        hasher.write(key);
+        (hasher.finish() % self.shards as u64) as usize
+    }
+
     /// Handle up to one request for a session
     fn read(&mut self, token: Token) -> Result<()> {
         let session = self
@@ -96,18 +158,130 @@ where
             .ok_or_else(|| Error::new(ErrorKind::Other, "non-existant session"))?;
 
         // fill the session
-        map_result(session.fill())?;
+        map_result(session.session.fill())?;
 
         // process up to one request
-        match session.receive() {
-            Ok(request) => self
-                .data_queue
-                .try_send_to(0, (request, token))
-                .map_err(|_| Error::new(ErrorKind::Other, "data queue is full")),
+        match session.session.receive() {
+            Ok(request) => self.send(token, request),
             Err(e) => map_err(e),
         }
     }
 
+    /// Send a request to the storage shards which own its keys
+    fn send(&mut self, token: Token, request: Request) -> Result<()> {
+        let route = if self.shards == 1 {
+            Route::Shard(0)
+        } else {
+            request.route(self.shards, &|key| self.shard(key))
+        };
+
+        let (request, fragments) = match route {
+            Route::Shard(shard) => (None, vec![(shard, request)]),
+            Route::Split(fragments) => (Some(request), fragments),
+        };
+
+        let id = self.pending.insert(Pending {
+            token: Some(token),
+            request,
+            responses: fragments.iter().map(|_| None).collect(),
+            remaining: fragments.len(),
+        });
+
+        let mut result = Ok(());
+
+        for (fragment, (shard, request)) in fragments.into_iter().enumerate() {
+            let tag = Tag {
+                pending: id,
+                fragment,
+            };
+
+            if self.data_queue.try_send_to(shard, (request, tag)).is_err() {
+                // this fragment will never receive a response
+                self.pending[id].remaining -= 1;
+                result = Err(Error::new(ErrorKind::Other, "data queue is full"));
+            }
+        }
+
+        if result.is_err() {
+            // the session will be closed, so drop the request once any fragments
+            // which were sent have been handled
+            if self.pending[id].remaining == 0 {
+                self.pending.remove(id);
+            } else {
+                self.pending[id].token = None;
+            }
+        } else if let Some(session) = self.sessions.get_mut(token.0) {
+            session.pending.push_back(id);
+        }
+
+        result
+    }
+
+    /// Send all completed responses to the session, stopping at the first
+    /// request which is still waiting on storage so responses are always sent
+    /// in the order the requests were received.
+    fn respond(&mut self, token: Token) {
+        while let Some(session) = self.sessions.get_mut(token.0) {
+            match session.pending.front() {
+                Some(id) if self.pending[*id].remaining == 0 => {}
+                _ => {
+                    return;
+                }
+            }
+
+            let id = session.pending.pop_front().unwrap();
+            let pending = self.pending.remove(id);
+
+            let request = pending.request.unwrap();
+            let mut responses: Vec<Response> =
+                pending.responses.into_iter().map(|r| r.unwrap()).collect();
+            let response = if responses.len() == 1 {
+                responses.pop().unwrap()
+            } else {
+                request.merge(responses)
+            };
+
+            request.klog(&response);
+
+            let session = &mut session.session;
+
+            if response.should_hangup() {
+                let _ = session.send(response);
+                self.close(token);
+                return;
+            } else if session.send(response).is_err() {
+                self.close(token);
+                return;
+            } else if session.write_pending() > 0 {
+                // try to immediately flush, if we still have pending bytes,
+                // reregister. This saves us one syscall when flushing would not
+                // block.
+                if let Err(e) = session.flush() {
+                    if map_err(e).is_err() {
+                        self.close(token);
+                        return;
+                    }
+                }
+
+                if session.write_pending() > 0 {
+                    let interest = session.interest();
+                    if session
+                        .reregister(self.poll.registry(), token, interest)
+                        .is_err()
+                    {
+                        self.close(token);
+                        return;
+                    }
+                }
+            }
+
+            if session.remaining() > 0 && self.read(token).is_err() {
+                self.close(token);
+                return;
+            }
+        }
+    }
+
     /// Handle write by flushing the session
     fn write(&mut self, token: Token) -> Result<()> {
         let session = self
@@ -115,7 +289,7 @@ where
             .get_mut(token.0)
             .ok_or_else(|| Error::new(ErrorKind::Other, "non-existant session"))?;
 
-        match session.flush() {
+        match session.session.flush() {
             Ok(_) => Ok(()),
             Err(e) => map_err(e),
         }
@@ -160,7 +334,10 @@ where
                                 .register(self.poll.registry(), Token(s.key()), interest)
                                 .is_ok()
                             {
-                                s.insert(ServerSession::new(session, self.parser.clone()));
+                                s.insert(WorkerSession {
+                                    session: ServerSession::new(session, self.parser.clone()),
+                                    pending: VecDeque::new(),
+                                });
                             } else {
                                 let _ = self.session_queue.try_send_any(session);
                             }
@@ -171,45 +348,26 @@ where
 
                         // handle all pending messages on the data queue
                         self.data_queue.try_recv_all(&mut messages);
-                        for (request, response, token) in messages.drain(..).map(|v| v.into_inner())
-                        {
-                            request.klog(&response);
-                            if let Some(session) = self.sessions.get_mut(token.0) {
-                                if response.should_hangup() {
-                                    let _ = session.send(response);
-                                    self.close(token);
-                                    continue;
-                                } else if session.send(response).is_err() {
-                                    self.close(token);
-                                    continue;
-                                } else if session.write_pending() > 0 {
-                                    // try to immediately flush, if we still
-                                    // have pending bytes, reregister. This
-                                    // saves us one syscall when flushing would
-                                    // not block.
-                                    if let Err(e) = session.flush() {
-                                        if map_err(e).is_err() {
-                                            self.close(token);
-                                            continue;
-                                        }
-                                    }
-
-                                    if session.write_pending() > 0 {
-                                        let interest = session.interest();
-                                        if session
-                                            .reregister(self.poll.registry(), token, interest)
-                                            .is_err()
-                                        {
-                                            self.close(token);
-                                            continue;
-                                        }
-                                    }
-                                }
+                        for (request, response, tag) in messages.drain(..).map(|v| v.into_inner()) {
+                            let pending = &mut self.pending[tag.pending];
+                            pending.responses[tag.fragment] = Some(response);
+                            pending.remaining -= 1;
+
+                            // if the request was split, the original request is
+                            // already held and the fragments are dropped
+                            if pending.request.is_none() {
+                                pending.request = Some(request);
+                            }
 
-                                if session.remaining() > 0 && self.read(token).is_err() {
-                                    self.close(token);
-                                    continue;
-                                }
+                            if pending.remaining > 0 {
+                                continue;
+                            }
+
+                            if let Some(token) = pending.token {
+                                self.respond(token);
+                            } else {
+                                // the session was closed
+                                self.pending.remove(tag.pending);
                             }
                         }
 
@@ -218,7 +376,9 @@ where
                             self.signal_queue.try_recv().map(|v| v.into_inner())
                         {
                             match signal {
-                                Signal::FlushAll => {}
+                                Signal::FlushAll
+                                | Signal::HeapSize(_)
+                                | Signal::DeleteMatching(_) => {}
                                 Signal::Shutdown => {
                                     // if we received a shutdown, we can return
                                     // and stop processing events
@@ -246,9 +246,24 @@ where
                                 Signal::FlushAll => {
                                     self.storage.clear();
                                 }
+                                Signal::HeapSize(bytes) => {
+                                    if let Err(e) = self.storage.resize(bytes) {
+                                        error!("failed to resize storage: {}", e);
+                                    }
+                                }
+                                Signal::DeleteMatching(pattern) => {
+                                    if let Err(e) = self.storage.delete_matching(&pattern) {
+                                        error!("failed to delete matching items: {}", e);
+                                    }
+                                }
                                 Signal::Shutdown => {
-                                    // if we received a shutdown, we can return
-                                    // and stop processing events
+                                    // if we received a shutdown, we checkpoint
+                                    // the storage so it can be restored, then we
+                                    // can return and stop processing events
+                                    if let Err(e) = self.storage.checkpoint() {
+                                        error!("failed to checkpoint storage: {}", e);
+                                    }
+
                                     return;
                                 }
                             }
@@ -54,7 +54,7 @@ impl<Request, Response, Storage> StorageWorkerBuilder<Request, Response, Storage
         self.waker.clone()
     }
 
-    pub fn build(
+    pub fn build<Token>(
         self,
         data_queue: Queues<(Request, Response, Token), (Request, Token)>,
         signal_queue: Queues<(), Signal>,
@@ -146,12 +146,28 @@ where
                             warn!("received flush_all");
                             self.storage.clear();
                         }
+                        Signal::HeapSize(bytes) => {
+                            warn!("received heap_size: {}", bytes);
+                            if let Err(e) = self.storage.resize(bytes) {
+                                error!("failed to resize storage: {}", e);
+                            }
+                        }
+                        Signal::DeleteMatching(pattern) => {
+                            warn!(
+                                "received delete_matching: {}",
+                                String::from_utf8_lossy(&pattern)
+                            );
+                            if let Err(e) = self.storage.delete_matching(&pattern) {
+                                error!("failed to delete matching items: {}", e);
+                            }
+                        }
                         Signal::Shutdown => {
-                            // if we received a shutdown, we can return and stop
-                            // processing events
-
-                            // TODO(bmartin): graceful shutdown would occur here
-                            // when we add persistence
+                            // if we received a shutdown, we checkpoint the
+                            // storage so it can be restored, then we can return
+                            // and stop processing events
+                            if let Err(e) = self.storage.checkpoint() {
+                                error!("failed to checkpoint storage: {}", e);
+                            }
 
                             return;
                         }
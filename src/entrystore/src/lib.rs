@@ -24,4 +24,32 @@ pub trait EntryStore {
 
     /// Remove all existing values from the entry store.
     fn clear(&mut self);
+
+    /// Persist the entry store so that it can be restored by a future process.
+    /// Storage types which are not persistent do not need to implement this
+    /// function. The default implementation is a no-op.
+    fn checkpoint(&mut self) -> Result<(), std::io::Error> {
+        Ok(())
+    }
+
+    /// Resize the memory used by the entry store to the given number of bytes
+    /// while it remains in use. The default implementation returns an error
+    /// for storage types which cannot be resized.
+    fn resize(&mut self, _heap_size: usize) -> Result<(), std::io::Error> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "storage cannot be resized",
+        ))
+    }
+
+    /// Begin removing every item with a key matching the glob pattern. The
+    /// items may be removed incrementally by later calls to `expire()`. The
+    /// default implementation returns an error for storage types which cannot
+    /// match keys.
+    fn delete_matching(&mut self, _pattern: &[u8]) -> Result<(), std::io::Error> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "storage cannot delete matching keys",
+        ))
+    }
 }
@@ -15,6 +15,25 @@ use std::time::Duration;
 
 impl Execute<Request, Response> for Seg {
     fn execute(&mut self, request: &Request) -> Response {
+        if self.hotkey.is_some() {
+            match request {
+                Request::Get(get) => get.keys().iter().for_each(|key| self.sample(key)),
+                Request::Gets(gets) => gets.keys().iter().for_each(|key| self.sample(key)),
+                Request::Set(set) => self.sample(set.key()),
+                Request::Add(add) => self.sample(add.key()),
+                Request::Replace(replace) => self.sample(replace.key()),
+                Request::Cas(cas) => self.sample(cas.key()),
+                Request::Incr(incr) => self.sample(incr.key()),
+                Request::Decr(decr) => self.sample(decr.key()),
+                Request::Append(append) => self.sample(append.key()),
+                Request::Prepend(prepend) => self.sample(prepend.key()),
+                Request::Delete(delete) => self.sample(delete.key()),
+                Request::MetaGet(meta_get) => self.sample(meta_get.key()),
+                Request::MetaSet(meta_set) => self.sample(meta_set.key()),
+                Request::FlushAll(_) | Request::InvalidateTag(_) | Request::Quit(_) => {}
+            }
+        }
+
         match request {
             Request::Get(get) => self.get(get),
             Request::Gets(gets) => self.gets(gets),
@@ -27,6 +46,9 @@ impl Execute<Request, Response> for Seg {
             Request::Append(append) => self.append(append),
             Request::Prepend(prepend) => self.prepend(prepend),
             Request::Delete(delete) => self.delete(delete),
+            Request::MetaGet(meta_get) => self.meta_get(meta_get),
+            Request::MetaSet(meta_set) => self.meta_set(meta_set),
+            Request::InvalidateTag(invalidate_tag) => self.invalidate_tag(invalidate_tag),
             Request::FlushAll(flush_all) => self.flush_all(flush_all),
             Request::Quit(quit) => self.quit(quit),
         }
@@ -52,6 +74,22 @@ impl Storage for Seg {
                             format!("{v}").as_bytes(),
                         ));
                     }
+                    segcache::Value::I64(v) => {
+                        values.push(Value::new(
+                            item.key(),
+                            flags,
+                            None,
+                            format!("{v}").as_bytes(),
+                        ));
+                    }
+                    segcache::Value::F64(v) => {
+                        values.push(Value::new(
+                            item.key(),
+                            flags,
+                            None,
+                            format!("{v}").as_bytes(),
+                        ));
+                    }
                 }
             } else {
                 values.push(Value::none(key));
@@ -78,6 +116,22 @@ impl Storage for Seg {
                             format!("{v}").as_bytes(),
                         ));
                     }
+                    segcache::Value::I64(v) => {
+                        values.push(Value::new(
+                            item.key(),
+                            flags,
+                            Some(item.cas().into()),
+                            format!("{v}").as_bytes(),
+                        ));
+                    }
+                    segcache::Value::F64(v) => {
+                        values.push(Value::new(
+                            item.key(),
+                            flags,
+                            Some(item.cas().into()),
+                            format!("{v}").as_bytes(),
+                        ));
+                    }
                 }
             } else {
                 values.push(Value::none(key));
@@ -92,51 +146,18 @@ impl Storage for Seg {
         if ttl < 0 {
             // immediate expire maps to a delete
             self.data.delete(set.key());
-            Response::stored(set.noreply())
-        } else if let Ok(s) = std::str::from_utf8(set.value()) {
-            if let Ok(v) = s.parse::<u64>() {
-                if self
-                    .data
-                    .insert(
-                        set.key(),
-                        v,
-                        Some(&set.flags().to_be_bytes()),
-                        Duration::from_secs(ttl as u64),
-                    )
-                    .is_ok()
-                {
-                    Response::stored(set.noreply())
-                } else {
-                    Response::server_error("")
-                }
-            } else if self
-                .data
-                .insert(
-                    set.key(),
-                    set.value(),
-                    Some(&set.flags().to_be_bytes()),
-                    Duration::from_secs(ttl as u64),
-                )
-                .is_ok()
-            {
-                Response::stored(set.noreply())
-            } else {
-                Response::server_error("")
-            }
-        } else if self
-            .data
-            .insert(
-                set.key(),
-                set.value(),
-                Some(&set.flags().to_be_bytes()),
-                Duration::from_secs(ttl as u64),
-            )
-            .is_ok()
-        {
-            Response::stored(set.noreply())
-        } else {
-            Response::server_error("")
+            return Response::stored(set.noreply());
         }
+
+        let flags = set.flags().to_be_bytes();
+        let ttl = Duration::from_secs(ttl as u64);
+
+        let result = match std::str::from_utf8(set.value()).map(|s| s.parse::<u64>()) {
+            Ok(Ok(v)) => self.data.insert(set.key(), v, Some(&flags), ttl),
+            _ => self.data.insert(set.key(), set.value(), Some(&flags), ttl),
+        };
+
+        stored(result, set.noreply())
     }
 
     fn add(&mut self, add: &Add) -> Response {
@@ -149,51 +170,18 @@ impl Storage for Seg {
         if ttl < 0 {
             // immediate expire maps to a delete
             self.data.delete(add.key());
-            Response::stored(add.noreply())
-        } else if let Ok(s) = std::str::from_utf8(add.value()) {
-            if let Ok(v) = s.parse::<u64>() {
-                if self
-                    .data
-                    .insert(
-                        add.key(),
-                        v,
-                        Some(&add.flags().to_be_bytes()),
-                        Duration::from_secs(ttl as u64),
-                    )
-                    .is_ok()
-                {
-                    Response::stored(add.noreply())
-                } else {
-                    Response::server_error("")
-                }
-            } else if self
-                .data
-                .insert(
-                    add.key(),
-                    add.value(),
-                    Some(&add.flags().to_be_bytes()),
-                    Duration::from_secs(ttl as u64),
-                )
-                .is_ok()
-            {
-                Response::stored(add.noreply())
-            } else {
-                Response::server_error("")
-            }
-        } else if self
-            .data
-            .insert(
-                add.key(),
-                add.value(),
-                Some(&add.flags().to_be_bytes()),
-                Duration::from_secs(ttl as u64),
-            )
-            .is_ok()
-        {
-            Response::stored(add.noreply())
-        } else {
-            Response::server_error("")
+            return Response::stored(add.noreply());
         }
+
+        let flags = add.flags().to_be_bytes();
+        let ttl = Duration::from_secs(ttl as u64);
+
+        let result = match std::str::from_utf8(add.value()).map(|s| s.parse::<u64>()) {
+            Ok(Ok(v)) => self.data.insert(add.key(), v, Some(&flags), ttl),
+            _ => self.data.insert(add.key(), add.value(), Some(&flags), ttl),
+        };
+
+        stored(result, add.noreply())
     }
 
     fn replace(&mut self, replace: &Replace) -> Response {
@@ -206,51 +194,20 @@ impl Storage for Seg {
         if ttl < 0 {
             // immediate expire maps to a delete
             self.data.delete(replace.key());
-            Response::stored(replace.noreply())
-        } else if let Ok(s) = std::str::from_utf8(replace.value()) {
-            if let Ok(v) = s.parse::<u64>() {
-                if self
-                    .data
-                    .insert(
-                        replace.key(),
-                        v,
-                        Some(&replace.flags().to_be_bytes()),
-                        Duration::from_secs(ttl as u64),
-                    )
-                    .is_ok()
-                {
-                    Response::stored(replace.noreply())
-                } else {
-                    Response::server_error("")
-                }
-            } else if self
-                .data
-                .insert(
-                    replace.key(),
-                    replace.value(),
-                    Some(&replace.flags().to_be_bytes()),
-                    Duration::from_secs(ttl as u64),
-                )
-                .is_ok()
-            {
-                Response::stored(replace.noreply())
-            } else {
-                Response::server_error("")
-            }
-        } else if self
-            .data
-            .insert(
-                replace.key(),
-                replace.value(),
-                Some(&replace.flags().to_be_bytes()),
-                Duration::from_secs(ttl as u64),
-            )
-            .is_ok()
-        {
-            Response::stored(replace.noreply())
-        } else {
-            Response::server_error("")
+            return Response::stored(replace.noreply());
         }
+
+        let flags = replace.flags().to_be_bytes();
+        let ttl = Duration::from_secs(ttl as u64);
+
+        let result = match std::str::from_utf8(replace.value()).map(|s| s.parse::<u64>()) {
+            Ok(Ok(v)) => self.data.insert(replace.key(), v, Some(&flags), ttl),
+            _ => self
+                .data
+                .insert(replace.key(), replace.value(), Some(&flags), ttl),
+        };
+
+        stored(result, replace.noreply())
     }
 
     fn append(&mut self, _: &Append) -> Response {
@@ -367,6 +324,127 @@ impl Storage for Seg {
         response
     }
 
+    fn meta_get(&mut self, meta_get: &MetaGet) -> Response {
+        let key = meta_get.key();
+
+        // a stale item is returned along with the result of trying to acquire
+        // the lease to refresh it
+        let (item, refresh) = match meta_get.lease() {
+            Some(seconds) => match self
+                .data
+                .get_lease(key, Duration::from_secs(seconds.into()))
+            {
+                segcache::Lease::Hit(item) => (item, None),
+                segcache::Lease::Stale(item, token) => (item, Some(token)),
+                // too many leases are outstanding, so this is a plain miss
+                segcache::Lease::Miss => return Meta::end().into(),
+                // a miss with a lease is returned as an empty item, with the
+                // lease token returned in place of the CAS value
+                lease => {
+                    let mut meta = if meta_get.return_value() {
+                        Meta::value(&[])
+                    } else {
+                        Meta::header()
+                    };
+                    if meta_get.return_key() {
+                        meta = meta.with_key(key);
+                    }
+                    if let segcache::Lease::Granted(token) = lease {
+                        meta = meta.won();
+                        if meta_get.return_cas() {
+                            meta = meta.with_cas(token);
+                        }
+                    } else {
+                        meta = meta.wait();
+                    }
+                    return meta.into();
+                }
+            },
+            None => match self.data.get(key) {
+                Some(item) => (item, None),
+                None => return Meta::end().into(),
+            },
+        };
+
+        let mut meta = if meta_get.return_value() {
+            match item.value() {
+                segcache::Value::Bytes(b) => Meta::value(b),
+                segcache::Value::U64(v) => Meta::value(format!("{v}").as_bytes()),
+                segcache::Value::I64(v) => Meta::value(format!("{v}").as_bytes()),
+                segcache::Value::F64(v) => Meta::value(format!("{v}").as_bytes()),
+            }
+        } else {
+            Meta::header()
+        };
+        if meta_get.return_flags() {
+            let o = item.optional().unwrap_or(&[0, 0, 0, 0]);
+            meta = meta.with_flags(u32::from_be_bytes([o[0], o[1], o[2], o[3]]));
+        }
+        if item.is_stale() {
+            meta = meta.stale();
+        }
+        match refresh {
+            Some(Some(_)) => meta = meta.won(),
+            Some(None) => meta = meta.wait(),
+            None => {}
+        }
+        if meta_get.return_cas() {
+            // the winner of the lease is given the token to refresh the item
+            let cas = match refresh {
+                Some(Some(token)) => token,
+                _ => item.cas().into(),
+            };
+            meta = meta.with_cas(cas);
+        }
+        if meta_get.return_key() {
+            meta = meta.with_key(item.key());
+        }
+
+        meta.into()
+    }
+
+    fn meta_set(&mut self, meta_set: &MetaSet) -> Response {
+        let ttl = meta_set.ttl().get().unwrap_or(0);
+
+        if ttl < 0 {
+            // immediate expire maps to a delete
+            self.data.delete(meta_set.key());
+            return Meta::header().noreply(meta_set.noreply()).into();
+        }
+
+        let value = match std::str::from_utf8(meta_set.value())
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+        {
+            Some(v) => segcache::Value::U64(v),
+            None => segcache::Value::Bytes(meta_set.value()),
+        };
+        let flags = meta_set.flags().to_be_bytes();
+        let ttl = Duration::from_secs(ttl as u64);
+
+        let tags: Vec<&[u8]> = meta_set.tags().iter().map(|tag| tag.as_ref()).collect();
+
+        let result = match meta_set.token() {
+            Some(_) if !tags.is_empty() => {
+                return Response::client_error("tags can not be used with a lease token");
+            }
+            Some(token) => self
+                .data
+                .insert_lease(meta_set.key(), value, Some(&flags), ttl, token),
+            None => self
+                .data
+                .insert_tagged(meta_set.key(), value, Some(&flags), ttl, &tags),
+        };
+
+        match result {
+            Ok(()) => Meta::header().noreply(meta_set.noreply()).into(),
+            Err(SegcacheError::InvalidLease | SegcacheError::NotAdmitted) => {
+                Meta::not_stored().into()
+            }
+            Err(_) => Response::server_error(""),
+        }
+    }
+
     fn delete(&mut self, delete: &Delete) -> Response {
         if self.data.delete(delete.key()) {
             Response::deleted(delete.noreply())
@@ -375,11 +453,33 @@ impl Storage for Seg {
         }
     }
 
-    fn flush_all(&mut self, _flush_all: &FlushAll) -> Response {
-        Response::error()
+    fn invalidate_tag(&mut self, invalidate_tag: &InvalidateTag) -> Response {
+        self.data.invalidate_tag(invalidate_tag.tag());
+        Response::deleted(invalidate_tag.noreply())
+    }
+
+    fn flush_all(&mut self, flush_all: &FlushAll) -> Response {
+        // like an expiration time, a delay of more than 30 days is a unix
+        // time, and a unix time which has passed clears immediately
+        let delay = Ttl::new(flush_all.delay().into(), TimeType::Memcache)
+            .get()
+            .unwrap_or(0)
+            .max(0);
+        self.data.clear_after(Duration::from_secs(delay as u64));
+        Response::ok(flush_all.noreply())
     }
 
     fn quit(&mut self, _quit: &Quit) -> Response {
         Response::hangup()
     }
 }
+
+/// Returns the response to a storage command. A write which was not admitted
+/// is reported as not stored.
+fn stored(result: Result<(), SegcacheError>, noreply: bool) -> Response {
+    match result {
+        Ok(()) => Response::stored(noreply),
+        Err(SegcacheError::NotAdmitted) => Response::not_stored(noreply),
+        Err(_) => Response::server_error(""),
+    }
+}
@@ -9,9 +9,13 @@
 
 use crate::EntryStore;
 
-use config::seg::Eviction;
-use config::SegConfig;
+use common::hotkey::{Crossing, Hotkey};
+use config::seg::{Admission, Compression, DiskEviction, Eviction, ExpireBudget, Mrc};
+use config::{HotkeyConfig, SegConfig};
+use logger::*;
 use segcache::{Policy, SegcacheError};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 mod memcache;
 mod resp;
@@ -20,12 +24,60 @@ mod resp;
 /// protocol traits.
 pub struct Seg {
     data: segcache::Segcache,
+    checkpoint_path: Option<PathBuf>,
+    hotkey: Option<Hotkey>,
+    /// The number of shards the heap is divided between
+    shards: usize,
+    /// The index of this shard, used when publishing the top keys
+    shard: usize,
+    /// When the top keys were last published
+    top_keys_published: Instant,
 }
 
+/// How often the top keys of each shard are published for the admin report.
+const TOP_KEYS_INTERVAL: Duration = Duration::from_secs(1);
+
 impl Seg {
     /// Create `Seg` storage based on the config and the `TimeType` which is
     /// used to interpret various expiry time formats.
-    pub fn new<T: SegConfig>(config: &T) -> Result<Self, std::io::Error> {
+    pub fn new<T: SegConfig + HotkeyConfig>(config: &T) -> Result<Self, std::io::Error> {
+        Self::shard(config, None)
+    }
+
+    /// Create `Seg` storage which is partitioned into the provided number of
+    /// shards. The heap is divided evenly between the shards and each shard
+    /// uses its own datapool and checkpoint files, which have the shard index
+    /// appended to the configured paths.
+    pub fn sharded<T: SegConfig + HotkeyConfig>(
+        config: &T,
+        shards: usize,
+    ) -> Result<Vec<Self>, std::io::Error> {
+        if shards <= 1 {
+            return Ok(vec![Self::new(config)?]);
+        }
+
+        (0..shards)
+            .map(|shard| Self::shard(config, Some((shard, shards))))
+            .collect()
+    }
+
+    /// Create `Seg` storage for one of the shards, or for the entire heap if
+    /// the storage is not sharded.
+    fn shard<T: SegConfig + HotkeyConfig>(
+        config: &T,
+        shard: Option<(usize, usize)>,
+    ) -> Result<Self, std::io::Error> {
+        let hotkey = config.hotkey();
+        let hotkey = if hotkey.enabled() {
+            Some(Hotkey::new(
+                hotkey.sample_size(),
+                hotkey.sample_rate(),
+                hotkey.threshold_ratio(),
+            ))
+        } else {
+            None
+        };
+
         let config = config.seg();
 
         // build up the eviction policy from the config
@@ -43,26 +95,197 @@ impl Seg {
             },
         };
 
+        // build up the compression policy from the config
+        let compression = match config.compression() {
+            Compression::None => segcache::Compression::None,
+            Compression::Zstd => segcache::Compression::Zstd {
+                level: config.compression_level(),
+                threshold: config.compression_threshold(),
+            },
+        };
+
+        // build up the admission policy from the config
+        let admission = match config.admission() {
+            Admission::None => segcache::Admission::None,
+            Admission::TinyLfu => segcache::Admission::TinyLfu {
+                counters: config.admission_counters(),
+                threshold: config.admission_threshold(),
+            },
+        };
+
+        // build up the expiration budget from the config
+        let expire_budget = match config.expire_budget() {
+            ExpireBudget::Unbounded => segcache::ExpireBudget::Unbounded,
+            ExpireBudget::Segments => {
+                segcache::ExpireBudget::Segments(config.expire_budget_segments())
+            }
+            ExpireBudget::Time => {
+                segcache::ExpireBudget::Time(Duration::from_micros(config.expire_budget_time_us()))
+            }
+        };
+
+        let disk_eviction = match config.disk_eviction() {
+            DiskEviction::Fifo => segcache::DiskPolicy::Fifo,
+            DiskEviction::Random => segcache::DiskPolicy::Random,
+        };
+
+        let mut hash_power = config.hash_power();
+        let mut heap_size = config.heap_size();
+        let mut datapool_path = config.datapool_path();
+        let mut checkpoint_path = config.checkpoint_path();
+        let mut disk_size = config.disk_size();
+        let mut disk_path = config.disk_path();
+        let mut mrc_max_keys = config.mrc_max_keys();
+        let mut top_keys = config.top_keys();
+        let mut lease_max_outstanding = config.lease_max_outstanding();
+
+        if let Some((shard, shards)) = shard {
+            // each shard holds a fraction of the items, so the hashtable is
+            // scaled down with the heap
+            hash_power = hash_power.saturating_sub(shards.ilog2() as u8).max(3);
+            heap_size /= shards;
+            datapool_path = datapool_path.map(|path| shard_path(path, shard));
+            checkpoint_path = checkpoint_path.map(|path| shard_path(path, shard));
+            disk_size /= shards;
+            mrc_max_keys /= shards;
+            top_keys = top_keys.div_ceil(shards);
+            lease_max_outstanding = lease_max_outstanding.div_ceil(shards);
+            disk_path = disk_path.map(|path| shard_path(path, shard));
+        }
+
+        // build up the miss ratio curve estimation from the config
+        let mrc = match config.mrc() {
+            Mrc::None => segcache::MissRatioCurve::None,
+            Mrc::Shards => segcache::MissRatioCurve::Shards {
+                sample_rate: config.mrc_sample_rate(),
+                max_keys: mrc_max_keys,
+            },
+        };
+
+        let builder =
+            config
+                .namespaces()
+                .iter()
+                .fold(segcache::Segcache::builder(), |builder, namespace| {
+                    let mut ns =
+                        segcache::Namespace::new(namespace.name()).share(namespace.share());
+                    if let Some(prefix) = namespace.prefix() {
+                        ns = ns.prefix(prefix.as_bytes());
+                    }
+                    builder.namespace(ns)
+                });
+
         // build the datastructure from the config
-        let data = segcache::Segcache::builder()
-            .hash_power(config.hash_power())
+        let data = builder
+            .hash_power(hash_power)
             .overflow_factor(config.overflow_factor())
-            .heap_size(config.heap_size())
+            .hash_expand_threshold(config.hash_expand_threshold())
+            .heap_size(heap_size)
             .segment_size(config.segment_size())
             .eviction(eviction)
-            .datapool_path(config.datapool_path())
+            .compression(compression)
+            .admission(admission)
+            .miss_ratio_curve(mrc)
+            .top_keys(top_keys)
+            .expire_budget(expire_budget)
+            .stale_grace(Duration::from_secs(config.stale_grace().into()))
+            .lease_max_duration(Duration::from_secs(config.lease_max_duration().into()))
+            .lease_max_outstanding(lease_max_outstanding)
+            .datapool_path(datapool_path)
+            .datapool_key_path(config.datapool_key_path())
+            .checkpoint_path(checkpoint_path.as_ref())
+            .disk_path(disk_path)
+            .disk_size(disk_size)
+            .disk_segment_size(config.disk_segment_size())
+            .disk_eviction(disk_eviction)
             .build()?;
 
-        Ok(Self { data })
+        Ok(Self {
+            data,
+            checkpoint_path,
+            hotkey,
+            shards: shard.map(|(_, shards)| shards).unwrap_or(1),
+            shard: shard.map(|(shard, _)| shard).unwrap_or(0),
+            top_keys_published: Instant::now(),
+        })
+    }
+
+    /// Publish the keys with the most hits in this shard, if they are tracked,
+    /// so they can be reported across all shards by the admin `topkeys`
+    /// command.
+    fn publish_top_keys(&mut self) {
+        if self.top_keys_published.elapsed() < TOP_KEYS_INTERVAL {
+            return;
+        }
+        self.top_keys_published = Instant::now();
+
+        if let Some(keys) = self.data.top_keys(usize::MAX) {
+            let keys = keys
+                .into_iter()
+                .map(|key| common::topkeys::TopKey {
+                    key: key.key,
+                    hits: key.hits,
+                    size: key.size,
+                })
+                .collect();
+            common::topkeys::publish(self.shard, keys);
+        }
+    }
+
+    /// Offer a key to the hotkey detector, if enabled. Keys which become hot
+    /// or cool down are logged to the command log.
+    fn sample(&mut self, key: &[u8]) {
+        if let Some(hotkey) = &mut self.hotkey {
+            for crossing in hotkey.sample(key) {
+                match crossing {
+                    Crossing::Hot(key, count) => {
+                        klog!("\"hotkey {}\" {}", String::from_utf8_lossy(&key), count);
+                    }
+                    Crossing::Cold(key, count) => {
+                        klog!("\"coldkey {}\" {}", String::from_utf8_lossy(&key), count);
+                    }
+                }
+            }
+        }
     }
 }
 
+/// Appends the shard index to a path.
+fn shard_path(path: PathBuf, shard: usize) -> PathBuf {
+    let mut path = path.into_os_string();
+    path.push(format!(".{shard}"));
+    path.into()
+}
+
 impl EntryStore for Seg {
     fn expire(&mut self) {
         self.data.expire();
+        self.publish_top_keys();
     }
 
     fn clear(&mut self) {
         self.data.clear();
     }
+
+    fn checkpoint(&mut self) -> Result<(), std::io::Error> {
+        if let Some(path) = &self.checkpoint_path {
+            self.data.checkpoint(path)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Resizes the heap, the size is divided evenly between the shards.
+    fn resize(&mut self, heap_size: usize) -> Result<(), std::io::Error> {
+        self.data
+            .resize_heap(heap_size / self.shards)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+    }
+
+    /// Starts a purge of the matching items, which is advanced by each call to
+    /// `expire()`.
+    fn delete_matching(&mut self, pattern: &[u8]) -> Result<(), std::io::Error> {
+        self.data.delete_matching(pattern);
+        Ok(())
+    }
 }
@@ -15,8 +15,15 @@ use std::time::Duration;
 impl Execute<Request, Response> for Seg {
     fn execute(&mut self, request: &Request) -> Response {
         match request {
-            Request::Get(get) => self.get(get),
-            Request::Set(set) => self.set(set),
+            Request::Get(get) => {
+                self.sample(get.key());
+                self.get(get)
+            }
+            Request::Set(set) => {
+                self.sample(set.key());
+                self.set(set)
+            }
+            Request::InvalidateTag(invalidate_tag) => self.invalidate_tag(invalidate_tag),
             _ => Response::error("not supported"),
         }
     }
@@ -24,10 +31,23 @@ impl Execute<Request, Response> for Seg {
 
 impl Storage for Seg {
     fn get(&mut self, get: &Get) -> Response {
-        if let Some(item) = self.data.get(get.key()) {
-            match item.value() {
+        let item = if get.return_stale() {
+            self.data.get_stale(get.key())
+        } else {
+            self.data.get(get.key())
+        };
+
+        if let Some(item) = item {
+            let value = match item.value() {
                 segcache::Value::Bytes(b) => Response::bulk_string(b),
                 segcache::Value::U64(v) => Response::bulk_string(format!("{v}").as_bytes()),
+                segcache::Value::I64(v) => Response::bulk_string(format!("{v}").as_bytes()),
+                segcache::Value::F64(v) => Response::bulk_string(format!("{v}").as_bytes()),
+            };
+            if get.return_stale() {
+                Response::array(vec![value, Response::integer(item.is_stale().into())])
+            } else {
+                value
             }
         } else {
             Response::null()
@@ -40,9 +60,17 @@ impl Storage for Seg {
             _ => 0,
         };
 
+        let tags: Vec<&[u8]> = set.tags().iter().map(|tag| tag.as_ref()).collect();
+
         if self
             .data
-            .insert(set.key(), set.value(), None, Duration::from_secs(ttl))
+            .insert_tagged(
+                set.key(),
+                set.value(),
+                None,
+                Duration::from_secs(ttl),
+                &tags,
+            )
             .is_ok()
         {
             Response::simple_string("OK")
@@ -50,4 +78,9 @@ impl Storage for Seg {
             Response::error("not stored")
         }
     }
+
+    fn invalidate_tag(&mut self, invalidate_tag: &InvalidateTag) -> Response {
+        self.data.invalidate_tag(invalidate_tag.tag());
+        Response::simple_string("OK")
+    }
 }
@@ -16,10 +16,22 @@ use std::io::{Error, ErrorKind, Result};
 // TODO(bmartin): see TODO for protocol::data::Request, this is cleaner here
 // since the variants are simple, but better to take the same approach in both
 // modules.
+/// The number of keys reported by `topkeys` when no count is given.
+const TOP_KEYS_DEFAULT: usize = 10;
+
 #[derive(PartialEq, Eq, Debug)]
 pub enum AdminRequest {
+    /// Start removing the items matching the glob pattern
+    DeleteMatching(Vec<u8>),
+    /// Report the progress of the purges started by `DeleteMatching`
+    DeleteMatchingProgress,
     FlushAll,
+    HeapSize(usize),
+    Hotkeys,
+    Mrc,
     Stats,
+    /// Report up to the given number of keys with the most hits
+    TopKeys(usize),
     Version,
     Quit,
 }
@@ -46,19 +58,55 @@ impl Parse<AdminRequest> for AdminRequestParser {
             let mut single_byte_windows = trimmed_buffer.windows(1);
             if let Some(command_verb_end) = single_byte_windows.position(|w| w == b" ") {
                 let command_verb = &trimmed_buffer[0..command_verb_end];
-                // TODO(bmartin): 'stats slab' will go here eventually which will
-                // remove the need for ignoring this lint.
-                #[allow(clippy::match_single_binding)]
+                let argument = &trimmed_buffer[(command_verb_end + 1)..].trim();
+                // TODO(bmartin): 'stats slab' will go here eventually
                 match command_verb {
+                    b"delete_matching" => Ok(ParseOk::new(
+                        AdminRequest::DeleteMatching(argument.to_vec()),
+                        command_end + CRLF.len(),
+                    )),
+                    b"heap_size" => {
+                        let bytes = std::str::from_utf8(argument)
+                            .ok()
+                            .and_then(|argument| argument.parse::<usize>().ok())
+                            .ok_or_else(|| Error::from(ErrorKind::InvalidInput))?;
+                        Ok(ParseOk::new(
+                            AdminRequest::HeapSize(bytes),
+                            command_end + CRLF.len(),
+                        ))
+                    }
+                    b"topkeys" => {
+                        let count = std::str::from_utf8(argument)
+                            .ok()
+                            .and_then(|argument| argument.parse::<usize>().ok())
+                            .ok_or_else(|| Error::from(ErrorKind::InvalidInput))?;
+                        Ok(ParseOk::new(
+                            AdminRequest::TopKeys(count),
+                            command_end + CRLF.len(),
+                        ))
+                    }
                     _ => Err(Error::from(ErrorKind::InvalidInput)),
                 }
             } else {
                 match &trimmed_buffer[0..] {
+                    b"delete_matching" => Ok(ParseOk::new(
+                        AdminRequest::DeleteMatchingProgress,
+                        command_end + CRLF.len(),
+                    )),
                     b"flush_all" => Ok(ParseOk::new(
                         AdminRequest::FlushAll,
                         command_end + CRLF.len(),
                     )),
+                    b"hotkeys" => Ok(ParseOk::new(
+                        AdminRequest::Hotkeys,
+                        command_end + CRLF.len(),
+                    )),
+                    b"mrc" => Ok(ParseOk::new(AdminRequest::Mrc, command_end + CRLF.len())),
                     b"stats" => Ok(ParseOk::new(AdminRequest::Stats, command_end + CRLF.len())),
+                    b"topkeys" => Ok(ParseOk::new(
+                        AdminRequest::TopKeys(TOP_KEYS_DEFAULT),
+                        command_end + CRLF.len(),
+                    )),
                     b"quit" => Ok(ParseOk::new(AdminRequest::Quit, command_end + CRLF.len())),
                     b"version" => Ok(ParseOk::new(
                         AdminRequest::Version,
@@ -88,17 +136,33 @@ impl Compose for Version {
 }
 
 pub enum AdminResponse {
+    DeleteMatching,
     Hangup,
+    Hotkeys,
+    Mrc,
     Ok,
     Stats,
+    TopKeys(usize),
     Version(Version),
 }
 
 impl AdminResponse {
+    pub fn delete_matching() -> Self {
+        Self::DeleteMatching
+    }
+
     pub fn hangup() -> Self {
         Self::Hangup
     }
 
+    pub fn hotkeys() -> Self {
+        Self::Hotkeys
+    }
+
+    pub fn mrc() -> Self {
+        Self::Mrc
+    }
+
     pub fn ok() -> Self {
         Self::Ok
     }
@@ -107,6 +171,10 @@ impl AdminResponse {
         Self::Stats
     }
 
+    pub fn top_keys(count: usize) -> Self {
+        Self::TopKeys(count)
+    }
+
     pub fn version(version: String) -> Self {
         Self::Version(Version { version })
     }
@@ -115,7 +183,22 @@ impl AdminResponse {
 impl Compose for AdminResponse {
     fn compose(&self, buf: &mut dyn BufMut) -> usize {
         match self {
+            Self::DeleteMatching => {
+                let message = delete_matching();
+                buf.put_slice(message.as_bytes());
+                message.len()
+            }
             Self::Hangup => 0,
+            Self::Hotkeys => {
+                let message = hotkeys();
+                buf.put_slice(message.as_bytes());
+                message.len()
+            }
+            Self::Mrc => {
+                let message = mrc();
+                buf.put_slice(message.as_bytes());
+                message.len()
+            }
             Self::Ok => {
                 buf.put_slice(b"OK\r\n");
                 4
@@ -125,11 +208,145 @@ impl Compose for AdminResponse {
                 buf.put_slice(message.as_bytes());
                 message.len()
             }
+            Self::TopKeys(count) => {
+                let message = top_keys(*count);
+                buf.put_slice(message.as_bytes());
+                message.len()
+            }
             Self::Version(v) => v.compose(buf),
         }
     }
 }
 
+/// Renders the progress of the purges started with `delete_matching`, from
+/// the `delete_matching_*` metrics. The line holds whether any purge is still
+/// running, the number of segments left to scan, and the number of items
+/// removed by all purges since startup.
+pub fn delete_matching() -> String {
+    let mut active = 0;
+    let mut remaining = 0;
+    let mut deleted = 0;
+
+    for metric in &metriken::metrics() {
+        let any = match metric.as_any() {
+            Some(any) => any,
+            None => {
+                continue;
+            }
+        };
+
+        match metric.name() {
+            "delete_matching_active" => {
+                if let Some(gauge) = any.downcast_ref::<Gauge>() {
+                    active = gauge.value();
+                }
+            }
+            "delete_matching_segment_remaining" => {
+                if let Some(gauge) = any.downcast_ref::<Gauge>() {
+                    remaining = gauge.value();
+                }
+            }
+            "delete_matching_item" => {
+                if let Some(counter) = any.downcast_ref::<Counter>() {
+                    deleted = counter.value();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let state = if active > 0 { "RUNNING" } else { "IDLE" };
+
+    format!("DELETE_MATCHING {state} {remaining} {deleted}\r\nEND\r\n")
+}
+
+/// Renders the currently detected hotkeys, one per line, from hottest to
+/// coldest along with the number of times the key was seen in the sample
+/// window.
+pub fn hotkeys() -> String {
+    let mut data = String::new();
+
+    for (key, count) in common::hotkey::hotkeys().iter() {
+        data.push_str(&format!(
+            "HOTKEY {} {}\r\n",
+            String::from_utf8_lossy(key),
+            count
+        ));
+    }
+
+    data + "END\r\n"
+}
+
+/// Renders up to `count` of the keys with the most hits across all storage
+/// shards, one per line, from most to fewest hits along with the estimated
+/// number of hits and the size of the item in bytes.
+pub fn top_keys(count: usize) -> String {
+    let mut data = String::new();
+
+    for key in common::topkeys::top_keys(count).iter() {
+        data.push_str(&format!(
+            "TOPKEY {} {} {}\r\n",
+            String::from_utf8_lossy(&key.key),
+            key.hits,
+            key.size
+        ));
+    }
+
+    data + "END\r\n"
+}
+
+/// Renders the miss ratio curve estimated by the storage, one line per heap
+/// size multiple along with the estimated miss ratio at that size. The curve
+/// is calculated from the `mrc_sample` and `mrc_hit` metrics, so it covers all
+/// of the lookups since startup.
+pub fn mrc() -> String {
+    let mut samples = 0;
+    let mut hits = Vec::new();
+
+    for metric in &metriken::metrics() {
+        let counter = match metric
+            .as_any()
+            .and_then(|any| any.downcast_ref::<Counter>())
+        {
+            Some(counter) => counter,
+            None => {
+                continue;
+            }
+        };
+
+        match metric.name() {
+            "mrc_sample" => {
+                samples = counter.value();
+            }
+            "mrc_hit" => {
+                if let Some(multiple) = metric
+                    .metadata()
+                    .get("heap_multiple")
+                    .and_then(|multiple| multiple.parse::<f64>().ok())
+                {
+                    hits.push((multiple, counter.value()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    hits.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut data = String::new();
+
+    for (multiple, hits) in hits {
+        let miss_ratio = if samples > 0 {
+            1.0 - hits as f64 / samples as f64
+        } else {
+            0.0
+        };
+        data.push_str(&format!("MRC {multiple} {miss_ratio:.4}\r\n"));
+    }
+
+    data + "END\r\n"
+}
+
 pub fn memcache_stats() -> String {
     let snapshots = SNAPSHOTS.read();
 
@@ -187,6 +404,53 @@ mod tests {
         assert_eq!(parsed.unwrap().into_inner(), AdminRequest::FlushAll);
     }
 
+    #[test]
+    fn parse_heap_size() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"heap_size 1073741824\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(
+            parsed.unwrap().into_inner(),
+            AdminRequest::HeapSize(1073741824)
+        );
+
+        let parsed = parser.parse(b"heap_size  4096 \r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::HeapSize(4096));
+
+        for buffer in [
+            &b"heap_size\r\n"[..],
+            b"heap_size -1\r\n",
+            b"heap_size 1GB\r\n",
+        ] {
+            if let Err(e) = parser.parse(buffer) {
+                assert_eq!(e.kind(), ErrorKind::InvalidInput);
+            } else {
+                panic!("parser should not have returned a request");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_delete_matching() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"delete_matching session:v3:*\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(
+            parsed.unwrap().into_inner(),
+            AdminRequest::DeleteMatching(b"session:v3:*".to_vec())
+        );
+
+        let parsed = parser.parse(b"delete_matching\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(
+            parsed.unwrap().into_inner(),
+            AdminRequest::DeleteMatchingProgress
+        );
+    }
+
     #[test]
     fn parse_quit() {
         let parser = AdminRequestParser::new();
@@ -205,6 +469,42 @@ mod tests {
         assert_eq!(parsed.unwrap().into_inner(), AdminRequest::Stats);
     }
 
+    #[test]
+    fn parse_hotkeys() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"hotkeys\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::Hotkeys);
+    }
+
+    #[test]
+    fn parse_mrc() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"mrc\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::Mrc);
+    }
+
+    #[test]
+    fn parse_topkeys() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"topkeys\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(
+            parsed.unwrap().into_inner(),
+            AdminRequest::TopKeys(TOP_KEYS_DEFAULT)
+        );
+
+        let parsed = parser.parse(b"topkeys 25\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::TopKeys(25));
+
+        assert!(parser.parse(b"topkeys many\r\n").is_err());
+    }
+
     #[test]
     fn parse_version() {
         let parser = AdminRequestParser::new();
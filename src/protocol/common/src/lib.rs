@@ -25,6 +25,39 @@ pub trait Execute<Request, Response: Compose> {
     fn execute(&mut self, request: &Request) -> Response;
 }
 
+/// Describes how a request is routed when the storage is partitioned into
+/// multiple shards.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Route<T> {
+    /// The request is handled entirely by a single shard.
+    Shard(usize),
+    /// The request is split into requests for several shards. The responses
+    /// are combined with [`Shard::merge`].
+    Split(Vec<(usize, T)>),
+}
+
+/// Allows a request to be routed to one of several storage shards. The default
+/// implementation routes every request to the first shard, which is suitable
+/// for protocols which are never used with sharded storage.
+pub trait Shard<Response>: Sized {
+    /// Determine which of the `shards` should handle this request. The `shard`
+    /// function maps a key to the index of the shard which owns it.
+    fn route(&self, shards: usize, shard: &dyn Fn(&[u8]) -> usize) -> Route<Self> {
+        let _ = (shards, shard);
+        Route::Shard(0)
+    }
+
+    /// Combine the responses for a request which was split across several
+    /// shards. The responses are in the same order as the requests returned by
+    /// [`Shard::route`]. The default implementation returns the first response.
+    fn merge(&self, responses: Vec<Response>) -> Response {
+        responses
+            .into_iter()
+            .next()
+            .expect("split requests must have at least one response")
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct ParseOk<T> {
     message: T,
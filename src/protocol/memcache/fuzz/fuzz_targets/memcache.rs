@@ -79,6 +79,16 @@ fuzz_target!(|data: &[u8]| {
             Request::Decr(decr) => {
                 validate_key(decr.key());
             }
+            Request::MetaGet(meta_get) => {
+                validate_key(meta_get.key());
+            }
+            Request::MetaSet(meta_set) => {
+                validate_key(meta_set.key());
+                validate_value(meta_set.value());
+            }
+            Request::InvalidateTag(invalidate_tag) => {
+                validate_key(invalidate_tag.tag());
+            }
             Request::FlushAll(_) => {}
             Request::Quit(_) => {}
         }
@@ -231,6 +231,48 @@ pub static FLUSH_ALL: Counter = Counter::new();
 #[metric(name = "flush_all_ex")]
 pub static FLUSH_ALL_EX: Counter = Counter::new();
 
+/*
+ * INVALIDATE_TAG
+ */
+
+#[metric(name = "invalidate_tag")]
+pub static INVALIDATE_TAG: Counter = Counter::new();
+
+#[metric(name = "invalidate_tag_ex")]
+pub static INVALIDATE_TAG_EX: Counter = Counter::new();
+
+/*
+ * META GET
+ */
+
+#[metric(name = "meta_get")]
+pub static META_GET: Counter = Counter::new();
+
+#[metric(name = "meta_get_ex")]
+pub static META_GET_EX: Counter = Counter::new();
+
+#[metric(name = "meta_get_hit")]
+pub static META_GET_HIT: Counter = Counter::new();
+
+#[metric(name = "meta_get_miss")]
+pub static META_GET_MISS: Counter = Counter::new();
+
+/*
+ * META SET
+ */
+
+#[metric(name = "meta_set")]
+pub static META_SET: Counter = Counter::new();
+
+#[metric(name = "meta_set_ex")]
+pub static META_SET_EX: Counter = Counter::new();
+
+#[metric(name = "meta_set_stored")]
+pub static META_SET_STORED: Counter = Counter::new();
+
+#[metric(name = "meta_set_not_stored")]
+pub static META_SET_NOT_STORED: Counter = Counter::new();
+
 /*
  * QUIT
  */
@@ -6,8 +6,8 @@ use super::*;
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct FlushAll {
-    delay: u32,
-    noreply: bool,
+    pub(crate) delay: u32,
+    pub(crate) noreply: bool,
 }
 
 impl FlushAll {
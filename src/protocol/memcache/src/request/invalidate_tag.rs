@@ -0,0 +1,153 @@
+// Copyright 2023 Pelikan Foundation LLC.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use super::*;
+
+/// Removes every item which was stored with a tag, `invalidate_tag <tag>
+/// [noreply]`. Items are tagged with the `G<tag>` flag of a meta set.
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidateTag {
+    pub(crate) tag: Box<[u8]>,
+    pub(crate) noreply: bool,
+}
+
+impl InvalidateTag {
+    pub fn tag(&self) -> &[u8] {
+        self.tag.as_ref()
+    }
+
+    pub fn noreply(&self) -> bool {
+        self.noreply
+    }
+}
+
+impl RequestParser {
+    // this is to be called after parsing the command, so we do not match the verb
+    pub(crate) fn parse_invalidate_tag_no_stats<'a>(
+        &self,
+        input: &'a [u8],
+    ) -> IResult<&'a [u8], InvalidateTag> {
+        let (input, _) = space1(input)?;
+
+        // tags have the same restrictions as keys
+        let (mut input, tag) = key(input, self.max_key_len)?;
+
+        let tag = match tag {
+            Some(t) => t,
+            None => {
+                return Err(nom::Err::Failure(nom::error::Error::new(
+                    input,
+                    nom::error::ErrorKind::Tag,
+                )));
+            }
+        };
+
+        let mut noreply = false;
+
+        // if we have a space, we might have a noreply
+        if let Ok((i, _)) = space1(input) {
+            if i.starts_with(b"noreply") {
+                input = &i[7..];
+                noreply = true;
+            }
+        }
+
+        let (input, _) = space0(input)?;
+        let (input, _) = crlf(input)?;
+
+        Ok((
+            input,
+            InvalidateTag {
+                tag: tag.to_owned().into_boxed_slice(),
+                noreply,
+            },
+        ))
+    }
+
+    // this is to be called after parsing the command, so we do not match the verb
+    pub fn parse_invalidate_tag<'a>(&self, input: &'a [u8]) -> IResult<&'a [u8], InvalidateTag> {
+        match self.parse_invalidate_tag_no_stats(input) {
+            Ok((input, request)) => {
+                INVALIDATE_TAG.increment();
+                Ok((input, request))
+            }
+            Err(e) => {
+                if !e.is_incomplete() {
+                    INVALIDATE_TAG.increment();
+                    INVALIDATE_TAG_EX.increment();
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Compose for InvalidateTag {
+    fn compose(&self, session: &mut dyn BufMut) -> usize {
+        let verb = b"invalidate_tag ";
+        let header_end = if self.noreply {
+            " noreply\r\n".as_bytes()
+        } else {
+            "\r\n".as_bytes()
+        };
+
+        let size = verb.len() + self.tag.len() + header_end.len();
+
+        session.put_slice(verb);
+        session.put_slice(&self.tag);
+        session.put_slice(header_end);
+
+        size
+    }
+}
+
+impl Klog for InvalidateTag {
+    type Response = Response;
+
+    fn klog(&self, response: &Self::Response) {
+        if let Response::Deleted(ref res) = response {
+            klog!(
+                "\"invalidate_tag {}\" {} {}",
+                string_key(self.tag()),
+                DELETED,
+                res.len()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let parser = RequestParser::new();
+
+        assert_eq!(
+            parser.parse_request(b"invalidate_tag user:1\r\n"),
+            Ok((
+                &b""[..],
+                Request::InvalidateTag(InvalidateTag {
+                    tag: b"user:1".to_vec().into_boxed_slice(),
+                    noreply: false,
+                })
+            ))
+        );
+
+        assert_eq!(
+            parser.parse_request(b"invalidate_tag user:1 noreply\r\n"),
+            Ok((
+                &b""[..],
+                Request::InvalidateTag(InvalidateTag {
+                    tag: b"user:1".to_vec().into_boxed_slice(),
+                    noreply: true,
+                })
+            ))
+        );
+
+        // a tag is required
+        assert!(parser.parse_request(b"invalidate_tag\r\n").is_err());
+    }
+}
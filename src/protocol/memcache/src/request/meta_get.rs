@@ -0,0 +1,229 @@
+// Copyright 2023 Pelikan Foundation LLC.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use super::*;
+
+/// A meta get, `mg <key> <flags>*`. The supported flags are:
+/// * `v` return the value
+/// * `f` return the client flags
+/// * `c` return the CAS value, or the lease token when a lease is granted
+/// * `k` return the key
+/// * `N<seconds>` on a miss, grant the first client a lease to fill the key
+///   which lasts for the given number of seconds. The winning client is told
+///   with the `W` flag, other clients are given the `Z` flag until the lease
+///   is released. If the item is stale, it is returned with the `X` flag and
+///   the first client is given the lease to refresh it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MetaGet {
+    pub(crate) key: Box<[u8]>,
+    pub(crate) value: bool,
+    pub(crate) flags: bool,
+    pub(crate) cas: bool,
+    pub(crate) return_key: bool,
+    pub(crate) lease: Option<u32>,
+}
+
+impl MetaGet {
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    pub fn return_value(&self) -> bool {
+        self.value
+    }
+
+    pub fn return_flags(&self) -> bool {
+        self.flags
+    }
+
+    pub fn return_cas(&self) -> bool {
+        self.cas
+    }
+
+    pub fn return_key(&self) -> bool {
+        self.return_key
+    }
+
+    /// The duration of the lease in seconds, if a lease should be granted on
+    /// a miss.
+    pub fn lease(&self) -> Option<u32> {
+        self.lease
+    }
+}
+
+impl RequestParser {
+    // this is to be called after parsing the command, so we do not match the verb
+    pub(crate) fn parse_meta_get_no_stats<'a>(
+        &self,
+        input: &'a [u8],
+    ) -> IResult<&'a [u8], MetaGet> {
+        let (input, _) = space1(input)?;
+        let (input, key) = key(input, self.max_key_len)?;
+
+        let key = match key {
+            Some(k) => k,
+            None => {
+                return Err(nom::Err::Failure(nom::error::Error::new(
+                    input,
+                    nom::error::ErrorKind::Tag,
+                )));
+            }
+        };
+
+        let (input, flags) = meta_flags(input)?;
+
+        let mut request = MetaGet {
+            key: key.to_owned().into_boxed_slice(),
+            value: false,
+            flags: false,
+            cas: false,
+            return_key: false,
+            lease: None,
+        };
+
+        for flag in flags {
+            match (flag[0], flag.len()) {
+                (b'v', 1) => request.value = true,
+                (b'f', 1) => request.flags = true,
+                (b'c', 1) => request.cas = true,
+                (b'k', 1) => request.return_key = true,
+                (b'N', _) if meta_token::<u32>(flag).is_some() => {
+                    request.lease = meta_token(flag);
+                }
+                _ => {
+                    return Err(nom::Err::Failure(nom::error::Error::new(
+                        input,
+                        nom::error::ErrorKind::Tag,
+                    )));
+                }
+            }
+        }
+
+        Ok((input, request))
+    }
+
+    // this is to be called after parsing the command, so we do not match the verb
+    pub fn parse_meta_get<'a>(&self, input: &'a [u8]) -> IResult<&'a [u8], MetaGet> {
+        match self.parse_meta_get_no_stats(input) {
+            Ok((input, request)) => {
+                META_GET.increment();
+                Ok((input, request))
+            }
+            Err(e) => {
+                if !e.is_incomplete() {
+                    META_GET.increment();
+                    META_GET_EX.increment();
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Compose for MetaGet {
+    fn compose(&self, session: &mut dyn BufMut) -> usize {
+        let verb = b"mg ";
+
+        let mut flags = Vec::new();
+        if self.value {
+            flags.extend_from_slice(b" v");
+        }
+        if self.flags {
+            flags.extend_from_slice(b" f");
+        }
+        if self.cas {
+            flags.extend_from_slice(b" c");
+        }
+        if self.return_key {
+            flags.extend_from_slice(b" k");
+        }
+        if let Some(lease) = self.lease {
+            let _ = write!(flags, " N{lease}");
+        }
+
+        let size = verb.len() + self.key.len() + flags.len() + CRLF.len();
+
+        session.put_slice(verb);
+        session.put_slice(&self.key);
+        session.put_slice(&flags);
+        session.put_slice(CRLF);
+
+        size
+    }
+}
+
+impl Klog for MetaGet {
+    type Response = Response;
+
+    fn klog(&self, response: &Self::Response) {
+        if let Response::Meta(ref res) = response {
+            // an empty value which was returned with a lease is a miss, a
+            // stale value is a hit even if it was returned with a lease
+            let hit = res.code() != MetaCode::End
+                && (res.is_stale() || (!res.is_won() && !res.is_wait()));
+            let (code, len) = if hit {
+                (HIT, res.data().map(|d| d.len()).unwrap_or(0))
+            } else {
+                (MISS, 0)
+            };
+
+            if code == HIT {
+                META_GET_HIT.increment();
+            } else {
+                META_GET_MISS.increment();
+            }
+
+            klog!("\"mg {}\" {} {}", string_key(self.key()), code, len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let parser = RequestParser::new();
+
+        // basic meta get command
+        assert_eq!(
+            parser.parse_request(b"mg 0 v\r\n"),
+            Ok((
+                &b""[..],
+                Request::MetaGet(MetaGet {
+                    key: b"0".to_vec().into_boxed_slice(),
+                    value: true,
+                    flags: false,
+                    cas: false,
+                    return_key: false,
+                    lease: None,
+                })
+            ))
+        );
+
+        // request a lease on miss
+        assert_eq!(
+            parser.parse_request(b"mg 0 v f c k N30\r\n"),
+            Ok((
+                &b""[..],
+                Request::MetaGet(MetaGet {
+                    key: b"0".to_vec().into_boxed_slice(),
+                    value: true,
+                    flags: true,
+                    cas: true,
+                    return_key: true,
+                    lease: Some(30),
+                })
+            ))
+        );
+
+        // flags are optional
+        assert!(parser.parse_request(b"mg 0\r\n").is_ok());
+
+        // unknown flags and invalid lease durations are rejected
+        assert!(parser.parse_request(b"mg 0 x\r\n").is_err());
+        assert!(parser.parse_request(b"mg 0 Nabc\r\n").is_err());
+    }
+}
@@ -0,0 +1,278 @@
+// Copyright 2023 Pelikan Foundation LLC.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use super::*;
+
+/// A meta set, `ms <key> <datalen> <flags>*`. The supported flags are:
+/// * `F<flags>` set the client flags
+/// * `T<ttl>` set the TTL
+/// * `C<token>` fill the key using the lease token returned by a meta get. The
+///   item is only stored if the lease is still valid.
+/// * `G<tag>` tag the item, every item with a tag can be removed with
+///   `invalidate_tag`. May be repeated to give the item several tags.
+/// * `q` do not send a response if the item is stored
+#[derive(Debug, PartialEq, Eq)]
+pub struct MetaSet {
+    pub(crate) key: Box<[u8]>,
+    pub(crate) value: Box<[u8]>,
+    pub(crate) flags: u32,
+    pub(crate) ttl: Ttl,
+    pub(crate) token: Option<u64>,
+    pub(crate) tags: Vec<Box<[u8]>>,
+    pub(crate) noreply: bool,
+}
+
+impl MetaSet {
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+
+    pub fn ttl(&self) -> Ttl {
+        self.ttl
+    }
+
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// The lease token which is required to fill the key, if any.
+    pub fn token(&self) -> Option<u64> {
+        self.token
+    }
+
+    /// The tags to store with the item.
+    pub fn tags(&self) -> &[Box<[u8]>] {
+        &self.tags
+    }
+
+    pub fn noreply(&self) -> bool {
+        self.noreply
+    }
+}
+
+impl RequestParser {
+    // this is to be called after parsing the command, so we do not match the verb
+    pub(crate) fn parse_meta_set_no_stats<'a>(
+        &self,
+        input: &'a [u8],
+    ) -> IResult<&'a [u8], MetaSet> {
+        let (input, _) = space1(input)?;
+        let (input, key) = key(input, self.max_key_len)?;
+
+        let key = match key {
+            Some(k) => k,
+            None => {
+                return Err(nom::Err::Failure(nom::error::Error::new(
+                    input,
+                    nom::error::ErrorKind::Tag,
+                )));
+            }
+        };
+
+        let (input, _) = space1(input)?;
+        let (input, bytes) = parse_usize(input)?;
+
+        if bytes > self.max_value_size {
+            return Err(nom::Err::Failure(nom::error::Error::new(
+                input,
+                nom::error::ErrorKind::Tag,
+            )));
+        }
+
+        let (input, flags) = meta_flags(input)?;
+
+        let mut request = MetaSet {
+            key: key.to_owned().into_boxed_slice(),
+            value: Box::new([]),
+            flags: 0,
+            ttl: Ttl::none(),
+            token: None,
+            tags: Vec::new(),
+            noreply: false,
+        };
+
+        for flag in flags {
+            let valid = match flag[0] {
+                b'F' => meta_token(flag).map(|flags| request.flags = flags),
+                b'T' => meta_token(flag).map(|ttl| request.ttl = Ttl::new(ttl, self.time_type)),
+                b'C' => meta_token(flag).map(|token| request.token = Some(token)),
+                b'G' if flag.len() > 1 => {
+                    request.tags.push(flag[1..].to_owned().into_boxed_slice());
+                    Some(())
+                }
+                b'q' if flag.len() == 1 => {
+                    request.noreply = true;
+                    Some(())
+                }
+                _ => None,
+            };
+
+            if valid.is_none() {
+                return Err(nom::Err::Failure(nom::error::Error::new(
+                    input,
+                    nom::error::ErrorKind::Tag,
+                )));
+            }
+        }
+
+        let (input, value) = take(bytes)(input)?;
+        let (input, _) = crlf(input)?;
+
+        request.value = value.to_owned().into_boxed_slice();
+
+        Ok((input, request))
+    }
+
+    // this is to be called after parsing the command, so we do not match the verb
+    pub fn parse_meta_set<'a>(&self, input: &'a [u8]) -> IResult<&'a [u8], MetaSet> {
+        match self.parse_meta_set_no_stats(input) {
+            Ok((input, request)) => {
+                META_SET.increment();
+                Ok((input, request))
+            }
+            Err(e) => {
+                if !e.is_incomplete() {
+                    META_SET.increment();
+                    META_SET_EX.increment();
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Compose for MetaSet {
+    fn compose(&self, session: &mut dyn BufMut) -> usize {
+        let verb = b"ms ";
+
+        let mut header = Vec::new();
+        let _ = write!(header, " {}", self.value.len());
+        if self.flags != 0 {
+            let _ = write!(header, " F{}", self.flags);
+        }
+        if let Some(ttl) = self.ttl.get() {
+            let _ = write!(header, " T{ttl}");
+        }
+        if let Some(token) = self.token {
+            let _ = write!(header, " C{token}");
+        }
+        for tag in &self.tags {
+            header.extend_from_slice(b" G");
+            header.extend_from_slice(tag);
+        }
+        if self.noreply {
+            header.extend_from_slice(b" q");
+        }
+
+        let size =
+            verb.len() + self.key.len() + header.len() + CRLF.len() + self.value.len() + CRLF.len();
+
+        session.put_slice(verb);
+        session.put_slice(&self.key);
+        session.put_slice(&header);
+        session.put_slice(CRLF);
+        session.put_slice(&self.value);
+        session.put_slice(CRLF);
+
+        size
+    }
+}
+
+impl Klog for MetaSet {
+    type Response = Response;
+
+    fn klog(&self, response: &Self::Response) {
+        let code = match response {
+            Response::Meta(ref res) if res.code() == MetaCode::Header => {
+                META_SET_STORED.increment();
+                STORED
+            }
+            Response::Meta(ref res) if res.code() == MetaCode::NotStored => {
+                META_SET_NOT_STORED.increment();
+                NOT_STORED
+            }
+            _ => {
+                return;
+            }
+        };
+        klog!(
+            "\"ms {} {} F{} T{}\" {}",
+            string_key(self.key()),
+            self.value().len(),
+            self.flags(),
+            self.ttl.get().unwrap_or(0),
+            code
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let parser = RequestParser::new();
+
+        // basic meta set command
+        assert_eq!(
+            parser.parse_request(b"ms 0 1\r\n0\r\n"),
+            Ok((
+                &b""[..],
+                Request::MetaSet(MetaSet {
+                    key: b"0".to_vec().into_boxed_slice(),
+                    value: b"0".to_vec().into_boxed_slice(),
+                    flags: 0,
+                    ttl: Ttl::none(),
+                    token: None,
+                    tags: Vec::new(),
+                    noreply: false,
+                })
+            ))
+        );
+
+        // fill with a lease token
+        assert_eq!(
+            parser.parse_request(b"ms 0 1 F2 T30 C42 q\r\n0\r\n"),
+            Ok((
+                &b""[..],
+                Request::MetaSet(MetaSet {
+                    key: b"0".to_vec().into_boxed_slice(),
+                    value: b"0".to_vec().into_boxed_slice(),
+                    flags: 2,
+                    ttl: Ttl::new(30, TimeType::Memcache),
+                    token: Some(42),
+                    tags: Vec::new(),
+                    noreply: true,
+                })
+            ))
+        );
+
+        // tag the item
+        assert_eq!(
+            parser.parse_request(b"ms 0 1 Guser:1 Gteam:1\r\n0\r\n"),
+            Ok((
+                &b""[..],
+                Request::MetaSet(MetaSet {
+                    key: b"0".to_vec().into_boxed_slice(),
+                    value: b"0".to_vec().into_boxed_slice(),
+                    flags: 0,
+                    ttl: Ttl::none(),
+                    token: None,
+                    tags: vec![b"user:1".to_vec().into(), b"team:1".to_vec().into()],
+                    noreply: false,
+                })
+            ))
+        );
+
+        // invalid flags are rejected
+        assert!(parser.parse_request(b"ms 0 1 Cabc\r\n0\r\n").is_err());
+        assert!(parser.parse_request(b"ms 0 1 x\r\n0\r\n").is_err());
+        assert!(parser.parse_request(b"ms 0 1 G\r\n0\r\n").is_err());
+    }
+}
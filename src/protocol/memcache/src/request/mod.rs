@@ -6,8 +6,9 @@ use crate::*;
 use clocksource::coarse::UnixInstant;
 use core::fmt::{Display, Formatter};
 use core::num::NonZeroI32;
-use protocol_common::{BufMut, Parse, ParseOk};
+use protocol_common::{BufMut, Parse, ParseOk, Route, Shard};
 use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
 
 mod add;
 mod append;
@@ -18,6 +19,9 @@ mod flush_all;
 mod get;
 mod gets;
 mod incr;
+mod invalidate_tag;
+mod meta_get;
+mod meta_set;
 mod prepend;
 mod quit;
 mod replace;
@@ -32,6 +36,9 @@ pub use flush_all::FlushAll;
 pub use get::Get;
 pub use gets::Gets;
 pub use incr::Incr;
+pub use invalidate_tag::InvalidateTag;
+pub use meta_get::MetaGet;
+pub use meta_set::MetaSet;
 pub use prepend::Prepend;
 pub use quit::Quit;
 pub use replace::Replace;
@@ -97,6 +104,9 @@ impl RequestParser {
             b"delete" | b"DELETE" => Command::Delete,
             b"flush_all" | b"FLUSH_ALL" => Command::FlushAll,
             b"incr" | b"INCR" => Command::Incr,
+            b"invalidate_tag" | b"INVALIDATE_TAG" => Command::InvalidateTag,
+            b"mg" | b"MG" => Command::MetaGet,
+            b"ms" | b"MS" => Command::MetaSet,
             b"get" | b"GET" => Command::Get,
             b"gets" | b"GETS" => Command::Gets,
             b"prepend" | b"PREPEND" => Command::Prepend,
@@ -144,6 +154,10 @@ impl RequestParser {
                 let (input, request) = self.parse_incr(input)?;
                 Ok((input, Request::Incr(request)))
             }
+            (input, Command::InvalidateTag) => {
+                let (input, request) = self.parse_invalidate_tag(input)?;
+                Ok((input, Request::InvalidateTag(request)))
+            }
             (input, Command::Get) => {
                 let (input, request) = self.parse_get(input)?;
                 Ok((input, Request::Get(request)))
@@ -152,6 +166,14 @@ impl RequestParser {
                 let (input, request) = self.parse_gets(input)?;
                 Ok((input, Request::Gets(request)))
             }
+            (input, Command::MetaGet) => {
+                let (input, request) = self.parse_meta_get(input)?;
+                Ok((input, Request::MetaGet(request)))
+            }
+            (input, Command::MetaSet) => {
+                let (input, request) = self.parse_meta_set(input)?;
+                Ok((input, Request::MetaSet(request)))
+            }
             (input, Command::Prepend) => {
                 let (input, request) = self.parse_prepend(input)?;
                 Ok((input, Request::Prepend(request)))
@@ -203,8 +225,11 @@ impl Compose for Request {
             Self::Delete(r) => r.compose(session),
             Self::FlushAll(r) => r.compose(session),
             Self::Incr(r) => r.compose(session),
+            Self::InvalidateTag(r) => r.compose(session),
             Self::Get(r) => r.compose(session),
             Self::Gets(r) => r.compose(session),
+            Self::MetaGet(r) => r.compose(session),
+            Self::MetaSet(r) => r.compose(session),
             Self::Prepend(r) => r.compose(session),
             Self::Quit(r) => r.compose(session),
             Self::Replace(r) => r.compose(session),
@@ -225,8 +250,11 @@ impl Klog for Request {
             Self::Delete(r) => r.klog(response),
             Self::FlushAll(r) => r.klog(response),
             Self::Incr(r) => r.klog(response),
+            Self::InvalidateTag(r) => r.klog(response),
             Self::Get(r) => r.klog(response),
             Self::Gets(r) => r.klog(response),
+            Self::MetaGet(r) => r.klog(response),
+            Self::MetaSet(r) => r.klog(response),
             Self::Prepend(r) => r.klog(response),
             Self::Quit(r) => r.klog(response),
             Self::Replace(r) => r.klog(response),
@@ -235,6 +263,130 @@ impl Klog for Request {
     }
 }
 
+impl Shard<Response> for Request {
+    fn route(&self, shards: usize, shard: &dyn Fn(&[u8]) -> usize) -> Route<Self> {
+        let key = match self {
+            Self::Add(r) => r.key(),
+            Self::Append(r) => r.key(),
+            Self::Cas(r) => r.key(),
+            Self::Decr(r) => r.key(),
+            Self::Delete(r) => r.key(),
+            Self::Incr(r) => r.key(),
+            Self::MetaGet(r) => r.key(),
+            Self::MetaSet(r) => r.key(),
+            Self::Prepend(r) => r.key(),
+            Self::Replace(r) => r.key(),
+            Self::Set(r) => r.key(),
+            Self::Get(r) => return route_keys(r.keys(), shards, shard, Request::get),
+            Self::Gets(r) => return route_keys(r.keys(), shards, shard, Request::gets),
+            Self::FlushAll(r) => {
+                // every shard must be flushed
+                return Route::Split(
+                    (0..shards)
+                        .map(|id| {
+                            (
+                                id,
+                                Self::FlushAll(FlushAll {
+                                    delay: r.delay,
+                                    noreply: r.noreply,
+                                }),
+                            )
+                        })
+                        .collect(),
+                );
+            }
+            Self::InvalidateTag(r) => {
+                // every shard may hold items with the tag
+                return Route::Split(
+                    (0..shards)
+                        .map(|id| {
+                            (
+                                id,
+                                Self::InvalidateTag(InvalidateTag {
+                                    tag: r.tag.clone(),
+                                    noreply: r.noreply,
+                                }),
+                            )
+                        })
+                        .collect(),
+                );
+            }
+            Self::Quit(_) => return Route::Shard(0),
+        };
+
+        Route::Shard(shard(key))
+    }
+
+    fn merge(&self, responses: Vec<Response>) -> Response {
+        let keys = match self {
+            Self::Get(r) => r.keys(),
+            Self::Gets(r) => r.keys(),
+            _ => {
+                return responses
+                    .into_iter()
+                    .next()
+                    .expect("split requests must have at least one response");
+            }
+        };
+
+        // the values from each shard are queued by key, a key which appears
+        // more than once in the request has a value for each occurrence
+        let mut queues: HashMap<Box<[u8]>, VecDeque<Value>> = HashMap::new();
+
+        for response in responses {
+            match response {
+                Response::Values(v) => {
+                    for value in v.values.into_vec() {
+                        queues
+                            .entry(value.key().into())
+                            .or_default()
+                            .push_back(value);
+                    }
+                }
+                // any other response is an error and is returned as-is
+                response => return response,
+            }
+        }
+
+        // return the values in the same order as the keys in the request
+        let values: Vec<Value> = keys
+            .iter()
+            .filter_map(|key| queues.get_mut(key).and_then(|queue| queue.pop_front()))
+            .collect();
+
+        Response::values(values.into_boxed_slice())
+    }
+}
+
+/// Route a multi-key request by grouping the keys for each shard. The request
+/// is only split if the keys belong to more than one shard.
+fn route_keys(
+    keys: &[Box<[u8]>],
+    shards: usize,
+    shard: &dyn Fn(&[u8]) -> usize,
+    request: impl Fn(Box<[Box<[u8]>]>) -> Request,
+) -> Route<Request> {
+    let ids: Vec<usize> = keys.iter().map(|key| shard(key)).collect();
+
+    if ids.iter().all(|id| *id == ids[0]) {
+        return Route::Shard(ids[0]);
+    }
+
+    let mut split: Vec<Vec<Box<[u8]>>> = vec![Vec::new(); shards];
+    for (key, id) in keys.iter().zip(ids) {
+        split[id].push(key.clone());
+    }
+
+    Route::Split(
+        split
+            .into_iter()
+            .enumerate()
+            .filter(|(_, keys)| !keys.is_empty())
+            .map(|(id, keys)| (id, request(keys.into_boxed_slice())))
+            .collect(),
+    )
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum Request {
     Add(Add),
@@ -244,8 +396,11 @@ pub enum Request {
     Delete(Delete),
     FlushAll(FlushAll),
     Incr(Incr),
+    InvalidateTag(InvalidateTag),
     Get(Get),
     Gets(Gets),
+    MetaGet(MetaGet),
+    MetaSet(MetaSet),
     Prepend(Prepend),
     Quit(Quit),
     Replace(Replace),
@@ -340,8 +495,11 @@ impl Display for Request {
             Request::Delete(_) => write!(f, "delete"),
             Request::FlushAll(_) => write!(f, "flush_all"),
             Request::Incr(_) => write!(f, "incr"),
+            Request::InvalidateTag(_) => write!(f, "invalidate_tag"),
             Request::Get(_) => write!(f, "get"),
             Request::Gets(_) => write!(f, "gets"),
+            Request::MetaGet(_) => write!(f, "mg"),
+            Request::MetaSet(_) => write!(f, "ms"),
             Request::Prepend(_) => write!(f, "prepend"),
             Request::Quit(_) => write!(f, "quit"),
             Request::Replace(_) => write!(f, "replace"),
@@ -359,8 +517,11 @@ pub enum Command {
     Delete,
     FlushAll,
     Incr,
+    InvalidateTag,
     Get,
     Gets,
+    MetaGet,
+    MetaSet,
     Prepend,
     Quit,
     Replace,
@@ -493,4 +654,104 @@ mod tests {
             Ok((&b" key \"value\"\r\n"[..], Command::Set))
         );
     }
+
+    #[test]
+    fn route() {
+        // route keys by their first byte
+        let shard = |key: &[u8]| (key[0] - b'a') as usize;
+        let keys = |keys: &[&[u8]]| {
+            keys.iter()
+                .map(|k| k.to_vec().into_boxed_slice())
+                .collect::<Vec<_>>()
+                .into_boxed_slice()
+        };
+
+        let request = Request::set(
+            keys(&[b"b"])[0].clone(),
+            Box::new([]),
+            0,
+            Ttl::none(),
+            false,
+        );
+        assert_eq!(request.route(3, &shard), Route::Shard(1));
+
+        // all keys belong to a single shard
+        let request = Request::get(keys(&[b"c0", b"c1"]));
+        assert_eq!(request.route(3, &shard), Route::Shard(2));
+
+        // keys are grouped by shard
+        let request = Request::get(keys(&[b"c0", b"a0", b"c1"]));
+        assert_eq!(
+            request.route(3, &shard),
+            Route::Split(vec![
+                (0, Request::get(keys(&[b"a0"]))),
+                (2, Request::get(keys(&[b"c0", b"c1"]))),
+            ])
+        );
+
+        // the merged response is in request order
+        let responses = vec![
+            Response::values(vec![Value::none(b"a0")].into_boxed_slice()),
+            Response::values(
+                vec![Value::new(b"c0", 0, None, b"0"), Value::none(b"c1")].into_boxed_slice(),
+            ),
+        ];
+        assert_eq!(
+            request.merge(responses),
+            Response::values(
+                vec![
+                    Value::new(b"c0", 0, None, b"0"),
+                    Value::none(b"a0"),
+                    Value::none(b"c1"),
+                ]
+                .into_boxed_slice()
+            )
+        );
+
+        // keys which are repeated keep their position in the request
+        let request = Request::get(keys(&[b"a0", b"c0", b"a0"]));
+        let responses = vec![
+            Response::values(
+                vec![
+                    Value::new(b"a0", 0, None, b"0"),
+                    Value::new(b"a0", 0, None, b"0"),
+                ]
+                .into_boxed_slice(),
+            ),
+            Response::values(vec![Value::new(b"c0", 0, None, b"1")].into_boxed_slice()),
+        ];
+        assert_eq!(
+            request.merge(responses),
+            Response::values(
+                vec![
+                    Value::new(b"a0", 0, None, b"0"),
+                    Value::new(b"c0", 0, None, b"1"),
+                    Value::new(b"a0", 0, None, b"0"),
+                ]
+                .into_boxed_slice()
+            )
+        );
+
+        // an error from any shard is returned
+        let responses = vec![
+            Response::values(vec![Value::none(b"a0")].into_boxed_slice()),
+            Response::server_error("oops"),
+        ];
+        assert_eq!(request.merge(responses), Response::server_error("oops"));
+
+        // flush_all is sent to every shard
+        let request = RequestParser::new()
+            .parse(b"flush_all\r\n")
+            .unwrap()
+            .into_inner();
+        match request.route(3, &shard) {
+            Route::Split(requests) => {
+                assert_eq!(
+                    requests.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+                    vec![0, 1, 2]
+                );
+            }
+            route => panic!("unexpected route: {route:?}"),
+        }
+    }
 }
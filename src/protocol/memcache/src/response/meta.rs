@@ -0,0 +1,308 @@
+// Copyright 2023 Pelikan Foundation LLC.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use super::*;
+
+/// The status code which starts a response to a meta command.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum MetaCode {
+    /// `VA`, the response carries a value
+    Value,
+    /// `HD`, the command succeeded and there is no value
+    Header,
+    /// `EN`, the key was not found
+    End,
+    /// `NS`, the item was not stored
+    NotStored,
+}
+
+/// A response to a meta command. The flags which are set are returned after
+/// the status code.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Meta {
+    code: MetaCode,
+    data: Option<Box<[u8]>>,
+    flags: Option<u32>,
+    cas: Option<u64>,
+    key: Option<Box<[u8]>>,
+    won: bool,
+    stale: bool,
+    wait: bool,
+    noreply: bool,
+}
+
+impl Meta {
+    fn new(code: MetaCode) -> Self {
+        Self {
+            code,
+            data: None,
+            flags: None,
+            cas: None,
+            key: None,
+            won: false,
+            stale: false,
+            wait: false,
+            noreply: false,
+        }
+    }
+
+    /// A response which carries a value.
+    pub fn value(data: &[u8]) -> Self {
+        let mut meta = Self::new(MetaCode::Value);
+        meta.data = Some(data.to_owned().into_boxed_slice());
+        meta
+    }
+
+    /// A response which indicates success without a value.
+    pub fn header() -> Self {
+        Self::new(MetaCode::Header)
+    }
+
+    /// A response which indicates that the key was not found.
+    pub fn end() -> Self {
+        Self::new(MetaCode::End)
+    }
+
+    /// A response which indicates that the item was not stored.
+    pub fn not_stored() -> Self {
+        Self::new(MetaCode::NotStored)
+    }
+
+    /// Return the client flags of the item with the `f` flag.
+    pub fn with_flags(mut self, flags: u32) -> Self {
+        self.flags = Some(flags);
+        self
+    }
+
+    /// Return the CAS value of the item, or a lease token, with the `c` flag.
+    pub fn with_cas(mut self, cas: u64) -> Self {
+        self.cas = Some(cas);
+        self
+    }
+
+    /// Return the key with the `k` flag.
+    pub fn with_key(mut self, key: &[u8]) -> Self {
+        self.key = Some(key.to_owned().into_boxed_slice());
+        self
+    }
+
+    /// Set the `W` flag, which tells the client that it was granted the lease
+    /// to fill the key.
+    pub fn won(mut self) -> Self {
+        self.won = true;
+        self
+    }
+
+    /// Set the `X` flag, which tells the client that the item has passed its
+    /// TTL and is being served during the grace period.
+    pub fn stale(mut self) -> Self {
+        self.stale = true;
+        self
+    }
+
+    /// Set the `Z` flag, which tells the client that another client holds the
+    /// lease for the key.
+    pub fn wait(mut self) -> Self {
+        self.wait = true;
+        self
+    }
+
+    /// A successful response without a value is not sent if `noreply` is set.
+    pub fn noreply(mut self, noreply: bool) -> Self {
+        self.noreply = noreply;
+        self
+    }
+
+    pub fn code(&self) -> MetaCode {
+        self.code
+    }
+
+    pub fn data(&self) -> Option<&[u8]> {
+        self.data.as_deref()
+    }
+
+    pub fn flags(&self) -> Option<u32> {
+        self.flags
+    }
+
+    pub fn cas(&self) -> Option<u64> {
+        self.cas
+    }
+
+    pub fn key(&self) -> Option<&[u8]> {
+        self.key.as_deref()
+    }
+
+    pub fn is_won(&self) -> bool {
+        self.won
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    pub fn is_wait(&self) -> bool {
+        self.wait
+    }
+
+    fn header_fields(&self) -> Vec<u8> {
+        let mut fields = Vec::new();
+
+        if let Some(data) = &self.data {
+            let _ = write!(fields, " {}", data.len());
+        }
+        if let Some(flags) = self.flags {
+            let _ = write!(fields, " f{flags}");
+        }
+        if let Some(cas) = self.cas {
+            let _ = write!(fields, " c{cas}");
+        }
+        if let Some(key) = &self.key {
+            fields.extend_from_slice(b" k");
+            fields.extend_from_slice(key);
+        }
+        if self.won {
+            fields.extend_from_slice(b" W");
+        }
+        if self.stale {
+            fields.extend_from_slice(b" X");
+        }
+        if self.wait {
+            fields.extend_from_slice(b" Z");
+        }
+
+        fields
+    }
+}
+
+impl Compose for Meta {
+    fn compose(&self, session: &mut dyn BufMut) -> usize {
+        let code: &[u8] = match self.code {
+            MetaCode::Value => b"VA",
+            MetaCode::Header => {
+                if self.noreply {
+                    return 0;
+                }
+                b"HD"
+            }
+            MetaCode::End => b"EN",
+            MetaCode::NotStored => b"NS",
+        };
+
+        let fields = self.header_fields();
+
+        session.put_slice(code);
+        session.put_slice(&fields);
+        session.put_slice(CRLF);
+
+        let mut size = code.len() + fields.len() + CRLF.len();
+
+        if let Some(data) = &self.data {
+            session.put_slice(data);
+            session.put_slice(CRLF);
+            size += data.len() + CRLF.len();
+        }
+
+        size
+    }
+}
+
+pub fn parse(input: &[u8], code: MetaCode) -> IResult<&[u8], Meta> {
+    let mut meta = Meta::new(code);
+
+    let mut input = input;
+    let mut bytes = None;
+
+    if code == MetaCode::Value {
+        let (i, _) = space1(input)?;
+        let (i, b) = parse_usize(i)?;
+        input = i;
+        bytes = Some(b);
+    }
+
+    let (mut input, flags) = meta_flags(input)?;
+
+    for flag in flags {
+        let invalid =
+            || nom::Err::Failure(nom::error::Error::new(input, nom::error::ErrorKind::Tag));
+        match flag[0] {
+            b'f' => meta.flags = Some(meta_token(flag).ok_or_else(invalid)?),
+            b'c' => meta.cas = Some(meta_token(flag).ok_or_else(invalid)?),
+            b'k' => meta.key = Some(flag[1..].to_owned().into_boxed_slice()),
+            b'W' => meta.won = true,
+            b'X' => meta.stale = true,
+            b'Z' => meta.wait = true,
+            // other flags are not used by this implementation
+            _ => {}
+        }
+    }
+
+    if let Some(bytes) = bytes {
+        let (i, data) = take(bytes)(input)?;
+        let (i, _) = crlf(i)?;
+        input = i;
+        meta.data = Some(data.to_owned().into_boxed_slice());
+    }
+
+    Ok((input, meta))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            response(b"VA 1 f2 c3\r\n0\r\n"),
+            Ok((
+                &b""[..],
+                Response::meta(Meta::value(b"0").with_flags(2).with_cas(3))
+            ))
+        );
+
+        assert_eq!(
+            response(b"HD W c42\r\n"),
+            Ok((&b""[..], Response::meta(Meta::header().with_cas(42).won())))
+        );
+
+        assert_eq!(
+            response(b"VA 1 W X\r\n0\r\n"),
+            Ok((&b""[..], Response::meta(Meta::value(b"0").won().stale())))
+        );
+
+        assert_eq!(
+            response(b"EN\r\n"),
+            Ok((&b""[..], Response::meta(Meta::end())))
+        );
+
+        assert_eq!(
+            response(b"NS\r\n"),
+            Ok((&b""[..], Response::meta(Meta::not_stored())))
+        );
+    }
+
+    #[test]
+    fn compose() {
+        let mut buffer = Vec::new();
+        Meta::value(b"abc")
+            .with_flags(1)
+            .with_key(b"key")
+            .compose(&mut buffer);
+        assert_eq!(buffer, b"VA 3 f1 kkey\r\nabc\r\n");
+
+        let mut buffer = Vec::new();
+        Meta::header().with_cas(7).won().compose(&mut buffer);
+        assert_eq!(buffer, b"HD c7 W\r\n");
+
+        let mut buffer = Vec::new();
+        Meta::value(b"0").stale().wait().compose(&mut buffer);
+        assert_eq!(buffer, b"VA 1 X Z\r\n0\r\n");
+
+        // a successful response is not sent with noreply
+        let mut buffer = Vec::new();
+        assert_eq!(Meta::header().noreply(true).compose(&mut buffer), 0);
+        assert!(buffer.is_empty());
+    }
+}
@@ -9,9 +9,11 @@ mod client_error;
 mod deleted;
 mod error;
 mod exists;
+mod meta;
 mod not_found;
 mod not_stored;
 mod numeric;
+mod okay;
 mod server_error;
 mod stored;
 mod values;
@@ -20,9 +22,11 @@ pub use client_error::ClientError;
 pub use deleted::Deleted;
 pub use error::Error;
 pub use exists::Exists;
+pub use meta::{Meta, MetaCode};
 pub use not_found::NotFound;
 pub use not_stored::NotStored;
 pub use numeric::Numeric;
+pub use okay::Okay;
 pub use server_error::ServerError;
 pub use stored::Stored;
 pub use values::{Value, Values};
@@ -39,6 +43,8 @@ pub enum Response {
     Values(Values),
     Numeric(Numeric),
     Deleted(Deleted),
+    Ok(Okay),
+    Meta(Meta),
     Hangup,
 }
 
@@ -90,6 +96,14 @@ impl Response {
     pub fn deleted(noreply: bool) -> Self {
         Self::Deleted(Deleted::new(noreply))
     }
+
+    pub fn ok(noreply: bool) -> Self {
+        Self::Ok(Okay::new(noreply))
+    }
+
+    pub fn meta(meta: Meta) -> Self {
+        Self::Meta(meta)
+    }
 }
 
 impl From<Values> for Response {
@@ -98,6 +112,12 @@ impl From<Values> for Response {
     }
 }
 
+impl From<Meta> for Response {
+    fn from(other: Meta) -> Self {
+        Self::Meta(other)
+    }
+}
+
 impl Compose for Response {
     fn compose(&self, session: &mut dyn BufMut) -> usize {
         match self {
@@ -111,6 +131,8 @@ impl Compose for Response {
             Self::Values(e) => e.compose(session),
             Self::Numeric(e) => e.compose(session),
             Self::Deleted(e) => e.compose(session),
+            Self::Ok(e) => e.compose(session),
+            Self::Meta(e) => e.compose(session),
             Self::Hangup => 0,
         }
     }
@@ -133,6 +155,8 @@ pub enum ResponseType {
     Empty,
     Numeric(u64),
     Deleted,
+    Ok,
+    Meta(MetaCode),
 }
 
 pub struct ResponseParser {}
@@ -150,6 +174,11 @@ pub(crate) fn response_type(input: &[u8]) -> IResult<&[u8], ResponseType> {
         b"VALUE" => ResponseType::Values,
         b"END" => ResponseType::Empty,
         b"DELETED" => ResponseType::Deleted,
+        b"OK" => ResponseType::Ok,
+        b"VA" => ResponseType::Meta(MetaCode::Value),
+        b"HD" => ResponseType::Meta(MetaCode::Header),
+        b"EN" => ResponseType::Meta(MetaCode::End),
+        b"NS" => ResponseType::Meta(MetaCode::NotStored),
         _ => {
             if let Ok(s) = std::str::from_utf8(response_type_token) {
                 if let Ok(value) = s.parse::<u64>() {
@@ -225,6 +254,14 @@ pub(crate) fn response(input: &[u8]) -> IResult<&[u8], Response> {
             let (input, response) = deleted::parse(input)?;
             Ok((input, Response::Deleted(response)))
         }
+        (input, ResponseType::Ok) => {
+            let (input, response) = okay::parse(input)?;
+            Ok((input, Response::Ok(response)))
+        }
+        (input, ResponseType::Meta(code)) => {
+            let (input, response) = meta::parse(input, code)?;
+            Ok((input, Response::Meta(response)))
+        }
     }
 }
 
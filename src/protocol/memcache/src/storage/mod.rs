@@ -14,6 +14,9 @@ pub trait Storage {
     fn get(&mut self, request: &Get) -> Response;
     fn gets(&mut self, request: &Gets) -> Response;
     fn incr(&mut self, request: &Incr) -> Response;
+    fn invalidate_tag(&mut self, request: &InvalidateTag) -> Response;
+    fn meta_get(&mut self, request: &MetaGet) -> Response;
+    fn meta_set(&mut self, request: &MetaSet) -> Response;
     fn prepend(&mut self, request: &Prepend) -> Response;
     fn quit(&mut self, request: &Quit) -> Response;
     fn replace(&mut self, request: &Replace) -> Response;
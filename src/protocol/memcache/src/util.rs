@@ -132,3 +132,29 @@ pub fn parse_u32(input: &[u8]) -> IResult<&[u8], u32> {
     })?;
     Ok((input, value))
 }
+
+// parses the space separated flags of a meta command up to and including the
+// CRLF which ends the command
+pub fn meta_flags(input: &[u8]) -> IResult<&[u8], Vec<&[u8]>> {
+    let mut flags = Vec::new();
+    let mut input = input;
+
+    while let Ok((i, _)) = space1(input) {
+        let (i, flag) = take_till(|b| b == b' ' || b == b'\r')(i)?;
+        input = i;
+        if flag.is_empty() {
+            break;
+        }
+        flags.push(flag);
+    }
+
+    let (input, _) = space0(input)?;
+    let (input, _) = crlf(input)?;
+
+    Ok((input, flags))
+}
+
+// parses the numeric token which follows the flag character of a meta flag
+pub fn meta_token<T: std::str::FromStr>(flag: &[u8]) -> Option<T> {
+    std::str::from_utf8(&flag[1..]).ok()?.parse::<T>().ok()
+}
@@ -14,6 +14,7 @@ mod test;
 use crate::Response;
 pub use keyword::Keyword;
 use logger::Klog;
+use protocol_common::Shard;
 
 pub use parse::Parser as RequestParser;
 
@@ -23,6 +24,9 @@ pub enum Request {
     Ping,
 }
 
+// ping requests do not have a key and are always handled by the first shard
+impl Shard<Response> for Request {}
+
 impl Klog for Request {
     type Response = Response;
 
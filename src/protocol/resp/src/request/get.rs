@@ -7,9 +7,13 @@ use logger::klog;
 use std::io::{Error, ErrorKind};
 use std::sync::Arc;
 
+/// A `GET <key> [WITHSTALE]` request. With `WITHSTALE`, a hit is returned as
+/// an array of the value and an integer which is `1` if the value has passed
+/// its TTL and is being served during the grace period.
 #[derive(Debug, PartialEq, Eq)]
 pub struct Get {
     key: Arc<[u8]>,
+    with_stale: bool,
 }
 
 impl TryFrom<Message> for Get {
@@ -23,27 +27,28 @@ impl TryFrom<Message> for Get {
 
             let mut array = array.inner.unwrap();
 
-            if array.len() != 2 {
+            if array.len() != 2 && array.len() != 3 {
                 return Err(Error::new(ErrorKind::Other, "malformed command"));
             }
 
-            let key = if let Message::BulkString(key) = array.remove(1) {
-                if key.inner.is_none() {
-                    return Err(Error::new(ErrorKind::Other, "malformed command"));
-                }
+            let _command = take_bulk_string(&mut array)?;
+
+            let key = take_bulk_string(&mut array)?
+                .ok_or_else(|| Error::new(ErrorKind::Other, "malformed command"))?;
 
-                let key = key.inner.unwrap();
+            if key.is_empty() {
+                return Err(Error::new(ErrorKind::Other, "malformed command"));
+            }
 
-                if key.len() == 0 {
+            let with_stale = match take_bulk_string_as_utf8(&mut array)? {
+                None => false,
+                Some(token) if token == "WITHSTALE" => true,
+                Some(_) => {
                     return Err(Error::new(ErrorKind::Other, "malformed command"));
                 }
-
-                key
-            } else {
-                return Err(Error::new(ErrorKind::Other, "malformed command"));
             };
 
-            Ok(Self { key })
+            Ok(Self { key, with_stale })
         } else {
             Err(Error::new(ErrorKind::Other, "malformed command"))
         }
@@ -52,22 +57,40 @@ impl TryFrom<Message> for Get {
 
 impl Get {
     pub fn new(key: &[u8]) -> Self {
-        Self { key: key.into() }
+        Self {
+            key: key.into(),
+            with_stale: false,
+        }
+    }
+
+    /// Request that the response indicates whether the value is stale.
+    pub fn with_stale(mut self) -> Self {
+        self.with_stale = true;
+        self
     }
 
     pub fn key(&self) -> &[u8] {
         &self.key
     }
+
+    /// Should the response indicate whether the value is stale?
+    pub fn return_stale(&self) -> bool {
+        self.with_stale
+    }
 }
 
 impl From<&Get> for Message {
     fn from(other: &Get) -> Message {
-        Message::Array(Array {
-            inner: Some(vec![
-                Message::BulkString(BulkString::new(b"GET")),
-                Message::BulkString(BulkString::from(other.key.clone())),
-            ]),
-        })
+        let mut v = vec![
+            Message::BulkString(BulkString::new(b"GET")),
+            Message::BulkString(BulkString::from(other.key.clone())),
+        ];
+
+        if other.with_stale {
+            v.push(Message::BulkString(BulkString::new(b"WITHSTALE")));
+        }
+
+        Message::Array(Array { inner: Some(v) })
     }
 }
 
@@ -85,6 +108,12 @@ impl Klog for Get {
         let (code, len) = match response {
             Message::BulkString(_) if *response == Response::null() => (ResponseCode::Miss, 0),
             Message::BulkString(s) => (ResponseCode::Hit, s.len()),
+            Message::Array(Array {
+                inner: Some(values),
+            }) => match values.first() {
+                Some(Message::BulkString(s)) => (ResponseCode::Hit, s.len()),
+                _ => (ResponseCode::Miss, 0),
+            },
             _ => (ResponseCode::Miss, 0),
         };
 
@@ -118,5 +147,12 @@ mod tests {
                 .into_inner(),
             Request::Get(Get::new(b"0"))
         );
+
+        assert_eq!(
+            parser.parse(b"get 0 WITHSTALE\r\n").unwrap().into_inner(),
+            Request::Get(Get::new(b"0").with_stale())
+        );
+
+        assert!(parser.parse(b"get 0 NX\r\n").is_err());
     }
 }
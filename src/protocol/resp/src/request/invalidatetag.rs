@@ -0,0 +1,103 @@
+// Copyright 2023 Pelikan Foundation LLC.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use std::io::Error;
+use std::sync::Arc;
+
+use super::*;
+
+#[metric(name = "invalidatetag")]
+pub static INVALIDATETAG: Counter = Counter::new();
+
+#[metric(name = "invalidatetag_ex")]
+pub static INVALIDATETAG_EX: Counter = Counter::new();
+
+/// Removes every item which was stored with a tag. Items are tagged with the
+/// `TAG` option of `SET`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct InvalidateTag {
+    tag: Arc<[u8]>,
+}
+
+impl TryFrom<Message> for InvalidateTag {
+    type Error = Error;
+
+    fn try_from(value: Message) -> Result<Self, Self::Error> {
+        let array = match value {
+            Message::Array(array) => array,
+            _ => return Err(Error::new(ErrorKind::Other, "malformed command")),
+        };
+
+        let mut array = array.inner.unwrap();
+        if array.len() != 2 {
+            return Err(Error::new(ErrorKind::Other, "malformed command"));
+        }
+
+        let _command = take_bulk_string(&mut array)?;
+
+        let tag = take_bulk_string(&mut array)?
+            .ok_or_else(|| Error::new(ErrorKind::Other, "malformed command"))?;
+
+        if tag.is_empty() {
+            return Err(Error::new(ErrorKind::Other, "malformed command"));
+        }
+
+        Ok(Self { tag })
+    }
+}
+
+impl InvalidateTag {
+    pub fn new(tag: &[u8]) -> Self {
+        Self { tag: tag.into() }
+    }
+
+    pub fn tag(&self) -> &[u8] {
+        &self.tag
+    }
+}
+
+impl From<&InvalidateTag> for Message {
+    fn from(value: &InvalidateTag) -> Self {
+        Message::Array(Array {
+            inner: Some(vec![
+                Message::bulk_string(b"INVALIDATETAG"),
+                Message::BulkString(BulkString::from(value.tag.clone())),
+            ]),
+        })
+    }
+}
+
+impl Compose for InvalidateTag {
+    fn compose(&self, dst: &mut dyn BufMut) -> usize {
+        Message::from(self).compose(dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parser() {
+        let parser = RequestParser::new();
+        assert_eq!(
+            parser
+                .parse(b"INVALIDATETAG user:1\r\n")
+                .unwrap()
+                .into_inner(),
+            Request::InvalidateTag(InvalidateTag::new(b"user:1"))
+        );
+
+        assert_eq!(
+            parser
+                .parse(b"*2\r\n$13\r\ninvalidatetag\r\n$1\r\nt\r\n")
+                .unwrap()
+                .into_inner(),
+            Request::InvalidateTag(InvalidateTag::new(b"t"))
+        );
+
+        assert!(parser.parse(b"INVALIDATETAG\r\n").is_err());
+        assert!(parser.parse(b"INVALIDATETAG a b\r\n").is_err());
+    }
+}
@@ -8,11 +8,15 @@ use logger::Klog;
 use protocol_common::BufMut;
 use protocol_common::Parse;
 use protocol_common::ParseOk;
+use protocol_common::{Route, Shard};
 use std::borrow::Cow;
 use std::fmt::{Display, Formatter};
 use std::io::{Error, ErrorKind};
 use std::sync::Arc;
 
+// `HashSet` is the name of the HSET request
+type MemberSet = std::collections::HashSet<Arc<[u8]>>;
+
 mod badd;
 mod del;
 mod get;
@@ -26,6 +30,7 @@ mod hlen;
 mod hmget;
 mod hset;
 mod hvals;
+mod invalidatetag;
 mod lindex;
 mod llen;
 mod lpop;
@@ -70,6 +75,7 @@ pub use hlen::*;
 pub use hmget::*;
 pub use hset::*;
 pub use hvals::*;
+pub use invalidatetag::*;
 pub use sadd::*;
 pub use set::*;
 
@@ -179,6 +185,7 @@ decl_request! {
         HashSet(HashSet) => "hset",
         HashValues(HashValues) => "hvals",
         HashIncrBy(HashIncrBy) => "hincrby",
+        InvalidateTag(InvalidateTag) => "invalidatetag",
         ListIndex(ListIndex) => "lindex",
         ListLen(ListLen) => "llen",
         ListPop(ListPop) => "lpop",
@@ -219,6 +226,187 @@ impl Klog for Request {
     }
 }
 
+impl Shard<Response> for Request {
+    fn route(&self, shards: usize, shard: &dyn Fn(&[u8]) -> usize) -> Route<Self> {
+        let key = match self {
+            Self::BtreeAdd(r) => r.outer_key(),
+            Self::Get(r) => r.key(),
+            Self::HashDelete(r) => r.key(),
+            Self::HashExists(r) => r.key(),
+            Self::HashGet(r) => r.key(),
+            Self::HashGetAll(r) => r.key(),
+            Self::HashKeys(r) => r.key(),
+            Self::HashLength(r) => r.key(),
+            Self::HashMultiGet(r) => r.key(),
+            Self::HashSet(r) => r.key(),
+            Self::HashValues(r) => r.key(),
+            Self::HashIncrBy(r) => r.key(),
+            Self::ListIndex(r) => r.key(),
+            Self::ListLen(r) => r.key(),
+            Self::ListPop(r) => r.key(),
+            Self::ListPopBack(r) => r.key(),
+            Self::ListRange(r) => r.key(),
+            Self::ListPush(r) => r.key(),
+            Self::ListPushBack(r) => r.key(),
+            Self::ListTrim(r) => r.key(),
+            Self::Set(r) => r.key(),
+            Self::SetAdd(r) => r.key(),
+            Self::SetRem(r) => r.key(),
+            Self::SetMembers(r) => r.key(),
+            Self::SetIsMember(r) => r.key(),
+            Self::InvalidateTag(r) => {
+                // every shard may hold items with the tag
+                return Route::Split(
+                    (0..shards)
+                        .map(|id| (id, Self::InvalidateTag(r.clone())))
+                        .collect(),
+                );
+            }
+            Self::Del(r) => return route_keys(r.keys(), shards, shard, Request::del),
+            Self::SetUnion(r) => {
+                return route_keys(r.keys(), shards, shard, |keys| {
+                    Self::SetUnion(SetUnion::new(keys))
+                })
+            }
+            Self::SetIntersect(r) => {
+                return route_keys(r.keys(), shards, shard, |keys| {
+                    Self::SetIntersect(SetIntersect::new(keys))
+                })
+            }
+            Self::SetDiff(r) => {
+                let mut groups = group_keys(r.keys(), shards, shard);
+                if groups.len() == 1 {
+                    return Route::Shard(groups[0].0);
+                }
+
+                // the shard which owns the first key takes the difference of
+                // its keys, while the other shards return the union of theirs,
+                // which is then removed from the difference
+                let first = shard(&r.keys()[0]);
+                let idx = groups.iter().position(|(id, _)| *id == first).unwrap();
+                let (id, keys) = groups.remove(idx);
+
+                let mut split = vec![(id, Self::SetDiff(SetDiff::new(&keys)))];
+                split.extend(
+                    groups
+                        .into_iter()
+                        .map(|(id, keys)| (id, Self::SetUnion(SetUnion::new(&keys)))),
+                );
+                return Route::Split(split);
+            }
+        };
+
+        Route::Shard(shard(key))
+    }
+
+    fn merge(&self, responses: Vec<Response>) -> Response {
+        match self {
+            Self::Del(_) => {
+                let mut deleted = 0;
+                for response in responses {
+                    match response {
+                        Response::Integer(count) => deleted += count.value(),
+                        // any other response is an error and is returned as-is
+                        response => return response,
+                    }
+                }
+                Response::integer(deleted)
+            }
+            Self::SetUnion(_) | Self::SetIntersect(_) | Self::SetDiff(_) => {
+                let mut sets = Vec::with_capacity(responses.len());
+                for response in responses {
+                    match members(response) {
+                        Ok(members) => sets.push(members),
+                        Err(response) => return response,
+                    }
+                }
+
+                let mut sets = sets.into_iter();
+                let mut result = sets.next().unwrap_or_default();
+                for set in sets {
+                    if let Self::SetUnion(_) = self {
+                        let seen: MemberSet = result.iter().cloned().collect();
+                        result.extend(set.into_iter().filter(|m| !seen.contains(m)));
+                        continue;
+                    }
+
+                    let set: MemberSet = set.into_iter().collect();
+                    let diff = matches!(self, Self::SetDiff(_));
+                    result.retain(|m| set.contains(m) != diff);
+                }
+
+                Response::array(
+                    result
+                        .into_iter()
+                        .map(|m| Response::bulk_string(&m))
+                        .collect(),
+                )
+            }
+            _ => responses
+                .into_iter()
+                .next()
+                .expect("split requests must have at least one response"),
+        }
+    }
+}
+
+/// Route a multi-key request by grouping the keys for each shard. The request
+/// is only split if the keys belong to more than one shard.
+fn route_keys(
+    keys: &[Arc<[u8]>],
+    shards: usize,
+    shard: &dyn Fn(&[u8]) -> usize,
+    request: impl Fn(&[&[u8]]) -> Request,
+) -> Route<Request> {
+    let groups = group_keys(keys, shards, shard);
+
+    if groups.len() == 1 {
+        return Route::Shard(groups[0].0);
+    }
+
+    Route::Split(
+        groups
+            .into_iter()
+            .map(|(id, keys)| (id, request(&keys)))
+            .collect(),
+    )
+}
+
+/// Group the keys by the shard which owns them, keeping the order of the keys
+/// within each group.
+fn group_keys<'a>(
+    keys: &'a [Arc<[u8]>],
+    shards: usize,
+    shard: &dyn Fn(&[u8]) -> usize,
+) -> Vec<(usize, Vec<&'a [u8]>)> {
+    let mut split: Vec<Vec<&[u8]>> = vec![Vec::new(); shards];
+    for key in keys {
+        split[shard(key)].push(key);
+    }
+
+    split
+        .into_iter()
+        .enumerate()
+        .filter(|(_, keys)| !keys.is_empty())
+        .collect()
+}
+
+/// Returns the members of a set from an array response, or the response itself
+/// if it is an error.
+fn members(response: Response) -> Result<Vec<Arc<[u8]>>, Response> {
+    match response {
+        Response::Array(Array { inner }) => Ok(inner
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|member| match member {
+                Response::BulkString(BulkString { inner }) => inner,
+                _ => None,
+            })
+            .collect()),
+        response => Err(response),
+    }
+}
+
 impl Request {
     pub fn del(keys: &[&[u8]]) -> Self {
         Self::Del(Del::new(keys))
@@ -268,6 +456,10 @@ impl Request {
         Self::HashIncrBy(HashIncrBy::new(key, field, increment))
     }
 
+    pub fn invalidate_tag(tag: &[u8]) -> Self {
+        Self::InvalidateTag(InvalidateTag::new(tag))
+    }
+
     pub fn set(
         key: &[u8],
         value: &[u8],
@@ -311,6 +503,7 @@ fn string_key(key: &[u8]) -> Cow<'_, str> {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
     use crate::RequestParser;
     use protocol_common::Parse;
 
@@ -319,4 +512,77 @@ mod tests {
         let parser = RequestParser::new();
         assert!(parser.parse(b"GET test\n").is_err());
     }
+
+    #[test]
+    fn route() {
+        // the first byte of the key selects the shard
+        let shard = |key: &[u8]| (key[0] - b'a') as usize;
+        let set = |members: &[&[u8]]| {
+            Response::array(members.iter().map(|m| Response::bulk_string(m)).collect())
+        };
+
+        assert_eq!(Request::get(b"b").route(3, &shard), Route::Shard(1));
+
+        // all keys belong to a single shard
+        let request = Request::del(&[b"c0", b"c1"]);
+        assert_eq!(request.route(3, &shard), Route::Shard(2));
+
+        // keys are grouped by shard and the deletes are summed
+        let request = Request::del(&[b"c0", b"a0", b"c1"]);
+        assert_eq!(
+            request.route(3, &shard),
+            Route::Split(vec![
+                (0, Request::del(&[b"a0"])),
+                (2, Request::del(&[b"c0", b"c1"])),
+            ])
+        );
+        assert_eq!(
+            request.merge(vec![Response::integer(1), Response::integer(2)]),
+            Response::integer(3)
+        );
+        assert_eq!(
+            request.merge(vec![Response::integer(1), Response::error("oops")]),
+            Response::error("oops")
+        );
+
+        // set unions and intersections are combined across shards
+        let request = Request::SetUnion(SetUnion::new(&[b"c0", b"a0"]));
+        assert_eq!(
+            request.route(3, &shard),
+            Route::Split(vec![
+                (0, Request::SetUnion(SetUnion::new(&[b"a0"]))),
+                (2, Request::SetUnion(SetUnion::new(&[b"c0"]))),
+            ])
+        );
+        assert_eq!(
+            request.merge(vec![set(&[b"x", b"y"]), set(&[b"y", b"z"])]),
+            set(&[b"x", b"y", b"z"])
+        );
+
+        let request = Request::SetIntersect(SetIntersect::new(&[b"c0", b"a0"]));
+        assert_eq!(
+            request.merge(vec![set(&[b"x", b"y"]), set(&[b"y", b"z"])]),
+            set(&[b"y"])
+        );
+
+        // the shard with the first key takes the difference, and the union of
+        // the keys on other shards is removed from it
+        let request = Request::SetDiff(SetDiff::new(&[b"c0", b"a0", b"c1", b"b0"]));
+        assert_eq!(
+            request.route(3, &shard),
+            Route::Split(vec![
+                (2, Request::SetDiff(SetDiff::new(&[b"c0", b"c1"]))),
+                (0, Request::SetUnion(SetUnion::new(&[b"a0"]))),
+                (1, Request::SetUnion(SetUnion::new(&[b"b0"]))),
+            ])
+        );
+        assert_eq!(
+            request.merge(vec![
+                set(&[b"w", b"x", b"y"]),
+                set(&[b"x"]),
+                set(&[b"y", b"z"])
+            ]),
+            set(&[b"w"])
+        );
+    }
 }
@@ -32,6 +32,7 @@ pub struct Set {
     expire_time: Option<ExpireTime>,
     mode: SetMode,
     get_old: bool,
+    tags: Vec<Arc<[u8]>>,
 }
 
 impl Set {
@@ -48,6 +49,7 @@ impl Set {
             expire_time,
             mode,
             get_old,
+            tags: Vec::new(),
         }
     }
 
@@ -70,6 +72,12 @@ impl Set {
     pub fn get_old(&self) -> bool {
         self.get_old
     }
+
+    /// The tags which the item is stored with, set with the repeatable `TAG`
+    /// option.
+    pub fn tags(&self) -> &[Arc<[u8]>] {
+        &self.tags
+    }
 }
 
 impl TryFrom<Message> for Set {
@@ -102,6 +110,7 @@ impl TryFrom<Message> for Set {
             let mut expire_time = None;
             let mut mode = SetMode::Set;
             let mut get_old = false;
+            let mut tags = Vec::new();
 
             while let Some(token) = take_bulk_string_as_utf8(&mut array)? {
                 match token.as_str() {
@@ -172,6 +181,16 @@ impl TryFrom<Message> for Set {
 
                         get_old = true;
                     }
+                    "TAG" => {
+                        let tag = take_bulk_string(&mut array)?
+                            .ok_or_else(|| Error::new(ErrorKind::Other, "malformed command"))?;
+
+                        if tag.is_empty() {
+                            return Err(Error::new(ErrorKind::Other, "malformed command"));
+                        }
+
+                        tags.push(tag);
+                    }
                     _ => {
                         return Err(Error::new(ErrorKind::Other, "malformed command"));
                     }
@@ -184,6 +203,7 @@ impl TryFrom<Message> for Set {
                 expire_time,
                 mode,
                 get_old,
+                tags,
             })
         } else {
             Err(Error::new(ErrorKind::Other, "malformed command"))
@@ -236,6 +256,11 @@ impl From<&Set> for Message {
             v.push(Message::bulk_string(b"GET"));
         }
 
+        for tag in &other.tags {
+            v.push(Message::bulk_string(b"TAG"));
+            v.push(Message::BulkString(BulkString::from(tag.clone())));
+        }
+
         Message::Array(Array { inner: Some(v) })
     }
 }
@@ -310,6 +335,23 @@ mod tests {
             panic!("invalid parse result");
         }
 
+        if let Request::Set(request) = parser
+            .parse(b"SET key value TAG a EX 1000 TAG b\r\n")
+            .unwrap()
+            .into_inner()
+        {
+            assert_eq!(request.key(), b"key");
+            assert_eq!(request.expire_time(), Some(ExpireTime::Seconds(1000)));
+            assert_eq!(
+                request.tags(),
+                &[Arc::from(&b"a"[..]), Arc::from(&b"b"[..])]
+            );
+        } else {
+            panic!("invalid parse result");
+        }
+
+        assert!(parser.parse(b"SET key value TAG\r\n").is_err());
+
         if let Request::Set(request) = parser
             .parse(b"*3\r\n$3\r\nset\r\n$1\r\n0\r\n$1\r\n1\r\n")
             .unwrap()
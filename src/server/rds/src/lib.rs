@@ -28,14 +28,14 @@ impl Rds {
         // initialize metrics
         common::metrics::init();
 
-        // initialize storage
-        let storage = Storage::new(&config)?;
+        // initialize storage, with one shard for each storage thread
+        let storage = Storage::sharded(&config, config.worker().storage_threads())?;
 
         // initialize parser
         let parser = Parser::new();
 
         // initialize process
-        let process_builder = ProcessBuilder::<Parser, Request, Response, Storage>::new(
+        let process_builder = ProcessBuilder::<Parser, Request, Response, Storage>::sharded(
             &config, log_drain, parser, storage,
         )?
         .version(env!("CARGO_PKG_VERSION"));
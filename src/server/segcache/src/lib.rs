@@ -30,8 +30,8 @@ impl Segcache {
         // initialize metrics
         common::metrics::init();
 
-        // initialize storage
-        let storage = Storage::new(&config)?;
+        // initialize storage, with one shard for each storage thread
+        let storage = Storage::sharded(&config, config.worker().storage_threads())?;
 
         // initialize parser
         let parser = Parser::new()
@@ -39,7 +39,7 @@ impl Segcache {
             .time_type(config.time().time_type());
 
         // initialize process
-        let process_builder = ProcessBuilder::<Parser, Request, Response, Storage>::new(
+        let process_builder = ProcessBuilder::<Parser, Request, Response, Storage>::sharded(
             &config, log_drain, parser, storage,
         )?
         .version(env!("CARGO_PKG_VERSION"));
@@ -119,6 +119,20 @@ pub fn tests() {
         ],
     );
 
+    test(
+        "multi get",
+        &[
+            // store two of the keys
+            ("set 19 0 0 1\r\n1\r\n", Some("STORED\r\n")),
+            ("set 20 0 0 1\r\n2\r\n", Some("STORED\r\n")),
+            // values are returned in the order they are requested
+            (
+                "get 20 21 19\r\n",
+                Some("VALUE 20 0 1\r\n2\r\nVALUE 19 0 1\r\n1\r\nEND\r\n"),
+            ),
+        ],
+    );
+
     // test pipelined commands
     test(
         "pipelined get (key: 4 depth: 2)",
@@ -283,6 +297,9 @@ pub fn admin_tests() {
             Some(&format!("VERSION {}\r\n", env!("CARGO_PKG_VERSION"))),
         )],
     );
+
+    // hotkey detection is disabled in the default config
+    admin_test("hotkeys", &[("hotkeys\r\n", Some("END\r\n"))]);
 }
 
 // opens a new connection to the admin port, sends a request, and checks the response.
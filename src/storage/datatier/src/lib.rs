@@ -12,12 +12,27 @@ use std::path::Path;
 #[cfg(os = "linux")]
 use std::os::unix::fs::OpenOptionsExt;
 
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce, Tag};
 use memmap2::{MmapMut, MmapOptions};
 
 const PAGE_SIZE: usize = 4096;
 const HEADER_SIZE: usize = core::mem::size_of::<Header>();
 const MAGIC: [u8; 8] = *b"PELIKAN!";
 
+// the minimum number of bytes of key material in a key file
+const KEY_MATERIAL_MIN: usize = 32;
+
+// contexts used to derive independent keys from the key material
+const CIPHER_CONTEXT: &str = "pelikan datatier 2023-10-01 encryption key";
+const FINGERPRINT_CONTEXT: &str = "pelikan datatier 2023-10-01 key fingerprint";
+
+// encrypted data is split into chunks which are each sealed with their own
+// nonce and authentication tag
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_SIZE: usize = 16;
+const SALT_SIZE: usize = 32;
+
 // NOTE: this must be incremented if there are breaking changes to the on-disk
 // format
 const VERSION: u64 = 0;
@@ -39,6 +54,20 @@ pub trait Datapool: Send {
     fn len(&self) -> usize {
         self.as_slice().len()
     }
+
+    /// Returns the key used to encrypt the backing store, if it is encrypted.
+    fn key(&self) -> Option<&Key> {
+        None
+    }
+
+    /// Changes the size of the datapool, keeping the data up to the new size.
+    /// Returns an error for datapools which cannot be resized.
+    fn resize(&mut self, _size: usize) -> Result<(), std::io::Error> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "datapool cannot be resized",
+        ))
+    }
 }
 
 /// Represents volatile in-memory storage.
@@ -52,18 +81,22 @@ impl Memory {
         // mmap an anonymous region
         let mut mmap = MmapOptions::new().populate().len(size).map_anon()?;
 
-        // causes the mmap'd region to be prefaulted by writing a zero at the
-        // start of each page
-        let mut offset = 0;
-        while offset < size {
-            mmap[offset] = 0;
-            offset += PAGE_SIZE;
-        }
+        prefault(&mut mmap, 0..size);
 
         Ok(Self { mmap, size })
     }
 }
 
+// causes the range of the mmap'd region to be prefaulted by writing a zero at
+// the start of each page
+fn prefault(mmap: &mut MmapMut, range: Range<usize>) {
+    let mut offset = range.start;
+    while offset < range.end {
+        mmap[offset] = 0;
+        offset += PAGE_SIZE;
+    }
+}
+
 impl Datapool for Memory {
     fn as_slice(&self) -> &[u8] {
         &self.mmap[..self.size]
@@ -76,6 +109,36 @@ impl Datapool for Memory {
     fn flush(&mut self) -> Result<(), std::io::Error> {
         self.mmap.flush()
     }
+
+    /// On Linux, the region is resized with `mremap()` so the data is never
+    /// copied and memory beyond the new size is returned to the OS. Elsewhere,
+    /// a new region is mapped and the data is copied into it.
+    fn resize(&mut self, size: usize) -> Result<(), std::io::Error> {
+        #[cfg(target_os = "linux")]
+        {
+            // safety: the region is anonymous and is only borrowed through
+            // this datapool, so there are no references into it
+            unsafe {
+                self.mmap
+                    .remap(size, memmap2::RemapOptions::new().may_move(true))?
+            };
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            let mut mmap = MmapOptions::new().len(size).map_anon()?;
+            let len = std::cmp::min(size, self.size);
+            mmap[..len].copy_from_slice(&self.mmap[..len]);
+            self.mmap = mmap;
+        }
+
+        if size > self.size {
+            prefault(&mut self.mmap, self.size..size);
+        }
+        self.size = size;
+
+        Ok(())
+    }
 }
 
 // NOTE: make sure this is a whole number of pages and that all fields which are
@@ -91,7 +154,11 @@ pub struct Header {
     time_unix_ns: clocksource::precise::UnixInstant,
     user_version: u64,
     options: u64,
-    _pad: [u8; 4008],
+    // all zeros unless the data region is encrypted
+    key_fingerprint: [u8; 32],
+    salt: [u8; SALT_SIZE],
+    counter: u64,
+    _pad: [u8; 3936],
 }
 
 impl Header {
@@ -106,7 +173,10 @@ impl Header {
             time_unix_ns: clocksource::precise::UnixInstant::now(),
             user_version: 0,
             options: 0,
-            _pad: [0; 4008],
+            key_fingerprint: [0; 32],
+            salt: [0; SALT_SIZE],
+            counter: 0,
+            _pad: [0; 3936],
         }
     }
 
@@ -165,6 +235,125 @@ impl Header {
     pub fn options(&self) -> u64 {
         self.options
     }
+
+    /// Checks that the data region was written with the provided key, or that
+    /// it is not encrypted if no key is provided. Returns an error with the
+    /// `PermissionDenied` kind if the key does not match, so that callers can
+    /// tell it apart from a datapool which is corrupt.
+    fn check_key(&self, key: Option<&Key>) -> Result<(), std::io::Error> {
+        let encrypted = self.key_fingerprint != [0; 32];
+        let denied = |msg| Err(Error::new(ErrorKind::PermissionDenied, msg));
+
+        match key {
+            None if encrypted => denied("datapool is encrypted"),
+            None => Ok(()),
+            Some(_) if !encrypted => denied("datapool is not encrypted"),
+            Some(key) if key.fingerprint != self.key_fingerprint => {
+                denied("encryption key mismatch")
+            }
+            Some(_) => Ok(()),
+        }
+    }
+
+    fn set_key(&mut self, key: &Key, salt: [u8; SALT_SIZE], counter: u64) {
+        self.key_fingerprint = key.fingerprint;
+        self.salt = salt;
+        self.counter = counter;
+    }
+}
+
+/// Key material for an [`EncryptedFile`] datapool. The key for the cipher and
+/// a fingerprint which is recorded in the file header are both derived from
+/// the key material. The fingerprint allows a datapool to be checked against a
+/// key without revealing the key.
+///
+/// Data is encrypted with ChaCha20-Poly1305. Each time data is encrypted, a
+/// subkey is derived from the cipher key and a random 256-bit salt, which is
+/// stored alongside the encrypted data. Nonces only need to be unique for a
+/// single subkey.
+#[derive(Clone)]
+pub struct Key {
+    cipher: [u8; 32],
+    fingerprint: [u8; 32],
+}
+
+impl Key {
+    /// Derive a key from the provided key material. Returns an error if there
+    /// are fewer than 32 bytes of key material.
+    pub fn new(material: &[u8]) -> Result<Self, std::io::Error> {
+        if material.len() < KEY_MATERIAL_MIN {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "key must contain at least 32 bytes",
+            ));
+        }
+
+        Ok(Self {
+            cipher: blake3::derive_key(CIPHER_CONTEXT, material),
+            fingerprint: blake3::derive_key(FINGERPRINT_CONTEXT, material),
+        })
+    }
+
+    /// Read the key material from a file. The entire contents of the file are
+    /// used as the key material.
+    pub fn from_file<T: AsRef<Path>>(path: T) -> Result<Self, std::io::Error> {
+        Self::new(&std::fs::read(path)?)
+    }
+
+    /// Returns the fingerprint of the key which is recorded in the header of
+    /// encrypted datapools.
+    pub fn fingerprint(&self) -> &[u8; 32] {
+        &self.fingerprint
+    }
+
+    /// Encrypts the buffer in place, then appends the salt and authentication
+    /// tag which are needed to decrypt it with [`Key::decrypt`].
+    pub fn encrypt(&self, buffer: &mut Vec<u8>) -> Result<(), std::io::Error> {
+        // the subkey is only ever used for this buffer, so the nonce is fixed
+        let salt = rand::random::<[u8; SALT_SIZE]>();
+        let tag = self
+            .cipher(&salt)
+            .encrypt_in_place_detached(&nonce(0, 0), &[], buffer)
+            .map_err(|_| Error::new(ErrorKind::Other, "encryption failed"))?;
+
+        buffer.extend_from_slice(&salt);
+        buffer.extend_from_slice(&tag);
+
+        Ok(())
+    }
+
+    /// Authenticates and decrypts a buffer which was encrypted by
+    /// [`Key::encrypt`] in place. Returns an error if the buffer was encrypted
+    /// with a different key or has been modified.
+    pub fn decrypt(&self, buffer: &mut Vec<u8>) -> Result<(), std::io::Error> {
+        let len = buffer
+            .len()
+            .checked_sub(SALT_SIZE + TAG_SIZE)
+            .ok_or_else(|| Error::new(ErrorKind::Other, "encrypted data is truncated"))?;
+
+        let tag = *Tag::from_slice(&buffer[(len + SALT_SIZE)..]);
+        let salt: [u8; SALT_SIZE] = buffer[len..(len + SALT_SIZE)].try_into().unwrap();
+        buffer.truncate(len);
+
+        self.cipher(&salt)
+            .decrypt_in_place_detached(&nonce(0, 0), &[], buffer, &tag)
+            .map_err(|_| Error::new(ErrorKind::Other, "authentication failed"))
+    }
+
+    /// Returns the cipher using the subkey for the given salt.
+    fn cipher(&self, salt: &[u8; SALT_SIZE]) -> ChaCha20Poly1305 {
+        let subkey = blake3::keyed_hash(&self.cipher, salt);
+        ChaCha20Poly1305::new(subkey.as_bytes().into())
+    }
+}
+
+/// Returns the nonce used to encrypt a chunk of data. The counter identifies
+/// each time the data is encrypted with the same subkey.
+fn nonce(counter: u64, chunk: u32) -> Nonce {
+    let mut nonce = Nonce::default();
+    nonce[0..8].copy_from_slice(&counter.to_le_bytes());
+    nonce[8..12].copy_from_slice(&chunk.to_le_bytes());
+    nonce
 }
 
 /// Represents storage that primarily exists in a file. This is best used in
@@ -199,15 +388,16 @@ impl MmapFile {
             .write(true)
             .open(path)?;
 
-        // make sure the file size matches the expected size
-        if file.metadata()?.len() != total_size as u64 {
+        // the file must be large enough to hold the header
+        if file.metadata()?.len() < HEADER_SIZE as u64 {
             return Err(Error::new(ErrorKind::Other, "filesize mismatch"));
         }
 
-        // data resides after a small header
+        // data resides after a small header, this must match the range used
+        // when the file was created
         let data = Range {
             start: HEADER_SIZE,
-            end: HEADER_SIZE + data_size,
+            end: total_size,
         };
 
         // mmap the file
@@ -220,8 +410,15 @@ impl MmapFile {
         // convert the header to a struct so we can check and manipulate it
         let header = unsafe { &mut *(header.as_ptr() as *mut Header) };
 
-        // check the header
+        // check the header, the key is checked before the file size because
+        // encrypted datapools have a different layout
         header.check()?;
+        header.check_key(None)?;
+
+        // make sure the file size matches the expected size
+        if mmap.len() != total_size {
+            return Err(Error::new(ErrorKind::Other, "filesize mismatch"));
+        }
 
         // check the user version
         if header.user_version() != user_version {
@@ -307,12 +504,9 @@ impl MmapFile {
     }
 
     pub fn header(&self) -> &Header {
-        // load copy the header from the mmap'd file
-        let mut header = [0; HEADER_SIZE];
-        header.copy_from_slice(&self.mmap[0..HEADER_SIZE]);
-
-        // convert the header to a struct
-        unsafe { &*(header.as_ptr() as *const Header) }
+        // SAFETY: the mmap'd region is at least HEADER_SIZE bytes and the
+        // header is packed, so there are no alignment requirements
+        unsafe { &*(self.mmap.as_ptr() as *const Header) }
     }
 
     pub fn time_monotonic_s(&self) -> clocksource::coarse::Instant {
@@ -480,6 +674,7 @@ impl FileBackedMemory {
 
         // check the header
         header.check()?;
+        header.check_key(None)?;
 
         // check the user version
         if header.user_version() != user_version {
@@ -676,6 +871,241 @@ impl Datapool for FileBackedMemory {
     }
 }
 
+/// Represents storage that is in-memory and which is encrypted when it is
+/// saved to an associated file. Like [`FileBackedMemory`], the data is kept
+/// resident in DRAM and is only written to the file when the datapool is
+/// flushed, so the plaintext never reaches the file.
+///
+/// The data region is encrypted with ChaCha20-Poly1305 in chunks, and the
+/// authentication tag for each chunk is stored after the data region. The
+/// header is authenticated along with each chunk, so a file which was modified
+/// is refused when it is opened. The header also records the fingerprint of
+/// the key, which allows opening with the wrong key to be reported as such.
+///
+/// Each `EncryptedFile` derives a new subkey from a random salt when it is
+/// opened or created, and each flush increments a counter which is part of
+/// the nonce, so a nonce is never reused with the same subkey.
+pub struct EncryptedFile {
+    memory: Memory,
+    header: Box<[u8]>,
+    file: File,
+    key: Key,
+    salt: [u8; SALT_SIZE],
+    counter: u64,
+    user_version: u64,
+}
+
+impl EncryptedFile {
+    /// Open an existing `EncryptedFile` datapool at the given path and with
+    /// the specified size (in bytes). Returns an error if the file does not
+    /// exist, does not match the expected size, was not written with the same
+    /// key, or fails authentication.
+    pub fn open<T: AsRef<Path>>(
+        path: T,
+        data_size: usize,
+        user_version: u64,
+        key: Key,
+    ) -> Result<Self, std::io::Error> {
+        let chunks = Self::chunks(data_size)?;
+
+        // open an existing file for read and write access
+        let mut file = OpenOptions::new()
+            .create_new(false)
+            .read(true)
+            .write(true)
+            .open(path)?;
+
+        // make sure the file size matches the expected size
+        if file.metadata()?.len() != Self::file_size(data_size) as u64 {
+            return Err(Error::new(ErrorKind::Other, "filesize mismatch"));
+        }
+
+        // read the header from disk
+        let mut header = [0; HEADER_SIZE];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut header)?;
+
+        // turn the raw header into the struct
+        let header = unsafe { &mut *(header.as_mut_ptr() as *mut Header) };
+
+        // check the header, the key must be checked before the data is
+        // authenticated so that the wrong key is not reported as corruption
+        header.check()?;
+        header.check_key(Some(&key))?;
+
+        // check the user version
+        if header.user_version() != user_version {
+            return Err(Error::new(ErrorKind::Other, "user version mismatch"));
+        }
+
+        // read the encrypted data region and the tags into memory
+        let mut memory = Memory::create(data_size)?;
+        file.read_exact(memory.as_mut_slice())?;
+        let mut tags = vec![0; chunks * TAG_SIZE];
+        file.read_exact(&mut tags)?;
+
+        // authenticate and decrypt each chunk in place
+        let cipher = key.cipher(&header.salt);
+        for (idx, (chunk, tag)) in memory
+            .as_mut_slice()
+            .chunks_mut(CHUNK_SIZE)
+            .zip(tags.chunks(TAG_SIZE))
+            .enumerate()
+        {
+            cipher
+                .decrypt_in_place_detached(
+                    &nonce(header.counter, idx as u32),
+                    header.as_bytes(),
+                    chunk,
+                    Tag::from_slice(tag),
+                )
+                .map_err(|_| Error::new(ErrorKind::Other, "authentication failed"))?;
+        }
+
+        Ok(Self {
+            memory,
+            header: header.as_bytes().to_owned().into_boxed_slice(),
+            file,
+            key,
+            salt: rand::random(),
+            counter: 0,
+            user_version,
+        })
+    }
+
+    /// Create a new `EncryptedFile` datapool at the given path and with the
+    /// specified size (in bytes). Returns an error if the file already exists,
+    /// could not be created, or couldn't be extended to the requested size.
+    pub fn create<T: AsRef<Path>>(
+        path: T,
+        data_size: usize,
+        user_version: u64,
+        key: Key,
+    ) -> Result<Self, std::io::Error> {
+        Self::chunks(data_size)?;
+
+        // create a new file with read and write access
+        let file = OpenOptions::new()
+            .create_new(true)
+            .read(true)
+            .write(true)
+            .open(path)?;
+
+        // grow the file to match the total size
+        file.set_len(Self::file_size(data_size) as u64)?;
+        file.sync_all()?;
+
+        let memory = Memory::create(data_size)?;
+
+        Ok(Self {
+            memory,
+            header: vec![0; HEADER_SIZE].into_boxed_slice(),
+            file,
+            key,
+            salt: rand::random(),
+            counter: 0,
+            user_version,
+        })
+    }
+
+    /// Returns the number of chunks needed to hold the data region. Returns
+    /// an error if there would be too many chunks to give each a unique nonce.
+    fn chunks(data_size: usize) -> Result<usize, std::io::Error> {
+        let chunks = data_size.div_ceil(CHUNK_SIZE);
+        if chunks > u32::MAX as usize {
+            return Err(Error::new(ErrorKind::Other, "datapool is too large"));
+        }
+        Ok(chunks)
+    }
+
+    /// Returns the size of the file, which holds the header, the data region
+    /// and the tags rounded up to a whole number of pages.
+    fn file_size(data_size: usize) -> usize {
+        let size = HEADER_SIZE + data_size + data_size.div_ceil(CHUNK_SIZE) * TAG_SIZE;
+        size.div_ceil(PAGE_SIZE) * PAGE_SIZE
+    }
+
+    pub fn header(&self) -> &Header {
+        unsafe { &*(self.header.as_ptr() as *const Header) }
+    }
+
+    pub fn time_monotonic_s(&self) -> clocksource::coarse::Instant {
+        self.header().time_monotonic_s
+    }
+
+    pub fn time_monotonic_ns(&self) -> clocksource::precise::Instant {
+        self.header().time_monotonic_ns
+    }
+
+    pub fn time_unix_s(&self) -> clocksource::coarse::UnixInstant {
+        self.header().time_unix_s
+    }
+
+    pub fn time_unix_ns(&self) -> clocksource::precise::UnixInstant {
+        self.header().time_unix_ns
+    }
+}
+
+impl Datapool for EncryptedFile {
+    fn as_slice(&self) -> &[u8] {
+        self.memory.as_slice()
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.memory.as_mut_slice()
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        // a nonce must never be reused with the same subkey, so each flush
+        // uses the next counter
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .ok_or_else(|| Error::new(ErrorKind::Other, "nonces exhausted"))?;
+
+        // prepare the header, which is authenticated with each chunk
+        let mut header = Header::new();
+        header.set_user_version(self.user_version);
+        header.set_key(&self.key, self.salt, self.counter);
+
+        // encrypt the data region one chunk at a time and write it to the file
+        let cipher = self.key.cipher(&self.salt);
+        let mut buffer = vec![0; CHUNK_SIZE];
+        let mut tags = Vec::new();
+        self.file.seek(SeekFrom::Start(HEADER_SIZE as u64))?;
+        for (idx, chunk) in self.memory.as_slice().chunks(CHUNK_SIZE).enumerate() {
+            let buffer = &mut buffer[..chunk.len()];
+            buffer.copy_from_slice(chunk);
+            let tag = cipher
+                .encrypt_in_place_detached(
+                    &nonce(self.counter, idx as u32),
+                    header.as_bytes(),
+                    buffer,
+                )
+                .map_err(|_| Error::new(ErrorKind::Other, "encryption failed"))?;
+            self.file.write_all(buffer)?;
+            tags.extend_from_slice(&tag);
+        }
+
+        // the tags follow the data region
+        self.file.write_all(&tags)?;
+
+        // write the header to the file
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(header.as_bytes())?;
+
+        self.file.sync_all()?;
+
+        self.header = header.as_bytes().to_owned().into_boxed_slice();
+
+        Ok(())
+    }
+
+    fn key(&self) -> Option<&Key> {
+        Some(&self.key)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -693,6 +1123,24 @@ mod tests {
         assert_eq!(datapool.len(), 2 * PAGE_SIZE);
     }
 
+    #[test]
+    fn memory_datapool_resize() {
+        let mut datapool = Memory::create(2 * PAGE_SIZE).expect("failed to create pool");
+        datapool.as_mut_slice()[PAGE_SIZE] = 0x42;
+
+        datapool.resize(4 * PAGE_SIZE).expect("failed to grow pool");
+        assert_eq!(datapool.len(), 4 * PAGE_SIZE);
+        assert_eq!(datapool.as_slice()[PAGE_SIZE], 0x42);
+        assert_eq!(datapool.as_slice()[3 * PAGE_SIZE], 0);
+        datapool.as_mut_slice()[3 * PAGE_SIZE] = 0x43;
+
+        datapool
+            .resize(PAGE_SIZE + 1)
+            .expect("failed to shrink pool");
+        assert_eq!(datapool.len(), PAGE_SIZE + 1);
+        assert_eq!(datapool.as_slice()[PAGE_SIZE], 0x42);
+    }
+
     #[test]
     fn mmapfile_datapool() {
         let tempdir = TempDir::new().expect("failed to generate tempdir");
@@ -742,6 +1190,27 @@ mod tests {
         }
     }
 
+    #[test]
+    fn mmapfile_datapool_partial_page() {
+        let tempdir = TempDir::new().expect("failed to generate tempdir");
+        let mut path = tempdir.into_path();
+        path.push("mmap_test.data");
+
+        // a datapool which is not a whole number of pages should still reopen
+        {
+            let mut datapool =
+                MmapFile::create(&path, PAGE_SIZE + 1, 0).expect("failed to create pool");
+            datapool.as_mut_slice()[PAGE_SIZE] = 0xAB;
+            datapool.flush().expect("failed to flush");
+        }
+
+        {
+            let datapool = MmapFile::open(&path, PAGE_SIZE + 1, 0).expect("failed to open pool");
+            assert_eq!(datapool.len(), 2 * PAGE_SIZE);
+            assert_eq!(datapool.as_slice()[PAGE_SIZE], 0xAB);
+        }
+    }
+
     #[test]
     fn filebackedmemory_datapool() {
         let tempdir = TempDir::new().expect("failed to generate tempdir");
@@ -791,4 +1260,113 @@ mod tests {
             assert!(FileBackedMemory::open(&path, 2 * PAGE_SIZE, 1).is_err());
         }
     }
+
+    #[test]
+    fn encryptedfile_datapool() {
+        let tempdir = TempDir::new().expect("failed to generate tempdir");
+        let mut path = tempdir.into_path();
+        path.push("encrypted_test.data");
+
+        let key = || Key::new(&[0xA5; 32]).expect("failed to create key");
+        let magic = [0xDE, 0xCA, 0xFB, 0xAD];
+
+        // key material must be long enough
+        assert!(Key::new(&[0xA5; 31]).is_err());
+
+        // create a datapool, write some content to it, and close it
+        {
+            let mut datapool = EncryptedFile::create(&path, PAGE_SIZE + 1, 0, key())
+                .expect("failed to create pool");
+            assert_eq!(datapool.len(), PAGE_SIZE + 1);
+
+            for (i, byte) in magic.iter().enumerate() {
+                datapool.as_mut_slice()[i] = *byte;
+            }
+            datapool.as_mut_slice()[PAGE_SIZE] = 0xAB;
+            datapool.flush().expect("failed to flush");
+        }
+
+        // the plaintext does not reach the file
+        {
+            let contents = std::fs::read(&path).expect("failed to read file");
+            assert!(!contents.windows(magic.len()).any(|w| w == magic));
+        }
+
+        // open the datapool with the same key and check the content
+        {
+            let mut datapool =
+                EncryptedFile::open(&path, PAGE_SIZE + 1, 0, key()).expect("failed to open pool");
+            assert_eq!(datapool.as_slice()[0..4], magic[0..4]);
+            assert_eq!(datapool.as_slice()[4..8], [0; 4]);
+            assert_eq!(datapool.as_slice()[PAGE_SIZE], 0xAB);
+            datapool.flush().expect("failed to flush");
+        }
+
+        // the datapool is refused with the wrong key, without a key, or with
+        // the wrong user version
+        {
+            let wrong = Key::new(&[0x5A; 32]).expect("failed to create key");
+            let err = EncryptedFile::open(&path, PAGE_SIZE + 1, 0, wrong)
+                .err()
+                .expect("opened with the wrong key");
+            assert_eq!(err.to_string(), "encryption key mismatch");
+
+            assert!(MmapFile::open(&path, PAGE_SIZE + 1, 0).is_err());
+            assert!(EncryptedFile::open(&path, PAGE_SIZE + 1, 1, key()).is_err());
+        }
+
+        // each flush encrypts the data differently
+        {
+            let before = std::fs::read(&path).expect("failed to read file");
+            let mut datapool =
+                EncryptedFile::open(&path, PAGE_SIZE + 1, 0, key()).expect("failed to open pool");
+            datapool.flush().expect("failed to flush");
+            let after = std::fs::read(&path).expect("failed to read file");
+            assert_ne!(
+                before[HEADER_SIZE..(HEADER_SIZE + 4)],
+                after[HEADER_SIZE..(HEADER_SIZE + 4)]
+            );
+        }
+
+        // a modified header or data region fails authentication
+        for offset in [64, HEADER_SIZE, HEADER_SIZE + PAGE_SIZE] {
+            let contents = std::fs::read(&path).expect("failed to read file");
+            let mut modified = contents.clone();
+            modified[offset] ^= 1;
+            std::fs::write(&path, modified).expect("failed to write file");
+
+            assert!(EncryptedFile::open(&path, PAGE_SIZE + 1, 0, key()).is_err());
+
+            std::fs::write(&path, contents).expect("failed to write file");
+            assert!(EncryptedFile::open(&path, PAGE_SIZE + 1, 0, key()).is_ok());
+        }
+    }
+
+    #[test]
+    fn encrypted_buffer() {
+        let key = Key::new(&[0xA5; 32]).expect("failed to create key");
+        let plaintext = b"coffee is strong".to_vec();
+
+        let mut buffer = plaintext.clone();
+        key.encrypt(&mut buffer).expect("failed to encrypt");
+        assert_eq!(buffer.len(), plaintext.len() + SALT_SIZE + TAG_SIZE);
+        assert!(!buffer.windows(6).any(|w| w == b"coffee"));
+
+        // the same plaintext is encrypted differently each time
+        let mut other = plaintext.clone();
+        key.encrypt(&mut other).expect("failed to encrypt");
+        assert_ne!(buffer, other);
+
+        // the buffer can only be decrypted with the same key and if unmodified
+        let wrong = Key::new(&[0x5A; 32]).expect("failed to create key");
+        assert!(wrong.decrypt(&mut buffer.clone()).is_err());
+
+        let mut modified = buffer.clone();
+        modified[0] ^= 1;
+        assert!(key.decrypt(&mut modified).is_err());
+        assert!(key.decrypt(&mut vec![0; TAG_SIZE]).is_err());
+
+        key.decrypt(&mut buffer).expect("failed to decrypt");
+        assert_eq!(buffer, plaintext);
+    }
 }
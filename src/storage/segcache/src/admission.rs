@@ -0,0 +1,159 @@
+// Copyright 2023 Pelikan Foundation LLC.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! A TinyLFU-style admission filter for new items.
+//!
+//! The access frequency of keys is estimated with a count-min sketch which is
+//! fronted by a doorkeeper bloom filter. The first access to a key only sets
+//! the key in the doorkeeper, so keys which are seen once do not consume space
+//! in the sketch. Once the number of recorded accesses reaches ten times the
+//! number of counters, all counters are halved and the doorkeeper is cleared
+//! so that the estimates favor recent accesses.
+
+use crate::*;
+use ahash::RandomState;
+use bloom::BloomFilter;
+
+/// The number of rows in the count-min sketch.
+const DEPTH: usize = 4;
+
+/// The number of bits in the doorkeeper for each counter in a row.
+const DOORKEEPER_BITS: usize = 8;
+
+/// The number of hashes the doorkeeper stores for each key.
+const DOORKEEPER_HASHES: usize = 4;
+
+/// The admission policy which is applied to new items when the cache is under
+/// eviction pressure.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Admission {
+    /// Every write is admitted.
+    #[default]
+    None,
+    /// Once there are no free segments, a write of a key which is not already
+    /// in the cache is only admitted if the key has been accessed at least
+    /// `threshold` times recently, including the write itself. Accesses are
+    /// tracked with `counters` counters for each of the four rows of a
+    /// count-min sketch. A write which is not admitted returns
+    /// `SegcacheError::NotAdmitted`.
+    TinyLfu { counters: usize, threshold: u8 },
+}
+
+/// Tracks the recent access frequency of keys to decide whether new items
+/// should be admitted.
+pub(crate) struct AdmissionFilter {
+    hash_builder: Box<RandomState>,
+    doorkeeper: BloomFilter<[u8]>,
+    sketch: Box<[u8]>,
+    width: usize,
+    threshold: u8,
+    samples: usize,
+    reset_at: usize,
+}
+
+impl AdmissionFilter {
+    /// Returns a new filter for the policy, or `None` if every write should be
+    /// admitted.
+    pub fn new(admission: Admission) -> Option<Self> {
+        match admission {
+            Admission::None => None,
+            Admission::TinyLfu {
+                counters,
+                threshold,
+            } => {
+                let width = counters.max(1);
+
+                Some(Self {
+                    hash_builder: Box::new(RandomState::with_seeds(
+                        0xbb8c484891ec6c86,
+                        0x0522a25ae9c769f9,
+                        0xeed2797b9571bc75,
+                        0x4feb29c1fbbd59d0,
+                    )),
+                    doorkeeper: BloomFilter::with_seed(
+                        width * DOORKEEPER_BITS,
+                        DOORKEEPER_HASHES,
+                        0x4feb29c1fbbd59d0,
+                    ),
+                    sketch: vec![0; width * DEPTH].into_boxed_slice(),
+                    width,
+                    threshold,
+                    samples: 0,
+                    reset_at: width * 10,
+                })
+            }
+        }
+    }
+
+    /// Returns the index of the counter for the key in each row of the sketch.
+    fn indices(&self, key: &[u8]) -> [usize; DEPTH] {
+        let mut hasher = self.hash_builder.build_hasher();
+        hasher.write(key);
+        let hash = hasher.finish();
+
+        // the row hashes are derived from the two halves of the key hash
+        let h1 = hash & 0xFFFF_FFFF;
+        let h2 = (hash >> 32) | 1;
+
+        let mut indices = [0; DEPTH];
+        for (row, index) in indices.iter_mut().enumerate() {
+            let hash = h1.wrapping_add(h2.wrapping_mul(row as u64));
+            *index = row * self.width + (hash % self.width as u64) as usize;
+        }
+        indices
+    }
+
+    /// Record an access to the key.
+    pub fn record(&mut self, key: &[u8]) {
+        if !self.doorkeeper.contains(key) {
+            self.doorkeeper.insert(key);
+        } else {
+            // conservative update, only the smallest counters are incremented
+            let indices = self.indices(key);
+            let min = indices.iter().map(|i| self.sketch[*i]).min().unwrap();
+            if min < u8::MAX {
+                for i in indices {
+                    if self.sketch[i] == min {
+                        self.sketch[i] += 1;
+                    }
+                }
+            }
+        }
+
+        self.samples += 1;
+        if self.samples >= self.reset_at {
+            self.reset();
+        }
+    }
+
+    /// Returns the estimated number of recent accesses to the key.
+    pub fn estimate(&self, key: &[u8]) -> u8 {
+        if !self.doorkeeper.contains(key) {
+            return 0;
+        }
+
+        let min = self
+            .indices(key)
+            .iter()
+            .map(|i| self.sketch[*i])
+            .min()
+            .unwrap();
+
+        min.saturating_add(1)
+    }
+
+    /// Returns true if a new item with the key should be admitted.
+    pub fn admit(&self, key: &[u8]) -> bool {
+        self.estimate(key) >= self.threshold
+    }
+
+    /// Halve all of the counters and clear the doorkeeper.
+    fn reset(&mut self) {
+        for counter in self.sketch.iter_mut() {
+            *counter /= 2;
+        }
+        self.doorkeeper.clear();
+        self.samples /= 2;
+    }
+}
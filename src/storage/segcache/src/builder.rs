@@ -5,13 +5,28 @@
 //! A builder for configuring a new [`Segcache`] instance.
 
 use crate::*;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 /// A builder that is used to construct a new [`Segcache`] instance.
 pub struct Builder {
     hash_power: u8,
     overflow_factor: f64,
+    expand_threshold: Option<f64>,
     segments_builder: SegmentsBuilder,
+    checkpoint_path: Option<PathBuf>,
+    compression: Compression,
+    admission: Admission,
+    miss_ratio_curve: MissRatioCurve,
+    top_keys: usize,
+    expire_budget: ExpireBudget,
+    stale_grace: std::time::Duration,
+    lease_max_duration: std::time::Duration,
+    lease_max_outstanding: usize,
+    namespaces: Vec<Namespace>,
+    disk_path: Option<PathBuf>,
+    disk_size: usize,
+    disk_segment_size: i32,
+    disk_eviction: DiskPolicy,
 }
 
 // Defines the default parameters
@@ -20,7 +35,22 @@ impl Default for Builder {
         Self {
             hash_power: 16,
             overflow_factor: 0.0,
+            expand_threshold: None,
             segments_builder: SegmentsBuilder::default(),
+            checkpoint_path: None,
+            compression: Compression::default(),
+            admission: Admission::default(),
+            miss_ratio_curve: MissRatioCurve::default(),
+            top_keys: 0,
+            expire_budget: ExpireBudget::default(),
+            stale_grace: std::time::Duration::ZERO,
+            lease_max_duration: std::time::Duration::from_secs(60),
+            lease_max_outstanding: 65536,
+            namespaces: Vec::new(),
+            disk_path: None,
+            disk_size: 64 * 1024 * 1024,
+            disk_segment_size: 1024 * 1024,
+            disk_eviction: DiskPolicy::default(),
         }
     }
 }
@@ -75,6 +105,34 @@ impl Builder {
         self
     }
 
+    /// Allow the hashtable to be expanded while the cache is in use. Once the
+    /// fraction of item slots in use reaches the threshold, or an insert fails
+    /// because the bucket chain for the key is full, the hashtable is doubled
+    /// in size. The items are moved into the larger table a few buckets at a
+    /// time by each insert and by `Segcache::expire()`. By default, the
+    /// hashtable is never expanded.
+    ///
+    /// ```
+    /// use segcache::Segcache;
+    ///
+    /// // start with room for ~57k items, and double the hashtable once it is
+    /// // three quarters full
+    /// let cache = Segcache::builder()
+    ///     .hash_power(16)
+    ///     .hash_expand_threshold(Some(0.75))
+    ///     .build();
+    /// ```
+    pub fn hash_expand_threshold(mut self, threshold: Option<f64>) -> Self {
+        if let Some(threshold) = threshold {
+            assert!(
+                threshold > 0.0 && threshold <= 1.0,
+                "hash expand threshold must be greater than 0.0 and at most 1.0"
+            );
+        }
+        self.expand_threshold = threshold;
+        self
+    }
+
     /// Specify the total number of bytes to be used for heap storage of items.
     /// This includes, key, value, and per-item overheads.
     ///
@@ -94,12 +152,11 @@ impl Builder {
         self
     }
 
-    /// Specify the segment size for item storage. The largest item which can be
-    /// held is `size - 5` bytes for builds without the `debug` or `magic` build
-    /// features enabled. Smaller segment sizes reduce the number of items which
-    /// would be evicted/expired at one time, at the cost of additional memory
-    /// and book-keeping overheads compared to using larger segments for the
-    /// same total size.
+    /// Specify the segment size for item storage. Items which are larger than
+    /// a segment are stored across a run of adjacent segments. Smaller segment
+    /// sizes reduce the number of items which would be evicted/expired at one
+    /// time, at the cost of additional memory and book-keeping overheads
+    /// compared to using larger segments for the same total size.
     ///
     /// ```
     /// use segcache::Segcache;
@@ -135,16 +192,293 @@ impl Builder {
         self
     }
 
-    /// Specify a backing file to be used for segment storage.
+    /// Specify a user-defined eviction policy. This replaces the policy set by
+    /// `eviction()`, though the merge parameters of a `Policy::Merge` are still
+    /// used when the custom policy chooses to merge segments. See the
+    /// `EvictionPolicy` documentation for an example.
+    pub fn custom_eviction<T: EvictionPolicy + 'static>(mut self, policy: T) -> Self {
+        self.segments_builder = self
+            .segments_builder
+            .custom_eviction_policy(Box::new(policy));
+        self
+    }
+
+    /// Specify a backing file to be used for segment storage. If the file
+    /// already exists, it will be opened and the cache contents will be
+    /// restored from it. Any items which expired while the cache was not
+    /// running are dropped during recovery.
     ///
-    /// # Panics
+    /// The datapool is flushed to the file when the cache is dropped. If the
+    /// file exists but could not be validated, for example if it was not
+    /// cleanly flushed or the heap and segment sizes have changed, it is
+    /// replaced with a new datapool and the cache starts empty.
     ///
-    /// This will panic if the file already exists
+    /// Tag invalidations are written alongside the datapool to a file with a
+    /// `.tags` suffix, so that restored items which carry tags are only kept
+    /// if none of their tags were invalidated. If that file is missing or does
+    /// not match the datapool, every restored item with tags is dropped.
     pub fn datapool_path<T: AsRef<Path>>(mut self, path: Option<T>) -> Self {
         self.segments_builder = self.segments_builder.datapool_path(path);
         self
     }
 
+    /// Specify a key file used to encrypt the datapool at rest. The entire
+    /// contents of the file, which must be at least 32 bytes, are used as the
+    /// key material. This has no effect unless a datapool path is also set.
+    ///
+    /// With a key, the datapool is held in memory and is encrypted as it is
+    /// written to the file when the cache is dropped. The file records a
+    /// fingerprint of the key, and building the cache returns an error if an
+    /// existing datapool was written with a different key or without a key.
+    /// Checkpoints are encrypted with the same key. The disk tier cannot be
+    /// encrypted, so building the cache returns an error if a disk path is
+    /// also set.
+    ///
+    /// ```
+    /// use segcache::Segcache;
+    ///
+    /// let dir = tempfile::tempdir().unwrap();
+    /// let datapool = dir.path().join("datapool");
+    /// let key = dir.path().join("datapool.key");
+    /// std::fs::write(&key, [0x42; 32]).unwrap();
+    ///
+    /// let cache = Segcache::builder()
+    ///     .datapool_path(Some(&datapool))
+    ///     .datapool_key_path(Some(&key))
+    ///     .build()
+    ///     .expect("failed to create cache");
+    /// drop(cache);
+    ///
+    /// // the datapool cannot be restored with a different key
+    /// std::fs::write(&key, [0x24; 32]).unwrap();
+    /// assert!(Segcache::builder()
+    ///     .datapool_path(Some(&datapool))
+    ///     .datapool_key_path(Some(&key))
+    ///     .build()
+    ///     .is_err());
+    /// ```
+    pub fn datapool_key_path<T: AsRef<Path>>(mut self, path: Option<T>) -> Self {
+        self.segments_builder = self.segments_builder.datapool_key_path(path);
+        self
+    }
+
+    /// Specify a checkpoint file which was written by [`Segcache::checkpoint`].
+    /// When the cache is restored from an existing datapool, the hashtable is
+    /// loaded from the checkpoint instead of scanning all of the segments. A
+    /// checkpoint which is missing or does not match the datapool is ignored
+    /// and the segments are scanned instead.
+    pub fn checkpoint_path<T: AsRef<Path>>(mut self, path: Option<T>) -> Self {
+        self.checkpoint_path = path.map(|p| p.as_ref().to_owned());
+        self
+    }
+
+    /// Specify the compression policy for item values. Values are compressed
+    /// on insert and decompressed when they are read. By default, values are
+    /// not compressed. See the `Compression` documentation for more details.
+    ///
+    /// ```
+    /// use segcache::{Compression, Segcache};
+    ///
+    /// // compress values which are at least 1KB with zstd
+    /// let compression = Compression::Zstd { level: 3, threshold: 1024 };
+    /// let cache = Segcache::builder().compression(compression).build();
+    /// ```
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Specify the admission policy for new items. By default, every write is
+    /// admitted. See the `Admission` documentation for more details.
+    ///
+    /// ```
+    /// use segcache::{Admission, Segcache};
+    ///
+    /// // once the heap is full, only admit keys which were recently accessed
+    /// let admission = Admission::TinyLfu { counters: 65536, threshold: 2 };
+    /// let cache = Segcache::builder().admission(admission).build();
+    /// ```
+    pub fn admission(mut self, admission: Admission) -> Self {
+        self.admission = admission;
+        self
+    }
+
+    /// Enable estimation of the miss ratio curve, which is the miss ratio the
+    /// cache would have at larger and smaller heap sizes. The estimate can be
+    /// read with `Segcache::miss_ratio_curve()` and is also exposed through the
+    /// `mrc_sample` and `mrc_hit` metrics. See the `MissRatioCurve`
+    /// documentation for more details.
+    ///
+    /// ```
+    /// use segcache::{MissRatioCurve, Segcache};
+    ///
+    /// // sample 1% of the keys, tracking at most 64k sampled keys
+    /// let mrc = MissRatioCurve::Shards { sample_rate: 0.01, max_keys: 65536 };
+    /// let cache = Segcache::builder().miss_ratio_curve(mrc).build();
+    /// ```
+    pub fn miss_ratio_curve(mut self, config: MissRatioCurve) -> Self {
+        self.miss_ratio_curve = config;
+        self
+    }
+
+    /// Enable tracking of the keys with the most hits, using the given number
+    /// of counters. The keys can be read with `Segcache::top_keys()`. More
+    /// counters give more accurate estimates, and any key which accounts for
+    /// more than one in `counters` of the hits is guaranteed to be tracked.
+    /// By default, the keys are not tracked.
+    ///
+    /// ```
+    /// use segcache::Segcache;
+    ///
+    /// let cache = Segcache::builder().top_keys(1024).build();
+    /// ```
+    pub fn top_keys(mut self, counters: usize) -> Self {
+        self.top_keys = counters;
+        self
+    }
+
+    /// Specify the budget for each call to `Segcache::expire()`. By default,
+    /// every expired segment is cleared in a single call, which may take a
+    /// long time if many segments expire together. With a budget, expiration
+    /// resumes where it stopped on the next call. See the `ExpireBudget`
+    /// documentation for more details.
+    ///
+    /// ```
+    /// use segcache::{ExpireBudget, Segcache};
+    /// use std::time::Duration;
+    ///
+    /// // spend at most 100us expiring segments in each call
+    /// let budget = ExpireBudget::Time(Duration::from_micros(100));
+    /// let cache = Segcache::builder().expire_budget(budget).build();
+    /// ```
+    pub fn expire_budget(mut self, budget: ExpireBudget) -> Self {
+        self.expire_budget = budget;
+        self
+    }
+
+    /// Keep items for a grace period after their TTL has passed. During the
+    /// grace period, `Segcache::get_stale()` and `Segcache::get_lease()` still
+    /// return the item but mark it as stale so the caller can refresh it. All
+    /// other operations treat the item as a miss. `get_lease()` makes sure
+    /// only one caller refreshes a stale item. By default, items are
+    /// expired as soon as their TTL passes.
+    ///
+    /// ```
+    /// use segcache::Segcache;
+    /// use std::time::Duration;
+    ///
+    /// // serve stale items for up to 30 seconds after they expire
+    /// let cache = Segcache::builder().stale_grace(Duration::from_secs(30)).build();
+    /// ```
+    pub fn stale_grace(mut self, grace: std::time::Duration) -> Self {
+        self.stale_grace = grace;
+        self
+    }
+
+    /// Specify the longest duration of a lease granted by
+    /// `Segcache::get_lease()`. Longer durations requested by the caller are
+    /// capped to this limit. By default, leases last for at most 60 seconds.
+    ///
+    /// ```
+    /// use segcache::Segcache;
+    /// use std::time::Duration;
+    ///
+    /// // leases last for at most 10 seconds
+    /// let cache = Segcache::builder()
+    ///     .lease_max_duration(Duration::from_secs(10))
+    ///     .build();
+    /// ```
+    pub fn lease_max_duration(mut self, duration: std::time::Duration) -> Self {
+        self.lease_max_duration = duration;
+        self
+    }
+
+    /// Specify the number of leases which may be outstanding at a time. Once
+    /// the limit is reached, `Segcache::get_lease()` returns `Lease::Miss` for
+    /// keys which are not in the cache, until leases are released or expire.
+    /// By default, up to 65536 leases may be outstanding.
+    ///
+    /// ```
+    /// use segcache::Segcache;
+    ///
+    /// let cache = Segcache::builder().lease_max_outstanding(1024).build();
+    /// ```
+    pub fn lease_max_outstanding(mut self, leases: usize) -> Self {
+        self.lease_max_outstanding = leases;
+        self
+    }
+
+    /// Add a namespace which is given a share of the heap. Namespaces are
+    /// numbered from one in the order they are added. See the `Namespace`
+    /// documentation for more details.
+    ///
+    /// ```
+    /// use segcache::{Namespace, Segcache};
+    ///
+    /// // split most of the heap between two teams, the remainder is used by
+    /// // keys which do not match either prefix
+    /// let cache = Segcache::builder()
+    ///     .namespace(Namespace::new("a").prefix(b"a:").share(0.4))
+    ///     .namespace(Namespace::new("b").prefix(b"b:").share(0.4))
+    ///     .build();
+    /// ```
+    pub fn namespace(mut self, namespace: Namespace) -> Self {
+        self.namespaces.push(namespace);
+        self
+    }
+
+    /// Specify a file to back a second storage tier on disk. Items which are
+    /// evicted from the heap are demoted to the disk tier, and reading an item
+    /// from the disk tier promotes it back into the heap. By default, there is
+    /// no disk tier.
+    ///
+    /// The disk tier is memory mapped from the file rather than being held in
+    /// memory, so it may be much larger than the heap. It is not persistent and
+    /// any existing file at the path is replaced when the cache is built. The
+    /// disk tier is not encrypted and cannot be used with a datapool key.
+    ///
+    /// ```
+    /// use segcache::{DiskPolicy, Segcache};
+    ///
+    /// const MB: usize = 1024 * 1024;
+    ///
+    /// let dir = tempfile::TempDir::new().expect("failed to generate tempdir");
+    ///
+    /// // keep up to 256MB of evicted items in a file on disk
+    /// let cache = Segcache::builder()
+    ///     .heap_size(64 * MB)
+    ///     .disk_path(Some(dir.path().join("segcache.disk")))
+    ///     .disk_size(256 * MB)
+    ///     .disk_eviction(DiskPolicy::Fifo)
+    ///     .build();
+    /// ```
+    pub fn disk_path<T: AsRef<Path>>(mut self, path: Option<T>) -> Self {
+        self.disk_path = path.map(|p| p.as_ref().to_owned());
+        self
+    }
+
+    /// Specify the total number of bytes to be used for the disk tier. This is
+    /// rounded down to a whole number of disk segments.
+    pub fn disk_size(mut self, bytes: usize) -> Self {
+        self.disk_size = bytes;
+        self
+    }
+
+    /// Specify the segment size for the disk tier. Evicted items which are
+    /// larger than a disk segment are not demoted.
+    pub fn disk_segment_size(mut self, size: i32) -> Self {
+        assert!(size > 0, "disk segment size must be positive");
+        self.disk_segment_size = size;
+        self
+    }
+
+    /// Specify the policy used to reuse segments once the disk tier is full.
+    /// See the `DiskPolicy` documentation for more details.
+    pub fn disk_eviction(mut self, policy: DiskPolicy) -> Self {
+        self.disk_eviction = policy;
+        self
+    }
+
     /// Consumes the builder and returns a fully-allocated `Segcache` instance.
     ///
     /// ```
@@ -159,15 +493,74 @@ impl Builder {
     ///     .eviction(Policy::Random).build();
     /// ```
     pub fn build(self) -> Result<Segcache, std::io::Error> {
-        let hashtable = HashTable::new(self.hash_power, self.overflow_factor);
+        // evicted items would be written to the disk tier in plaintext
+        if self.disk_path.is_some() && self.segments_builder.encrypted() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "disk tier cannot be used with an encrypted datapool",
+            ));
+        }
+
+        let mut hashtable = HashTable::new(self.hash_power, self.overflow_factor);
+        if let Some(threshold) = self.expand_threshold {
+            hashtable.set_expand_threshold(threshold);
+        }
+        let tags_path = self.segments_builder.tags_path();
         let segments = self.segments_builder.build()?;
-        let ttl_buckets = TtlBuckets::default();
+        let namespaces = Namespaces::new(self.namespaces, segments.cap());
+        let ttl_buckets = match &namespaces {
+            Some(namespaces) => TtlBuckets::with_namespaces(namespaces.len()),
+            None => TtlBuckets::default(),
+        };
+
+        let tier = match self.disk_path {
+            Some(path) => Some(DiskTier::new(
+                path,
+                self.disk_size,
+                self.disk_segment_size,
+                self.disk_eviction,
+            )?),
+            None => None,
+        };
 
-        Ok(Segcache {
+        let mut cache = Segcache {
             hashtable,
             segments,
             ttl_buckets,
             time: Instant::now(),
-        })
+            compression: self.compression,
+            admission: AdmissionFilter::new(self.admission),
+            mrc: MrcEstimator::new(self.miss_ratio_curve),
+            top_keys: TopKeys::new(self.top_keys),
+            expire_budget: self.expire_budget,
+            stale_grace: Duration::from_secs(std::cmp::min(
+                u32::MAX as u64,
+                self.stale_grace.as_secs(),
+            ) as u32),
+            #[cfg(feature = "metrics")]
+            expire_backlog: 0,
+            namespaces,
+            leases: Leases::new(
+                Duration::from_secs(std::cmp::min(
+                    u32::MAX as u64,
+                    self.lease_max_duration.as_secs(),
+                ) as u32),
+                self.lease_max_outstanding,
+            ),
+            tags: Tags::default(),
+            tags_path,
+            purge: None,
+            clear_at: None,
+        };
+
+        // if the segments were restored from an existing datapool, we need to
+        // relink them into the ttl buckets and the hashtable
+        cache.restore(self.checkpoint_path.as_deref());
+
+        if let Some(tier) = tier {
+            cache.hashtable.enable_demotion(tier);
+        }
+
+        Ok(cache)
     }
 }
@@ -0,0 +1,398 @@
+// Copyright 2023 Pelikan Foundation LLC.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Checkpoints contain a copy of the segment headers and the hashtable. They
+//! allow a cache with a file backed datapool to be restored without scanning
+//! every segment to rebuild the hashtable. The segment chains for each
+//! `TtlBucket` are recovered from the links in the segment headers.
+//!
+//! If the datapool is encrypted, the entire checkpoint is encrypted with the
+//! same key.
+//!
+//! The tag invalidations are written to a separate file next to the datapool
+//! each time it is flushed. The file includes a digest of the segment headers
+//! which are written by the same flush, so the invalidations are only restored
+//! along with the datapool they belong to. Items which carry tags are dropped
+//! on restore if the invalidations cannot be restored.
+//!
+//! Checkpoint layout:
+//! ```text
+//! ┌──────────────┬──────────────┬──────────────┬──────────────┐
+//! │    MAGIC     │   VERSION    │   SEGMENTS   │ SEGMENT SIZE │
+//! │              │              │              │              │
+//! │    64 bit    │    64 bit    │    64 bit    │    64 bit    │
+//! ├──────────────┼──────────────┼──────────────┼──────────────┤
+//! │  HASH POWER  │   BUCKETS    │NEXT TO CHAIN │              │
+//! │              │              │              │  SEGMENT     │
+//! │    64 bit    │    64 bit    │    64 bit    │  HEADERS ... │
+//! ├──────────────┴──────────────┴──────────────┼──────────────┤
+//! │                HASH BUCKETS ...            │   CHECKSUM   │
+//! │                                            │              │
+//! │                                            │   256 bit    │
+//! └────────────────────────────────────────────┴──────────────┘
+//! ```
+
+use crate::*;
+use std::io::{BufWriter, Error, ErrorKind, Write};
+use std::path::Path;
+
+const CHECKPOINT_MAGIC: [u8; 8] = *b"SEGCKPT\0";
+
+// magic, version, segments, segment size, hash power, buckets, next to chain
+const CHECKPOINT_HDR_SIZE: usize = 7 * std::mem::size_of::<u64>();
+
+const CHECKSUM_SIZE: usize = blake3::OUT_LEN;
+
+const TAGS_MAGIC: [u8; 8] = *b"SEGTAGS\0";
+
+// magic, version, segment headers digest
+const TAGS_HDR_SIZE: usize = 2 * std::mem::size_of::<u64>() + blake3::OUT_LEN;
+
+impl Segcache {
+    /// Writes a checkpoint of the segment headers and the hashtable to the
+    /// provided path. The datapool is flushed first, so that a cache built with
+    /// the same datapool and checkpoint paths will be restored without needing
+    /// to scan the segments. The checkpoint is only valid until the cache is
+    /// next modified.
+    ///
+    /// Returns an error if the datapool is not backed by a file.
+    ///
+    /// ```
+    /// use segcache::Segcache;
+    /// use std::time::Duration;
+    ///
+    /// let dir = tempfile::tempdir().unwrap();
+    /// let datapool = dir.path().join("datapool");
+    /// let checkpoint = dir.path().join("checkpoint");
+    ///
+    /// let mut cache = Segcache::builder()
+    ///     .datapool_path(Some(&datapool))
+    ///     .build()
+    ///     .expect("failed to create cache");
+    /// cache.insert(b"coffee", b"strong", None, Duration::ZERO);
+    /// cache.checkpoint(&checkpoint).expect("failed to checkpoint");
+    /// drop(cache);
+    ///
+    /// let mut cache = Segcache::builder()
+    ///     .datapool_path(Some(&datapool))
+    ///     .checkpoint_path(Some(&checkpoint))
+    ///     .build()
+    ///     .expect("failed to restore cache");
+    /// assert!(cache.get(b"coffee").is_some());
+    /// ```
+    pub fn checkpoint<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        if !self.segments.persistent() {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "datapool is not backed by a file",
+            ));
+        }
+
+        self.write_tags()?;
+        self.segments.flush()?;
+
+        // the checkpoint holds a single table, so any expansion which is in
+        // progress is completed first
+        self.hashtable.finish_expansion(&mut self.segments);
+
+        let headers = self.segments.headers_bytes();
+        let buckets = self.hashtable.buckets_bytes();
+
+        let mut header = Vec::with_capacity(CHECKPOINT_HDR_SIZE);
+        header.extend_from_slice(&CHECKPOINT_MAGIC);
+        for value in [
+            crate::VERSION,
+            (headers.len() / std::mem::size_of::<SegmentHeader>()) as u64,
+            self.segments.segment_size() as u64,
+            self.hashtable.power() as u64,
+            (buckets.len() / std::mem::size_of::<HashBucket>()) as u64,
+            self.hashtable.next_to_chain(),
+        ] {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&header);
+        hasher.update(headers);
+        hasher.update(buckets);
+
+        // write to a temporary file and rename it, so that an existing
+        // checkpoint is only replaced by a complete one
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+
+        let file = std::fs::File::create(&tmp)?;
+        let mut writer = BufWriter::new(file);
+        if let Some(key) = self.segments.key() {
+            // the checkpoint is encrypted as a whole, so it is assembled in
+            // memory first
+            let mut data =
+                Vec::with_capacity(header.len() + headers.len() + buckets.len() + CHECKSUM_SIZE);
+            data.extend_from_slice(&header);
+            data.extend_from_slice(headers);
+            data.extend_from_slice(buckets);
+            data.extend_from_slice(hasher.finalize().as_bytes());
+            key.encrypt(&mut data)?;
+            writer.write_all(&data)?;
+        } else {
+            writer.write_all(&header)?;
+            writer.write_all(headers)?;
+            writer.write_all(buckets)?;
+            writer.write_all(hasher.finalize().as_bytes())?;
+        }
+
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+
+        std::fs::rename(&tmp, path)
+    }
+
+    /// Restores a cache with segments which were loaded from an existing
+    /// datapool. If a checkpoint exists and matches the datapool, the
+    /// hashtable is loaded from the checkpoint. Otherwise, the segments are
+    /// scanned to rebuild the hashtable. Returns the number of items which were
+    /// restored.
+    pub(crate) fn restore(&mut self, checkpoint: Option<&Path>) -> usize {
+        if !self.segments.restored() {
+            return 0;
+        }
+
+        let restored = match checkpoint
+            .filter(|path| path.exists())
+            .map(|path| self.restore_checkpoint(path))
+        {
+            Some(Ok(items)) => {
+                debug!("restored {} items from checkpoint", items);
+                items
+            }
+            Some(Err(e)) => {
+                warn!("failed to restore from checkpoint: {}", e);
+                self.segments
+                    .restore(&mut self.ttl_buckets, &mut self.hashtable)
+            }
+            None => self
+                .segments
+                .restore(&mut self.ttl_buckets, &mut self.hashtable),
+        };
+
+        let removed = match self.restore_tags() {
+            Ok(()) => 0,
+            Err(e) => {
+                warn!("failed to restore tag invalidations: {}", e);
+                self.remove_tagged()
+            }
+        };
+
+        restored.saturating_sub(removed)
+    }
+
+    /// Writes the tag invalidations to the file next to the datapool. This
+    /// must be called before the datapool is flushed, since the file records a
+    /// digest of the segment headers which the flush will write.
+    pub(crate) fn write_tags(&self) -> Result<(), Error> {
+        let path = match self.tags_path.as_ref() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let tags = self.tags.to_bytes();
+
+        let mut data = Vec::with_capacity(TAGS_HDR_SIZE + tags.len() + CHECKSUM_SIZE);
+        data.extend_from_slice(&TAGS_MAGIC);
+        data.extend_from_slice(&crate::VERSION.to_le_bytes());
+        data.extend_from_slice(blake3::hash(self.segments.headers_bytes()).as_bytes());
+        data.extend_from_slice(&tags);
+        let checksum = blake3::hash(&data);
+        data.extend_from_slice(checksum.as_bytes());
+
+        if let Some(key) = self.segments.key() {
+            key.encrypt(&mut data)?;
+        }
+
+        // write to a temporary file and rename it, so that an existing file is
+        // only replaced by a complete one
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+
+        std::fs::rename(&tmp, path)
+    }
+
+    /// Loads the tag invalidations which were written when the restored
+    /// datapool was flushed. The tags are left unmodified if an error is
+    /// returned.
+    fn restore_tags(&mut self) -> Result<(), Error> {
+        let invalid = |msg| Error::new(ErrorKind::InvalidData, msg);
+
+        let path = self
+            .tags_path
+            .as_ref()
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "datapool is not backed by a file"))?;
+
+        let mut data = std::fs::read(path)?;
+
+        if let Some(key) = self.segments.key() {
+            key.decrypt(&mut data)?;
+        }
+
+        if data.len() < TAGS_HDR_SIZE + CHECKSUM_SIZE {
+            return Err(invalid("tags are truncated"));
+        }
+
+        let (data, checksum) = data.split_at(data.len() - CHECKSUM_SIZE);
+        if blake3::hash(data).as_bytes() != checksum {
+            return Err(invalid("tags checksum mismatch"));
+        }
+
+        let (header, tags) = data.split_at(TAGS_HDR_SIZE);
+        if header[0..8] != TAGS_MAGIC {
+            return Err(invalid("tags have invalid magic"));
+        }
+
+        if header[8..16] != crate::VERSION.to_le_bytes() {
+            return Err(invalid("tags have incompatible version"));
+        }
+
+        if header[16..] != *blake3::hash(self.segments.flushed_headers_bytes()).as_bytes() {
+            return Err(invalid("tags do not match the datapool"));
+        }
+
+        if !self.tags.load(tags) {
+            return Err(invalid("tags are malformed"));
+        }
+
+        Ok(())
+    }
+
+    /// Removes the restored items which carry tags. This is used when the tag
+    /// invalidations could not be restored, since any of the tags may have
+    /// been invalidated before the restart.
+    fn remove_tagged(&mut self) -> usize {
+        let mut tagged = Vec::new();
+        let mut cursor = 0;
+        loop {
+            let (next, items) = self.segments.scan(cursor, 1024, &mut self.hashtable);
+            tagged.extend(
+                items
+                    .iter()
+                    .filter(|item| item.tags().is_some())
+                    .map(|item| item.key().to_vec()),
+            );
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+
+        for key in &tagged {
+            self.hashtable
+                .delete(key, &mut self.ttl_buckets, &mut self.segments);
+        }
+
+        tagged.len()
+    }
+
+    /// Internal function which loads and validates a checkpoint before using
+    /// it to restore the cache. The cache is left unmodified if an error is
+    /// returned.
+    fn restore_checkpoint(&mut self, path: &Path) -> Result<usize, Error> {
+        let invalid = |msg| Error::new(ErrorKind::InvalidData, msg);
+
+        let mut data = std::fs::read(path)?;
+
+        if let Some(key) = self.segments.key() {
+            key.decrypt(&mut data)?;
+        }
+
+        if data.len() < CHECKPOINT_HDR_SIZE + CHECKSUM_SIZE {
+            return Err(invalid("checkpoint is truncated"));
+        }
+
+        let (data, checksum) = data.split_at(data.len() - CHECKSUM_SIZE);
+        if blake3::hash(data).as_bytes() != checksum {
+            return Err(invalid("checkpoint checksum mismatch"));
+        }
+
+        let (header, data) = data.split_at(CHECKPOINT_HDR_SIZE);
+        if header[0..8] != CHECKPOINT_MAGIC {
+            return Err(invalid("checkpoint has invalid magic"));
+        }
+
+        let field = |idx: usize| {
+            let start = idx * std::mem::size_of::<u64>();
+            u64::from_le_bytes(header[start..(start + 8)].try_into().unwrap())
+        };
+
+        let version = field(1);
+        let segments = field(2) as usize;
+        let segment_size = field(3);
+        let power = field(4);
+        let buckets = field(5) as usize;
+        let next_to_chain = field(6);
+
+        if version != crate::VERSION {
+            return Err(invalid("checkpoint has incompatible version"));
+        }
+
+        if segment_size != self.segments.segment_size() as u64 {
+            return Err(invalid("checkpoint segment size does not match"));
+        }
+
+        let headers_size = segments * std::mem::size_of::<SegmentHeader>();
+        let buckets_size = buckets * std::mem::size_of::<HashBucket>();
+
+        // the hashtable may have been expanded before the checkpoint was taken
+        let power =
+            u8::try_from(power).map_err(|_| invalid("checkpoint has invalid hash power"))?;
+        if self.hashtable.buckets_for(power) != Some(buckets) {
+            return Err(invalid("checkpoint hash power does not match"));
+        }
+
+        if data.len() != headers_size + buckets_size || next_to_chain > buckets as u64 {
+            return Err(invalid("checkpoint size does not match"));
+        }
+
+        let (headers, buckets) = data.split_at(headers_size);
+
+        // the segments are validated first, since they will reject a stale
+        // checkpoint before any state is changed
+        let ids = self
+            .segments
+            .restore_checkpoint(headers, &mut self.ttl_buckets)?;
+
+        // the size was checked above, so the buckets will always load
+        self.hashtable.load(power, buckets, next_to_chain).unwrap();
+
+        // drop all items which were stored in segments that expired while the
+        // cache was not running
+        let mut live = vec![false; segments];
+        for id in ids.iter() {
+            live[id.get() as usize - 1] = true;
+        }
+        self.hashtable.retain(&live);
+
+        Ok(ids
+            .iter()
+            .map(|id| self.segments.get_mut(*id).unwrap().live_items() as usize)
+            .sum())
+    }
+}
+
+impl Drop for Segcache {
+    fn drop(&mut self) {
+        if !self.segments.persistent() {
+            return;
+        }
+
+        // the datapool itself is flushed after this, when the segments are
+        // dropped
+        if let Err(e) = self.write_tags() {
+            error!("failed to write tag invalidations: {}", e);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+// Copyright 2023 Pelikan Foundation LLC.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Transparent compression of item values.
+
+/// The compression policy which is applied to item values when they are
+/// inserted. Compressed values are decompressed when they are read, so the
+/// policy only changes how the value is represented within the segments.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Values are stored as provided.
+    #[default]
+    None,
+    /// Values of at least `threshold` bytes are compressed with zstd using the
+    /// provided compression `level`. Values which do not become smaller when
+    /// compressed are stored as provided.
+    Zstd { level: i32, threshold: usize },
+}
+
+impl Compression {
+    /// Compress the value according to the policy. Returns `None` if the value
+    /// should be stored as provided.
+    pub(crate) fn compress(&self, value: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Self::None => None,
+            Self::Zstd { level, threshold } => {
+                if value.len() < *threshold {
+                    return None;
+                }
+
+                match zstd::bulk::compress(value, *level) {
+                    Ok(compressed) if compressed.len() < value.len() => Some(compressed),
+                    Ok(_) => None,
+                    Err(e) => {
+                        warn!("failed to compress value: {}", e);
+                        None
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Decompress a value which was compressed on insert.
+pub(crate) fn decompress(value: &[u8]) -> std::io::Result<Vec<u8>> {
+    zstd::stream::decode_all(value)
+}
@@ -25,4 +25,22 @@ pub enum SegcacheError {
     DataCorrupted,
     #[error("item is not numeric")]
     NotNumeric,
+    #[error("item is not an integer")]
+    NotInteger,
+    #[error("increment or decrement would overflow")]
+    Overflow,
+    #[error("increment would produce NaN or Infinity")]
+    NotFinite,
+    #[error("namespace not found")]
+    UnknownNamespace,
+    #[error("invalid heap size")]
+    InvalidHeapSize,
+    #[error("heap resize failed")]
+    HeapResizeFailed,
+    #[error("lease is not valid")]
+    InvalidLease,
+    #[error("too many tags")]
+    TooManyTags,
+    #[error("item was not admitted")]
+    NotAdmitted,
 }
@@ -0,0 +1,60 @@
+// Copyright 2023 Pelikan Foundation LLC.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use core::num::NonZeroU32;
+
+use crate::SegmentHeader;
+
+/// The action taken to free space for new items, as chosen by an
+/// [`EvictionPolicy`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Decision {
+    /// Evict all of the items in the segment and return it to the free queue.
+    Evict(NonZeroU32),
+    /// Merge the segment with the segments which follow it in the same TTL
+    /// bucket. The least frequently accessed items are evicted, and the
+    /// segments which are emptied are returned to the free queue. Segments
+    /// which hold a large item are evicted instead.
+    Merge(NonZeroU32),
+}
+
+/// A user-defined eviction strategy, which is registered with
+/// [`crate::Builder::custom_eviction`] and replaces the built-in [`Policy`].
+///
+/// When there are no free segments, the policy is given the headers for all of
+/// the segments in the heap, indexed by segment id minus one, and chooses a
+/// segment to evict or merge. The chosen segment must be evictable, as
+/// reported by [`SegmentHeader::can_evict`], otherwise the eviction fails.
+///
+/// ```
+/// use segcache::{Decision, EvictionPolicy, SegmentHeader, Segcache};
+///
+/// // evict the segment with the fewest live bytes
+/// struct LeastUtilized;
+///
+/// impl EvictionPolicy for LeastUtilized {
+///     fn choose(
+///         &mut self,
+///         headers: &[SegmentHeader],
+///         namespace: Option<u16>,
+///     ) -> Option<Decision> {
+///         headers
+///             .iter()
+///             .filter(|h| h.can_evict())
+///             .filter(|h| namespace.map(|ns| h.namespace() == ns).unwrap_or(true))
+///             .min_by_key(|h| h.live_bytes())
+///             .map(|h| Decision::Evict(h.id()))
+///     }
+/// }
+///
+/// let cache = Segcache::builder().custom_eviction(LeastUtilized).build();
+/// ```
+///
+/// [`Policy`]: crate::Policy
+pub trait EvictionPolicy: Send {
+    /// Choose a segment to evict or merge. If a namespace is provided, the
+    /// segment must belong to that namespace. Returning `None` indicates that
+    /// there are no segments which can be evicted.
+    fn choose(&mut self, headers: &[SegmentHeader], namespace: Option<u16>) -> Option<Decision>;
+}
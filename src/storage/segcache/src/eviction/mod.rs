@@ -4,6 +4,7 @@
 
 //! Eviction is used to select a segment to remove when the cache becomes full.
 //! An eviction [`Policy`] determines what data will be evicted from the cache.
+//! The built-in policies may be replaced by a user-defined [`EvictionPolicy`].
 
 use core::cmp::{max, Ordering};
 use core::num::NonZeroU32;
@@ -15,18 +16,24 @@ use crate::segments::*;
 use crate::Random;
 use crate::*;
 
+mod custom;
 mod policy;
 
+pub use custom::{Decision, EvictionPolicy};
 pub use policy::Policy;
 
 /// The `Eviction` struct is used to rank and return segments for eviction. It
 /// implements eviction strategies corresponding to the `Policy`.
 pub struct Eviction {
     policy: Policy,
+    /// A user-defined policy which replaces the `Policy`
+    custom: Option<Box<dyn EvictionPolicy>>,
     last_update_time: Instant,
     ranked_segs: Box<[Option<NonZeroU32>]>,
     index: usize,
     rng: Box<Random>,
+    /// Number of segments used by each namespace
+    pub(crate) namespace_segments: Vec<u32>,
 }
 
 impl Eviction {
@@ -40,18 +47,47 @@ impl Eviction {
 
         Self {
             policy,
+            custom: None,
             last_update_time: Instant::now(),
             ranked_segs,
             index: 0,
             rng: Box::new(rng()),
+            namespace_segments: Vec::new(),
         }
     }
 
+    /// Resize the ranking to handle up to `nseg` segments. The segments are
+    /// ranked again before the next eviction.
+    pub fn resize(&mut self, nseg: usize) {
+        self.ranked_segs = vec![None; nseg].into_boxed_slice();
+        self.index = 0;
+    }
+
     #[inline]
     pub fn policy(&self) -> Policy {
         self.policy
     }
 
+    /// Replace the built-in policy with a user-defined policy.
+    pub fn set_custom(&mut self, policy: Box<dyn EvictionPolicy>) {
+        self.custom = Some(policy);
+    }
+
+    /// Returns true if a user-defined policy is used.
+    #[inline]
+    pub fn is_custom(&self) -> bool {
+        self.custom.is_some()
+    }
+
+    /// Asks the user-defined policy to choose a segment to evict or merge.
+    pub fn choose(
+        &mut self,
+        headers: &[SegmentHeader],
+        namespace: Option<u16>,
+    ) -> Option<Decision> {
+        self.custom.as_mut()?.choose(headers, namespace)
+    }
+
     /// Returns the segment id of the least valuable segment
     pub fn least_valuable_seg(&mut self) -> Option<NonZeroU32> {
         let index = self.index;
@@ -63,6 +99,21 @@ impl Eviction {
         }
     }
 
+    /// Returns the least valuable segment which matches the predicate. Unlike
+    /// `least_valuable_seg()` this does not advance through the ranking, so
+    /// segments which do not match remain available.
+    pub fn least_valuable_seg_matching<F: Fn(NonZeroU32) -> bool>(
+        &self,
+        predicate: F,
+    ) -> Option<NonZeroU32> {
+        self.ranked_segs
+            .get(self.index..)?
+            .iter()
+            .flatten()
+            .copied()
+            .find(|id| predicate(*id))
+    }
+
     /// Returns a random u32
     #[inline]
     pub fn random(&mut self) -> u32 {
@@ -73,6 +73,9 @@ const N_BUCKET_SLOT: usize = 8;
 /// Maximum number of buckets in a chain. Must be <= 255.
 const MAX_CHAIN_LEN: u64 = 16;
 
+/// The hashtable is not expanded beyond this power.
+const MAX_POWER: u8 = 40;
+
 use crate::*;
 use ahash::RandomState;
 use core::marker::PhantomData;
@@ -93,10 +96,10 @@ struct IterState {
 }
 
 impl IterState {
-    fn new(hashtable: &HashTable, hash: u64) -> Self {
-        let bucket_id = (hash & hashtable.mask) as usize;
-        let buckets_len = hashtable.data.len();
-        let bucket = hashtable.data[bucket_id];
+    fn new(data: &[HashBucket], mask: u64, hash: u64) -> Self {
+        let bucket_id = (hash & mask) as usize;
+        let buckets_len = data.len();
+        let bucket = data[bucket_id];
         let chain_len = chain_len(bucket.data[0]) as usize;
 
         Self {
@@ -133,9 +136,15 @@ struct IterMut<'a> {
 
 impl<'a> IterMut<'a> {
     fn new(hashtable: &'a mut HashTable, hash: u64) -> Self {
-        let state = IterState::new(hashtable, hash);
+        let (data, mask) = hashtable.table(hash);
+        Self::with_table(data, mask, hash)
+    }
 
-        let ptr = hashtable.data.as_mut_ptr();
+    /// Iterate over the chain for the hash within the provided buckets.
+    fn with_table(data: &'a mut [HashBucket], mask: u64, hash: u64) -> Self {
+        let state = IterState::new(data, mask, hash);
+
+        let ptr = data.as_mut_ptr();
 
         Self {
             ptr,
@@ -187,17 +196,47 @@ impl<'a> Iterator for IterMut<'a> {
     }
 }
 
+/// Tracks the number of items in the hashtable so that it can be expanded once
+/// the load reaches a threshold, along with the progress of an expansion.
+struct Expansion {
+    /// The load at which the hashtable is expanded, if it may be expanded
+    threshold: Option<f64>,
+    overflow_factor: f64,
+    /// The number of item info entries in the hashtable
+    items: u64,
+    /// The table which is being replaced, while an expansion is in progress
+    previous: Option<Previous>,
+}
+
+/// The buckets of the table which is being replaced by an expansion. Primary
+/// buckets are marked once their entries have been moved into the new table,
+/// and every primary bucket below the cursor has been moved.
+struct Previous {
+    data: Box<[HashBucket]>,
+    mask: u64,
+    cursor: u64,
+}
+
 /// Main structure for performing item lookup. Contains a contiguous allocation
 /// of [`HashBucket`]s which are used to store item info and metadata.
+///
+/// The hashtable may be expanded to double the number of buckets while it is
+/// in use. The entries are moved into the new table a few buckets at a time,
+/// and entries which have not yet been moved are found in the previous table.
 #[repr(C)]
 pub(crate) struct HashTable {
     hash_builder: Box<RandomState>,
-    power: u64,
     mask: u64,
     data: Box<[HashBucket]>,
     started: Instant,
+    power: u8,
     next_to_chain: u64,
-    _pad: [u8; 8],
+    /// The disk tier which evicted items are demoted to, if it is enabled. The
+    /// tier is held here so that items are copied into it directly from the
+    /// segment they are evicted from, and is boxed to keep the size of the
+    /// hashtable unchanged.
+    tier: Option<Box<DiskTier>>,
+    expansion: Box<Expansion>,
 }
 
 impl HashTable {
@@ -237,18 +276,249 @@ impl HashTable {
 
         Self {
             hash_builder: Box::new(hash_builder),
-            power: power.into(),
             mask,
             data: data.into_boxed_slice(),
             started: Instant::now(),
+            power,
             next_to_chain: buckets,
-            _pad: [0; 8],
+            tier: None,
+            expansion: Box::new(Expansion {
+                threshold: None,
+                overflow_factor,
+                items: 0,
+                previous: None,
+            }),
         }
     }
 
+    /// Allow the hashtable to be expanded once the fraction of item slots in
+    /// use reaches the threshold.
+    pub fn set_expand_threshold(&mut self, threshold: f64) {
+        self.expansion.threshold = Some(threshold);
+    }
+
+    /// Returns true if an expansion is in progress.
+    pub fn expanding(&self) -> bool {
+        self.expansion.previous.is_some()
+    }
+
+    /// Returns the current hash power.
+    pub fn power(&self) -> u8 {
+        self.power
+    }
+
+    /// Returns the number of items in the hashtable.
+    #[cfg(test)]
+    pub fn len(&self) -> u64 {
+        self.expansion.items
+    }
+
+    /// Starts an expansion of the hashtable, doubling the number of buckets,
+    /// if the hashtable may be expanded and no expansion is in progress.
+    pub fn expand(&mut self) {
+        if self.expansion.threshold.is_none() || self.expanding() || self.power >= MAX_POWER {
+            return;
+        }
+
+        let buckets = (self.mask + 1) * 2;
+        let total_buckets =
+            (buckets as f64 * (1.0 + self.expansion.overflow_factor)).ceil() as usize;
+
+        debug!(
+            "expanding hashtable to {} primary buckets and {} total buckets",
+            buckets, total_buckets
+        );
+
+        let data = std::mem::replace(
+            &mut self.data,
+            vec![HashBucket::new(); total_buckets].into_boxed_slice(),
+        );
+
+        self.expansion.previous = Some(Previous {
+            data,
+            mask: self.mask,
+            cursor: 0,
+        });
+        self.mask = buckets - 1;
+        self.next_to_chain = buckets;
+        self.power += 1;
+
+        #[cfg(feature = "metrics")]
+        {
+            HASH_EXPAND.increment();
+            HASH_EXPAND_PENDING.add((buckets / 2) as _);
+        }
+    }
+
+    /// Moves up to the provided number of primary buckets from the previous
+    /// table into the new table while an expansion is in progress. Otherwise,
+    /// an expansion is started if the load has reached the threshold.
+    pub fn expand_step(&mut self, buckets: usize, segments: &mut Segments) {
+        let previous = match self.expansion.previous.as_ref() {
+            Some(previous) => previous,
+            None => {
+                if let Some(threshold) = self.expansion.threshold {
+                    let capacity = (self.data.len() * (N_BUCKET_SLOT - 1)) as f64;
+                    if self.expansion.items as f64 >= threshold * capacity {
+                        self.expand();
+                    }
+                }
+                return;
+            }
+        };
+
+        let end = previous.mask + 1;
+        let mut cursor = previous.cursor;
+
+        for _ in 0..buckets {
+            if cursor == end {
+                break;
+            }
+            self.move_bucket(cursor, segments);
+            cursor += 1;
+        }
+
+        if cursor == end {
+            debug!("hashtable expansion finished");
+            self.expansion.previous = None;
+        } else if let Some(previous) = self.expansion.previous.as_mut() {
+            previous.cursor = cursor;
+        }
+    }
+
+    /// Moves every remaining bucket into the new table if an expansion is in
+    /// progress.
+    pub fn finish_expansion(&mut self, segments: &mut Segments) {
+        if self.expanding() {
+            self.expand_step(usize::MAX, segments);
+        }
+    }
+
+    /// Returns the buckets and the mask for the primary buckets of the table
+    /// which holds the entries for the hash.
+    fn table(&mut self, hash: u64) -> (&mut [HashBucket], u64) {
+        if let Some(previous) = self.expansion.previous.as_mut() {
+            let bucket_info = previous.data[(hash & previous.mask) as usize].data[0];
+            if bucket_info & BUCKET_MOVED == 0 {
+                return (&mut previous.data, previous.mask);
+            }
+        }
+        (&mut self.data, self.mask)
+    }
+
+    /// Ensures that the entries for the hash have been moved into the new
+    /// table if an expansion is in progress, so that the primary bucket for
+    /// the hash can be used directly.
+    fn prepare(&mut self, hash: u64, segments: &mut Segments) {
+        if let Some(previous) = self.expansion.previous.as_ref() {
+            self.move_bucket(hash & previous.mask, segments);
+        }
+    }
+
+    /// Moves a primary bucket of the previous table, along with its chain,
+    /// into the new table. The entries are split between the two primary
+    /// buckets in the new table by rehashing the keys. Both buckets keep the
+    /// CAS value and timestamp of the previous bucket. This has no effect if
+    /// the bucket was already moved.
+    fn move_bucket(&mut self, bucket_id: u64, segments: &mut Segments) {
+        let previous = self.expansion.previous.as_mut().unwrap();
+        let bucket_info = previous.data[bucket_id as usize].data[0];
+        if bucket_info & BUCKET_MOVED != 0 {
+            return;
+        }
+        previous.data[bucket_id as usize].data[0] |= BUCKET_MOVED;
+
+        let entries: Vec<u64> = IterMut::with_table(&mut previous.data, previous.mask, bucket_id)
+            .map(|item_info| *item_info)
+            .filter(|item_info| *item_info != 0)
+            .collect();
+
+        let bucket_info = bucket_info & !BUCKET_CHAIN_LEN_MASK;
+        for id in [bucket_id, bucket_id + previous.mask + 1] {
+            self.data[id as usize].data[0] = bucket_info;
+        }
+
+        for item_info in entries {
+            let hash = self.hash(segments.get_item(item_info).unwrap().key());
+            if self.place(hash, item_info).is_err() {
+                // the chain in the new table is full, so the item is dropped
+                if let Some(seg) = get_seg_id(item_info) {
+                    segments
+                        .get_mut(seg)
+                        .unwrap()
+                        .remove_item(clear_freq(item_info));
+                }
+                self.expansion.items -= 1;
+
+                #[cfg(feature = "metrics")]
+                HASH_INSERT_EX.increment();
+            }
+        }
+
+        #[cfg(feature = "metrics")]
+        HASH_EXPAND_PENDING.decrement();
+    }
+
+    /// Places an item info entry into the first empty slot in the chain for
+    /// the hash, extending the chain if there are no empty slots.
+    fn place(&mut self, hash: u64, item_info: u64) -> Result<(), ()> {
+        for slot in IterMut::new(self, hash) {
+            if *slot == 0 {
+                *slot = item_info;
+                return Ok(());
+            }
+        }
+        self.extend_chain(hash, item_info)
+    }
+
+    /// Extends the chain for the hash with a bucket from the overflow area and
+    /// stores the item info entry in it. Returns an error if the chain is at
+    /// its maximum length or if there are no more overflow buckets.
+    fn extend_chain(&mut self, hash: u64, item_info: u64) -> Result<(), ()> {
+        let mut bucket_id = (hash & self.mask) as usize;
+        let chain_len = chain_len(self.data[bucket_id].data[0]);
+
+        if chain_len >= MAX_CHAIN_LEN || (self.next_to_chain as usize) >= self.data.len() {
+            return Err(());
+        }
+
+        // we need to chase through the buckets to get the id of the last
+        // bucket in the chain
+        for _ in 0..chain_len {
+            bucket_id = self.data[bucket_id].data[N_BUCKET_SLOT - 1] as usize;
+        }
+
+        let next_id = self.next_to_chain as usize;
+        self.next_to_chain += 1;
+
+        self.data[next_id].data[0] = self.data[bucket_id].data[N_BUCKET_SLOT - 1];
+        self.data[next_id].data[1] = item_info;
+        self.data[bucket_id].data[N_BUCKET_SLOT - 1] = next_id as u64;
+
+        self.data[(hash & self.mask) as usize].data[0] += 0x0000_0000_0001_0000;
+
+        Ok(())
+    }
+
+    /// Demote each evicted item to the disk tier.
+    pub fn enable_demotion(&mut self, tier: DiskTier) {
+        self.tier = Some(Box::new(tier));
+    }
+
+    /// Returns the disk tier, if it is enabled.
+    pub fn tier(&self) -> Option<&DiskTier> {
+        self.tier.as_deref()
+    }
+
+    /// Returns the disk tier, if it is enabled.
+    pub fn tier_mut(&mut self) -> Option<&mut DiskTier> {
+        self.tier.as_deref_mut()
+    }
+
     /// Lookup an item by key and return it
     pub fn get(&mut self, key: &[u8], time: Instant, segments: &mut Segments) -> Option<Item> {
         let hash = self.hash(key);
+        self.prepare(hash, segments);
         let tag = tag_from_hash(hash);
         let bucket_id = hash & self.mask;
 
@@ -286,11 +556,13 @@ impl HashTable {
                         *item_info = (*item_info & !FREQ_MASK) | freq;
                     }
 
-                    let item = Item::new(
+                    let stale = segments.is_stale(*item_info);
+                    let mut item = Item::new(
                         current_item,
                         get_cas(self.data[(hash & self.mask) as usize].data[0]),
                     );
                     item.check_magic();
+                    item.set_stale(stale);
 
                     return Some(item);
                 }
@@ -305,6 +577,7 @@ impl HashTable {
     /// not want a successful item lookup to count as a hit for that item.
     pub fn get_no_freq_incr(&mut self, key: &[u8], segments: &mut Segments) -> Option<Item> {
         let hash = self.hash(key);
+        self.prepare(hash, segments);
 
         let iter = IterMut::new(self, hash);
 
@@ -317,11 +590,13 @@ impl HashTable {
                     #[cfg(feature = "metrics")]
                     HASH_TAG_COLLISION.increment();
                 } else {
-                    let item = Item::new(
+                    let stale = segments.is_stale(*item_info);
+                    let mut item = Item::new(
                         current_item,
                         get_cas(self.data[(hash & self.mask) as usize].data[0]),
                     );
                     item.check_magic();
+                    item.set_stale(stale);
 
                     return Some(item);
                 }
@@ -331,6 +606,66 @@ impl HashTable {
         None
     }
 
+    /// Lookup the item info for the item with the key. This does not update
+    /// the item frequency.
+    pub(crate) fn item_info(&mut self, key: &[u8], segments: &mut Segments) -> Option<u64> {
+        let hash = self.hash(key);
+        self.prepare(hash, segments);
+        let tag = tag_from_hash(hash);
+
+        let iter = IterMut::new(self, hash);
+
+        for item_info in iter {
+            if get_tag(*item_info) == tag {
+                if segments.get_item(*item_info).unwrap().key() == key {
+                    return Some(*item_info);
+                }
+
+                #[cfg(feature = "metrics")]
+                HASH_TAG_COLLISION.increment();
+            }
+        }
+
+        None
+    }
+
+    /// Points the entry for the key at a new location. Unlike an insert, this
+    /// keeps the item frequency and CAS value. Returns the previous item info
+    /// so that the caller can remove the old copy of the item.
+    #[allow(clippy::result_unit_err)]
+    pub(crate) fn relocate(
+        &mut self,
+        key: &[u8],
+        seg: NonZeroU32,
+        offset: u64,
+        segments: &mut Segments,
+    ) -> Result<u64, ()> {
+        let hash = self.hash(key);
+        self.prepare(hash, segments);
+        let tag = tag_from_hash(hash);
+
+        let iter = IterMut::new(self, hash);
+
+        for item_info in iter {
+            if get_tag(*item_info) == tag {
+                if segments.get_item(*item_info).unwrap().key() == key {
+                    let previous = *item_info;
+                    *item_info = build_item_info(tag, seg, offset) | (previous & FREQ_MASK);
+
+                    #[cfg(feature = "metrics")]
+                    ITEM_RELINK.increment();
+
+                    return Ok(previous);
+                }
+
+                #[cfg(feature = "metrics")]
+                HASH_TAG_COLLISION.increment();
+            }
+        }
+
+        Err(())
+    }
+
     /// Return the frequency for the item with the key
     pub fn get_freq(&mut self, key: &[u8], segment: &mut Segment, offset: u64) -> Option<u64> {
         let hash = self.hash(key);
@@ -403,6 +738,30 @@ impl HashTable {
         false
     }
 
+    /// Returns the item stored at the given segment and offset if the
+    /// hashtable still links its key to that location. Items which were
+    /// deleted or replaced by a newer copy return `None`.
+    pub(crate) fn get_at(&mut self, item: RawItem, seg: NonZeroU32, offset: u64) -> Option<Item> {
+        let hash = self.hash(item.key());
+        let tag = tag_from_hash(hash);
+        let (data, mask) = self.table(hash);
+        let cas = get_cas(data[(hash & mask) as usize].data[0]);
+        let iter = IterMut::new(self, hash);
+
+        for item_info in iter {
+            if get_tag(*item_info) == tag
+                && get_seg_id(*item_info) == Some(seg)
+                && get_offset(*item_info) == offset
+            {
+                let item = Item::new(item, cas);
+                item.check_magic();
+                return Some(item);
+            }
+        }
+
+        None
+    }
+
     /// Inserts a new item into the hashtable. This may fail if the hashtable is
     /// full.
     #[allow(clippy::result_unit_err)]
@@ -419,6 +778,7 @@ impl HashTable {
 
         let hash = self.hash(item.key());
         let tag = tag_from_hash(hash);
+        self.prepare(hash, segments);
 
         // check the item magic
         item.check_magic();
@@ -450,34 +810,17 @@ impl HashTable {
             }
         }
 
+        if insert_item_info != 0 && self.extend_chain(hash, insert_item_info).is_ok() {
+            insert_item_info = 0;
+        }
+
         if let Some(removed_item) = removed {
             #[cfg(feature = "metrics")]
             ITEM_REPLACE.increment();
 
             let _ = segments.remove_item(removed_item, ttl_buckets, self);
-        }
-
-        if insert_item_info != 0 {
-            let mut bucket_id = (hash & self.mask) as usize;
-            let chain_len = chain_len(self.data[bucket_id].data[0]);
-
-            if chain_len < MAX_CHAIN_LEN && (self.next_to_chain as usize) < self.data.len() {
-                // we need to chase through the buckets to get the id of the last
-                // bucket in the chain
-                for _ in 0..chain_len {
-                    bucket_id = self.data[bucket_id].data[N_BUCKET_SLOT - 1] as usize;
-                }
-
-                let next_id = self.next_to_chain as usize;
-                self.next_to_chain += 1;
-
-                self.data[next_id].data[0] = self.data[bucket_id].data[N_BUCKET_SLOT - 1];
-                self.data[next_id].data[1] = insert_item_info;
-                insert_item_info = 0;
-                self.data[bucket_id].data[N_BUCKET_SLOT - 1] = next_id as u64;
-
-                self.data[(hash & self.mask) as usize].data[0] += 0x0000_0000_0001_0000;
-            }
+        } else if insert_item_info == 0 {
+            self.expansion.items += 1;
         }
 
         if insert_item_info == 0 {
@@ -507,6 +850,7 @@ impl HashTable {
     ) -> Result<(), SegcacheError> {
         let hash = self.hash(key);
         let tag = tag_from_hash(hash);
+        self.prepare(hash, segments);
         let bucket_id = hash & self.mask;
 
         let iter = IterMut::new(self, hash);
@@ -552,6 +896,7 @@ impl HashTable {
     ) -> bool {
         let hash = self.hash(key);
         let tag = tag_from_hash(hash);
+        self.prepare(hash, segments);
 
         let iter = IterMut::new(self, hash);
 
@@ -580,6 +925,8 @@ impl HashTable {
             #[cfg(feature = "metrics")]
             ITEM_DELETE.increment();
 
+            self.expansion.items -= 1;
+
             let _ = segments.remove_item(removed_item, ttl_buckets, self);
             true
         } else {
@@ -589,10 +936,28 @@ impl HashTable {
 
     /// Evict a single item from the cache
     pub fn evict(&mut self, key: &[u8], offset: i32, segment: &mut Segment) -> bool {
+        // the item is demoted before it is marked as deleted in the segment
+        let demoted = match (self.tier.as_mut(), segment.get_item_at(offset as usize)) {
+            (Some(tier), Some(item)) => {
+                tier.insert(
+                    &item,
+                    segment.create_at(),
+                    segment.create_at() + segment.ttl(),
+                );
+                true
+            }
+            _ => false,
+        };
+
         let result = self.remove_from(key, offset, segment);
         if result {
             #[cfg(feature = "metrics")]
             ITEM_EVICT.increment();
+        } else if demoted {
+            // the item was not linked, so it must not be promoted either
+            if let Some(tier) = self.tier.as_mut() {
+                tier.remove(key);
+            }
         }
         result
     }
@@ -607,6 +972,21 @@ impl HashTable {
         result
     }
 
+    /// Remove a single item which failed its checksum
+    pub(crate) fn remove_corrupted(
+        &mut self,
+        key: &[u8],
+        offset: i32,
+        segment: &mut Segment,
+    ) -> bool {
+        let result = self.remove_from(key, offset, segment);
+        if result {
+            #[cfg(feature = "metrics")]
+            ITEM_CORRUPTED.increment();
+        }
+        result
+    }
+
     /// Internal function that removes an item from a segment
     fn remove_from(&mut self, key: &[u8], offset: i32, segment: &mut Segment) -> bool {
         let hash = self.hash(key);
@@ -633,6 +1013,7 @@ impl HashTable {
             if evict_item_info == current_item_info {
                 segment.remove_item(current_item_info);
                 *item_info = 0;
+                self.expansion.items -= 1;
                 return true;
             }
         }
@@ -640,6 +1021,101 @@ impl HashTable {
         false
     }
 
+    /// Returns the raw bytes of the hash buckets, used to checkpoint the
+    /// hashtable.
+    pub(crate) fn buckets_bytes(&self) -> &[u8] {
+        // safety: the buckets are plain data and the slice covers exactly the
+        // memory occupied by the buckets
+        unsafe {
+            std::slice::from_raw_parts(
+                self.data.as_ptr() as *const u8,
+                std::mem::size_of_val(&*self.data),
+            )
+        }
+    }
+
+    /// Returns the id of the next bucket which will be used to extend a bucket
+    /// chain.
+    pub(crate) fn next_to_chain(&self) -> u64 {
+        self.next_to_chain
+    }
+
+    /// Returns the total number of buckets in a hashtable with the given
+    /// power and the same overflow factor as this one. Returns `None` if the
+    /// hashtable cannot be loaded from a checkpoint taken at that power, which
+    /// must be at least the current power since the hashtable only grows.
+    pub(crate) fn buckets_for(&self, power: u8) -> Option<usize> {
+        if power < self.power || power > MAX_POWER || self.expanding() {
+            return None;
+        }
+
+        let buckets = 1_u64 << (power - 3);
+        Some((buckets as f64 * (1.0 + self.expansion.overflow_factor)).ceil() as usize)
+    }
+
+    /// Replaces the hash buckets with the raw bytes from a checkpoint of a
+    /// hashtable with the given power. If the hashtable was expanded before
+    /// the checkpoint was taken, this hashtable is resized to match. Returns an
+    /// error if the checkpoint does not match a hashtable of that power.
+    pub(crate) fn load(&mut self, power: u8, buckets: &[u8], next_to_chain: u64) -> Result<(), ()> {
+        let total_buckets = self.buckets_for(power).ok_or(())?;
+
+        if buckets.len() != total_buckets * std::mem::size_of::<HashBucket>()
+            || next_to_chain > total_buckets as u64
+        {
+            return Err(());
+        }
+
+        if power != self.power {
+            debug!("resizing hashtable to hash power {} from checkpoint", power);
+
+            self.data = vec![HashBucket::new(); total_buckets].into_boxed_slice();
+            self.mask = (1_u64 << (power - 3)) - 1;
+            self.power = power;
+        }
+
+        // safety: the length is checked above and the buckets are plain data
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                buckets.as_ptr(),
+                self.data.as_mut_ptr() as *mut u8,
+                buckets.len(),
+            );
+        }
+        self.next_to_chain = next_to_chain;
+
+        Ok(())
+    }
+
+    /// Removes all item info entries which do not point into one of the live
+    /// segments. Used to drop items from segments which were freed while
+    /// restoring from a checkpoint. The number of items in the hashtable is
+    /// recounted.
+    pub(crate) fn retain(&mut self, live: &[bool]) {
+        let mut items = 0;
+
+        for bucket_id in 0..=self.mask {
+            for item_info in IterMut::new(self, bucket_id) {
+                if *item_info == 0 {
+                    continue;
+                }
+
+                let retain = get_seg_id(*item_info)
+                    .and_then(|id| live.get(id.get() as usize - 1))
+                    .copied()
+                    .unwrap_or(false);
+
+                if retain {
+                    items += 1;
+                } else {
+                    *item_info = 0;
+                }
+            }
+        }
+
+        self.expansion.items = items;
+    }
+
     /// Internal function used to calculate a hash value for a key
     fn hash(&self, key: &[u8]) -> u64 {
         #[cfg(feature = "metrics")]
@@ -7,25 +7,33 @@
 //!
 //! Item Header:
 //! ```text
-//! ┌──────────────────────────────┬──────────────────────┬──────┬──────┐
-//! │      MAGIC (Optional)        │         VLEN         │ KLEN │FLAGS │
-//! │                              │                      │      │      │
-//! │            32 bit            │        24 bit        │8 bit │ 8bit │
-//! │          0xDECAFBAD          │                      │      │      │
-//! │0                           31│32                  55│56  63│64  71│
-//! └──────────────────────────────┴──────────────────────┴──────┴──────┘
+//! ┌──────────────────────────────┬──────────────────────┬──────┬───────┐
+//! │      MAGIC (Optional)        │         VLEN         │ KLEN │ FLAGS │
+//! │                              │                      │      │       │
+//! │            32 bit            │        24 bit        │8 bit │16 bit │
+//! │          0xDECAFBAD          │                      │      │       │
+//! │0                           31│32                  55│56  63│64   79│
+//! └──────────────────────────────┴──────────────────────┴──────┴───────┘
 //! ```
 //!
+//! If the `checksum` feature is enabled, a 32 bit checksum of the item follows
+//! the magic and precedes the lengths. Bit offsets below are given without the
+//! checksum.
+//!
 //! Flags:
 //! ```text
-//! ┌──────────────┬──────────────┬──────────────────────────────┐
-//! │    TYPED?    │   PADDING    │             OLEN             │
-//! │              │              │                              │
-//! │    1 bit     │    1 bit     │            6 bit             │
-//! │              │              │                              │
-//! │      64      │      65      │  66                      71  │
-//! └──────────────┴──────────────┴──────────────────────────────┘
+//! ┌──────────────┬──────────────┬──────────────┬──────────────┬─────────────┬─────────────┬─────────────┐
+//! │   PADDING    │  NO EXPIRE?  │   TAGGED?    │ COMPRESSED?  │   TYPED?    │  DELETED?   │    OLEN     │
+//! │              │              │              │              │             │             │             │
+//! │    5 bit     │    1 bit     │    1 bit     │    1 bit     │    1 bit    │    1 bit    │    6 bit    │
+//! │              │              │              │              │             │             │             │
+//! │  64      68  │      69      │      70      │      71      │     72      │     73      │  74     79  │
+//! └──────────────┴──────────────┴──────────────┴──────────────┴─────────────┴─────────────┴─────────────┘
 //! ```
+//!
+//! The optional data follows the header. If the item is tagged, the optional
+//! data is followed by the encoded tags, which begin with the number of tags.
+//! The key and value follow the optional data and tags.
 
 // item constants
 
@@ -54,6 +62,9 @@ const VLEN_MASK: u32 = 0xFFFFFF00;
 /// mask to get the actual value length
 const VLEN_SHIFT: u32 = 8;
 
+/// The maximum length of an item value in bytes
+pub const ITEM_MAX_VLEN: usize = (u32::MAX >> VLEN_SHIFT) as usize;
+
 /// The number of bits to shift the length field masked with the value length
 /// mask to get the value type. This is only valid if typed bit is set!!!
 const TYPE_MASK: u32 = 0xFF000000;
@@ -62,22 +73,39 @@ const TYPE_SHIFT: u32 = 24;
 // olen/del/typed
 /// A mask to get the optional data length in bytes from the item header's flags
 /// field
-const OLEN_MASK: u8 = 0b00111111;
+const OLEN_MASK: u16 = 0b0000_0000_0011_1111;
 /// A mask to get the bit indicating the item value should be treated as a
 /// typed value from the item header's flags field
-const TYPED_MASK: u8 = 0b10000000;
+const TYPED_MASK: u16 = 0b0000_0000_1000_0000;
+/// A mask to get the bit indicating the item has been removed and should not
+/// be restored when scanning the segment
+const DELETED_MASK: u16 = 0b0000_0000_0100_0000;
+/// A mask to get the bit indicating the item carries tags
+const TAGGED_MASK: u16 = 0b0000_0010_0000_0000;
+/// A mask to get the bit indicating the item value has been compressed
+const COMPRESSED_MASK: u16 = 0b0000_0001_0000_0000;
+/// A mask to get the bit indicating the item was stored without a TTL from the
+/// item header's flags field
+const NO_EXPIRE_MASK: u16 = 0b0000_0100_0000_0000;
+
+/// The maximum length of the optional data in bytes
+pub const ITEM_MAX_OLEN: usize = OLEN_MASK as usize;
 
 use core::convert::TryFrom;
 
 #[derive(Copy, Clone, Debug)]
 pub(super) enum ValueType {
     U64,
+    I64,
+    F64,
 }
 
 impl ValueType {
     pub fn len(&self) -> u32 {
         (match self {
             Self::U64 => std::mem::size_of::<u64>(),
+            Self::I64 => std::mem::size_of::<i64>(),
+            Self::F64 => std::mem::size_of::<f64>(),
         }) as u32
     }
 }
@@ -87,6 +115,8 @@ impl TryFrom<u8> for ValueType {
     fn try_from(other: u8) -> Result<Self, <Self as TryFrom<u8>>::Error> {
         match other {
             0 => Ok(Self::U64),
+            1 => Ok(Self::I64),
+            2 => Ok(Self::F64),
             _ => Err(()),
         }
     }
@@ -97,6 +127,8 @@ impl Into<u8> for ValueType {
     fn into(self) -> u8 {
         match self {
             Self::U64 => 0,
+            Self::I64 => 1,
+            Self::F64 => 2,
         }
     }
 }
@@ -111,8 +143,10 @@ impl Into<u8> for ValueType {
 pub struct ItemHeader {
     #[cfg(feature = "magic")]
     magic: u32,
-    len: u32,  // packs vlen:24 klen:8
-    flags: u8, // packs is_num:1, deleted:1, olen:6
+    #[cfg(feature = "checksum")]
+    checksum: u32,
+    len: u32,   // packs vlen:24 klen:8
+    flags: u16, // packs tagged:1, compressed:1, is_num:1, deleted:1, olen:6
 }
 
 impl ItemHeader {
@@ -137,6 +171,30 @@ impl ItemHeader {
         assert_eq!(self.magic(), ITEM_MAGIC);
     }
 
+    /// Get the checksum stored in the header
+    #[cfg(feature = "checksum")]
+    #[inline]
+    pub fn checksum(&self) -> u32 {
+        self.checksum
+    }
+
+    /// Store the checksum in the header
+    #[cfg(feature = "checksum")]
+    #[inline]
+    pub fn set_checksum(&mut self, checksum: u32) {
+        self.checksum = checksum;
+    }
+
+    /// Returns the bytes of the header which are covered by the checksum. The
+    /// deleted flag is excluded, since it is set after the item is written.
+    #[cfg(feature = "checksum")]
+    #[inline]
+    pub fn checksummed(&self) -> [u8; 6] {
+        let len = self.len.to_le_bytes();
+        let flags = (self.flags & !DELETED_MASK).to_le_bytes();
+        [len[0], len[1], len[2], len[3], flags[0], flags[1]]
+    }
+
     /// Get the item's key length
     #[inline]
     pub fn klen(&self) -> u8 {
@@ -156,7 +214,7 @@ impl ItemHeader {
     /// get the optional data length
     #[inline]
     pub fn olen(&self) -> u8 {
-        self.flags & OLEN_MASK
+        (self.flags & OLEN_MASK) as u8
     }
 
     /// Is the item a typed value?
@@ -165,6 +223,60 @@ impl ItemHeader {
         self.flags & TYPED_MASK != 0
     }
 
+    /// Has the item been removed?
+    #[inline]
+    pub fn is_deleted(&self) -> bool {
+        self.flags & DELETED_MASK != 0
+    }
+
+    /// Mark the item as removed. Since removed items remain in the segment
+    /// until it is compacted or cleared, this allows the segment to be scanned
+    /// without consulting the hashtable.
+    #[inline]
+    pub fn set_deleted(&mut self) {
+        self.flags |= DELETED_MASK;
+    }
+
+    /// Is the item value compressed?
+    #[inline]
+    pub fn is_compressed(&self) -> bool {
+        self.flags & COMPRESSED_MASK != 0
+    }
+
+    /// Mark the item value as compressed.
+    #[inline]
+    pub fn set_compressed(&mut self) {
+        self.flags |= COMPRESSED_MASK;
+    }
+
+    /// Does the item carry tags?
+    #[inline]
+    pub fn is_tagged(&self) -> bool {
+        self.flags & TAGGED_MASK != 0
+    }
+
+    /// Mark the item as carrying tags.
+    #[inline]
+    pub fn set_tagged(&mut self) {
+        self.flags |= TAGGED_MASK;
+    }
+
+    /// Was the item stored without a TTL?
+    #[inline]
+    pub fn no_expire(&self) -> bool {
+        self.flags & NO_EXPIRE_MASK != 0
+    }
+
+    /// Set whether the item was stored without a TTL.
+    #[inline]
+    pub fn set_no_expire(&mut self, no_expire: bool) {
+        if no_expire {
+            self.flags |= NO_EXPIRE_MASK;
+        } else {
+            self.flags &= !NO_EXPIRE_MASK;
+        }
+    }
+
     pub(super) fn value_type(&self) -> Option<ValueType> {
         if self.is_typed() {
             if let Ok(t) = ValueType::try_from((self.len >> TYPE_SHIFT) as u8) {
@@ -217,6 +329,11 @@ impl ItemHeader {
         #[cfg(feature = "magic")]
         self.set_magic();
 
+        #[cfg(feature = "checksum")]
+        {
+            self.checksum = 0;
+        }
+
         self.len = 0;
         self.flags = 0;
     }
@@ -224,8 +341,8 @@ impl ItemHeader {
     /// Set the optional length
     #[inline]
     pub fn set_olen(&mut self, len: u8) {
-        debug_assert!(len <= OLEN_MASK);
-        self.flags = (self.flags & !OLEN_MASK) | len;
+        assert!(len as usize <= ITEM_MAX_OLEN, "optional data is too long");
+        self.flags = (self.flags & !OLEN_MASK) | len as u16;
     }
 }
 
@@ -236,6 +353,8 @@ impl std::fmt::Debug for ItemHeader {
             .field("klen", &self.klen())
             .field("vlen", &self.vlen())
             .field("type", &self.value_type())
+            .field("deleted", &self.is_deleted())
+            .field("tagged", &self.is_tagged())
             .field("olen", &self.olen())
             .finish()
     }
@@ -250,6 +369,8 @@ impl std::fmt::Debug for ItemHeader {
             .field("klen", &self.klen())
             .field("vlen", &self.vlen())
             .field("typed", &self.is_typed())
+            .field("deleted", &self.is_deleted())
+            .field("tagged", &self.is_tagged())
             .field("olen", &self.olen())
             .finish()
     }
@@ -13,21 +13,45 @@ pub(crate) use header::ITEM_MAGIC_SIZE;
 
 use crate::SegcacheError;
 use crate::Value;
+use std::cell::OnceCell;
 
-pub(crate) use header::{ItemHeader, ITEM_HDR_SIZE};
+pub(crate) use header::{ItemHeader, ITEM_HDR_SIZE, ITEM_MAX_OLEN, ITEM_MAX_VLEN};
 pub(crate) use raw::RawItem;
 pub(crate) use reserved::ReservedItem;
 
+/// The remaining lifetime of an item, as returned by
+/// [`Segcache::ttl`](crate::Segcache::ttl).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Expiry {
+    /// The item was stored without a TTL. It is kept until it is evicted,
+    /// or until the segment holding it reaches the longest TTL.
+    Never,
+    /// The item expires after the duration.
+    After(std::time::Duration),
+}
+
 /// Items are the base unit of data stored within the cache.
 pub struct Item {
     cas: u32,
     raw: RawItem,
+    decompressed: OnceCell<Box<[u8]>>,
+    stale: bool,
 }
 
 impl Item {
     /// Creates a new `Item` from its parts
     pub(crate) fn new(raw: RawItem, cas: u32) -> Self {
-        Item { cas, raw }
+        Item {
+            cas,
+            raw,
+            decompressed: OnceCell::new(),
+            stale: false,
+        }
+    }
+
+    /// Marks the item as stale.
+    pub(crate) fn set_stale(&mut self, stale: bool) {
+        self.stale = stale;
     }
 
     /// If the `magic` or `debug` features are enabled, this allows for checking
@@ -46,9 +70,48 @@ impl Item {
         self.raw.key()
     }
 
-    /// Borrow the item value
+    /// Borrow the item value. If the value was compressed when it was stored,
+    /// it is decompressed the first time it is borrowed. Items returned by
+    /// lookups have already been decompressed, so only items from a scan can
+    /// fall back to an empty value if decompression fails.
     pub fn value(&self) -> Value {
-        self.raw.value()
+        if !self.raw.is_compressed() {
+            return self.raw.value();
+        }
+
+        if !self.decompress() {
+            return Value::Bytes(&[]);
+        }
+
+        Value::Bytes(self.decompressed.get().unwrap())
+    }
+
+    /// Decompresses the value if it was compressed when it was stored. Returns
+    /// false if the value could not be decompressed.
+    pub(crate) fn decompress(&self) -> bool {
+        if !self.raw.is_compressed() || self.decompressed.get().is_some() {
+            return true;
+        }
+
+        let compressed = match self.raw.value() {
+            Value::Bytes(bytes) => bytes,
+            _ => unreachable!("numeric values are never compressed"),
+        };
+        match crate::compression::decompress(compressed) {
+            Ok(value) => {
+                let _ = self.decompressed.set(value.into_boxed_slice());
+                true
+            }
+            Err(e) => {
+                error!("failed to decompress value: {}", e);
+                false
+            }
+        }
+    }
+
+    /// The number of bytes used to store the item
+    pub(crate) fn size(&self) -> usize {
+        self.raw.size()
     }
 
     /// CAS value for the item
@@ -56,11 +119,22 @@ impl Item {
         self.cas
     }
 
+    /// Returns true if the item has passed its TTL and is only being served
+    /// during the grace period. A stale item should be refreshed.
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
     /// Borrow the optional data
     pub fn optional(&self) -> Option<&[u8]> {
         self.raw.optional()
     }
 
+    /// Borrow the encoded tags, if the item carries tags
+    pub(crate) fn tags(&self) -> Option<&[u8]> {
+        self.raw.tags()
+    }
+
     /// Perform a wrapping addition on the value. Returns an error if the item
     /// is not a numeric type.
     pub fn wrapping_add(&mut self, rhs: u64) -> Result<(), SegcacheError> {
@@ -72,6 +146,20 @@ impl Item {
     pub fn saturating_sub(&mut self, rhs: u64) -> Result<(), SegcacheError> {
         self.raw.saturating_sub(rhs)
     }
+
+    /// Add a signed delta to an integer value. Returns an error if the item
+    /// is not an integer or if the result would overflow, in which case the
+    /// value is unchanged.
+    pub fn checked_add(&mut self, rhs: i64) -> Result<(), SegcacheError> {
+        self.raw.checked_add(rhs)
+    }
+
+    /// Add a delta to a numeric value, which is stored as a float afterwards.
+    /// Returns an error if the item is not numeric or if the result would be
+    /// NaN or infinite, in which case the value is unchanged.
+    pub fn checked_add_float(&mut self, rhs: f64) -> Result<(), SegcacheError> {
+        self.raw.checked_add_float(rhs)
+    }
 }
 
 impl std::fmt::Debug for Item {
@@ -79,6 +167,7 @@ impl std::fmt::Debug for Item {
         f.debug_struct("Item")
             .field("cas", &self.cas())
             .field("raw", &self.raw)
+            .field("stale", &self.stale)
             .finish()
     }
 }
@@ -87,5 +176,7 @@ pub fn size_of(value: &Value) -> usize {
     match value {
         Value::Bytes(v) => v.len(),
         Value::U64(_) => core::mem::size_of::<u64>(),
+        Value::I64(_) => core::mem::size_of::<i64>(),
+        Value::F64(_) => core::mem::size_of::<f64>(),
     }
 }
@@ -72,12 +72,10 @@ impl RawItem {
             std::slice::from_raw_parts(ptr, len)
         };
 
-        // TODO(bmartin): consider allowing other return types here by encoding
-        // the type in the vlen portion of the header
         match self.header().value_type() {
-            Some(ValueType::U64) => Value::U64(u64::from_be_bytes([
-                bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
-            ])),
+            Some(ValueType::U64) => Value::U64(u64::from_be_bytes(word(bytes))),
+            Some(ValueType::I64) => Value::I64(i64::from_be_bytes(word(bytes))),
+            Some(ValueType::F64) => Value::F64(f64::from_be_bytes(word(bytes))),
             None => Value::Bytes(bytes),
         }
     }
@@ -101,14 +99,120 @@ impl RawItem {
         }
     }
 
+    /// Returns the length of the encoded tags, which is zero if the item does
+    /// not carry tags. The first byte of the encoded tags is the number of
+    /// tags.
+    #[inline]
+    fn tags_len(&self) -> usize {
+        if self.header().is_tagged() {
+            let count = unsafe { *self.data.add(self.tags_offset()) };
+            crate::tags::encoded_len(count as usize)
+        } else {
+            0
+        }
+    }
+
+    /// Borrow the encoded tags
+    pub(crate) fn tags(&self) -> Option<&[u8]> {
+        if self.header().is_tagged() {
+            unsafe {
+                let ptr = self.data.add(self.tags_offset());
+                Some(std::slice::from_raw_parts(ptr, self.tags_len()))
+            }
+        } else {
+            None
+        }
+    }
+
     /// Check the header magic bytes
     #[inline]
     pub(crate) fn check_magic(&self) {
         self.header().check_magic()
     }
 
+    /// Returns true if the item has been marked as removed
+    #[inline]
+    pub(crate) fn is_deleted(&self) -> bool {
+        self.header().is_deleted()
+    }
+
+    /// Mark the item as removed
+    #[inline]
+    pub(crate) fn set_deleted(&mut self) {
+        unsafe { (*self.header_mut()).set_deleted() }
+    }
+
+    /// Is the item value compressed?
+    #[inline]
+    pub(crate) fn is_compressed(&self) -> bool {
+        self.header().is_compressed()
+    }
+
+    /// Mark the item value as compressed
+    #[inline]
+    pub(crate) fn set_compressed(&mut self) {
+        unsafe { (*self.header_mut()).set_compressed() }
+        self.set_checksum();
+    }
+
+    /// Was the item stored without a TTL?
+    #[inline]
+    pub(crate) fn no_expire(&self) -> bool {
+        self.header().no_expire()
+    }
+
+    /// Set whether the item was stored without a TTL
+    #[inline]
+    pub(crate) fn set_no_expire(&mut self, no_expire: bool) {
+        unsafe { (*self.header_mut()).set_no_expire(no_expire) }
+        self.set_checksum();
+    }
+
+    /// Calculate the checksum of the item. It covers the lengths and flags
+    /// from the header along with the optional data, tags, key, and value.
+    #[cfg(feature = "checksum")]
+    fn calculate_checksum(&self) -> u32 {
+        let len =
+            self.olen() as usize + self.tags_len() + self.klen() as usize + self.vlen() as usize;
+        let data = unsafe { std::slice::from_raw_parts(self.data.add(ITEM_HDR_SIZE), len) };
+
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&self.header().checksummed());
+        hasher.update(data);
+        let hash = hasher.finalize();
+        let bytes = hash.as_bytes();
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    /// Store the checksum of the item in the header. This must be called
+    /// whenever the item is changed in place. It does nothing unless the
+    /// `checksum` feature is enabled.
+    #[inline]
+    pub(crate) fn set_checksum(&mut self) {
+        #[cfg(feature = "checksum")]
+        unsafe {
+            let checksum = self.calculate_checksum();
+            (*self.header_mut()).set_checksum(checksum);
+        }
+    }
+
+    /// Returns false if the item does not match the checksum in its header.
+    /// Items are always considered intact unless the `checksum` feature is
+    /// enabled.
+    #[inline]
+    pub(crate) fn is_intact(&self) -> bool {
+        #[cfg(feature = "checksum")]
+        {
+            self.header().checksum() == self.calculate_checksum()
+        }
+        #[cfg(not(feature = "checksum"))]
+        {
+            true
+        }
+    }
+
     /// Copy data into the item
-    pub(crate) fn define(&mut self, key: &[u8], value: Value, optional: &[u8]) {
+    pub(crate) fn define(&mut self, key: &[u8], value: Value, optional: &[u8], tags: &[u8]) {
         unsafe {
             (*self.header_mut()).init();
         }
@@ -121,6 +225,7 @@ impl RawItem {
                     self.data.add(self.optional_offset()),
                     optional.len(),
                 );
+                self.define_tags(tags);
                 (*self.header_mut()).set_klen(key.len() as u8);
                 std::ptr::copy_nonoverlapping(
                     key.as_ptr(),
@@ -135,27 +240,56 @@ impl RawItem {
                 );
             },
             Value::U64(value) => unsafe {
-                (*self.header_mut()).set_type(Some(ValueType::U64));
-                (*self.header_mut()).set_olen(optional.len() as u8);
-                std::ptr::copy_nonoverlapping(
-                    optional.as_ptr(),
-                    self.data.add(self.optional_offset()),
-                    optional.len(),
-                );
-                (*self.header_mut()).set_klen(key.len() as u8);
-                std::ptr::copy_nonoverlapping(
-                    key.as_ptr(),
-                    self.data.add(self.key_offset()),
-                    key.len(),
-                );
-                let bytes = value.to_be_bytes();
-                std::ptr::copy_nonoverlapping(
-                    bytes.as_ptr(),
-                    self.data.add(self.value_offset()),
-                    bytes.len(),
-                );
+                self.define_typed(key, ValueType::U64, value.to_be_bytes(), optional, tags);
+            },
+            Value::I64(value) => unsafe {
+                self.define_typed(key, ValueType::I64, value.to_be_bytes(), optional, tags);
+            },
+            Value::F64(value) => unsafe {
+                self.define_typed(key, ValueType::F64, value.to_be_bytes(), optional, tags);
             },
         }
+        self.set_checksum();
+    }
+
+    /// Copy a typed value into the item. The value is stored as big-endian
+    /// bytes and the type is recorded in the header.
+    unsafe fn define_typed(
+        &mut self,
+        key: &[u8],
+        value_type: ValueType,
+        value: [u8; 8],
+        optional: &[u8],
+        tags: &[u8],
+    ) {
+        (*self.header_mut()).set_type(Some(value_type));
+        (*self.header_mut()).set_olen(optional.len() as u8);
+        std::ptr::copy_nonoverlapping(
+            optional.as_ptr(),
+            self.data.add(self.optional_offset()),
+            optional.len(),
+        );
+        self.define_tags(tags);
+        (*self.header_mut()).set_klen(key.len() as u8);
+        std::ptr::copy_nonoverlapping(key.as_ptr(), self.data.add(self.key_offset()), key.len());
+        std::ptr::copy_nonoverlapping(
+            value.as_ptr(),
+            self.data.add(self.value_offset()),
+            value.len(),
+        );
+    }
+
+    /// Copy the encoded tags into the item, which must follow the optional
+    /// data
+    unsafe fn define_tags(&mut self, tags: &[u8]) {
+        if !tags.is_empty() {
+            (*self.header_mut()).set_tagged();
+            std::ptr::copy_nonoverlapping(
+                tags.as_ptr(),
+                self.data.add(self.tags_offset()),
+                tags.len(),
+            );
+        }
     }
 
     // Gets the offset to the optional data
@@ -164,10 +298,16 @@ impl RawItem {
         ITEM_HDR_SIZE
     }
 
+    // Gets the offset to the encoded tags
+    #[inline]
+    fn tags_offset(&self) -> usize {
+        self.optional_offset() + self.olen() as usize
+    }
+
     // Gets the offset to the key
     #[inline]
     fn key_offset(&self) -> usize {
-        self.optional_offset() + self.olen() as usize
+        self.tags_offset() + self.tags_len()
     }
 
     // Gets the offset to the value
@@ -176,9 +316,31 @@ impl RawItem {
         self.key_offset() + self.klen() as usize
     }
 
+    /// Borrow the bytes of the entire item, including the header
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.data, self.size()) }
+    }
+
+    /// Overwrite the item with the bytes of another item. This is used to
+    /// relocate an item without redefining it.
+    ///
+    /// # Safety
+    ///
+    /// The bytes must have been taken from a valid item and the allocation
+    /// backing this item must be at least as large as the source item.
+    pub(crate) fn copy_from(&mut self, bytes: &[u8]) {
+        unsafe {
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), self.data, bytes.len());
+        }
+    }
+
     /// Returns item size, rounded up for alignment
     pub(crate) fn size(&self) -> usize {
-        (((ITEM_HDR_SIZE + self.olen() as usize + self.klen() as usize + self.vlen() as usize)
+        (((ITEM_HDR_SIZE
+            + self.olen() as usize
+            + self.tags_len()
+            + self.klen() as usize
+            + self.vlen() as usize)
             >> 3)
             + 1)
             << 3
@@ -186,33 +348,74 @@ impl RawItem {
 
     pub(crate) fn wrapping_add(&mut self, rhs: u64) -> Result<(), SegcacheError> {
         match self.value() {
-            Value::U64(v) => unsafe {
-                let new = v.wrapping_add(rhs);
-                std::ptr::copy_nonoverlapping(
-                    new.to_be_bytes().as_ptr(),
-                    self.data.add(self.value_offset()),
-                    core::mem::size_of::<u64>(),
-                );
+            Value::U64(v) => {
+                self.set_typed_value(ValueType::U64, v.wrapping_add(rhs).to_be_bytes());
                 Ok(())
-            },
+            }
             _ => Err(SegcacheError::NotNumeric),
         }
     }
 
     pub(crate) fn saturating_sub(&mut self, rhs: u64) -> Result<(), SegcacheError> {
         match self.value() {
-            Value::U64(v) => unsafe {
-                let new = v.saturating_sub(rhs);
-                std::ptr::copy_nonoverlapping(
-                    new.to_be_bytes().as_ptr(),
-                    self.data.add(self.value_offset()),
-                    core::mem::size_of::<u64>(),
-                );
+            Value::U64(v) => {
+                self.set_typed_value(ValueType::U64, v.saturating_sub(rhs).to_be_bytes());
                 Ok(())
-            },
+            }
             _ => Err(SegcacheError::NotNumeric),
         }
     }
+
+    pub(crate) fn checked_add(&mut self, rhs: i64) -> Result<(), SegcacheError> {
+        let v = match self.value() {
+            Value::I64(v) => v,
+            Value::U64(v) => i64::try_from(v).map_err(|_| SegcacheError::Overflow)?,
+            Value::F64(_) => return Err(SegcacheError::NotInteger),
+            Value::Bytes(_) => return Err(SegcacheError::NotNumeric),
+        };
+
+        let new = v.checked_add(rhs).ok_or(SegcacheError::Overflow)?;
+        self.set_typed_value(ValueType::I64, new.to_be_bytes());
+        Ok(())
+    }
+
+    pub(crate) fn checked_add_float(&mut self, rhs: f64) -> Result<(), SegcacheError> {
+        let v = match self.value() {
+            Value::F64(v) => v,
+            Value::I64(v) => v as f64,
+            Value::U64(v) => v as f64,
+            Value::Bytes(_) => return Err(SegcacheError::NotNumeric),
+        };
+
+        let new = v + rhs;
+        if !new.is_finite() {
+            return Err(SegcacheError::NotFinite);
+        }
+
+        self.set_typed_value(ValueType::F64, new.to_be_bytes());
+        Ok(())
+    }
+
+    /// Overwrite a typed value in place. All typed values have the same
+    /// length, so the type may change.
+    fn set_typed_value(&mut self, value_type: ValueType, value: [u8; 8]) {
+        unsafe {
+            (*self.header_mut()).set_type(Some(value_type));
+            std::ptr::copy_nonoverlapping(
+                value.as_ptr(),
+                self.data.add(self.value_offset()),
+                value.len(),
+            );
+        }
+        self.set_checksum();
+    }
+}
+
+/// Returns the first eight bytes of a typed value.
+fn word(bytes: &[u8]) -> [u8; 8] {
+    [
+        bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+    ]
 }
 
 impl std::fmt::Debug for RawItem {
@@ -24,9 +24,14 @@ impl ReservedItem {
         Self { item, seg, offset }
     }
 
-    /// Store the key, value, and optional data into the item
-    pub fn define(&mut self, key: &[u8], value: Value, optional: &[u8]) {
-        self.item.define(key, value, optional)
+    /// Store the key, value, optional data, and encoded tags into the item
+    pub fn define(&mut self, key: &[u8], value: Value, optional: &[u8], tags: &[u8]) {
+        self.item.define(key, value, optional, tags)
+    }
+
+    /// Store the bytes of an existing item into the item
+    pub fn copy_from(&mut self, bytes: &[u8]) {
+        self.item.copy_from(bytes)
     }
 
     /// Get the `RawItem` that backs the `ReservedItem`
@@ -0,0 +1,147 @@
+// Copyright 2023 Pelikan Foundation LLC.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Leases which prevent a thundering herd of clients from refilling a key
+//! after it misses.
+//!
+//! The first lookup of a missing key is granted a lease, which is a token that
+//! allows the client to fill the key. Lookups of the key while the lease is
+//! outstanding are told to wait instead, so only one client fetches the value
+//! from the backing store. A fill is only accepted if it carries the token of
+//! the outstanding lease. Any other write or delete of the key invalidates the
+//! lease, so a fill which raced with an update can not store a stale value.
+//!
+//! If items are kept for a grace period after their TTL, a lookup of a stale
+//! item returns the item along with a lease to refresh it. As with a miss,
+//! only the first caller is granted the lease.
+//!
+//! Leases last for at most the configured maximum duration, and only a
+//! bounded number of leases may be outstanding. Once the limit is reached, a
+//! miss is not granted a lease until some of the outstanding leases are
+//! released or expire.
+
+use crate::*;
+use std::cmp::min;
+use std::collections::HashMap;
+
+/// The result of a lookup which may be granted a lease.
+#[derive(Debug)]
+pub enum Lease {
+    /// The item is in the cache.
+    Hit(Item),
+    /// The item has passed its TTL and is being served during the grace
+    /// period. The first caller is given a lease token to refresh the item,
+    /// other callers may use the stale item until it is refreshed.
+    Stale(Item, Option<u64>),
+    /// The item is not in the cache, and the caller is given the lease token
+    /// which must be provided when the item is filled.
+    Granted(u64),
+    /// The item is not in the cache and another caller holds the lease. The
+    /// caller should wait and retry the lookup.
+    Wait,
+    /// The item is not in the cache and no lease could be granted because too
+    /// many leases are outstanding. The caller may fill the item without a
+    /// lease.
+    Miss,
+}
+
+/// The outcome of trying to acquire a lease for a key.
+pub(crate) enum Acquire {
+    /// The lease was granted with the token.
+    Granted(u64),
+    /// Another caller holds an unexpired lease for the key.
+    Held,
+    /// The limit on outstanding leases has been reached.
+    Full,
+}
+
+/// A lease which has been granted for a key.
+struct Outstanding {
+    token: u64,
+    expire_at: Instant,
+}
+
+/// Tracks the outstanding leases.
+pub(crate) struct Leases {
+    leases: HashMap<Box<[u8]>, Outstanding>,
+    next_token: u64,
+    max_duration: Duration,
+    max_outstanding: usize,
+}
+
+impl Leases {
+    /// Create a new set of leases, each lasting at most `max_duration`, with
+    /// at most `max_outstanding` leases held at a time.
+    pub fn new(max_duration: Duration, max_outstanding: usize) -> Self {
+        Self {
+            leases: HashMap::new(),
+            next_token: 1,
+            max_duration,
+            max_outstanding,
+        }
+    }
+
+    /// Tries to acquire a new lease for the key. The duration is capped at
+    /// the maximum lease duration.
+    pub fn acquire(&mut self, key: &[u8], duration: Duration) -> Acquire {
+        let now = Instant::now();
+
+        match self.leases.get(key) {
+            Some(lease) if lease.expire_at > now => return Acquire::Held,
+            // an expired lease for the key is replaced
+            Some(_) => {}
+            None => {
+                if self.leases.len() >= self.max_outstanding {
+                    return Acquire::Full;
+                }
+            }
+        }
+
+        let duration = min(duration, self.max_duration);
+
+        let token = self.next_token;
+        self.next_token = self.next_token.wrapping_add(1).max(1);
+
+        self.leases.insert(
+            key.into(),
+            Outstanding {
+                token,
+                expire_at: now + duration,
+            },
+        );
+
+        Acquire::Granted(token)
+    }
+
+    /// Ends the lease for the key. Returns true if the token belongs to the
+    /// lease and the lease has not expired.
+    pub fn release(&mut self, key: &[u8], token: u64) -> bool {
+        match self.leases.get(key) {
+            Some(lease) if lease.token == token => {
+                let valid = lease.expire_at > Instant::now();
+                self.leases.remove(key);
+                valid
+            }
+            _ => false,
+        }
+    }
+
+    /// Invalidates any lease for the key.
+    pub fn invalidate(&mut self, key: &[u8]) {
+        if !self.leases.is_empty() {
+            self.leases.remove(key);
+        }
+    }
+
+    /// Invalidates every lease.
+    pub fn clear(&mut self) {
+        self.leases.clear();
+    }
+
+    /// Drops the leases which have expired.
+    pub fn expire(&mut self) {
+        let now = Instant::now();
+        self.leases.retain(|_, lease| lease.expire_at > now);
+    }
+}
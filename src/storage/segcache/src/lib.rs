@@ -34,18 +34,30 @@ use core::hash::{BuildHasher, Hasher};
 use std::convert::TryInto;
 
 // NOTE: this represents the versioning of the internal data layout and must be
-// incremented when breaking changes are made to the datastructures
-const VERSION: u64 = 0;
+// incremented when breaking changes are made to the datastructures. Item
+// checksums change the size of the item header, so data written with and
+// without them is not compatible.
+const VERSION: u64 = 6 | ((cfg!(feature = "checksum") as u64) << 32);
 
 // submodules
+mod admission;
 mod builder;
+mod checkpoint;
+mod compression;
 mod error;
 mod eviction;
 mod hashtable;
 mod item;
+mod lease;
+mod mrc;
+mod namespace;
+mod purge;
 mod rand;
 mod segcache;
 mod segments;
+mod tags;
+mod tier;
+mod topk;
 mod ttl_buckets;
 mod value;
 
@@ -58,17 +70,36 @@ mod tests;
 
 // publicly exported items from submodules
 pub use crate::segcache::Segcache;
+pub use admission::Admission;
 pub use builder::Builder;
+pub use compression::Compression;
 pub use error::SegcacheError;
-pub use eviction::Policy;
-pub use item::Item;
+pub use eviction::{Decision, EvictionPolicy, Policy};
+pub use item::{Expiry, Item};
+pub use lease::Lease;
+pub use mrc::MissRatioCurve;
+pub use namespace::Namespace;
+pub use purge::PurgeProgress;
+pub use segments::SegmentHeader;
+pub use tags::MAX_TAGS;
+pub use tier::DiskPolicy;
+pub use topk::TopKey;
+pub use ttl_buckets::ExpireBudget;
 pub use value::Value;
 
 // items from submodules which are imported for convenience to the crate level
 pub(crate) use crate::rand::*;
+pub(crate) use admission::AdmissionFilter;
 pub(crate) use hashtable::*;
 pub(crate) use item::*;
+pub(crate) use lease::{Acquire, Leases};
+pub(crate) use mrc::MrcEstimator;
+pub(crate) use namespace::{Namespaces, NO_NAMESPACE};
+pub(crate) use purge::{Pattern, Purge};
 pub(crate) use segments::*;
+pub(crate) use tags::Tags;
+pub(crate) use tier::DiskTier;
+pub(crate) use topk::TopKeys;
 pub(crate) use ttl_buckets::*;
 
 #[cfg(feature = "metrics")]
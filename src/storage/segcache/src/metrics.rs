@@ -5,6 +5,8 @@
 // All metrics for the Seg crate
 
 use metriken::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
 // segment related
 #[metric(
@@ -58,12 +60,30 @@ pub static SEGMENT_CLEAR: Counter = Counter::new();
 )]
 pub static SEGMENT_EXPIRE: Counter = Counter::new();
 
+#[metric(
+    name = "segment_stale",
+    description = "total number of segments which passed their TTL and entered the grace period"
+)]
+pub static SEGMENT_STALE: Counter = Counter::new();
+
+#[metric(
+    name = "segment_restore",
+    description = "number of segments restored from an existing datapool"
+)]
+pub static SEGMENT_RESTORE: Counter = Counter::new();
+
 #[metric(
     name = "clear_time",
     description = "amount of time, in nanoseconds, spent clearing segments"
 )]
 pub static CLEAR_TIME: Counter = Counter::new();
 
+#[metric(
+    name = "expire_backlog",
+    description = "current number of expired segments waiting to be cleared"
+)]
+pub static EXPIRE_BACKLOG: Gauge = Gauge::new();
+
 #[metric(
     name = "expire_time",
     description = "amount of time, in nanoseconds, spent expiring segments"
@@ -110,6 +130,18 @@ pub static HASH_INSERT_EX: Counter = Counter::new();
 )]
 pub static HASH_REMOVE: Counter = Counter::new();
 
+#[metric(
+    name = "hash_expand",
+    description = "number of times the hash table was expanded"
+)]
+pub static HASH_EXPAND: Counter = Counter::new();
+
+#[metric(
+    name = "hash_expand_pending",
+    description = "current number of hash buckets waiting to be moved by an expansion"
+)]
+pub static HASH_EXPAND_PENDING: Gauge = Gauge::new();
+
 #[metric(
     name = "hash_lookup",
     description = "total number of lookups against the hash table"
@@ -159,6 +191,72 @@ pub static ITEM_COMPACTED: Counter = Counter::new();
 )]
 pub static ITEM_RELINK: Counter = Counter::new();
 
+#[metric(
+    name = "item_touch",
+    description = "number of times items have been moved to a new ttl"
+)]
+pub static ITEM_TOUCH: Counter = Counter::new();
+
+#[metric(
+    name = "item_compress",
+    description = "number of items which had their value compressed"
+)]
+pub static ITEM_COMPRESS: Counter = Counter::new();
+
+#[metric(
+    name = "item_compress_saved_bytes",
+    description = "number of value bytes saved by compression"
+)]
+pub static ITEM_COMPRESS_SAVED_BYTES: Counter = Counter::new();
+
+#[metric(
+    name = "item_decompress_error",
+    description = "number of items removed on lookup because their value could not be decompressed"
+)]
+pub static ITEM_DECOMPRESS_ERROR: Counter = Counter::new();
+
+#[metric(
+    name = "admission_admit",
+    description = "number of new items admitted while there were no free segments"
+)]
+pub static ADMISSION_ADMIT: Counter = Counter::new();
+
+#[metric(
+    name = "admission_reject",
+    description = "number of new items rejected by the admission filter"
+)]
+pub static ADMISSION_REJECT: Counter = Counter::new();
+
+#[metric(
+    name = "disk_demote",
+    description = "number of evicted items demoted to the disk tier"
+)]
+pub static DISK_DEMOTE: Counter = Counter::new();
+
+#[metric(
+    name = "disk_promote",
+    description = "number of items promoted from the disk tier"
+)]
+pub static DISK_PROMOTE: Counter = Counter::new();
+
+#[metric(
+    name = "disk_evict",
+    description = "number of items evicted from the disk tier"
+)]
+pub static DISK_EVICT: Counter = Counter::new();
+
+#[metric(
+    name = "disk_item_current",
+    description = "current number of items in the disk tier"
+)]
+pub static DISK_ITEM_CURRENT: Gauge = Gauge::new();
+
+#[metric(
+    name = "item_restore",
+    description = "number of items restored from an existing datapool"
+)]
+pub static ITEM_RESTORE: Counter = Counter::new();
+
 #[metric(name = "item_current", description = "current number of live items")]
 pub static ITEM_CURRENT: Gauge = Gauge::new();
 
@@ -176,3 +274,142 @@ pub static ITEM_DEAD: Gauge = Gauge::new();
     description = "current number of dead bytes for storing items"
 )]
 pub static ITEM_DEAD_BYTES: Gauge = Gauge::new();
+
+#[metric(
+    name = "lease_grant",
+    description = "number of leases granted for keys which missed"
+)]
+pub static LEASE_GRANT: Counter = Counter::new();
+
+#[metric(
+    name = "lease_wait",
+    description = "number of lookups which missed while another client held the lease"
+)]
+pub static LEASE_WAIT: Counter = Counter::new();
+
+#[metric(
+    name = "lease_full",
+    description = "number of lookups which missed and were not granted a lease because too many leases were outstanding"
+)]
+pub static LEASE_FULL: Counter = Counter::new();
+
+#[metric(
+    name = "lease_reject",
+    description = "number of fills rejected because the lease was not valid"
+)]
+pub static LEASE_REJECT: Counter = Counter::new();
+
+#[metric(
+    name = "tag_invalidate",
+    description = "number of tags which were invalidated"
+)]
+pub static TAG_INVALIDATE: Counter = Counter::new();
+
+#[metric(
+    name = "item_tag_invalidated",
+    description = "number of items removed on lookup because one of their tags was invalidated"
+)]
+pub static ITEM_TAG_INVALIDATED: Counter = Counter::new();
+
+#[metric(
+    name = "delete_matching_active",
+    description = "current number of caches with a delete_matching purge in progress"
+)]
+pub static DELETE_MATCHING_ACTIVE: Gauge = Gauge::new();
+
+#[metric(
+    name = "delete_matching_segment_remaining",
+    description = "current number of segments left to scan by delete_matching purges"
+)]
+pub static DELETE_MATCHING_SEGMENT_REMAINING: Gauge = Gauge::new();
+
+#[metric(
+    name = "delete_matching_item",
+    description = "number of items removed by delete_matching purges"
+)]
+pub static DELETE_MATCHING_ITEM: Counter = Counter::new();
+
+#[metric(
+    name = "item_corrupted",
+    description = "number of items removed because they did not match their checksum"
+)]
+pub static ITEM_CORRUPTED: Counter = Counter::new();
+
+#[metric(
+    name = "item_stale_hit",
+    description = "number of lookups which returned an item during its grace period"
+)]
+pub static ITEM_STALE_HIT: Counter = Counter::new();
+
+#[metric(
+    name = "mrc_sample",
+    description = "number of lookups sampled for miss ratio curve estimation"
+)]
+pub static MRC_SAMPLE: Counter = Counter::new();
+
+// the number of sampled lookups which would have been hits at each of the heap
+// size multiples
+pub static MRC_HIT: Lazy<Vec<DynBoxedMetric<Counter>>> = Lazy::new(|| {
+    crate::mrc::HEAP_MULTIPLES
+        .iter()
+        .map(|multiple| {
+            MetricBuilder::new("mrc_hit")
+                .description(
+                    "number of sampled lookups which would hit at a multiple of the heap size",
+                )
+                .metadata("heap_multiple", format!("{multiple}"))
+                .build(Counter::new())
+        })
+        .collect()
+});
+
+// per-namespace metrics, these are shared by all caches which have a namespace
+// with the same name
+static NAMESPACE_METRICS: Lazy<Mutex<HashMap<String, Arc<NamespaceMetrics>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub struct NamespaceMetrics {
+    pub hit: DynBoxedMetric<Counter>,
+    pub miss: DynBoxedMetric<Counter>,
+    pub items: DynBoxedMetric<Gauge>,
+    pub bytes: DynBoxedMetric<Gauge>,
+    pub segments: DynBoxedMetric<Gauge>,
+}
+
+impl NamespaceMetrics {
+    /// Returns the metrics for the namespace with the given name, registering
+    /// them if they do not yet exist.
+    pub fn get(name: &str) -> Arc<Self> {
+        NAMESPACE_METRICS
+            .lock()
+            .unwrap()
+            .entry(name.to_string())
+            .or_insert_with(|| {
+                let build = |metric: &'static str, description: &'static str| {
+                    MetricBuilder::new(metric)
+                        .description(description)
+                        .metadata("namespace", name)
+                };
+
+                Arc::new(Self {
+                    hit: build("namespace_hit", "number of lookups which were hits")
+                        .build(Counter::new()),
+                    miss: build("namespace_miss", "number of lookups which were misses")
+                        .build(Counter::new()),
+                    items: build("namespace_item_current", "current number of live items")
+                        .build(Gauge::new()),
+                    bytes: build(
+                        "namespace_item_current_bytes",
+                        "current number of live bytes for storing items",
+                    )
+                    .build(Gauge::new()),
+                    segments: build(
+                        "namespace_segment_current",
+                        "current number of segments used by the namespace",
+                    )
+                    .build(Gauge::new()),
+                })
+            })
+            .clone()
+    }
+}
@@ -0,0 +1,312 @@
+// Copyright 2023 Pelikan Foundation LLC.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Online miss ratio curve estimation using spatially hashed sampling, as
+//! described in the SHARDS paper:
+//! <https://www.usenix.org/conference/fast15/presentation/waldspurger>
+//!
+//! Keys are sampled by comparing the hash of the key to a threshold, so every
+//! reference to a sampled key is tracked. For each lookup of a sampled key,
+//! the reuse distance is the number of bytes used by the distinct sampled keys
+//! which were referenced since the previous reference to the key, scaled up by
+//! the inverse of the sampling rate. A cache which is larger than the reuse
+//! distance would have held the item, so the lookup is counted as a hit for
+//! each heap size which is larger than the distance.
+//!
+//! The number of tracked keys is bounded. Once the bound is reached, the key
+//! with the largest hash is dropped and the threshold is lowered to its hash,
+//! which reduces the sampling rate.
+//!
+//! The estimate models an LRU cache which stores items with no overhead beyond
+//! the item itself, so it should be used to compare heap sizes rather than to
+//! predict the exact hit rate of the cache.
+
+use crate::*;
+use ahash::RandomState;
+use std::collections::BTreeMap;
+
+/// The heap sizes, as multiples of the current heap size, for which the miss
+/// ratio is estimated.
+pub(crate) const HEAP_MULTIPLES: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+
+/// Once this many lookups have been sampled, the counts are halved so that the
+/// estimate favors recent traffic.
+const DECAY_SAMPLES: f64 = 1_048_576.0;
+
+/// Configures miss ratio curve estimation.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum MissRatioCurve {
+    /// The miss ratio curve is not estimated.
+    #[default]
+    None,
+    /// Estimate the miss ratio curve by sampling `sample_rate` of the keys and
+    /// tracking at most `max_keys` sampled keys.
+    Shards { sample_rate: f64, max_keys: usize },
+}
+
+/// The most recent reference to a sampled key.
+#[derive(Copy, Clone)]
+struct Reference {
+    time: usize,
+    size: u32,
+}
+
+/// Tracks the reuse distance of sampled keys to estimate the miss ratio of the
+/// cache at different heap sizes.
+pub(crate) struct MrcEstimator {
+    hash_builder: Box<RandomState>,
+    /// Keys with a hash below the threshold are sampled
+    threshold: u64,
+    max_keys: usize,
+    /// The most recent reference to each sampled key, by key hash
+    keys: BTreeMap<u64, Reference>,
+    /// A Fenwick tree holding the size of each key at the time of its most
+    /// recent reference
+    tree: Box<[i64]>,
+    /// The sum of the sizes in the tree
+    total: i64,
+    /// The logical time of the next reference
+    time: usize,
+    samples: f64,
+    hits: [f64; HEAP_MULTIPLES.len()],
+}
+
+impl MrcEstimator {
+    /// Returns a new estimator for the configuration, or `None` if the miss
+    /// ratio curve is not estimated.
+    pub fn new(config: MissRatioCurve) -> Option<Self> {
+        match config {
+            MissRatioCurve::None => None,
+            MissRatioCurve::Shards {
+                sample_rate,
+                max_keys,
+            } => {
+                let max_keys = max_keys.max(1);
+                let threshold = (sample_rate.clamp(0.0, 1.0) * u64::MAX as f64) as u64;
+
+                Some(Self {
+                    hash_builder: Box::new(RandomState::with_seeds(
+                        0xbb8c484891ec6c86,
+                        0x0522a25ae9c769f9,
+                        0xeed2797b9571bc75,
+                        0x4feb29c1fbbd59d0,
+                    )),
+                    threshold: threshold.max(1),
+                    max_keys,
+                    keys: BTreeMap::new(),
+                    tree: vec![0; 2 * max_keys].into_boxed_slice(),
+                    total: 0,
+                    time: 0,
+                    samples: 0.0,
+                    hits: [0.0; HEAP_MULTIPLES.len()],
+                })
+            }
+        }
+    }
+
+    /// Record a reference to the key. Lookups are counted as samples for the
+    /// miss ratio, while writes only update the size and recency of the key.
+    /// A size of zero keeps the previously recorded size, which is used for
+    /// lookups which miss.
+    pub fn record(&mut self, key: &[u8], size: usize, lookup: bool, heap_size: usize) {
+        let mut hasher = self.hash_builder.build_hasher();
+        hasher.write(key);
+        let hash = hasher.finish();
+
+        if hash >= self.threshold {
+            return;
+        }
+
+        let mut size = size.min(u32::MAX as usize) as u32;
+
+        match self.keys.remove(&hash) {
+            Some(previous) => {
+                if lookup {
+                    let scale = u64::MAX as f64 / self.threshold as f64;
+                    let distance = (self.total - self.prefix(previous.time + 1)) as f64 * scale;
+                    self.sample(Some(distance), heap_size);
+                }
+                self.add(previous.time, -(previous.size as i64));
+                if size == 0 {
+                    size = previous.size;
+                }
+            }
+            None => {
+                if lookup {
+                    self.sample(None, heap_size);
+                }
+            }
+        }
+
+        if self.time == self.tree.len() {
+            self.compact();
+        }
+
+        self.add(self.time, size as i64);
+        self.keys.insert(
+            hash,
+            Reference {
+                time: self.time,
+                size,
+            },
+        );
+        self.time += 1;
+
+        // drop the key with the largest hash and lower the sampling rate so
+        // that it is no longer sampled
+        while self.keys.len() > self.max_keys {
+            if let Some((hash, reference)) = self.keys.pop_last() {
+                self.add(reference.time, -(reference.size as i64));
+                self.threshold = hash;
+            }
+        }
+    }
+
+    /// Returns the estimated miss ratio for each of the `HEAP_MULTIPLES`.
+    pub fn curve(&self) -> Vec<(f64, f64)> {
+        HEAP_MULTIPLES
+            .iter()
+            .zip(self.hits.iter())
+            .map(|(multiple, hits)| {
+                let miss_ratio = if self.samples > 0.0 {
+                    1.0 - hits / self.samples
+                } else {
+                    0.0
+                };
+                (*multiple, miss_ratio)
+            })
+            .collect()
+    }
+
+    /// Count a sampled lookup with the given reuse distance in bytes. A lookup
+    /// of a key which was not referenced before has no reuse distance and is a
+    /// miss for every heap size.
+    fn sample(&mut self, distance: Option<f64>, heap_size: usize) {
+        self.samples += 1.0;
+
+        #[cfg(feature = "metrics")]
+        MRC_SAMPLE.increment();
+
+        if let Some(distance) = distance {
+            for (i, multiple) in HEAP_MULTIPLES.iter().enumerate() {
+                if distance < multiple * heap_size as f64 {
+                    self.hits[i] += 1.0;
+
+                    #[cfg(feature = "metrics")]
+                    MRC_HIT[i].increment();
+                }
+            }
+        }
+
+        if self.samples >= DECAY_SAMPLES {
+            self.samples /= 2.0;
+            for hits in self.hits.iter_mut() {
+                *hits /= 2.0;
+            }
+        }
+    }
+
+    /// Add to the size recorded at the given time.
+    fn add(&mut self, time: usize, delta: i64) {
+        self.total += delta;
+
+        let mut i = time + 1;
+        while i <= self.tree.len() {
+            self.tree[i - 1] += delta;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    /// Returns the sum of the sizes recorded before the given time.
+    fn prefix(&self, time: usize) -> i64 {
+        let mut sum = 0;
+        let mut i = time;
+        while i > 0 {
+            sum += self.tree[i - 1];
+            i -= i & i.wrapping_neg();
+        }
+        sum
+    }
+
+    /// Renumber the references so that the tracked keys occupy the start of
+    /// the tree. Since the tree has room for twice the number of tracked keys,
+    /// this happens at most once every `max_keys` references.
+    fn compact(&mut self) {
+        let mut references: Vec<&mut Reference> = self.keys.values_mut().collect();
+        references.sort_by_key(|reference| reference.time);
+
+        let mut sizes = Vec::with_capacity(references.len());
+        for (time, reference) in references.into_iter().enumerate() {
+            reference.time = time;
+            sizes.push(reference.size);
+        }
+
+        self.tree.fill(0);
+        self.total = 0;
+        self.time = sizes.len();
+        for (time, size) in sizes.into_iter().enumerate() {
+            self.add(time, size as i64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuse_distance() {
+        // sample every key
+        let mut mrc = MrcEstimator::new(MissRatioCurve::Shards {
+            sample_rate: 1.0,
+            max_keys: 16,
+        })
+        .unwrap();
+
+        // a heap of 400 bytes, with eight keys of 100 bytes each referenced
+        // in a loop, so each lookup has a reuse distance of 700 bytes
+        for _ in 0..100 {
+            for i in 0..8 {
+                let key = format!("{i}");
+                mrc.record(key.as_bytes(), 100, true, 400);
+            }
+        }
+
+        let curve = mrc.curve();
+        let miss_ratio = |multiple: f64| {
+            curve
+                .iter()
+                .find(|(m, _)| *m == multiple)
+                .map(|(_, miss_ratio)| *miss_ratio)
+                .unwrap()
+        };
+
+        // only the heaps which are larger than the loop hit, with the first
+        // reference to each key being a miss
+        assert_eq!(miss_ratio(1.0), 1.0);
+        assert!(miss_ratio(2.0) < 0.02);
+        assert!(miss_ratio(4.0) < 0.02);
+        assert_eq!(miss_ratio(0.5), 1.0);
+    }
+
+    #[test]
+    fn bounded() {
+        let mut mrc = MrcEstimator::new(MissRatioCurve::Shards {
+            sample_rate: 1.0,
+            max_keys: 64,
+        })
+        .unwrap();
+
+        for i in 0..10_000 {
+            let key = format!("{i}");
+            mrc.record(key.as_bytes(), 100, false, 4096);
+        }
+
+        // the sampling rate is lowered to keep the number of tracked keys
+        // bounded
+        assert_eq!(mrc.keys.len(), 64);
+        assert!(mrc.threshold < u64::MAX / 64);
+        assert_eq!(mrc.total, 6400);
+    }
+}
@@ -0,0 +1,236 @@
+// Copyright 2023 Pelikan Foundation LLC.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Namespaces partition the heap between groups of keys.
+//!
+//! Each namespace has a share of the segments in the heap. Segments only hold
+//! items from a single namespace, and while a namespace is within its share,
+//! eviction takes segments from a namespace which is over its share instead.
+//! Namespaces may use free segments beyond their share, so the heap is fully
+//! used when some namespaces are idle.
+//!
+//! Keys are assigned to the namespace with the longest matching prefix, or
+//! may be inserted into a namespace explicitly by its id. The default
+//! namespace has an id of zero, holds all keys which do not match a prefix,
+//! and receives the share of the heap which is not given to the configured
+//! namespaces.
+
+use crate::*;
+
+/// Marks a segment which does not belong to any namespace.
+pub(crate) const NO_NAMESPACE: u16 = u16::MAX;
+
+/// The name of the namespace which holds keys which do not match any prefix.
+const DEFAULT_NAMESPACE: &str = "default";
+
+/// Configuration for a namespace.
+///
+/// ```
+/// use segcache::{Namespace, Segcache};
+///
+/// // give keys starting with `a:` a quarter of the heap
+/// let cache = Segcache::builder()
+///     .namespace(Namespace::new("a").prefix(b"a:").share(0.25))
+///     .build();
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Namespace {
+    name: String,
+    prefix: Option<Box<[u8]>>,
+    share: f64,
+}
+
+impl Namespace {
+    /// Create a new namespace with the given name. The name is used to label
+    /// the per-namespace metrics.
+    pub fn new<T: Into<String>>(name: T) -> Self {
+        Self {
+            name: name.into(),
+            prefix: None,
+            share: 0.0,
+        }
+    }
+
+    /// Keys which start with the prefix are assigned to this namespace. A
+    /// namespace without a prefix only holds keys which are inserted with its
+    /// id.
+    pub fn prefix(mut self, prefix: &[u8]) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// The fraction of the heap which belongs to this namespace.
+    pub fn share(mut self, share: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&share),
+            "namespace share must be between 0.0 and 1.0"
+        );
+        self.share = share;
+        self
+    }
+}
+
+/// The configured namespaces and their quotas.
+pub(crate) struct Namespaces {
+    namespaces: Vec<Namespace>,
+    /// The number of segments which belong to each namespace
+    quotas: Vec<usize>,
+    #[cfg(feature = "metrics")]
+    metrics: Vec<std::sync::Arc<NamespaceMetrics>>,
+    /// The item and byte counts last added to the shared gauges
+    #[cfg(feature = "metrics")]
+    reported: Vec<(i64, i64, i64)>,
+    #[cfg(feature = "metrics")]
+    reported_at: Instant,
+}
+
+impl Namespaces {
+    /// Returns the namespaces for a heap with the given number of segments, or
+    /// `None` if no namespaces were configured.
+    pub fn new(configured: Vec<Namespace>, segments: usize) -> Option<Self> {
+        if configured.is_empty() {
+            return None;
+        }
+
+        assert!(
+            configured.len() < NO_NAMESPACE as usize - 1,
+            "too many namespaces"
+        );
+
+        let configured_share: f64 = configured.iter().map(|n| n.share).sum();
+        assert!(
+            configured_share <= 1.0,
+            "namespace shares must not add up to more than 1.0"
+        );
+
+        let mut namespaces = vec![Namespace::new(DEFAULT_NAMESPACE).share(1.0 - configured_share)];
+        namespaces.extend(configured);
+
+        let quotas = Self::quotas(&namespaces, segments);
+
+        Some(Self {
+            #[cfg(feature = "metrics")]
+            metrics: namespaces
+                .iter()
+                .map(|n| NamespaceMetrics::get(&n.name))
+                .collect(),
+            #[cfg(feature = "metrics")]
+            reported: vec![(0, 0, 0); namespaces.len()],
+            #[cfg(feature = "metrics")]
+            reported_at: Instant::now(),
+            namespaces,
+            quotas,
+        })
+    }
+
+    /// Returns the number of segments which belong to each namespace.
+    fn quotas(namespaces: &[Namespace], segments: usize) -> Vec<usize> {
+        namespaces
+            .iter()
+            .map(|n| (n.share * segments as f64).floor() as usize)
+            .collect()
+    }
+
+    /// Recalculates the quotas after the heap was resized to the given number
+    /// of segments.
+    pub fn resize(&mut self, segments: usize) {
+        self.quotas = Self::quotas(&self.namespaces, segments);
+    }
+
+    /// Returns the number of namespaces, including the default namespace.
+    pub fn len(&self) -> u16 {
+        self.namespaces.len() as u16
+    }
+
+    /// Returns the namespace for the key. This is the namespace with the
+    /// longest matching prefix, or the default namespace.
+    pub fn resolve(&self, key: &[u8]) -> u16 {
+        let mut namespace = 0;
+        let mut len = 0;
+
+        for (id, n) in self.namespaces.iter().enumerate() {
+            if let Some(prefix) = &n.prefix {
+                if prefix.len() >= len && key.starts_with(prefix) {
+                    namespace = id;
+                    len = prefix.len();
+                }
+            }
+        }
+
+        namespace as u16
+    }
+
+    /// Returns the namespace which segments should be evicted from to make
+    /// room for an item in the provided namespace. This is the namespace
+    /// itself if it is over its quota, otherwise it is the namespace which is
+    /// furthest over its quota. Returns `None` if no namespace is over its
+    /// quota.
+    pub fn victim(&self, namespace: u16, segments: &Segments) -> Option<u16> {
+        let over = |id: u16| {
+            segments.namespace_segments(id) as f64 / self.quotas[id as usize].max(1) as f64
+        };
+
+        if segments.namespace_segments(namespace) > self.quotas[namespace as usize] {
+            return Some(namespace);
+        }
+
+        (0..self.len())
+            .filter(|id| segments.namespace_segments(*id) > self.quotas[*id as usize])
+            .max_by(|a, b| over(*a).total_cmp(&over(*b)))
+    }
+
+    /// Records a lookup in the namespace. A hit is recorded in the namespace
+    /// of the segment holding the item, and a miss in the namespace the key
+    /// resolves to.
+    #[cfg(feature = "metrics")]
+    pub fn record_get(&self, namespace: u16, hit: bool) {
+        let metrics = &self.metrics[namespace as usize];
+        if hit {
+            metrics.hit.increment();
+        } else {
+            metrics.miss.increment();
+        }
+    }
+
+    /// Updates the per-namespace gauges from the segment headers. This is
+    /// relatively expensive, so it is only done once per second.
+    #[allow(unused_variables)]
+    pub fn update_metrics(&mut self, segments: &Segments) {
+        #[cfg(feature = "metrics")]
+        {
+            let now = Instant::now();
+            if now - self.reported_at < Duration::from_secs(1) {
+                return;
+            }
+            self.reported_at = now;
+
+            let usage = segments.namespace_usage(self.namespaces.len());
+
+            // the gauges are shared by caches with the same namespaces, so only
+            // the change since the last update is applied
+            for (id, (items, bytes)) in usage.iter().enumerate() {
+                let segments = segments.namespace_segments(id as u16) as i64;
+                let metrics = &self.metrics[id];
+                let (reported_items, reported_bytes, reported_segments) = self.reported[id];
+
+                metrics.items.add(*items - reported_items);
+                metrics.bytes.add(*bytes - reported_bytes);
+                metrics.segments.add(segments - reported_segments);
+
+                self.reported[id] = (*items, *bytes, segments);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Drop for Namespaces {
+    fn drop(&mut self) {
+        for (metrics, (items, bytes, segments)) in self.metrics.iter().zip(self.reported.iter()) {
+            metrics.items.sub(*items);
+            metrics.bytes.sub(*bytes);
+            metrics.segments.sub(*segments);
+        }
+    }
+}
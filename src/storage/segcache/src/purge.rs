@@ -0,0 +1,248 @@
+// Copyright 2023 Pelikan Foundation LLC.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Removal of all items with keys matching a pattern.
+//!
+//! A purge removes the matching items which were written before it started.
+//! The segments holding items at that point are marked, and are no longer
+//! written to, so new items are never removed. The marked segments are walked
+//! in id order using the same cursor as [`Segcache::scan`](crate::Segcache::scan),
+//! and the matching items found are removed. Merges never combine marked and
+//! unmarked segments, and a marked segment which is merged into after it was
+//! scanned is walked again, so items moved by eviction are still found. Until
+//! the purge completes, lookups treat the matching items in marked segments
+//! as misses.
+//!
+//! Only a bounded number of items are visited by each step, so a purge of a
+//! large heap is spread across many calls to
+//! [`Segcache::expire`](crate::Segcache::expire) and does not stall the thread
+//! which owns the cache.
+//!
+//! Patterns are globs where `*` matches any sequence of bytes, `?` matches any
+//! single byte, and `\` escapes the following byte. A prefix is matched by a
+//! pattern which ends in `*`, for example `session:v3:*`.
+
+#[cfg(feature = "metrics")]
+use crate::metrics::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    /// Matches any sequence of bytes, including the empty sequence
+    Any,
+    /// Matches exactly one byte
+    One,
+    /// Matches the byte itself
+    Byte(u8),
+}
+
+/// A glob pattern which is matched against keys.
+#[derive(Clone)]
+pub(crate) struct Pattern {
+    tokens: Vec<Token>,
+}
+
+impl Pattern {
+    pub fn new(pattern: &[u8]) -> Self {
+        let mut tokens = Vec::with_capacity(pattern.len());
+        let mut bytes = pattern.iter();
+
+        while let Some(byte) = bytes.next() {
+            let token = match byte {
+                b'*' => {
+                    // consecutive wildcards are equivalent to one
+                    if tokens.last() == Some(&Token::Any) {
+                        continue;
+                    }
+                    Token::Any
+                }
+                b'?' => Token::One,
+                // a trailing escape matches itself
+                b'\\' => Token::Byte(*bytes.next().unwrap_or(&b'\\')),
+                _ => Token::Byte(*byte),
+            };
+            tokens.push(token);
+        }
+
+        Self { tokens }
+    }
+
+    /// Returns true if the pattern matches the entire key.
+    pub fn matches(&self, key: &[u8]) -> bool {
+        let mut token = 0;
+        let mut idx = 0;
+
+        // the position of the last wildcard and the key index it is currently
+        // matched up to, used to backtrack on a mismatch
+        let mut backtrack: Option<(usize, usize)> = None;
+
+        while idx < key.len() {
+            match self.tokens.get(token) {
+                Some(Token::Any) => {
+                    backtrack = Some((token, idx));
+                    token += 1;
+                    continue;
+                }
+                Some(Token::One) => {
+                    token += 1;
+                    idx += 1;
+                    continue;
+                }
+                Some(Token::Byte(byte)) if *byte == key[idx] => {
+                    token += 1;
+                    idx += 1;
+                    continue;
+                }
+                _ => {}
+            }
+
+            // extend the match of the last wildcard by one byte and retry
+            match backtrack {
+                Some((any, matched)) => {
+                    token = any + 1;
+                    idx = matched + 1;
+                    backtrack = Some((any, matched + 1));
+                }
+                None => {
+                    return false;
+                }
+            }
+        }
+
+        self.tokens[token..].iter().all(|t| *t == Token::Any)
+    }
+}
+
+/// The progress of a purge started by
+/// [`Segcache::delete_matching`](crate::Segcache::delete_matching).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PurgeProgress {
+    /// The number of segments which have been scanned
+    pub segments_scanned: usize,
+    /// The number of segments in the heap
+    pub segments_total: usize,
+    /// The number of items which have been removed
+    pub items_deleted: usize,
+    /// True once every segment has been scanned
+    pub done: bool,
+}
+
+/// A purge which is in progress, or the last one which completed.
+pub(crate) struct Purge {
+    pub pattern: Pattern,
+    pub cursor: u64,
+    pub progress: PurgeProgress,
+    /// The remaining segments last added to the shared gauge
+    #[cfg(feature = "metrics")]
+    remaining: i64,
+}
+
+impl Purge {
+    pub fn new(pattern: &[u8], segments_total: usize) -> Self {
+        #[cfg(feature = "metrics")]
+        DELETE_MATCHING_ACTIVE.increment();
+
+        let mut purge = Self {
+            pattern: Pattern::new(pattern),
+            cursor: 0,
+            progress: PurgeProgress {
+                segments_scanned: 0,
+                segments_total,
+                items_deleted: 0,
+                done: false,
+            },
+            #[cfg(feature = "metrics")]
+            remaining: 0,
+        };
+        purge.report();
+        purge
+    }
+
+    /// Record items which were removed without being visited by the scan.
+    pub fn removed(&mut self, deleted: usize) {
+        self.progress.items_deleted += deleted;
+
+        #[cfg(feature = "metrics")]
+        DELETE_MATCHING_ITEM.add(deleted as _);
+    }
+
+    /// Record that the scan has advanced to the given cursor. A cursor of
+    /// zero indicates that every segment has been scanned, and the purge is
+    /// complete unless some segments must be scanned again.
+    pub fn advance(&mut self, cursor: u64, segments_total: usize, deleted: usize, pending: bool) {
+        self.cursor = cursor;
+        self.progress.segments_total = segments_total;
+        self.progress.items_deleted += deleted;
+
+        #[cfg(feature = "metrics")]
+        DELETE_MATCHING_ITEM.add(deleted as _);
+
+        if cursor == 0 && !pending {
+            self.finish();
+        } else {
+            // the segment in the cursor may only be partially scanned, and a
+            // later pass revisits segments which were already counted
+            let scanned = (cursor >> 32).saturating_sub(1) as usize;
+            self.progress.segments_scanned = std::cmp::min(
+                std::cmp::max(self.progress.segments_scanned, scanned),
+                segments_total,
+            );
+            self.report();
+        }
+    }
+
+    /// Mark the purge as complete, either because every segment was scanned
+    /// or because it was replaced by another purge.
+    pub fn finish(&mut self) {
+        if self.progress.done {
+            return;
+        }
+
+        self.progress.done = true;
+        self.progress.segments_scanned = self.progress.segments_total;
+
+        #[cfg(feature = "metrics")]
+        DELETE_MATCHING_ACTIVE.decrement();
+
+        self.report();
+    }
+
+    /// The gauge is shared by all caches, so only the change since the last
+    /// update is applied.
+    fn report(&mut self) {
+        #[cfg(feature = "metrics")]
+        {
+            let remaining = (self.progress.segments_total - self.progress.segments_scanned) as i64;
+            DELETE_MATCHING_SEGMENT_REMAINING.add(remaining - self.remaining);
+            self.remaining = remaining;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern() {
+        let prefix = Pattern::new(b"session:v3:*");
+        assert!(prefix.matches(b"session:v3:"));
+        assert!(prefix.matches(b"session:v3:abc"));
+        assert!(!prefix.matches(b"session:v2:abc"));
+        assert!(!prefix.matches(b"session:v3"));
+
+        let glob = Pattern::new(b"user:*:profile?");
+        assert!(glob.matches(b"user:1:profile1"));
+        assert!(glob.matches(b"user:1:2:profile:profileA"));
+        assert!(!glob.matches(b"user:1:profile"));
+        assert!(!glob.matches(b"user:1:profile12"));
+
+        let literal = Pattern::new(b"a\\*b");
+        assert!(literal.matches(b"a*b"));
+        assert!(!literal.matches(b"aab"));
+
+        assert!(Pattern::new(b"*").matches(b""));
+        assert!(Pattern::new(b"**a**").matches(b"banana"));
+        assert!(!Pattern::new(b"").matches(b"a"));
+    }
+}
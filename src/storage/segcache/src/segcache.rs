@@ -7,9 +7,22 @@
 use crate::Value;
 use crate::*;
 use std::cmp::min;
+use std::path::PathBuf;
 
 const RESERVE_RETRIES: usize = 3;
 
+/// The number of hash buckets moved by each insert while the hashtable is
+/// being expanded.
+const EXPAND_STEP: usize = 4;
+
+/// The number of hash buckets moved by each call to `expire()` while the
+/// hashtable is being expanded.
+const EXPIRE_EXPAND_STEP: usize = 1024;
+
+/// The number of items visited by each call to `expire()` while a
+/// `delete_matching()` purge is in progress.
+const PURGE_STEP: usize = 1024;
+
 /// A pre-allocated key-value store with eager expiration. It uses a
 /// segment-structured design that stores data in fixed-size segments, grouping
 /// objects with nearby expiration time into the same segment, and lifting most
@@ -19,6 +32,25 @@ pub struct Segcache {
     pub(crate) segments: Segments,
     pub(crate) ttl_buckets: TtlBuckets,
     pub(crate) time: Instant,
+    pub(crate) compression: Compression,
+    pub(crate) admission: Option<AdmissionFilter>,
+    pub(crate) mrc: Option<MrcEstimator>,
+    pub(crate) top_keys: Option<TopKeys>,
+    pub(crate) expire_budget: ExpireBudget,
+    /// How long items are kept, and served as stale, after their TTL
+    pub(crate) stale_grace: Duration,
+    /// The expiration backlog last added to the shared gauge
+    #[cfg(feature = "metrics")]
+    pub(crate) expire_backlog: i64,
+    pub(crate) namespaces: Option<Namespaces>,
+    pub(crate) leases: Leases,
+    pub(crate) tags: Tags,
+    /// Where the tag invalidations are written when the datapool is flushed
+    pub(crate) tags_path: Option<PathBuf>,
+    /// The current or most recent `delete_matching()` purge
+    pub(crate) purge: Option<Purge>,
+    /// When a clear scheduled by `clear_after()` takes effect
+    pub(crate) clear_at: Option<Instant>,
 }
 
 impl Segcache {
@@ -57,7 +89,9 @@ impl Segcache {
         self.segments.items()
     }
 
-    /// Get the item in the `Segcache` with the provided key
+    /// Get the item in the `Segcache` with the provided key. Items which are
+    /// past their TTL but kept for the grace period are treated as a miss, see
+    /// `get_stale()`.
     ///
     /// ```
     /// use segcache::{Policy, Segcache};
@@ -71,7 +105,269 @@ impl Segcache {
     /// assert_eq!(item.value(), b"strong");
     /// ```
     pub fn get(&mut self, key: &[u8]) -> Option<Item> {
-        self.hashtable.get(key, self.time, &mut self.segments)
+        self.lookup(key, false)
+    }
+
+    /// Get the item in the `Segcache` with the provided key, including items
+    /// which are past their TTL but kept for the grace period. These are
+    /// marked as stale, and should only be returned to clients which asked
+    /// for stale items.
+    ///
+    /// ```
+    /// use segcache::{Policy, Segcache};
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Segcache::builder().build().expect("failed to create cache");
+    ///
+    /// cache.insert(b"coffee", b"strong", None, Duration::ZERO);
+    /// let item = cache.get_stale(b"coffee").expect("didn't get item back");
+    /// assert!(!item.is_stale());
+    /// ```
+    pub fn get_stale(&mut self, key: &[u8]) -> Option<Item> {
+        self.lookup(key, true)
+    }
+
+    fn lookup(&mut self, key: &[u8], stale: bool) -> Option<Item> {
+        if let Some(admission) = self.admission.as_mut() {
+            admission.record(key);
+        }
+
+        self.promote(key);
+        self.remove_invalid(key);
+        let item = self.hashtable.get(key, self.time, &mut self.segments);
+        let item = self.remove_undecompressable(key, item);
+        let item = if stale { item } else { fresh(item) };
+
+        #[cfg(feature = "metrics")]
+        if item.as_ref().map(|item| item.is_stale()).unwrap_or(false) {
+            ITEM_STALE_HIT.increment();
+        }
+
+        // an item inserted with `insert_namespace()` may be held in a
+        // namespace other than the one its key resolves to
+        #[cfg(feature = "metrics")]
+        if self.namespaces.is_some() {
+            let namespace = match item {
+                Some(_) => self
+                    .hashtable
+                    .item_info(key, &mut self.segments)
+                    .and_then(|item_info| self.segments.namespace_of(item_info)),
+                None => None,
+            }
+            .unwrap_or_else(|| self.resolve_namespace(key));
+
+            if let Some(namespaces) = self.namespaces.as_ref() {
+                namespaces.record_get(namespace, item.is_some());
+            }
+        }
+
+        if let Some(mrc) = self.mrc.as_mut() {
+            let size = item.as_ref().map(|item| item.size()).unwrap_or(0);
+            mrc.record(key, size, true, self.segments.heap_size());
+        }
+
+        if let (Some(top_keys), Some(item)) = (self.top_keys.as_mut(), item.as_ref()) {
+            top_keys.record(key, item.size());
+        }
+
+        item
+    }
+
+    /// Get the item with the provided key, or a lease to fill it if it is not
+    /// in the cache. Only the first caller to miss is granted a lease, which
+    /// lasts for the provided duration, up to the maximum lease duration.
+    /// Other callers are told to wait until the lease is released by
+    /// `insert_lease()` or expires. A stale item is returned along with a
+    /// lease to refresh it, which is also only granted to the first caller. If
+    /// too many leases are outstanding, a miss is returned without a lease.
+    /// See the `Lease` documentation for more details.
+    ///
+    /// ```
+    /// use segcache::{Lease, Segcache, SegcacheError};
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Segcache::builder().build().expect("failed to create cache");
+    ///
+    /// // the first miss is granted the lease, the next is told to wait
+    /// let token = match cache.get_lease(b"coffee", Duration::from_secs(5)) {
+    ///     Lease::Granted(token) => token,
+    ///     _ => panic!("expected a lease"),
+    /// };
+    /// assert!(matches!(cache.get_lease(b"coffee", Duration::from_secs(5)), Lease::Wait));
+    ///
+    /// // only the lease holder can fill the item
+    /// assert_eq!(
+    ///     cache.insert_lease(b"coffee", b"strong", None, Duration::ZERO, token + 1),
+    ///     Err(SegcacheError::InvalidLease)
+    /// );
+    /// assert!(cache.insert_lease(b"coffee", b"strong", None, Duration::ZERO, token).is_ok());
+    /// assert!(matches!(cache.get_lease(b"coffee", Duration::from_secs(5)), Lease::Hit(_)));
+    /// ```
+    pub fn get_lease(&mut self, key: &[u8], duration: std::time::Duration) -> Lease {
+        let duration = Duration::from_secs(min(u32::MAX as u64, duration.as_secs()) as u32);
+
+        match self.get_stale(key) {
+            Some(item) if item.is_stale() => {
+                let token = match self.leases.acquire(key, duration) {
+                    Acquire::Granted(token) => Some(token),
+                    Acquire::Held | Acquire::Full => None,
+                };
+
+                #[cfg(feature = "metrics")]
+                if token.is_some() {
+                    LEASE_GRANT.increment();
+                }
+
+                return Lease::Stale(item, token);
+            }
+            Some(item) => return Lease::Hit(item),
+            None => {}
+        }
+
+        match self.leases.acquire(key, duration) {
+            Acquire::Granted(token) => {
+                #[cfg(feature = "metrics")]
+                LEASE_GRANT.increment();
+
+                Lease::Granted(token)
+            }
+            Acquire::Held => {
+                #[cfg(feature = "metrics")]
+                LEASE_WAIT.increment();
+
+                Lease::Wait
+            }
+            Acquire::Full => {
+                #[cfg(feature = "metrics")]
+                LEASE_FULL.increment();
+
+                Lease::Miss
+            }
+        }
+    }
+
+    /// Fill an item using the lease token returned by `get_lease()`. The item
+    /// is only inserted if the lease has not expired and was not invalidated
+    /// by another write or delete of the key, otherwise this returns an error.
+    /// The lease is released either way.
+    pub fn insert_lease<'a, T: Into<Value<'a>>>(
+        &mut self,
+        key: &'a [u8],
+        value: T,
+        optional: Option<&[u8]>,
+        ttl: std::time::Duration,
+        token: u64,
+    ) -> Result<(), SegcacheError> {
+        if !self.leases.release(key, token) {
+            #[cfg(feature = "metrics")]
+            LEASE_REJECT.increment();
+
+            return Err(SegcacheError::InvalidLease);
+        }
+
+        self.insert(key, value, optional, ttl)
+    }
+
+    /// Returns the estimated miss ratio at multiples of the current heap size,
+    /// or `None` if miss ratio curve estimation is not enabled. Each entry is
+    /// a pair of the heap size multiple and the estimated miss ratio.
+    ///
+    /// ```
+    /// use segcache::{MissRatioCurve, Segcache};
+    ///
+    /// let mut cache = Segcache::builder()
+    ///     .miss_ratio_curve(MissRatioCurve::Shards { sample_rate: 1.0, max_keys: 1024 })
+    ///     .build()
+    ///     .expect("failed to create cache");
+    ///
+    /// let curve = cache.miss_ratio_curve().unwrap();
+    /// assert!(curve.iter().any(|(multiple, _)| *multiple == 2.0));
+    /// ```
+    pub fn miss_ratio_curve(&self) -> Option<Vec<(f64, f64)>> {
+        self.mrc.as_ref().map(|mrc| mrc.curve())
+    }
+
+    /// Returns up to `k` of the keys with the most hits, from most to fewest
+    /// hits, or `None` if the keys are not tracked. The hit counts are
+    /// estimates which may be too high by at most the reported error.
+    ///
+    /// ```
+    /// use segcache::Segcache;
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Segcache::builder()
+    ///     .top_keys(1024)
+    ///     .build()
+    ///     .expect("failed to create cache");
+    ///
+    /// cache.insert(b"coffee", b"strong", None, Duration::ZERO);
+    /// cache.insert(b"tea", b"green", None, Duration::ZERO);
+    /// for _ in 0..3 {
+    ///     cache.get(b"coffee");
+    /// }
+    /// cache.get(b"tea");
+    ///
+    /// let top = cache.top_keys(1).unwrap();
+    /// assert_eq!(&*top[0].key, b"coffee");
+    /// assert_eq!(top[0].hits, 3);
+    /// ```
+    pub fn top_keys(&self, k: usize) -> Option<Vec<TopKey>> {
+        self.top_keys.as_ref().map(|top_keys| top_keys.top(k))
+    }
+
+    /// Resizes the heap while the cache is in use. The heap size is rounded
+    /// down to a whole number of segments.
+    ///
+    /// Growing the heap extends the existing region of memory, the segments
+    /// are not copied on Linux. Shrinking the heap evicts any large item which
+    /// extends beyond the new end of the heap. The other segments beyond the
+    /// new end are not used for new segments, and are evicted before any other
+    /// segment once the cache is full. The memory is released as these
+    /// segments are evicted or expire. Evicted items are moved into the disk
+    /// tier if one is configured.
+    ///
+    /// Returns an error if the heap would have no segments or too many
+    /// segments, or if the heap is backed by a file.
+    ///
+    /// ```
+    /// use segcache::Segcache;
+    ///
+    /// const MB: usize = 1024 * 1024;
+    ///
+    /// let mut cache = Segcache::builder()
+    ///     .heap_size(4 * MB)
+    ///     .segment_size(MB as i32)
+    ///     .build()
+    ///     .expect("failed to create cache");
+    ///
+    /// cache.resize_heap(8 * MB).expect("failed to grow heap");
+    /// assert_eq!(cache.heap_size(), 8 * MB);
+    ///
+    /// cache.resize_heap(2 * MB).expect("failed to shrink heap");
+    /// assert_eq!(cache.heap_size(), 2 * MB);
+    /// ```
+    pub fn resize_heap(&mut self, heap_size: usize) -> Result<(), SegcacheError> {
+        let segments = heap_size / self.segments.segment_size() as usize;
+
+        let result = self
+            .segments
+            .resize(segments, &mut self.ttl_buckets, &mut self.hashtable);
+
+        match result {
+            Ok(()) => {
+                if let Some(namespaces) = self.namespaces.as_mut() {
+                    namespaces.resize(segments);
+                }
+                Ok(())
+            }
+            Err(SegmentsError::InvalidHeapSize) => Err(SegcacheError::InvalidHeapSize),
+            Err(_) => Err(SegcacheError::HeapResizeFailed),
+        }
+    }
+
+    /// Returns the current size of the heap in bytes.
+    pub fn heap_size(&self) -> usize {
+        self.segments.heap_size()
     }
 
     /// Get the item in the `Segcache` with the provided key without
@@ -84,7 +380,10 @@ impl Segcache {
     /// assert!(cache.get_no_freq_incr(b"coffee").is_none());
     /// ```
     pub fn get_no_freq_incr(&mut self, key: &[u8]) -> Option<Item> {
-        self.hashtable.get_no_freq_incr(key, &mut self.segments)
+        self.promote(key);
+        self.remove_invalid(key);
+        let item = self.hashtable.get_no_freq_incr(key, &mut self.segments);
+        fresh(self.remove_undecompressable(key, item))
     }
 
     /// Insert a new item into the cache. May return an error indicating that
@@ -111,60 +410,160 @@ impl Segcache {
         optional: Option<&[u8]>,
         ttl: std::time::Duration,
     ) -> Result<(), SegcacheError> {
-        let value: Value = value.into();
+        let namespace = self.resolve_namespace(key);
+        self.insert_namespace(namespace, key, value, optional, ttl)
+    }
+
+    /// Insert a new item into the namespace with the provided id, instead of
+    /// the namespace matching the key prefix. Namespaces are numbered from one
+    /// in the order they were added to the `Builder`, and the default
+    /// namespace has an id of zero. May return an error indicating that the
+    /// insert was not successful.
+    ///
+    /// ```
+    /// use segcache::{Namespace, Segcache, SegcacheError};
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Segcache::builder()
+    ///     .namespace(Namespace::new("drinks").share(0.5))
+    ///     .build()
+    ///     .expect("failed to create cache");
+    ///
+    /// assert!(cache.insert_namespace(1, b"coffee", b"strong", None, Duration::ZERO).is_ok());
+    /// assert_eq!(
+    ///     cache.insert_namespace(2, b"tea", b"green", None, Duration::ZERO),
+    ///     Err(SegcacheError::UnknownNamespace)
+    /// );
+    /// ```
+    pub fn insert_namespace<'a, T: Into<Value<'a>>>(
+        &mut self,
+        namespace: u16,
+        key: &'a [u8],
+        value: T,
+        optional: Option<&[u8]>,
+        ttl: std::time::Duration,
+    ) -> Result<(), SegcacheError> {
+        self.insert_item(namespace, key, value.into(), optional, ttl, &[])
+    }
+
+    /// Insert a new item which carries the provided tags. Every item with a
+    /// tag can be removed together by `invalidate_tag()`. An item may carry at
+    /// most `MAX_TAGS` tags.
+    ///
+    /// ```
+    /// use segcache::Segcache;
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Segcache::builder().build().expect("failed to create cache");
+    ///
+    /// let tags: &[&[u8]] = &[b"user:1"];
+    /// cache.insert_tagged(b"user:1:profile", b"alice", None, Duration::ZERO, tags);
+    /// cache.insert_tagged(b"user:1:friends", b"bob", None, Duration::ZERO, tags);
+    /// cache.insert(b"user:2:profile", b"carol", None, Duration::ZERO);
+    ///
+    /// cache.invalidate_tag(b"user:1");
+    /// assert!(cache.get(b"user:1:profile").is_none());
+    /// assert!(cache.get(b"user:1:friends").is_none());
+    /// assert!(cache.get(b"user:2:profile").is_some());
+    /// ```
+    pub fn insert_tagged<'a, T: Into<Value<'a>>>(
+        &mut self,
+        key: &'a [u8],
+        value: T,
+        optional: Option<&[u8]>,
+        ttl: std::time::Duration,
+        tags: &[&[u8]],
+    ) -> Result<(), SegcacheError> {
+        let tags = self.tags.encode(tags)?;
+        let namespace = self.resolve_namespace(key);
+        self.insert_item(namespace, key, value.into(), optional, ttl, &tags)
+    }
+
+    /// Logically removes every item which carries the tag. This does not scan
+    /// the cache, items are removed when they are next looked up and their
+    /// space is reclaimed by expiration and eviction.
+    pub fn invalidate_tag(&mut self, tag: &[u8]) {
+        #[cfg(feature = "metrics")]
+        TAG_INVALIDATE.increment();
+
+        self.tags.invalidate(tag);
+    }
+
+    /// Internal function which inserts an item with the encoded tags into the
+    /// namespace.
+    fn insert_item<'a>(
+        &mut self,
+        namespace: u16,
+        key: &'a [u8],
+        value: Value<'a>,
+        optional: Option<&[u8]>,
+        ttl: std::time::Duration,
+        tags: &[u8],
+    ) -> Result<(), SegcacheError> {
+        if namespace >= self.ttl_buckets.namespaces() {
+            return Err(SegcacheError::UnknownNamespace);
+        }
+
+        // the item must not be written into a segment which is due to be
+        // flushed
+        self.flush_scheduled();
+
+        self.hashtable.expand_step(EXPAND_STEP, &mut self.segments);
+
+        // a write which is not admitted leaves the cache unchanged
+        if !self.admit(key) {
+            return Err(SegcacheError::NotAdmitted);
+        }
+
+        // a write replaces any value which is being filled under a lease
+        self.leases.invalidate(key);
+
+        // any copy of the item in the disk tier is replaced by the new item
+        if let Some(tier) = self.hashtable.tier_mut() {
+            tier.remove(key);
+        }
+
+        // values are compressed according to the compression policy
+        let compressed = match value {
+            Value::Bytes(bytes) => self.compression.compress(bytes),
+            _ => None,
+        };
+
+        #[cfg(feature = "metrics")]
+        if let Some(compressed) = &compressed {
+            ITEM_COMPRESS.increment();
+            ITEM_COMPRESS_SAVED_BYTES.add((size_of(&value) - compressed.len()) as _);
+        }
+
+        let value = compressed.as_deref().map(Value::Bytes).unwrap_or(value);
 
         // default optional data is empty
         let optional = optional.unwrap_or(&[]);
 
         // calculate size for item
-        let size = (((ITEM_HDR_SIZE + key.len() + size_of(&value) + optional.len()) >> 3) + 1) << 3;
+        let size = (((ITEM_HDR_SIZE + key.len() + size_of(&value) + optional.len() + tags.len())
+            >> 3)
+            + 1)
+            << 3;
 
-        let ttl = Duration::from_secs(min(u32::MAX as u64, ttl.as_secs()) as u32);
+        if let Some(mrc) = self.mrc.as_mut() {
+            mrc.record(key, size, false, self.segments.heap_size());
+        }
 
-        // try to get a `ReservedItem`
-        let mut retries = RESERVE_RETRIES;
-        let reserved;
-        loop {
-            match self
-                .ttl_buckets
-                .get_mut_bucket(ttl)
-                .reserve(size, &mut self.segments)
-            {
-                Ok(mut reserved_item) => {
-                    reserved_item.define(key, value, optional);
-                    reserved = reserved_item;
-                    break;
-                }
-                Err(TtlBucketsError::ItemOversized { size }) => {
-                    return Err(SegcacheError::ItemOversized { size });
-                }
-                Err(TtlBucketsError::NoFreeSegments) => {
-                    if self
-                        .segments
-                        .evict(&mut self.ttl_buckets, &mut self.hashtable)
-                        .is_err()
-                    {
-                        retries -= 1;
-                    } else {
-                        // we successfully evicted a segment, return to start of
-                        // loop to reserve the item
-                        continue;
-                    }
-                }
-            }
-            if retries == 0 {
-                // segment acquire failed, increment the stats and return with
-                // an error
+        if size_of(&value) > ITEM_MAX_VLEN || optional.len() > ITEM_MAX_OLEN {
+            return Err(SegcacheError::ItemOversized { size });
+        }
 
-                #[cfg(feature = "metrics")]
-                {
-                    SEGMENT_REQUEST.increment();
-                    SEGMENT_REQUEST_FAILURE.increment();
-                }
+        let ttl = Duration::from_secs(min(u32::MAX as u64, ttl.as_secs()) as u32);
 
-                return Err(SegcacheError::NoFreeSegments);
-            }
-            retries -= 1;
+        // try to get a `ReservedItem`
+        let mut reserved = self.reserve(namespace, size, ttl)?;
+        reserved.define(key, value, optional, tags);
+        if compressed.is_some() {
+            reserved.item().set_compressed();
+        }
+        if ttl.as_secs() == 0 {
+            reserved.item().set_no_expire(true);
         }
 
         // insert into the hashtable, or roll-back by removing the item
@@ -189,6 +588,11 @@ impl Segcache {
                 &mut self.ttl_buckets,
                 &mut self.hashtable,
             );
+
+            // the bucket chain for the key is full, so the hashtable is
+            // expanded if it is allowed to be
+            self.hashtable.expand();
+
             Err(SegcacheError::HashTableInsertEx)
         } else {
             Ok(())
@@ -232,6 +636,11 @@ impl Segcache {
         ttl: std::time::Duration,
         cas: u32,
     ) -> Result<(), SegcacheError> {
+        self.promote(key);
+        self.remove_invalid(key);
+        if self.is_stale(key) {
+            return Err(SegcacheError::NotFound);
+        }
         match self.hashtable.try_update_cas(key, cas, &mut self.segments) {
             Ok(()) => self.insert(key, value, optional, ttl),
             Err(e) => Err(e),
@@ -257,12 +666,302 @@ impl Segcache {
     /// ```
     // TODO(bmartin): a result would be better here
     pub fn delete(&mut self, key: &[u8]) -> bool {
+        self.leases.invalidate(key);
+
+        let demoted = self
+            .hashtable
+            .tier_mut()
+            .map(|tier| tier.remove(key).is_some())
+            .unwrap_or(false);
+
         self.hashtable
             .delete(key, &mut self.ttl_buckets, &mut self.segments)
+            || demoted
+    }
+
+    /// Updates the TTL of the item with the given key. The item is moved into
+    /// a segment belonging to the `TtlBucket` for the new TTL, keeping its
+    /// value, CAS value, and frequency.
+    ///
+    /// ```
+    /// use segcache::{Expiry, Policy, Segcache, SegcacheError};
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Segcache::builder().build().expect("failed to create cache");
+    ///
+    /// // If the item is not in the cache, touch will fail as 'NotFound'
+    /// assert_eq!(
+    ///     cache.touch(b"coffee", Duration::from_secs(60)),
+    ///     Err(SegcacheError::NotFound)
+    /// );
+    ///
+    /// cache.insert(b"coffee", b"strong", None, Duration::from_secs(5));
+    /// assert!(cache.touch(b"coffee", Duration::from_secs(60)).is_ok());
+    ///
+    /// // The item is unchanged but expires later
+    /// let item = cache.get(b"coffee").expect("didn't get item back");
+    /// assert_eq!(item.value(), b"strong");
+    /// assert!(matches!(
+    ///     cache.ttl(b"coffee"),
+    ///     Some(Expiry::After(ttl)) if ttl > Duration::from_secs(5)
+    /// ));
+    /// ```
+    pub fn touch(&mut self, key: &[u8], ttl: std::time::Duration) -> Result<(), SegcacheError> {
+        self.promote(key);
+        self.remove_invalid(key);
+
+        let item_info = self
+            .hashtable
+            .item_info(key, &mut self.segments)
+            .filter(|item_info| !self.segments.is_stale(*item_info))
+            .ok_or(SegcacheError::NotFound)?;
+
+        // the item stays in the namespace of the segment which holds it
+        let namespace = self
+            .segments
+            .get_mut(get_seg_id(item_info).ok_or(SegcacheError::NotFound)?)
+            .map_err(|_| SegcacheError::NotFound)?
+            .namespace();
+
+        // the item is copied out because reserving space may evict or merge
+        // the segment which holds it
+        let bytes = self
+            .segments
+            .get_item(item_info)
+            .unwrap()
+            .as_bytes()
+            .to_vec();
+
+        let ttl = Duration::from_secs(min(u32::MAX as u64, ttl.as_secs()) as u32);
+
+        let mut reserved = self.reserve(namespace, bytes.len(), ttl)?;
+        reserved.copy_from(&bytes);
+        reserved.item().set_no_expire(ttl.as_secs() == 0);
+
+        match self.hashtable.relocate(
+            key,
+            reserved.seg(),
+            reserved.offset() as u64,
+            &mut self.segments,
+        ) {
+            Ok(previous) => {
+                let _ =
+                    self.segments
+                        .remove_item(previous, &mut self.ttl_buckets, &mut self.hashtable);
+
+                #[cfg(feature = "metrics")]
+                ITEM_TOUCH.increment();
+
+                Ok(())
+            }
+            Err(()) => {
+                // the item was evicted while reserving space, roll-back by
+                // removing the copy
+                let _ = self.segments.remove_at(
+                    reserved.seg(),
+                    reserved.offset(),
+                    &mut self.ttl_buckets,
+                    &mut self.hashtable,
+                );
+                Err(SegcacheError::NotFound)
+            }
+        }
+    }
+
+    /// Returns the remaining TTL of the item with the given key. The TTL is
+    /// tracked per-segment, so this is the time until the segment holding the
+    /// item expires. Items stored without a TTL report `Expiry::Never`.
+    ///
+    /// ```
+    /// use segcache::{Expiry, Policy, Segcache};
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Segcache::builder().build().expect("failed to create cache");
+    /// assert!(cache.ttl(b"coffee").is_none());
+    ///
+    /// cache.insert(b"coffee", b"strong", None, Duration::from_secs(60));
+    /// assert!(matches!(
+    ///     cache.ttl(b"coffee"),
+    ///     Some(Expiry::After(ttl)) if ttl <= Duration::from_secs(60)
+    /// ));
+    ///
+    /// cache.insert(b"tea", b"green", None, Duration::ZERO);
+    /// assert_eq!(cache.ttl(b"tea"), Some(Expiry::Never));
+    /// ```
+    pub fn ttl(&mut self, key: &[u8]) -> Option<Expiry> {
+        self.promote(key);
+        self.remove_invalid(key);
+
+        let item_info = self.hashtable.item_info(key, &mut self.segments)?;
+        if self.segments.get_item(item_info)?.no_expire() {
+            return Some(Expiry::Never);
+        }
+
+        let segment = self.segments.get_mut(get_seg_id(item_info)?).ok()?;
+
+        let expire_at = segment.create_at() + segment.ttl();
+        let now = Instant::now();
+
+        if expire_at <= now {
+            Some(Expiry::After(std::time::Duration::ZERO))
+        } else {
+            Some(Expiry::After(std::time::Duration::from_secs(
+                (expire_at - now).as_secs() as u64,
+            )))
+        }
+    }
+
+    /// Returns up to `count` live items starting from the position given by
+    /// `cursor`, along with the cursor to pass to the next call. Start a scan
+    /// with a cursor of zero; the scan is complete when the returned cursor is
+    /// zero.
+    ///
+    /// The cursor remains valid across inserts, deletes, and evictions. Items
+    /// which are present for the entire scan are returned, but items which are
+    /// moved by merge eviction or inserted during the scan may be missed or
+    /// returned more than once.
+    ///
+    /// ```
+    /// use segcache::{Policy, Segcache};
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Segcache::builder().build().expect("failed to create cache");
+    ///
+    /// cache.insert(b"coffee", b"strong", None, Duration::ZERO);
+    /// cache.insert(b"tea", b"green", None, Duration::ZERO);
+    ///
+    /// let mut keys = Vec::new();
+    /// let mut cursor = 0;
+    /// loop {
+    ///     let (next, items) = cache.scan(cursor, 1);
+    ///     keys.extend(items.iter().map(|item| item.key().to_vec()));
+    ///     if next == 0 {
+    ///         break;
+    ///     }
+    ///     cursor = next;
+    /// }
+    ///
+    /// keys.sort();
+    /// assert_eq!(keys, vec![b"coffee".to_vec(), b"tea".to_vec()]);
+    /// ```
+    pub fn scan(&mut self, cursor: u64, count: usize) -> (u64, Vec<Item>) {
+        self.flush_scheduled();
+
+        let (cursor, mut items) = self.segments.scan(cursor, count, &mut self.hashtable);
+        items.retain(|item| self.tags.is_valid(item.tags()));
+
+        // items which are yet to be removed by the purge are skipped
+        if let Some(purge) = self.purge.as_ref().filter(|purge| !purge.progress.done) {
+            let (segments, hashtable) = (&mut self.segments, &mut self.hashtable);
+            items.retain(|item| {
+                !purge.pattern.matches(item.key())
+                    || !hashtable
+                        .item_info(item.key(), segments)
+                        .map(|item_info| segments.is_purging(item_info))
+                        .unwrap_or(false)
+            });
+        }
+
+        (cursor, items)
+    }
+
+    /// Removes every item with a key which matches the pattern. Patterns are
+    /// globs where `*` matches any sequence of bytes, `?` matches any single
+    /// byte, and `\` escapes the following byte, so a prefix is matched with
+    /// a pattern such as `session:*`.
+    ///
+    /// The items are removed incrementally by subsequent calls to `expire()`,
+    /// and `delete_matching_progress()` reports how far the purge has
+    /// progressed. Starting a purge abandons any purge which is in progress.
+    /// Only items written before the call are removed, and lookups treat them
+    /// as misses until the purge completes. Matching items in the disk tier
+    /// are removed when the purge starts, and matching items which are evicted
+    /// while the purge runs are not demoted.
+    /// ```
+    /// use segcache::Segcache;
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Segcache::builder().build().expect("failed to create cache");
+    ///
+    /// cache.insert(b"session:1", b"a", None, Duration::ZERO);
+    /// cache.insert(b"session:2", b"b", None, Duration::ZERO);
+    /// cache.insert(b"user:1", b"c", None, Duration::ZERO);
+    ///
+    /// cache.delete_matching(b"session:*");
+    /// while !cache.delete_matching_progress().unwrap().done {
+    ///     cache.expire();
+    /// }
+    ///
+    /// assert_eq!(cache.delete_matching_progress().unwrap().items_deleted, 2);
+    /// assert!(cache.get(b"session:1").is_none());
+    /// assert!(cache.get(b"user:1").is_some());
+    /// ```
+    pub fn delete_matching(&mut self, pattern: &[u8]) {
+        if let Some(purge) = self.purge.as_mut() {
+            purge.finish();
+        }
+
+        self.segments.start_purge();
+
+        let mut purge = Purge::new(pattern, self.segments.mapped());
+
+        // the disk tier index is held in memory, so it is purged immediately
+        if let Some(tier) = self.hashtable.tier_mut() {
+            purge.removed(tier.remove_matching(&purge.pattern));
+            tier.set_purge(Some(purge.pattern.clone()));
+        }
+
+        self.purge = Some(purge);
+    }
+
+    /// Returns the progress of the current purge, or of the most recent one
+    /// if it has completed. Returns `None` if `delete_matching()` was never
+    /// called.
+    pub fn delete_matching_progress(&self) -> Option<PurgeProgress> {
+        self.purge.as_ref().map(|purge| purge.progress)
+    }
+
+    /// Removes the matching items from the next `PURGE_STEP` items visited by
+    /// the current purge.
+    fn purge_step(&mut self) {
+        let purge = match self.purge.as_mut() {
+            Some(purge) if !purge.progress.done => purge,
+            _ => {
+                return;
+            }
+        };
+
+        let (cursor, items) =
+            self.segments
+                .purge_scan(purge.cursor, PURGE_STEP, &mut self.hashtable);
+
+        let keys: Vec<Box<[u8]>> = items
+            .iter()
+            .map(|item| item.key())
+            .filter(|key| purge.pattern.matches(key))
+            .map(Box::from)
+            .collect();
+
+        let deleted = keys.iter().filter(|key| self.delete(key)).count();
+
+        // segments merged into after they were scanned need another pass
+        let pending = cursor == 0 && self.segments.purge_pending();
+
+        if let Some(purge) = self.purge.as_mut() {
+            purge.advance(cursor, self.segments.mapped(), deleted, pending);
+            if purge.progress.done {
+                self.segments.finish_purge();
+                if let Some(tier) = self.hashtable.tier_mut() {
+                    tier.set_purge(None);
+                }
+            }
+        }
     }
 
     /// Loops through the TTL Buckets to handle eager expiration, returns the
-    /// number of segments expired
+    /// number of segments expired. If an `ExpireBudget` was provided to the
+    /// `Builder`, this stops once the budget is exhausted and the next call
+    /// resumes where it stopped.
     /// ```
     /// use segcache::{Policy, Segcache, SegcacheError};
     /// use std::time::Duration;
@@ -284,16 +983,111 @@ impl Segcache {
     /// ```
     pub fn expire(&mut self) -> usize {
         self.time = Instant::now();
-        self.ttl_buckets
-            .expire(&mut self.hashtable, &mut self.segments)
+
+        self.flush_scheduled();
+
+        if let Some(namespaces) = self.namespaces.as_mut() {
+            namespaces.update_metrics(&self.segments);
+        }
+
+        self.hashtable
+            .expand_step(EXPIRE_EXPAND_STEP, &mut self.segments);
+
+        self.leases.expire();
+
+        self.purge_step();
+
+        let expired = self.ttl_buckets.expire(
+            &mut self.hashtable,
+            &mut self.segments,
+            self.expire_budget,
+            self.stale_grace,
+        );
+
+        self.prune_tags();
+
+        // the gauge is shared by all caches, so only the change since the
+        // last update is applied
+        #[cfg(feature = "metrics")]
+        {
+            let backlog =
+                self.ttl_buckets
+                    .expire_backlog(&mut self.segments, self.stale_grace) as i64;
+            EXPIRE_BACKLOG.add(backlog - self.expire_backlog);
+            self.expire_backlog = backlog;
+        }
+
+        expired
     }
 
+    /// Removes all items from the cache, returns the number of segments
+    /// cleared. This replaces any clear which was scheduled by
+    /// `clear_after()`.
     pub fn clear(&mut self) -> usize {
         self.time = Instant::now();
+        self.clear_at = None;
+
+        if let Some(tier) = self.hashtable.tier_mut() {
+            tier.clear();
+        }
+
+        self.leases.clear();
+
         self.ttl_buckets
             .clear(&mut self.hashtable, &mut self.segments)
     }
 
+    /// Schedules all items which are in the cache when the delay has passed to
+    /// be removed, like memcache `flush_all <delay>`. Once the delay has
+    /// passed, the items stored before it are treated as misses and are
+    /// removed by later calls to `expire()`, while items stored after it are
+    /// kept. A zero delay clears the cache immediately. This replaces any
+    /// clear which was scheduled earlier.
+    ///
+    /// ```
+    /// use segcache::Segcache;
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Segcache::builder().build().expect("failed to create cache");
+    ///
+    /// cache.insert(b"coffee", b"strong", None, Duration::ZERO);
+    /// cache.clear_after(Duration::from_secs(2));
+    ///
+    /// // the item remains until the delay has passed
+    /// cache.expire();
+    /// assert!(cache.get(b"coffee").is_some());
+    ///
+    /// std::thread::sleep(Duration::from_secs(3));
+    /// assert!(cache.get(b"coffee").is_none());
+    /// ```
+    pub fn clear_after(&mut self, delay: std::time::Duration) {
+        if delay.is_zero() {
+            self.clear();
+            return;
+        }
+
+        let delay = Duration::from_secs(min(delay.as_secs(), u32::MAX as u64) as u32);
+        self.clear_at = Some(Instant::now() + delay);
+    }
+
+    /// Flushes the segments once the clear scheduled by `clear_after()` is
+    /// due. Segments created before the flush are no longer written to and
+    /// their items are hidden from lookups until they are expired. Every item
+    /// in the disk tier was demoted before the flush, so it is cleared.
+    fn flush_scheduled(&mut self) {
+        let at = match self.clear_at {
+            Some(at) if at <= Instant::now() => at,
+            _ => return,
+        };
+
+        self.clear_at = None;
+        self.segments.set_flush_at(at);
+
+        if let Some(tier) = self.hashtable.tier_mut() {
+            tier.clear();
+        }
+    }
+
     /// Checks the integrity of all segments
     /// *NOTE*: this operation is relatively expensive
     #[cfg(feature = "debug")]
@@ -309,9 +1103,12 @@ impl Segcache {
     /// Returns an error if the key is invalid, the item is not found, or the
     /// stored value is not a numeric type.
     pub fn wrapping_add(&mut self, key: &[u8], rhs: u64) -> Result<Item, SegcacheError> {
+        self.promote(key);
+        self.remove_invalid(key);
         let mut item = self
             .hashtable
             .get(key, self.time, &mut self.segments)
+            .filter(|item| !item.is_stale())
             .ok_or(SegcacheError::NotFound)?;
         item.wrapping_add(rhs)?;
         Ok(item)
@@ -321,11 +1118,379 @@ impl Segcache {
     /// key. Returns an error if the key is invalid, the item is not found, or
     /// the stored value is not a numeric type.
     pub fn saturating_sub(&mut self, key: &[u8], rhs: u64) -> Result<Item, SegcacheError> {
+        self.promote(key);
+        self.remove_invalid(key);
         let mut item = self
             .hashtable
             .get(key, self.time, &mut self.segments)
+            .filter(|item| !item.is_stale())
             .ok_or(SegcacheError::NotFound)?;
         item.saturating_sub(rhs)?;
         Ok(item)
     }
+
+    /// Add a signed delta to the integer value stored at the supplied key,
+    /// like Redis `INCRBY` and `DECRBY`. Unsigned values are converted, and
+    /// the value is stored as a signed integer afterwards. Returns an error if
+    /// the item is not found, if the stored value is not an integer, or if the
+    /// value or the result is outside the range of an `i64`. The stored value
+    /// is unchanged if an error is returned.
+    ///
+    /// ```
+    /// use segcache::{Segcache, SegcacheError, Value};
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Segcache::builder().build().expect("failed to create cache");
+    ///
+    /// cache.insert(b"count", Value::I64(-5), None, Duration::ZERO);
+    /// let item = cache.checked_add(b"count", -10).expect("not an integer");
+    /// assert_eq!(item.value(), Value::I64(-15));
+    ///
+    /// assert_eq!(
+    ///     cache.checked_add(b"count", i64::MIN).err(),
+    ///     Some(SegcacheError::Overflow)
+    /// );
+    /// ```
+    pub fn checked_add(&mut self, key: &[u8], rhs: i64) -> Result<Item, SegcacheError> {
+        self.promote(key);
+        self.remove_invalid(key);
+        let mut item = self
+            .hashtable
+            .get(key, self.time, &mut self.segments)
+            .filter(|item| !item.is_stale())
+            .ok_or(SegcacheError::NotFound)?;
+        item.checked_add(rhs)?;
+        Ok(item)
+    }
+
+    /// Add a delta to the numeric value stored at the supplied key, like Redis
+    /// `INCRBYFLOAT`. Integer values are converted, and the value is stored as
+    /// a float afterwards. Returns an error if the item is not found, if the
+    /// stored value is not numeric, or if the result would be NaN or infinite.
+    /// The stored value is unchanged if an error is returned.
+    pub fn checked_add_float(&mut self, key: &[u8], rhs: f64) -> Result<Item, SegcacheError> {
+        self.promote(key);
+        self.remove_invalid(key);
+        let mut item = self
+            .hashtable
+            .get(key, self.time, &mut self.segments)
+            .filter(|item| !item.is_stale())
+            .ok_or(SegcacheError::NotFound)?;
+        item.checked_add_float(rhs)?;
+        Ok(item)
+    }
+
+    /// Returns the namespace for the key.
+    fn resolve_namespace(&self, key: &[u8]) -> u16 {
+        self.namespaces
+            .as_ref()
+            .map(|namespaces| namespaces.resolve(key))
+            .unwrap_or(0)
+    }
+
+    /// Reserves space for an item of the given size in the `TtlBucket` for the
+    /// namespace and TTL, evicting segments as necessary. Segments are evicted
+    /// from a namespace which is over its quota if there is one.
+    fn reserve(
+        &mut self,
+        namespace: u16,
+        size: usize,
+        ttl: Duration,
+    ) -> Result<ReservedItem, SegcacheError> {
+        let mut retries = RESERVE_RETRIES;
+        loop {
+            match self
+                .ttl_buckets
+                .get_mut_bucket(namespace, ttl)
+                .reserve(size, &mut self.segments)
+            {
+                Ok(reserved_item) => return Ok(reserved_item),
+                Err(TtlBucketsError::ItemOversized { size }) => {
+                    return Err(SegcacheError::ItemOversized { size });
+                }
+                Err(TtlBucketsError::NoFreeSegments) => {
+                    // items larger than a segment need a run of adjacent
+                    // segments to be freed
+                    let victim = self
+                        .namespaces
+                        .as_ref()
+                        .and_then(|namespaces| namespaces.victim(namespace, &self.segments));
+
+                    // if the victim namespace has nothing which can be
+                    // evicted, we fall back to evicting from any namespace
+                    let mut evicted = Err(SegmentsError::NoEvictableSegments);
+                    for namespace in [victim, None] {
+                        evicted = if size > self.segments.segment_size() as usize {
+                            self.segments.evict_span(
+                                size,
+                                &mut self.ttl_buckets,
+                                &mut self.hashtable,
+                                namespace,
+                            )
+                        } else {
+                            self.segments.evict(
+                                &mut self.ttl_buckets,
+                                &mut self.hashtable,
+                                namespace,
+                            )
+                        };
+
+                        if evicted.is_ok() || victim.is_none() {
+                            break;
+                        }
+                    }
+
+                    if evicted.is_err() {
+                        retries -= 1;
+                    } else {
+                        // we successfully evicted a segment, return to start of
+                        // loop to reserve the item
+                        continue;
+                    }
+                }
+            }
+            if retries == 0 {
+                // segment acquire failed, increment the stats and return with
+                // an error
+
+                #[cfg(feature = "metrics")]
+                {
+                    SEGMENT_REQUEST.increment();
+                    SEGMENT_REQUEST_FAILURE.increment();
+                }
+
+                return Err(SegcacheError::NoFreeSegments);
+            }
+            retries -= 1;
+        }
+    }
+
+    /// Records the write and returns true if it should be admitted. Writes are
+    /// always admitted while there are free segments or if the key is already
+    /// in the cache. A write which is not admitted is dropped and reported to
+    /// the caller as `SegcacheError::NotAdmitted`.
+    fn admit(&mut self, key: &[u8]) -> bool {
+        let admission = match self.admission.as_mut() {
+            Some(admission) => admission,
+            None => return true,
+        };
+
+        admission.record(key);
+
+        if self.segments.free() > 0
+            || self
+                .hashtable
+                .get_no_freq_incr(key, &mut self.segments)
+                .is_some()
+        {
+            return true;
+        }
+
+        if admission.admit(key) {
+            #[cfg(feature = "metrics")]
+            ADMISSION_ADMIT.increment();
+
+            true
+        } else {
+            #[cfg(feature = "metrics")]
+            ADMISSION_REJECT.increment();
+
+            false
+        }
+    }
+
+    /// Drops the tag invalidations which happened before every segment in the
+    /// heap and the disk tier was created.
+    fn prune_tags(&mut self) {
+        if !self.tags.active() {
+            return;
+        }
+
+        let oldest = [
+            self.segments.oldest(),
+            self.hashtable.tier().and_then(|tier| tier.oldest()),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+        .unwrap_or(self.time);
+
+        self.tags.prune(oldest);
+    }
+
+    /// Removes the item with the key if it must not be returned to the caller,
+    /// either because it was invalidated or because it is corrupted.
+    fn remove_invalid(&mut self, key: &[u8]) {
+        self.remove_flushed(key);
+
+        #[cfg(feature = "checksum")]
+        self.remove_corrupted(key);
+
+        self.remove_invalidated(key);
+        self.remove_purged(key);
+    }
+
+    /// Removes the item with the key if it was written before the purge in
+    /// progress started and matches its pattern, so that the lookup is treated
+    /// as a miss before the purge reaches the item.
+    fn remove_purged(&mut self, key: &[u8]) {
+        let purge = match self.purge.as_mut() {
+            Some(purge) if !purge.progress.done && purge.pattern.matches(key) => purge,
+            _ => return,
+        };
+
+        let purging = self
+            .hashtable
+            .item_info(key, &mut self.segments)
+            .map(|item_info| self.segments.is_purging(item_info))
+            .unwrap_or(false);
+
+        if purging
+            && self
+                .hashtable
+                .delete(key, &mut self.ttl_buckets, &mut self.segments)
+        {
+            purge.removed(1);
+        }
+    }
+
+    /// Removes the item with the key if it was stored before the segments were
+    /// flushed and has not been expired yet.
+    fn remove_flushed(&mut self, key: &[u8]) {
+        let flushed = self
+            .hashtable
+            .item_info(key, &mut self.segments)
+            .map(|item_info| self.segments.is_flushed(item_info))
+            .unwrap_or(false);
+
+        if flushed {
+            self.hashtable
+                .delete(key, &mut self.ttl_buckets, &mut self.segments);
+        }
+    }
+
+    /// Removes the item with the key if it does not match its checksum.
+    #[cfg(feature = "checksum")]
+    fn remove_corrupted(&mut self, key: &[u8]) {
+        let item_info = match self.hashtable.item_info(key, &mut self.segments) {
+            Some(item_info) => item_info,
+            None => return,
+        };
+
+        if !self.segments.is_intact(item_info) {
+            #[cfg(feature = "metrics")]
+            ITEM_CORRUPTED.increment();
+
+            self.hashtable
+                .delete(key, &mut self.ttl_buckets, &mut self.segments);
+        }
+    }
+
+    /// Removes the item with the key if its value was compressed and cannot be
+    /// decompressed, so that the lookup is treated as a miss.
+    fn remove_undecompressable(&mut self, key: &[u8], item: Option<Item>) -> Option<Item> {
+        let item = item?;
+        if item.decompress() {
+            return Some(item);
+        }
+
+        #[cfg(feature = "metrics")]
+        ITEM_DECOMPRESS_ERROR.increment();
+
+        self.hashtable
+            .delete(key, &mut self.ttl_buckets, &mut self.segments);
+
+        None
+    }
+
+    /// Returns true if the item with the key is past its TTL and only kept for
+    /// the grace period.
+    fn is_stale(&mut self, key: &[u8]) -> bool {
+        self.hashtable
+            .item_info(key, &mut self.segments)
+            .map(|item_info| self.segments.is_stale(item_info))
+            .unwrap_or(false)
+    }
+
+    /// Removes the item with the key if any of its tags were invalidated after
+    /// it was inserted.
+    fn remove_invalidated(&mut self, key: &[u8]) {
+        if !self.tags.active() {
+            return;
+        }
+
+        let valid = self
+            .hashtable
+            .get_no_freq_incr(key, &mut self.segments)
+            .map(|item| self.tags.is_valid(item.tags()))
+            .unwrap_or(true);
+
+        if !valid {
+            #[cfg(feature = "metrics")]
+            ITEM_TAG_INVALIDATED.increment();
+
+            self.hashtable
+                .delete(key, &mut self.ttl_buckets, &mut self.segments);
+        }
+    }
+
+    /// Moves the item with the given key from the disk tier back into the
+    /// heap. This does nothing if there is no disk tier or if the item is not
+    /// held in it. Every lookup starts here, so a scheduled flush which is due
+    /// is applied first.
+    ///
+    /// Items are demoted as they are evicted, so an item evicted after a flush
+    /// or a tag invalidation may still be held in the tier. These are dropped
+    /// rather than promoted.
+    fn promote(&mut self, key: &[u8]) {
+        self.flush_scheduled();
+
+        let (bytes, created, ttl) =
+            match self.hashtable.tier_mut().and_then(|tier| tier.remove(key)) {
+                Some(demoted) => demoted,
+                None => return,
+            };
+
+        let item = RawItem::from_ptr(bytes.as_ptr() as *mut u8);
+        if created < self.segments.flush_at() || !self.tags.is_valid(item.tags()) {
+            return;
+        }
+
+        let ttl = Duration::from_secs(min(u32::MAX as u64, ttl.as_secs() as u64) as u32);
+
+        let namespace = self.resolve_namespace(key);
+        let mut reserved = match self.reserve(namespace, bytes.len(), ttl) {
+            Ok(reserved) => reserved,
+            Err(_) => return,
+        };
+        reserved.copy_from(&bytes);
+
+        if self
+            .hashtable
+            .insert(
+                reserved.item(),
+                reserved.seg(),
+                reserved.offset() as u64,
+                &mut self.ttl_buckets,
+                &mut self.segments,
+            )
+            .is_err()
+        {
+            let _ = self.segments.remove_at(
+                reserved.seg(),
+                reserved.offset(),
+                &mut self.ttl_buckets,
+                &mut self.hashtable,
+            );
+        } else {
+            #[cfg(feature = "metrics")]
+            DISK_PROMOTE.increment();
+        }
+    }
+}
+
+/// Items which are kept past their TTL for the grace period are only returned
+/// to callers which asked for them, everywhere else they are a miss.
+fn fresh(item: Option<Item>) -> Option<Item> {
+    item.filter(|item| !item.is_stale())
 }
@@ -15,7 +15,9 @@ pub(crate) struct SegmentsBuilder {
     pub(super) heap_size: usize,
     pub(super) segment_size: i32,
     pub(super) evict_policy: Policy,
+    pub(super) custom_policy: Option<Box<dyn EvictionPolicy>>,
     pub(super) datapool_path: Option<PathBuf>,
+    pub(super) datapool_key_path: Option<PathBuf>,
 }
 
 impl Default for SegmentsBuilder {
@@ -24,7 +26,9 @@ impl Default for SegmentsBuilder {
             segment_size: 1024 * 1024,
             heap_size: 64 * 1024 * 1024,
             evict_policy: Policy::Random,
+            custom_policy: None,
             datapool_path: None,
+            datapool_key_path: None,
         }
     }
 }
@@ -62,14 +66,45 @@ impl SegmentsBuilder {
         self
     }
 
+    /// Specify a user-defined [`EvictionPolicy`] which replaces the eviction
+    /// [`Policy`].
+    pub fn custom_eviction_policy(mut self, policy: Box<dyn EvictionPolicy>) -> Self {
+        self.custom_policy = Some(policy);
+        self
+    }
+
     /// Specify a backing file to be used for the segment storage. If provided,
     /// a file will be created at the corresponding path and used for segment
-    /// storage.
+    /// storage. If the file already exists, the segments will be restored from
+    /// it.
     pub fn datapool_path<T: AsRef<Path>>(mut self, path: Option<T>) -> Self {
         self.datapool_path = path.map(|p| p.as_ref().to_owned());
         self
     }
 
+    /// Specify a key file used to encrypt the backing file. If provided, the
+    /// segment storage is kept in memory and is encrypted when it is written
+    /// to the backing file.
+    pub fn datapool_key_path<T: AsRef<Path>>(mut self, path: Option<T>) -> Self {
+        self.datapool_key_path = path.map(|p| p.as_ref().to_owned());
+        self
+    }
+
+    /// Returns true if the segments will be stored in an encrypted file.
+    pub fn encrypted(&self) -> bool {
+        self.datapool_path.is_some() && self.datapool_key_path.is_some()
+    }
+
+    /// Returns the path of the file which holds the tag invalidations for the
+    /// datapool, if the segments will be stored in a file.
+    pub(crate) fn tags_path(&self) -> Option<PathBuf> {
+        self.datapool_path.as_ref().map(|path| {
+            let mut path = path.as_os_str().to_owned();
+            path.push(".tags");
+            path.into()
+        })
+    }
+
     /// Construct the [`Segments`] from the builder
     pub fn build(self) -> Result<Segments, std::io::Error> {
         Segments::from_builder(self)
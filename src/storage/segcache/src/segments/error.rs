@@ -16,4 +16,8 @@ pub enum SegmentsError {
     NoEvictableSegments,
     #[error("evict failure")]
     EvictFailure,
+    #[error("invalid heap size")]
+    InvalidHeapSize,
+    #[error("heap resize failure")]
+    ResizeFailure,
 }
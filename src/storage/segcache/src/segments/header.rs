@@ -15,15 +15,18 @@
 //! │   PREV SEG   │   NEXT SEG   │  CREATE AT   │   MERGE AT   │
 //! │              │              │              │              │
 //! │    32 bit    │    32 bit    │    32 bit    │    32 bit    │
-//! ├──────────────┼──┬──┬────────┴──────────────┴──────────────┤
-//! │     TTL      │  │  │               PADDING                │   Accessible
-//! │              │  │◀─┼──────────────────────────────────────┼──    8 bit
-//! │    32 bit    │8b│8b│                80 bit                │
-//! ├──────────────┴──┴──┴──────────────────────────────────────┤    Evictable
-//! │                          PADDING                          │      8 bit
-//! │                                                           │
-//! │                          128 bit                          │
-//! └───────────────────────────────────────────────────────────┘
+//! ├──────────────┼──┬──┬────────┼──────────────┼──────────────┤
+//! │     TTL      │  │  │   NS   │     SPAN     │    OWNER     │   Accessible
+//! │              │  │◀─┼────────┼──────────────┼──────────────┼──    8 bit
+//! │    32 bit    │8b│8b│ 16 bit │    32 bit    │    32 bit    │
+//! ├──┬──┬──┬─────┴──┴──┴────────┴──────────────┴──────────────┤    Evictable
+//! │  │  │  │                 PADDING                          │      8 bit
+//! │  │  │  │                                                  │
+//! │8b│8b│8b│                 104 bit                          │      Stale
+//! └──┴──┴──┴──────────────────────────────────────────────────┘      8 bit
+//!   ▲  ▲
+//!   │  └── Purge Scanned 8 bit
+//!   └───── Purging       8 bit
 //! ```
 
 use super::SEG_MAGIC;
@@ -35,6 +38,8 @@ use crate::*;
 // TODO(bmartin): this should be parameterized.
 const SEG_MATURE_TIME: Duration = Duration::from_secs(20);
 
+/// Metadata about a segment. A read-only view of the headers is provided to an
+/// [`EvictionPolicy`] to choose which segment to evict.
 #[derive(Debug)]
 #[repr(C)]
 pub struct SegmentHeader {
@@ -60,11 +65,28 @@ pub struct SegmentHeader {
     accessible: bool,
     /// Is the segment evictable?
     evictable: bool,
-    _pad: [u8; 25],
+    /// The namespace which the segment belongs to, only set while the segment
+    /// is linked into a `TtlBucket`
+    namespace: u16,
+    /// The number of segments following this one which hold the remainder of
+    /// an item that is larger than a segment
+    span: u32,
+    /// The segment holding the start of the item which this segment is part
+    /// of, only set for the segments following the head of a span
+    owner: Option<NonZeroU32>,
+    /// Has the segment passed its TTL? A stale segment is only kept around
+    /// during the grace period, it is no longer written to.
+    stale: bool,
+    /// Was the segment in use when the current purge started? Its items may
+    /// be removed by the purge.
+    purging: bool,
+    /// Has the current purge scanned the segment since it was last written?
+    scanned: bool,
+    _pad: [u8; 13],
 }
 
 impl SegmentHeader {
-    pub fn new(id: NonZeroU32) -> Self {
+    pub(crate) fn new(id: NonZeroU32) -> Self {
         let now = Instant::now();
         Self {
             id,
@@ -78,11 +100,17 @@ impl SegmentHeader {
             merge_at: now,
             accessible: false,
             evictable: false,
-            _pad: [0; 25],
+            namespace: NO_NAMESPACE,
+            span: 0,
+            owner: None,
+            stale: false,
+            purging: false,
+            scanned: false,
+            _pad: [0; 13],
         }
     }
 
-    pub fn init(&mut self) {
+    pub(crate) fn init(&mut self) {
         // TODO(bmartin): should these be `debug_assert` or are we enforcing
         // invariants? Eitherway, keeping them before changing values in the
         // header is probably wise?
@@ -99,10 +127,11 @@ impl SegmentHeader {
         self.create_at = now;
         self.merge_at = now;
         self.accessible = true;
+        self.stale = false;
     }
 
     // TODO(bmartin): maybe have some debug_assert for n_item == 0 ?
-    pub fn reset(&mut self) {
+    pub(crate) fn reset(&mut self) {
         let offset = if cfg!(feature = "magic") {
             std::mem::size_of_val(&SEG_MAGIC) as i32
         } else {
@@ -111,9 +140,12 @@ impl SegmentHeader {
 
         self.write_offset = offset;
         self.live_bytes = offset;
+        self.purging = false;
+        self.scanned = false;
     }
 
     #[inline]
+    /// Returns the id of the segment.
     pub fn id(&self) -> NonZeroU32 {
         self.id
     }
@@ -127,7 +159,7 @@ impl SegmentHeader {
     #[inline]
     /// Sets the write offset to the provided value. Typically used when
     /// resetting the segment.
-    pub fn set_write_offset(&mut self, bytes: i32) {
+    pub(crate) fn set_write_offset(&mut self, bytes: i32) {
         self.write_offset = bytes;
     }
 
@@ -135,7 +167,7 @@ impl SegmentHeader {
     /// Moves the write offset forward by some number of bytes and returns the
     /// previous value. This is used as part of writing a new item to reserve
     /// some number of bytes and return the position to begin writing.
-    pub fn incr_write_offset(&mut self, bytes: i32) -> i32 {
+    pub(crate) fn incr_write_offset(&mut self, bytes: i32) -> i32 {
         let prev = self.write_offset;
         self.write_offset += bytes;
         prev
@@ -149,7 +181,7 @@ impl SegmentHeader {
 
     #[inline]
     /// Set whether the segment is accessible.
-    pub fn set_accessible(&mut self, accessible: bool) {
+    pub(crate) fn set_accessible(&mut self, accessible: bool) {
         self.accessible = accessible;
     }
 
@@ -161,10 +193,88 @@ impl SegmentHeader {
 
     #[inline]
     /// Set whether the segment is evictable.
-    pub fn set_evictable(&mut self, evictable: bool) {
+    pub(crate) fn set_evictable(&mut self, evictable: bool) {
         self.evictable = evictable;
     }
 
+    #[inline]
+    /// Has the segment passed its TTL and entered the grace period?
+    pub fn stale(&self) -> bool {
+        self.stale
+    }
+
+    #[inline]
+    /// Mark the segment as having passed its TTL.
+    pub(crate) fn set_stale(&mut self, stale: bool) {
+        self.stale = stale;
+    }
+
+    #[inline]
+    /// Was the segment in use when the current purge started?
+    pub(crate) fn purging(&self) -> bool {
+        self.purging
+    }
+
+    #[inline]
+    /// Set whether the segment was in use when the current purge started.
+    pub(crate) fn set_purging(&mut self, purging: bool) {
+        self.purging = purging;
+        self.scanned = false;
+    }
+
+    #[inline]
+    /// Has the current purge scanned the segment since it was last written?
+    pub(crate) fn scanned(&self) -> bool {
+        self.scanned
+    }
+
+    #[inline]
+    /// Set whether the current purge has scanned the segment.
+    pub(crate) fn set_scanned(&mut self, scanned: bool) {
+        self.scanned = scanned;
+    }
+
+    #[inline]
+    /// Returns the namespace which the segment belongs to, or `u16::MAX` if
+    /// the segment is not linked into a `TtlBucket`.
+    pub fn namespace(&self) -> u16 {
+        self.namespace
+    }
+
+    #[inline]
+    /// Set the namespace which the segment belongs to.
+    pub(crate) fn set_namespace(&mut self, namespace: u16) {
+        self.namespace = namespace;
+    }
+
+    #[inline]
+    /// Returns the number of segments following this one which hold the
+    /// remainder of a large item. This is zero for regular segments.
+    pub fn span(&self) -> u32 {
+        self.span
+    }
+
+    #[inline]
+    /// Set the number of segments following this one which hold the remainder
+    /// of a large item.
+    pub(crate) fn set_span(&mut self, span: u32) {
+        self.span = span;
+    }
+
+    #[inline]
+    /// Returns the id of the segment at the head of the span if this segment
+    /// holds part of a large item.
+    pub fn owner(&self) -> Option<NonZeroU32> {
+        self.owner
+    }
+
+    #[inline]
+    /// Set the id of the segment at the head of the span which this segment is
+    /// part of.
+    pub(crate) fn set_owner(&mut self, id: Option<NonZeroU32>) {
+        self.owner = id;
+    }
+
     #[inline]
     /// The number of live items within the segment.
     pub fn live_items(&self) -> i32 {
@@ -173,13 +283,13 @@ impl SegmentHeader {
 
     #[inline]
     /// Increment the number of live items.
-    pub fn incr_live_items(&mut self) {
+    pub(crate) fn incr_live_items(&mut self) {
         self.live_items += 1;
     }
 
     #[inline]
     /// Decrement the number of live items.
-    pub fn decr_live_items(&mut self) {
+    pub(crate) fn decr_live_items(&mut self) {
         self.live_items -= 1;
     }
 
@@ -191,7 +301,7 @@ impl SegmentHeader {
 
     #[inline]
     /// Sets the TTL for the segment.
-    pub fn set_ttl(&mut self, ttl: Duration) {
+    pub(crate) fn set_ttl(&mut self, ttl: Duration) {
         self.ttl = ttl.as_secs();
     }
 
@@ -203,7 +313,7 @@ impl SegmentHeader {
 
     #[inline]
     /// Increment the number of bytes used in the segment.
-    pub fn incr_live_bytes(&mut self, bytes: i32) -> i32 {
+    pub(crate) fn incr_live_bytes(&mut self, bytes: i32) -> i32 {
         let prev = self.live_bytes;
         self.live_bytes += bytes;
         prev
@@ -211,7 +321,7 @@ impl SegmentHeader {
 
     #[inline]
     /// Decrement the number of bytes used in the segment.
-    pub fn decr_live_bytes(&mut self, bytes: i32) -> i32 {
+    pub(crate) fn decr_live_bytes(&mut self, bytes: i32) -> i32 {
         let prev = self.live_bytes;
         self.live_bytes -= bytes;
         prev
@@ -226,7 +336,7 @@ impl SegmentHeader {
     #[inline]
     /// Set the previous segment to some id. Passing a negative id results in
     /// clearing the previous segment pointer.
-    pub fn set_prev_seg(&mut self, id: Option<NonZeroU32>) {
+    pub(crate) fn set_prev_seg(&mut self, id: Option<NonZeroU32>) {
         self.prev_seg = id;
     }
 
@@ -239,7 +349,7 @@ impl SegmentHeader {
     #[inline]
     /// Set the next segment to some id. Passing a negative id results in
     /// clearing the next segment pointer.
-    pub fn set_next_seg(&mut self, id: Option<NonZeroU32>) {
+    pub(crate) fn set_next_seg(&mut self, id: Option<NonZeroU32>) {
         self.next_seg = id;
     }
 
@@ -251,19 +361,46 @@ impl SegmentHeader {
 
     #[inline]
     /// Update the created time
-    pub fn mark_created(&mut self) {
+    pub(crate) fn mark_created(&mut self) {
         self.create_at = Instant::now();
     }
 
+    #[inline]
+    /// Set the created time, used when restoring the segment from a datapool
+    /// that was written by a previous process.
+    pub(crate) fn set_create_at(&mut self, instant: Instant) {
+        self.create_at = instant;
+    }
+
     #[inline]
     /// Returns the instant at which the segment was merged
     pub fn merge_at(&self) -> Instant {
         self.merge_at
     }
 
+    #[inline]
+    /// Set the merged time, used when restoring the segment from a datapool
+    /// that was written by a previous process.
+    pub(crate) fn set_merge_at(&mut self, instant: Instant) {
+        self.merge_at = instant;
+    }
+
+    /// Zero the live item and byte counts so that they can be recounted while
+    /// scanning the segment.
+    pub(crate) fn clear_live(&mut self) {
+        let offset = if cfg!(feature = "magic") {
+            std::mem::size_of_val(&SEG_MAGIC) as i32
+        } else {
+            0
+        };
+
+        self.live_bytes = offset;
+        self.live_items = 0;
+    }
+
     #[inline]
     /// Update the created time
-    pub fn mark_merged(&mut self) {
+    pub(crate) fn mark_merged(&mut self) {
         self.merge_at = Instant::now();
     }
 
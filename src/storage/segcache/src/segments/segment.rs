@@ -28,12 +28,19 @@ impl<'a> Segment<'a> {
     /// Initialize the segment. Sets the magic bytes in the data segment (if the
     /// feature is enabled) and initializes the header fields.
     pub fn init(&mut self) {
+        self.write_magic();
+        self.header.init();
+    }
+
+    /// Sets the magic bytes in the data segment (if the feature is enabled).
+    /// Segments which held part of a large item have had their magic bytes
+    /// overwritten and must have them restored before they are reused.
+    pub fn write_magic(&mut self) {
         if cfg!(feature = "magic") {
             for (i, byte) in SEG_MAGIC.to_be_bytes().iter().enumerate() {
                 self.data[i] = *byte;
             }
         }
-        self.header.init();
     }
 
     #[cfg(feature = "magic")]
@@ -59,10 +66,13 @@ impl<'a> Segment<'a> {
     ///
     /// This function will panic if the magic bytes do not match the expected
     /// value. This would indicate data corruption or that the segment was
-    /// constructed from invalid data.
+    /// constructed from invalid data. Segments which hold the remainder of a
+    /// large item do not have magic bytes and are not checked.
     pub fn check_magic(&self) {
         #[cfg(feature = "magic")]
-        assert_eq!(self.magic(), SEG_MAGIC)
+        if self.header.owner().is_none() {
+            assert_eq!(self.magic(), SEG_MAGIC)
+        }
     }
 
     /// Convenience function which is used as a stop point for scanning through
@@ -183,18 +193,56 @@ impl<'a> Segment<'a> {
         self.header.set_evictable(evictable)
     }
 
+    /// Returns whether the segment has passed its TTL and is being kept for
+    /// the grace period.
+    #[inline]
+    pub fn stale(&self) -> bool {
+        self.header.stale()
+    }
+
+    /// Mark whether the segment has passed its TTL.
+    #[inline]
+    pub fn set_stale(&mut self, stale: bool) {
+        self.header.set_stale(stale)
+    }
+
+    /// Returns whether the segment was in use when the current purge started.
+    #[inline]
+    pub(crate) fn purging(&self) -> bool {
+        self.header.purging()
+    }
+
+    /// Mark whether the current purge has scanned the segment.
+    #[inline]
+    pub(crate) fn set_scanned(&mut self, scanned: bool) {
+        self.header.set_scanned(scanned)
+    }
+
     /// Performs some checks to determine if the segment can actually be evicted
     #[inline]
     pub fn can_evict(&self) -> bool {
         self.header.can_evict()
     }
 
+    #[inline]
+    /// Returns the number of segments following this one which hold the
+    /// remainder of a large item.
+    pub fn span(&self) -> u32 {
+        self.header.span()
+    }
+
     /// Return the segment's TTL
     #[inline]
     pub fn ttl(&self) -> Duration {
         self.header.ttl()
     }
 
+    /// Return the namespace the segment belongs to
+    #[inline]
+    pub fn namespace(&self) -> u16 {
+        self.header.namespace()
+    }
+
     /// Set the segment's TTL, used when linking it into a TtlBucket
     #[inline]
     pub fn set_ttl(&mut self, ttl: Duration) {
@@ -298,7 +346,8 @@ impl<'a> Segment<'a> {
 
     /// Remove an item based on its offset into the segment
     pub(crate) fn remove_item_at(&mut self, offset: usize) {
-        let item = self.get_item_at(offset).unwrap();
+        let mut item = self.get_item_at(offset).unwrap();
+        item.set_deleted();
 
         let item_size = item.size() as i64;
 
@@ -328,6 +377,30 @@ impl<'a> Segment<'a> {
         }))
     }
 
+    /// Returns false if the item at the offset extends past the end of the
+    /// segment, or the span of segments which hold a large item, or if it
+    /// does not match its checksum. The segment data covers the whole span.
+    pub(crate) fn is_intact(&mut self, offset: usize) -> bool {
+        let item = self.get_item_at(offset).unwrap();
+        offset + item.size() <= self.data.len() && item.is_intact()
+    }
+
+    /// Removes the item at the offset if it fails its checksum, returning true
+    /// if it was removed. Items are never removed unless the `checksum`
+    /// feature is enabled.
+    fn remove_corrupted(&mut self, hashtable: &mut HashTable, offset: usize) -> bool {
+        if !cfg!(feature = "checksum") || self.is_intact(offset) {
+            return false;
+        }
+
+        let item = self.get_item_at(offset).unwrap();
+        if !hashtable.remove_corrupted(item.key(), offset as i32, self) {
+            // the item should be linked, but it must not be kept either way
+            self.remove_item_at(offset);
+        }
+        true
+    }
+
     /// This is used as part of segment merging, it moves all occupied space to
     /// the beginning of the segment, leaving the end of the segment free
     #[allow(clippy::unnecessary_wraps)]
@@ -356,8 +429,9 @@ impl<'a> Segment<'a> {
 
             let item_size = item.size();
 
-            // don't copy deleted items
-            let deleted = !hashtable.is_item_at(item.key(), self.id(), read_offset as u64);
+            // don't copy deleted or corrupted items
+            let deleted = !hashtable.is_item_at(item.key(), self.id(), read_offset as u64)
+                || self.remove_corrupted(hashtable, read_offset);
             if deleted {
                 #[cfg(feature = "metrics")]
                 {
@@ -455,8 +529,10 @@ impl<'a> Segment<'a> {
 
             let write_offset = target.write_offset() as usize;
 
-            // skip deleted items and ones that won't fit in the target segment
-            let deleted = !hashtable.is_item_at(item.key(), self.id(), read_offset as u64);
+            // skip deleted or corrupted items and ones that won't fit in the
+            // target segment
+            let deleted = !hashtable.is_item_at(item.key(), self.id(), read_offset as u64)
+                || self.remove_corrupted(hashtable, read_offset);
             if deleted || write_offset + item_size >= target.data.len() {
                 read_offset += item_size;
                 continue;
@@ -549,7 +625,8 @@ impl<'a> Segment<'a> {
 
             let item_size = item.size();
 
-            let deleted = !hashtable.is_item_at(item.key(), self.id(), offset as u64);
+            let deleted = !hashtable.is_item_at(item.key(), self.id(), offset as u64)
+                || self.remove_corrupted(hashtable, offset);
             if deleted {
                 // do we need to evict again here? Why is that done in the C code?
                 offset += item_size;
@@ -5,8 +5,11 @@
 use crate::eviction::*;
 use crate::item::*;
 use crate::segments::*;
+use clocksource::coarse::UnixInstant;
 use core::num::NonZeroU32;
 use datatier::*;
+use std::io::ErrorKind;
+use std::path::Path;
 
 /// `Segments` contain all items within the cache. This struct is a collection
 /// of individual `Segment`s which are represented by a `SegmentHeader` and a
@@ -28,6 +31,10 @@ pub(crate) struct Segments {
     flush_at: Instant,
     /// Eviction configuration and state
     evict: Box<Eviction>,
+    /// Segments were loaded from an existing datapool and must be restored
+    restored: bool,
+    /// The datapool is backed by a file
+    persistent: bool,
 }
 
 impl Segments {
@@ -51,72 +58,634 @@ impl Segments {
 
         debug!("eviction policy: {:?}", evict_policy);
 
-        let mut headers = Vec::with_capacity(0);
-        headers.reserve_exact(segments);
-        for id in 0..segments {
-            // safety: we start iterating from 1 and seg id is constrained to < 2^24
-            let header = SegmentHeader::new(unsafe { NonZeroU32::new_unchecked(id as u32 + 1) });
-            headers.push(header);
+        let mut evict = Box::new(Eviction::new(segments, evict_policy));
+        if let Some(policy) = builder.custom_policy {
+            debug!("using custom eviction policy");
+            evict.set_custom(policy);
         }
-        let mut headers = headers.into_boxed_slice();
+
+        let mut headers = Self::new_headers(segments);
 
         let heap_size = segments * segment_size as usize;
 
-        // TODO(bmartin): we will need to make additional changes before we
-        // allow restoring state from an existing datapool file, for now this
-        // retains the previous behavior and always creates a new file to mmap
-        // if a datapool path is provided.
-        let mut data: Box<dyn Datapool> = if let Some(file) = builder.datapool_path {
-            Box::new(MmapFile::create(file, heap_size, crate::VERSION)?)
+        // file backed datapools also store the segment headers after the
+        // segment data so that they can be restored by a future process
+        let metadata_size = segments * std::mem::size_of::<SegmentHeader>();
+
+        // if the datapool file already exists, we open it and restore the
+        // segments. Otherwise, a new datapool is created. If a key is provided,
+        // the datapool is held in memory and only written to the file encrypted.
+        let persistent = builder.datapool_path.is_some();
+        let key = builder
+            .datapool_key_path
+            .filter(|_| persistent)
+            .map(Key::from_file)
+            .transpose()?;
+        let (mut data, mut restored) = if let Some(file) = builder.datapool_path {
+            Self::open_datapool(&file, heap_size + metadata_size, key)?
         } else {
-            Box::new(Memory::create(heap_size)?)
+            (
+                Box::new(Memory::create(heap_size)?) as Box<dyn Datapool>,
+                None,
+            )
         };
 
-        for idx in 0..segments {
-            let begin = segment_size as usize * idx;
-            let end = begin + segment_size as usize;
-
-            let mut segment =
-                Segment::from_raw_parts(&mut headers[idx], &mut data.as_mut_slice()[begin..end]);
-            segment.init();
+        if let Some((time_monotonic_s, time_unix_s)) = restored {
+            // safety: the metadata region was written from the headers by
+            // `flush()` and has been verified against the datapool checksum
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    data.as_slice()[heap_size..].as_ptr(),
+                    headers.as_mut_ptr() as *mut u8,
+                    metadata_size,
+                );
+            }
 
-            let id = idx as u32 + 1; // we index segments from 1
-            segment.set_prev_seg(NonZeroU32::new(id - 1));
-            if id < segments as u32 {
-                segment.set_next_seg(NonZeroU32::new(id + 1));
+            // the segments are initialized as if the datapool was new if the
+            // headers do not match the configuration
+            if let Err(e) =
+                Self::load_headers(&mut headers, segment_size, time_monotonic_s, time_unix_s)
+            {
+                warn!("discarding datapool which could not be restored: {}", e);
+                headers = Self::new_headers(segments);
+                restored = None;
             }
         }
 
+        let (free, free_q, flush_at) = if restored.is_some() {
+            // the free queue is rebuilt once the segments are restored. The
+            // restored segments may have been created before this process
+            // started, so we must not treat them as flushed.
+            (0, None, Instant::default())
+        } else {
+            for idx in 0..segments {
+                let begin = segment_size as usize * idx;
+                let end = begin + segment_size as usize;
+
+                let mut segment = Segment::from_raw_parts(
+                    &mut headers[idx],
+                    &mut data.as_mut_slice()[begin..end],
+                );
+                segment.init();
+
+                let id = idx as u32 + 1; // we index segments from 1
+                segment.set_prev_seg(NonZeroU32::new(id - 1));
+                if id < segments as u32 {
+                    segment.set_next_seg(NonZeroU32::new(id + 1));
+                }
+            }
+
+            (segments, NonZeroU32::new(1), Instant::now())
+        };
+
         #[cfg(feature = "metrics")]
         {
             SEGMENT_CURRENT.set(segments as _);
-            SEGMENT_FREE.set(segments as _);
+            SEGMENT_FREE.set(free as _);
         }
 
         Ok(Self {
             headers,
             segment_size,
             cap: segments as u32,
-            free: segments as u32,
-            free_q: NonZeroU32::new(1),
+            free: free as u32,
+            free_q,
             data,
-            flush_at: Instant::now(),
-            evict: Box::new(Eviction::new(segments, evict_policy)),
+            flush_at,
+            evict,
+            restored: restored.is_some(),
+            persistent,
         })
     }
 
+    /// Allocates the headers for the given number of segments.
+    fn new_headers(segments: usize) -> Box<[SegmentHeader]> {
+        let mut headers = Vec::with_capacity(0);
+        headers.reserve_exact(segments);
+        for id in 0..segments {
+            // safety: we start iterating from 1 and seg id is constrained to < 2^24
+            let header = SegmentHeader::new(unsafe { NonZeroU32::new_unchecked(id as u32 + 1) });
+            headers.push(header);
+        }
+        headers.into_boxed_slice()
+    }
+
+    /// Opens the datapool file at the given path, or creates it if it does not
+    /// exist. Returns the datapool and, if an existing datapool was opened, the
+    /// times at which it was last flushed.
+    ///
+    /// An existing file which fails validation, for example because it was not
+    /// cleanly flushed or the heap size or version has changed, is replaced by
+    /// a new datapool so that the cache starts cold. A file which was written
+    /// with a different key, or without one, is refused instead.
+    #[allow(clippy::type_complexity)]
+    fn open_datapool(
+        path: &Path,
+        size: usize,
+        key: Option<Key>,
+    ) -> Result<(Box<dyn Datapool>, Option<(Instant, UnixInstant)>), std::io::Error> {
+        if path.exists() {
+            let pool: Result<(Box<dyn Datapool>, _), _> = match key.clone() {
+                Some(key) => EncryptedFile::open(path, size, crate::VERSION, key).map(|pool| {
+                    let time = (pool.time_monotonic_s(), pool.time_unix_s());
+                    (Box::new(pool) as Box<dyn Datapool>, Some(time))
+                }),
+                None => MmapFile::open(path, size, crate::VERSION).map(|pool| {
+                    let time = (pool.time_monotonic_s(), pool.time_unix_s());
+                    (Box::new(pool) as Box<dyn Datapool>, Some(time))
+                }),
+            };
+
+            match pool {
+                Ok(pool) => return Ok(pool),
+                Err(e) if e.kind() == ErrorKind::PermissionDenied => return Err(e),
+                Err(e) => {
+                    warn!("discarding datapool which could not be restored: {}", e);
+                    std::fs::remove_file(path)?;
+                }
+            }
+        }
+
+        let pool: Box<dyn Datapool> = match key {
+            Some(key) => Box::new(EncryptedFile::create(path, size, crate::VERSION, key)?),
+            None => Box::new(MmapFile::create(path, size, crate::VERSION)?),
+        };
+
+        Ok((pool, None))
+    }
+
+    /// Validates the segment headers which were loaded from an existing
+    /// datapool and shifts their create and merge times onto the current
+    /// coarse clock by using the time the datapool was flushed. Segments which
+    /// expired while the cache was not running are marked as not evictable so
+    /// that they will be dropped from their segment chains.
+    fn load_headers(
+        headers: &mut [SegmentHeader],
+        segment_size: i32,
+        time_monotonic_s: Instant,
+        time_unix_s: UnixInstant,
+    ) -> Result<(), std::io::Error> {
+        let segments = headers.len();
+        let now = Instant::now();
+
+        // account for the time that passed while there was no process running
+        let downtime = UnixInstant::now()
+            .checked_duration_since(time_unix_s)
+            .unwrap_or_default();
+
+        for (idx, header) in headers.iter_mut().enumerate() {
+            let in_range = |id: Option<NonZeroU32>| id.map(|id| id.get() as usize <= segments);
+
+            // the head of a span may be written beyond the end of the segment
+            let max_offset = segment_size as i64 * (header.span() as i64 + 1);
+
+            if header.id().get() as usize != idx + 1
+                || header.write_offset() < 0
+                || header.write_offset() as i64 > max_offset
+                || idx + header.span() as usize >= segments
+                || in_range(header.prev_seg()) == Some(false)
+                || in_range(header.next_seg()) == Some(false)
+                || in_range(header.owner()) == Some(false)
+            {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "datapool does not match the segment configuration",
+                ));
+            }
+
+            let create_age = time_monotonic_s
+                .checked_duration_since(header.create_at())
+                .unwrap_or_default()
+                + downtime;
+            let merge_age = time_monotonic_s
+                .checked_duration_since(header.merge_at())
+                .unwrap_or_default()
+                + downtime;
+
+            if header.evictable() && create_age.as_secs() >= header.ttl().as_secs() {
+                debug!("segment: {} expired during restart", header.id());
+                header.set_evictable(false);
+
+                #[cfg(feature = "metrics")]
+                SEGMENT_EXPIRE.increment();
+            }
+
+            // if the host was rebooted recently, the segment may have been
+            // created before the earliest representable instant
+            header.set_create_at(now.checked_sub(create_age).unwrap_or_default());
+            header.set_merge_at(now.checked_sub(merge_age).unwrap_or_default());
+
+            // a purge does not survive a restart
+            header.set_purging(false);
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if the segments were loaded from an existing datapool and
+    /// have not yet been restored.
+    pub(crate) fn restored(&self) -> bool {
+        self.restored
+    }
+
+    /// Links the segments which were restored from an existing datapool into
+    /// their `TtlBucket`s and indexes their items in the hashtable by scanning
+    /// each segment. Returns the number of items which were restored. This has
+    /// no effect for a newly created datapool.
+    pub(crate) fn restore(
+        &mut self,
+        ttl_buckets: &mut TtlBuckets,
+        hashtable: &mut HashTable,
+    ) -> usize {
+        if !self.restored {
+            return 0;
+        }
+
+        let mut restored = 0;
+        for id in self.relink(ttl_buckets) {
+            restored += self.restore_items(id, ttl_buckets, hashtable);
+        }
+
+        debug!("restored {} items", restored);
+
+        restored
+    }
+
+    /// Links the segments which were restored from an existing datapool into
+    /// their `TtlBucket`s by using the segment headers from a checkpoint. The
+    /// headers must exactly match those stored in the datapool. Returns the ids
+    /// of all the segments which are linked into a `TtlBucket`. It is up to the
+    /// caller to restore the hashtable and drop any items which are not stored
+    /// in these segments.
+    pub(crate) fn restore_checkpoint(
+        &mut self,
+        headers: &[u8],
+        ttl_buckets: &mut TtlBuckets,
+    ) -> Result<Vec<NonZeroU32>, std::io::Error> {
+        let heap_size = self.cap as usize * self.segment_size as usize;
+
+        if !self.restored
+            || self
+                .data
+                .as_slice()
+                .get(heap_size..(heap_size + headers.len()))
+                != Some(headers)
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "checkpoint does not match the datapool",
+            ));
+        }
+
+        let ids = self.relink(ttl_buckets);
+
+        #[cfg(feature = "metrics")]
+        {
+            let offset = if cfg!(feature = "magic") {
+                std::mem::size_of_val(&SEG_MAGIC) as i64
+            } else {
+                0
+            };
+
+            // NOTE: the number of dead items is not tracked in the headers, so
+            // only the dead bytes can be restored without scanning
+            for id in ids.iter() {
+                let header = &self.headers[id.get() as usize - 1];
+                ITEM_RESTORE.add(header.live_items() as _);
+                ITEM_CURRENT.add(header.live_items() as _);
+                ITEM_CURRENT_BYTES.add(header.live_bytes() as i64 - offset);
+                ITEM_DEAD_BYTES.add((header.write_offset() - header.live_bytes()) as _);
+            }
+        }
+
+        Ok(ids)
+    }
+
+    /// Relinks the restored segment chains into the `TtlBuckets`, skipping any
+    /// segments which expired while the cache was not running. All segments
+    /// which are not part of a chain are initialized and linked into a new free
+    /// queue. Returns the ids of all the segments which were linked into a
+    /// `TtlBucket`.
+    fn relink(&mut self, ttl_buckets: &mut TtlBuckets) -> Vec<NonZeroU32> {
+        self.restored = false;
+
+        let segments = self.headers.len();
+        let mut live = vec![false; segments];
+        let mut linked = Vec::new();
+
+        // walk each segment chain starting from the heads, the chains are the
+        // full chain for a single ttl bucket. Heads which were relinked will
+        // appear again as a segment with no previous segment, but will be
+        // skipped because they are already live.
+        for idx in 0..segments {
+            if self.headers[idx].prev_seg().is_some() {
+                continue;
+            }
+
+            let mut head = None;
+            let mut tail: Option<NonZeroU32> = None;
+            let mut nseg = 0;
+            let mut next = Some(self.headers[idx].id());
+            let mut steps = 0;
+
+            while let Some(id) = next {
+                let id_idx = id.get() as usize - 1;
+
+                // stop if the chain loops back on itself
+                steps += 1;
+                if steps > segments || live[id_idx] {
+                    break;
+                }
+
+                next = self.headers[id_idx].next_seg();
+
+                // segments belonging to a namespace which is no longer
+                // configured are dropped
+                if !self.headers[id_idx].evictable()
+                    || self.headers[id_idx].namespace() >= ttl_buckets.namespaces()
+                {
+                    continue;
+                }
+
+                self.headers[id_idx].set_prev_seg(tail);
+                self.headers[id_idx].set_next_seg(None);
+                if let Some(tail) = tail {
+                    self.headers[tail.get() as usize - 1].set_next_seg(Some(id));
+                }
+
+                head = head.or(Some(id));
+                tail = Some(id);
+                nseg += 1;
+                live[id_idx] = true;
+                linked.push(id);
+            }
+
+            if let (Some(head), Some(tail)) = (head, tail) {
+                let header = &self.headers[head.get() as usize - 1];
+                ttl_buckets
+                    .get_mut_bucket(header.namespace(), header.ttl())
+                    .restore(head, tail, nseg);
+            }
+        }
+
+        // the segments holding the remainder of a large item remain part of the
+        // span if its head was linked
+        self.evict.namespace_segments.clear();
+        for id in linked.iter() {
+            let id_idx = id.get() as usize - 1;
+            let span = self.headers[id_idx].span() as usize;
+
+            let namespace = self.headers[id_idx].namespace();
+            self.headers[id_idx].set_namespace(NO_NAMESPACE);
+            self.assign_namespace(*id, namespace);
+
+            for header in self.headers[(id_idx + 1)..=(id_idx + span)].iter_mut() {
+                header.set_owner(Some(*id));
+                live[header.id().get() as usize - 1] = true;
+            }
+        }
+
+        // all remaining segments are initialized and placed on the free queue,
+        // we push them in reverse order so the queue is ordered by id
+        self.free = 0;
+        self.free_q = None;
+
+        for idx in (0..segments).rev() {
+            if live[idx] {
+                #[cfg(feature = "metrics")]
+                SEGMENT_RESTORE.increment();
+
+                continue;
+            }
+
+            let begin = self.segment_size as usize * idx;
+            let end = begin + self.segment_size as usize;
+
+            self.headers[idx].set_evictable(false);
+            self.headers[idx].set_accessible(false);
+            self.headers[idx].set_span(0);
+            self.headers[idx].set_owner(None);
+            self.headers[idx].set_namespace(NO_NAMESPACE);
+
+            let mut segment = Segment::from_raw_parts(
+                &mut self.headers[idx],
+                &mut self.data.as_mut_slice()[begin..end],
+            );
+            segment.init();
+
+            let id = segment.id();
+            self.push_front(id, self.free_q);
+            self.free_q = Some(id);
+            self.free += 1;
+        }
+
+        #[cfg(feature = "metrics")]
+        SEGMENT_FREE.set(self.free as _);
+
+        linked
+    }
+
+    /// Scans a restored segment and links all items which were not removed
+    /// into the hashtable. The live item and byte counts are recalculated.
+    fn restore_items(
+        &mut self,
+        id: NonZeroU32,
+        ttl_buckets: &mut TtlBuckets,
+        hashtable: &mut HashTable,
+    ) -> usize {
+        let id_idx = id.get() as usize - 1;
+        let write_offset = self.headers[id_idx].write_offset() as usize;
+        self.headers[id_idx].clear_live();
+
+        let mut offset = if cfg!(feature = "magic") {
+            std::mem::size_of_val(&SEG_MAGIC)
+        } else {
+            0
+        };
+
+        let mut restored = 0;
+
+        while offset + ITEM_HDR_SIZE <= write_offset {
+            let mut item = self.get_item_at(Some(id), offset).unwrap();
+            if item.klen() == 0 {
+                break;
+            }
+
+            item.check_magic();
+
+            let item_size = item.size();
+
+            if !item.is_deleted()
+                && hashtable
+                    .insert(item, id, offset as u64, ttl_buckets, self)
+                    .is_ok()
+            {
+                self.headers[id_idx].incr_live_items();
+                self.headers[id_idx].incr_live_bytes(item_size as i32);
+                restored += 1;
+
+                #[cfg(feature = "metrics")]
+                {
+                    ITEM_RESTORE.increment();
+                    ITEM_CURRENT.increment();
+                    ITEM_CURRENT_BYTES.add(item_size as _);
+                }
+            } else {
+                item.set_deleted();
+
+                #[cfg(feature = "metrics")]
+                {
+                    ITEM_DEAD.increment();
+                    ITEM_DEAD_BYTES.add(item_size as _);
+                }
+            }
+
+            offset += item_size;
+        }
+
+        restored
+    }
+
+    /// Returns the raw bytes of the segment headers. These are written into the
+    /// datapool on flush and are also stored in checkpoints.
+    pub(crate) fn headers_bytes(&self) -> &[u8] {
+        // safety: the headers are plain data and the slice covers exactly the
+        // memory occupied by the headers
+        unsafe {
+            std::slice::from_raw_parts(
+                self.headers.as_ptr() as *const u8,
+                std::mem::size_of_val(&*self.headers),
+            )
+        }
+    }
+
+    /// Returns the raw bytes of the segment headers as they were last flushed
+    /// into the datapool, or as they were loaded from an existing datapool.
+    pub(crate) fn flushed_headers_bytes(&self) -> &[u8] {
+        let heap_size = self.cap as usize * self.segment_size as usize;
+        let metadata_size = self.headers_bytes().len();
+        &self.data.as_slice()[heap_size..(heap_size + metadata_size)]
+    }
+
+    /// Returns true if the datapool is backed by a file and can be restored by
+    /// a future process.
+    pub(crate) fn persistent(&self) -> bool {
+        self.persistent
+    }
+
+    /// Returns the key used to encrypt the datapool file, if it is encrypted.
+    pub(crate) fn key(&self) -> Option<&Key> {
+        self.data.key()
+    }
+
+    /// Writes the segment headers into the datapool and flushes it so that the
+    /// segments can be restored by a future process. This has no effect for
+    /// datapools which are not backed by a file.
+    pub(crate) fn flush(&mut self) -> Result<(), std::io::Error> {
+        if !self.persistent() {
+            return Ok(());
+        }
+
+        let heap_size = self.cap as usize * self.segment_size as usize;
+        let metadata_size = self.headers_bytes().len();
+
+        // safety: the headers are plain data and are not aliased by the
+        // datapool
+        let metadata = unsafe {
+            std::slice::from_raw_parts(self.headers.as_ptr() as *const u8, metadata_size)
+        };
+
+        self.data.as_mut_slice()[heap_size..(heap_size + metadata_size)].copy_from_slice(metadata);
+        self.data.flush()
+    }
+
     /// Return the size of each segment in bytes
     #[inline]
     pub fn segment_size(&self) -> i32 {
         self.segment_size
     }
 
+    /// Returns the number of segments following the head of a span which are
+    /// needed to hold an item of the given size. This is zero for items which
+    /// fit within a single segment. Returns `None` if the item is too large to
+    /// be held by the heap.
+    pub(crate) fn span(&self, size: usize) -> Option<u32> {
+        let offset = if cfg!(feature = "magic") {
+            std::mem::size_of_val(&SEG_MAGIC)
+        } else {
+            0
+        };
+
+        let span = (offset + size.max(1) - 1) / self.segment_size as usize;
+
+        if span < self.cap as usize {
+            Some(span as u32)
+        } else {
+            None
+        }
+    }
+
     /// Returns the number of free segments
-    #[cfg(test)]
     pub fn free(&self) -> usize {
         self.free as usize
     }
 
+    /// Returns the total number of segments
+    pub fn cap(&self) -> usize {
+        self.cap as usize
+    }
+
+    /// Returns the size of the heap in bytes
+    pub fn heap_size(&self) -> usize {
+        self.cap as usize * self.segment_size as usize
+    }
+
+    /// Returns the number of live items and live bytes held by each of the
+    /// namespaces.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn namespace_usage(&self, namespaces: usize) -> Vec<(i64, i64)> {
+        let offset = if cfg!(feature = "magic") {
+            std::mem::size_of_val(&SEG_MAGIC) as i64
+        } else {
+            0
+        };
+
+        let mut usage = vec![(0, 0); namespaces];
+        for header in self.headers.iter() {
+            if let Some((items, bytes)) = usage.get_mut(header.namespace() as usize) {
+                *items += header.live_items() as i64;
+                *bytes += header.live_bytes() as i64 - offset;
+            }
+        }
+        usage
+    }
+
+    /// Returns the number of segments used by the namespace
+    pub fn namespace_segments(&self, namespace: u16) -> usize {
+        self.evict
+            .namespace_segments
+            .get(namespace as usize)
+            .copied()
+            .unwrap_or(0) as usize
+    }
+
+    /// Assigns a segment which is being linked into a `TtlBucket` to the
+    /// namespace. The segments holding the remainder of a large item are
+    /// counted towards the namespace of the head of the span.
+    pub(crate) fn assign_namespace(&mut self, id: NonZeroU32, namespace: u16) {
+        let header = &mut self.headers[id.get() as usize - 1];
+        debug_assert_eq!(header.namespace(), NO_NAMESPACE);
+        header.set_namespace(namespace);
+
+        let segments = header.span() + 1;
+        if self.evict.namespace_segments.len() <= namespace as usize {
+            self.evict
+                .namespace_segments
+                .resize(namespace as usize + 1, 0);
+        }
+        self.evict.namespace_segments[namespace as usize] += segments;
+    }
+
     /// Returns the time the segments were last flushed
     pub fn flush_at(&self) -> Instant {
         self.flush_at
@@ -127,6 +696,84 @@ impl Segments {
         self.flush_at = instant;
     }
 
+    /// Returns the creation time of the oldest segment which holds items, if
+    /// any segment holds items.
+    pub(crate) fn oldest(&self) -> Option<Instant> {
+        self.headers
+            .iter()
+            .filter(|header| header.evictable())
+            .map(|header| header.create_at())
+            .min()
+    }
+
+    /// Returns true if the segment holding the item was created before the
+    /// segments were last flushed.
+    pub(crate) fn is_flushed(&self, item_info: u64) -> bool {
+        get_seg_id(item_info)
+            .map(|id| self.headers[id.get() as usize - 1].create_at() < self.flush_at)
+            .unwrap_or(false)
+    }
+
+    /// Returns the namespace of the segment holding the item.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn namespace_of(&self, item_info: u64) -> Option<u16> {
+        get_seg_id(item_info).map(|id| self.headers[id.get() as usize - 1].namespace())
+    }
+
+    /// Returns true if the segment holding the item has passed its TTL and is
+    /// only kept for the grace period.
+    pub(crate) fn is_stale(&self, item_info: u64) -> bool {
+        get_seg_id(item_info)
+            .map(|id| self.headers[id.get() as usize - 1].stale())
+            .unwrap_or(false)
+    }
+
+    /// Marks the segments which currently hold items as subject to a purge
+    /// which is starting now. These segments are not written to again until
+    /// the purge completes, so only items written before the purge started
+    /// are held in marked segments.
+    pub(crate) fn start_purge(&mut self) {
+        for header in self.headers.iter_mut() {
+            let purging = header.evictable();
+            header.set_purging(purging);
+        }
+    }
+
+    /// Clears the purge marks once the purge has completed.
+    pub(crate) fn finish_purge(&mut self) {
+        for header in self.headers.iter_mut() {
+            header.set_purging(false);
+        }
+    }
+
+    /// Returns true if any segment marked by the current purge has not been
+    /// scanned since it was last merged into.
+    pub(crate) fn purge_pending(&self) -> bool {
+        self.headers
+            .iter()
+            .any(|header| header.purging() && !header.scanned())
+    }
+
+    /// Returns true if the item with the item info is held in a segment which
+    /// is marked by the current purge.
+    pub(crate) fn is_purging(&self, item_info: u64) -> bool {
+        get_seg_id(item_info)
+            .map(|id| self.headers[id.get() as usize - 1].purging())
+            .unwrap_or(false)
+    }
+
+    /// Returns false if the item with the item info fails its checksum.
+    #[cfg(feature = "checksum")]
+    pub(crate) fn is_intact(&mut self, item_info: u64) -> bool {
+        match get_seg_id(item_info) {
+            Some(seg_id) => self
+                .get_mut(seg_id)
+                .map(|mut segment| segment.is_intact(get_offset(item_info) as usize))
+                .unwrap_or(false),
+            None => false,
+        }
+    }
+
     /// Retrieve a `RawItem` from the segment id and offset encoded in the
     /// item info.
     pub(crate) fn get_item(&mut self, item_info: u64) -> Option<RawItem> {
@@ -144,18 +791,129 @@ impl Segments {
     ) -> Option<RawItem> {
         let seg_id = seg_id.map(|v| v.get())?;
         trace!("getting item from: seg: {} offset: {}", seg_id, offset);
-        assert!(seg_id <= self.cap);
+        assert!(seg_id as usize <= self.headers.len());
 
-        let seg_begin = self.segment_size() as usize * (seg_id as usize - 1);
-        let seg_end = seg_begin + self.segment_size() as usize;
+        let range = self.data_range(seg_id as usize - 1);
         let mut segment = Segment::from_raw_parts(
             &mut self.headers[seg_id as usize - 1],
-            &mut self.data.as_mut_slice()[seg_begin..seg_end],
+            &mut self.data.as_mut_slice()[range],
         );
 
         segment.get_item_at(offset)
     }
 
+    /// Walks the segments in id order starting from the position encoded in
+    /// the cursor and returns up to `count` live and unexpired items along
+    /// with the cursor to resume from. A returned cursor of zero indicates
+    /// that all segments have been visited.
+    ///
+    /// The cursor holds the segment id in the upper 32 bits and the byte
+    /// offset within the segment in the lower 32 bits. Resuming always
+    /// re-walks the item headers from the start of the segment, so a cursor
+    /// remains safe to use if the segment was evicted and reused between
+    /// calls.
+    pub(crate) fn scan(
+        &mut self,
+        cursor: u64,
+        count: usize,
+        hashtable: &mut HashTable,
+    ) -> (u64, Vec<Item>) {
+        self.walk(cursor, count, hashtable, false)
+    }
+
+    /// Walks the segments marked by the current purge in the same way as
+    /// `scan()`. Segments which were already scanned are skipped, and a
+    /// segment which was merged into since the cursor entered it is scanned
+    /// again from the start. Segments which are merged into after they were
+    /// scanned are left for another pass, see `purge_pending()`.
+    pub(crate) fn purge_scan(
+        &mut self,
+        cursor: u64,
+        count: usize,
+        hashtable: &mut HashTable,
+    ) -> (u64, Vec<Item>) {
+        self.walk(cursor, count, hashtable, true)
+    }
+
+    fn walk(
+        &mut self,
+        cursor: u64,
+        count: usize,
+        hashtable: &mut HashTable,
+        purge: bool,
+    ) -> (u64, Vec<Item>) {
+        let mut items = Vec::with_capacity(count);
+
+        let mut id = std::cmp::max(1, cursor >> 32);
+        let mut resume = (cursor & 0xFFFF_FFFF) as usize;
+
+        let now = Instant::now();
+
+        while id <= self.headers.len() as u64 {
+            // safety: id is within 1..=segments which is non-zero
+            let seg_id = unsafe { NonZeroU32::new_unchecked(id as u32) };
+
+            if purge {
+                let header = &mut self.headers[id as usize - 1];
+                if !header.purging() || (header.scanned() && resume == 0) {
+                    id += 1;
+                    resume = 0;
+                    continue;
+                }
+                // items may have moved if the segment was merged into
+                if !header.scanned() {
+                    resume = 0;
+                }
+                header.set_scanned(true);
+            }
+
+            let header = &self.headers[id as usize - 1];
+
+            // free segments and segments pending expiration are skipped, but a
+            // purge must also remove the items kept for the grace period
+            let expired =
+                header.create_at() + header.ttl() <= now || header.create_at() < self.flush_at;
+            if !header.accessible() || (expired && !purge) {
+                id += 1;
+                resume = 0;
+                continue;
+            }
+
+            let write_offset = header.write_offset() as usize;
+
+            let mut offset = if cfg!(feature = "magic") {
+                std::mem::size_of_val(&SEG_MAGIC)
+            } else {
+                0
+            };
+
+            while offset + ITEM_HDR_SIZE <= write_offset {
+                let item = self.get_item_at(Some(seg_id), offset).unwrap();
+                if item.klen() == 0 {
+                    break;
+                }
+
+                item.check_magic();
+
+                if offset >= resume {
+                    if items.len() == count {
+                        return ((id << 32) | offset as u64, items);
+                    }
+                    if let Some(item) = hashtable.get_at(item, seg_id, offset as u64) {
+                        items.push(item);
+                    }
+                }
+
+                offset += item.size();
+            }
+
+            id += 1;
+            resume = 0;
+        }
+
+        (0, items)
+    }
+
     /// Tries to clear a segment by id
     fn clear_segment(
         &mut self,
@@ -177,14 +935,35 @@ impl Segments {
         }
     }
 
+    /// Returns true if the segment belongs to the namespace, or if no namespace
+    /// is provided.
+    fn in_namespace(&self, idx: usize, namespace: Option<u16>) -> bool {
+        namespace
+            .map(|namespace| self.headers[idx].namespace() == namespace)
+            .unwrap_or(true)
+    }
+
     /// Perform eviction based on the configured eviction policy. A success from
     /// this function indicates that a segment was put onto the free queue and
-    /// that `pop_free()` should return some segment id.
+    /// that `pop_free()` should return some segment id. If a namespace is
+    /// provided, only segments belonging to that namespace are evicted.
     pub fn evict(
         &mut self,
         ttl_buckets: &mut TtlBuckets,
         hashtable: &mut HashTable,
+        namespace: Option<u16>,
     ) -> Result<(), SegmentsError> {
+        // while the heap is shrinking, the segments beyond the new end of the
+        // heap are evicted first. They are retired rather than freed, so we
+        // still evict another segment below
+        if self.headers.len() > self.cap as usize {
+            self.retire_next(ttl_buckets, hashtable);
+        }
+
+        if self.evict.is_custom() {
+            return self.evict_custom(ttl_buckets, hashtable, namespace);
+        }
+
         #[cfg(feature = "metrics")]
         let now = Instant::now();
 
@@ -196,17 +975,51 @@ impl Segments {
                 let mut seg_idx = self.evict.random();
 
                 seg_idx %= self.cap;
-                let ttl = self.headers[seg_idx as usize].ttl();
-                let offset = ttl_buckets.get_bucket_index(ttl);
-                let buckets = ttl_buckets.buckets.len();
+                let header = &self.headers[seg_idx as usize];
+                let mut offset = ttl_buckets.get_bucket_index(header.ttl());
+                if namespace.is_none() && header.namespace() != NO_NAMESPACE {
+                    offset += ttl_buckets.namespace_range(header.namespace()).start;
+                }
+
+                let range = match namespace {
+                    Some(namespace) => ttl_buckets.namespace_range(namespace),
+                    None => 0..ttl_buckets.buckets.len(),
+                };
+                let buckets = range.len();
+
+                // since merging starts in the middle of a segment chain, we may
+                // need to loop back around to the first ttl bucket we checked
+                for i in 0..=buckets {
+                    let bucket_id = range.start + (offset + i) % buckets;
+                    let ttl_bucket = &mut ttl_buckets.buckets[bucket_id];
+                    if let Some(first_seg) = ttl_bucket.head() {
+                        let start = ttl_bucket.next_to_merge().unwrap_or(first_seg);
+                        let start_idx = start.get() as usize - 1;
+
+                        // large items are never merged, instead the whole span
+                        // is evicted
+                        if self.headers[start_idx].span() > 0 {
+                            if !self.headers[start_idx].can_evict() {
+                                ttl_bucket.set_next_to_merge(None);
+                                continue;
+                            }
+
+                            let next = self.headers[start_idx].next_seg();
+                            if self.headers[start_idx].prev_seg().is_none() {
+                                ttl_bucket.set_head(next);
+                            }
+                            ttl_bucket.set_next_to_merge(next);
+
+                            self.clear_segment(start, hashtable, false)
+                                .map_err(|_| SegmentsError::EvictFailure)?;
+                            self.push_free(start);
+
+                            #[cfg(feature = "metrics")]
+                            EVICT_TIME.add(now.elapsed().as_nanos() as _);
+
+                            return Ok(());
+                        }
 
-                // since merging starts in the middle of a segment chain, we may
-                // need to loop back around to the first ttl bucket we checked
-                for i in 0..=buckets {
-                    let bucket_id = (offset + i) % buckets;
-                    let ttl_bucket = &mut ttl_buckets.buckets[bucket_id];
-                    if let Some(first_seg) = ttl_bucket.head() {
-                        let start = ttl_bucket.next_to_merge().unwrap_or(first_seg);
                         match self.merge_evict(start, hashtable) {
                             Ok(next_to_merge) => {
                                 debug!("merged ttl_bucket: {} seg: {}", bucket_id, start);
@@ -246,7 +1059,7 @@ impl Segments {
                 #[cfg(feature = "metrics")]
                 SEGMENT_EVICT.increment();
 
-                if let Some(id) = self.least_valuable_seg(ttl_buckets) {
+                if let Some(id) = self.least_valuable_seg(ttl_buckets, namespace) {
                     let result = self
                         .clear_segment(id, hashtable, false)
                         .map_err(|_| SegmentsError::EvictFailure);
@@ -260,8 +1073,10 @@ impl Segments {
 
                     let id_idx = id.get() as usize - 1;
                     if self.headers[id_idx].prev_seg().is_none() {
-                        let ttl_bucket = ttl_buckets.get_mut_bucket(self.headers[id_idx].ttl());
-                        ttl_bucket.set_head(self.headers[id_idx].next_seg());
+                        let header = &self.headers[id_idx];
+                        let ttl_bucket =
+                            ttl_buckets.get_mut_bucket(header.namespace(), header.ttl());
+                        ttl_bucket.set_head(header.next_seg());
                     }
                     self.push_free(id);
 
@@ -282,16 +1097,416 @@ impl Segments {
         }
     }
 
+    /// Perform eviction using the user-defined `EvictionPolicy`. The segment
+    /// which the policy chooses is checked before it is evicted or merged.
+    fn evict_custom(
+        &mut self,
+        ttl_buckets: &mut TtlBuckets,
+        hashtable: &mut HashTable,
+        namespace: Option<u16>,
+    ) -> Result<(), SegmentsError> {
+        #[cfg(feature = "metrics")]
+        let now = Instant::now();
+
+        #[cfg(feature = "metrics")]
+        SEGMENT_EVICT.increment();
+
+        let result = match self.evict.choose(&self.headers, namespace) {
+            Some(Decision::Evict(id)) => self.evict_chosen(id, ttl_buckets, hashtable, namespace),
+            Some(Decision::Merge(id)) => {
+                let id_idx = id.get() as usize - 1;
+                if self.headers.get(id_idx).map(|h| h.span() > 0) == Some(false) {
+                    self.merge_chosen(id, hashtable, namespace)
+                } else {
+                    self.evict_chosen(id, ttl_buckets, hashtable, namespace)
+                }
+            }
+            None => Err(SegmentsError::NoEvictableSegments),
+        };
+
+        #[cfg(feature = "metrics")]
+        {
+            if result.is_err() {
+                SEGMENT_EVICT_EX.increment();
+            }
+            EVICT_TIME.add(now.elapsed().as_nanos() as _);
+        }
+
+        result
+    }
+
+    /// Returns an error if a segment chosen by the `EvictionPolicy` cannot be
+    /// evicted.
+    fn check_chosen(&self, id: NonZeroU32, namespace: Option<u16>) -> Result<(), SegmentsError> {
+        let id_idx = id.get() as usize - 1;
+        if id_idx >= self.headers.len() {
+            return Err(SegmentsError::BadSegmentId);
+        }
+        if !self.headers[id_idx].can_evict() || !self.in_namespace(id_idx, namespace) {
+            return Err(SegmentsError::EvictFailure);
+        }
+        Ok(())
+    }
+
+    /// Evicts a segment chosen by the `EvictionPolicy`.
+    fn evict_chosen(
+        &mut self,
+        id: NonZeroU32,
+        ttl_buckets: &mut TtlBuckets,
+        hashtable: &mut HashTable,
+        namespace: Option<u16>,
+    ) -> Result<(), SegmentsError> {
+        self.check_chosen(id, namespace)?;
+
+        let id_idx = id.get() as usize - 1;
+        let header = &self.headers[id_idx];
+        let ttl_bucket = ttl_buckets.get_mut_bucket(header.namespace(), header.ttl());
+        if header.prev_seg().is_none() {
+            ttl_bucket.set_head(header.next_seg());
+        }
+        if ttl_bucket.next_to_merge() == Some(id) {
+            ttl_bucket.set_next_to_merge(None);
+        }
+
+        self.clear_segment(id, hashtable, false)
+            .map_err(|_| SegmentsError::EvictFailure)?;
+        self.push_free(id);
+
+        Ok(())
+    }
+
+    /// Merges a segment chosen by the `EvictionPolicy` with the segments which
+    /// follow it.
+    fn merge_chosen(
+        &mut self,
+        id: NonZeroU32,
+        hashtable: &mut HashTable,
+        namespace: Option<u16>,
+    ) -> Result<(), SegmentsError> {
+        self.check_chosen(id, namespace)?;
+
+        let free = self.free;
+        self.merge_evict(id, hashtable)?;
+
+        // the merge stops early if the following segments cannot be evicted,
+        // in which case no segment was freed
+        if self.free > free {
+            Ok(())
+        } else {
+            Err(SegmentsError::NoEvictableSegments)
+        }
+    }
+
+    /// Evict segments to make room for an item which is larger than a segment.
+    /// A success from this function indicates that there is a run of free
+    /// segments which can hold an item of the given size and that
+    /// `pop_free_span()` should return some segment id.
+    ///
+    /// The run is found by searching for adjacent segments which are either
+    /// free or can be evicted. A segment which holds the remainder of another
+    /// large item can be evicted if the head of its span can be evicted. All
+    /// of the segments within the run are then freed, which may also free
+    /// segments outside of the run if the run overlaps another span.
+    pub fn evict_span(
+        &mut self,
+        size: usize,
+        ttl_buckets: &mut TtlBuckets,
+        hashtable: &mut HashTable,
+        namespace: Option<u16>,
+    ) -> Result<(), SegmentsError> {
+        if let Policy::None = self.evict.policy() {
+            return Err(SegmentsError::NoEvictableSegments);
+        }
+
+        let len = self.span(size).ok_or(SegmentsError::NoEvictableSegments)? as usize + 1;
+
+        #[cfg(feature = "metrics")]
+        let now = Instant::now();
+
+        let free = self.free_map();
+        let cap = self.cap as usize;
+
+        // search from a random segment so that eviction is spread across the
+        // heap, runs may not wrap around the end of the heap
+        let start = self.evict.random() as usize % cap;
+        let mut run = 0;
+        let mut found = None;
+
+        for i in 0..(cap + len) {
+            let idx = (start + i) % cap;
+            if idx == 0 {
+                run = 0;
+            }
+
+            let owner = self.headers[idx]
+                .owner()
+                .map(|id| id.get() as usize - 1)
+                .unwrap_or(idx);
+            if free[idx] || (self.headers[owner].can_evict() && self.in_namespace(owner, namespace))
+            {
+                run += 1;
+                if run == len {
+                    found = Some(idx + 1 - len);
+                    break;
+                }
+            } else {
+                run = 0;
+            }
+        }
+
+        let first = match found {
+            Some(first) => first,
+            None => {
+                #[cfg(feature = "metrics")]
+                {
+                    SEGMENT_EVICT_EX.increment();
+                    EVICT_TIME.add(now.elapsed().as_nanos() as _);
+                }
+
+                return Err(SegmentsError::NoEvictableSegments);
+            }
+        };
+
+        for idx in first..(first + len) {
+            // safety: we are always adding 1 to the index
+            let id = self.headers[idx]
+                .owner()
+                .unwrap_or(unsafe { NonZeroU32::new_unchecked(idx as u32 + 1) });
+            let idx = id.get() as usize - 1;
+
+            // free segments, and spans which were already evicted, are skipped
+            if !self.headers[idx].evictable() {
+                continue;
+            }
+
+            #[cfg(feature = "metrics")]
+            SEGMENT_EVICT.increment();
+
+            let header = &self.headers[idx];
+            let ttl_bucket = ttl_buckets.get_mut_bucket(header.namespace(), header.ttl());
+            if self.headers[idx].prev_seg().is_none() {
+                ttl_bucket.set_head(self.headers[idx].next_seg());
+            }
+            if ttl_bucket.next_to_merge() == Some(id) {
+                ttl_bucket.set_next_to_merge(None);
+            }
+
+            self.clear_segment(id, hashtable, false)
+                .map_err(|_| SegmentsError::EvictFailure)?;
+            self.push_free(id);
+        }
+
+        #[cfg(feature = "metrics")]
+        EVICT_TIME.add(now.elapsed().as_nanos() as _);
+
+        Ok(())
+    }
+
+    /// Changes the number of segments in the heap while the cache is in use.
+    ///
+    /// When growing, the datapool is extended in place and the new segments
+    /// are added to the free queue. When shrinking, the free segments beyond
+    /// the new end of the heap are removed from the free queue right away. The
+    /// remaining segments beyond the new end are retired as they are evicted
+    /// or expire, and `evict()` prefers them over any other segment. The end
+    /// of the datapool is unmapped as the segments are retired.
+    ///
+    /// Datapools which are backed by a file cannot be resized.
+    pub(crate) fn resize(
+        &mut self,
+        segments: usize,
+        ttl_buckets: &mut TtlBuckets,
+        hashtable: &mut HashTable,
+    ) -> Result<(), SegmentsError> {
+        if segments == 0 || segments >= (1 << 24) {
+            return Err(SegmentsError::InvalidHeapSize);
+        }
+
+        if self.persistent() {
+            return Err(SegmentsError::ResizeFailure);
+        }
+
+        let cap = self.cap as usize;
+        if segments == cap {
+            return Ok(());
+        }
+
+        debug!("resizing heap from {} to {} segments", cap, segments);
+
+        if segments < cap {
+            self.cap = segments as u32;
+
+            let free = self.free_map();
+            for idx in (segments..cap).filter(|idx| free[*idx]) {
+                // safety: we are always adding 1 to the index
+                let id = unsafe { NonZeroU32::new_unchecked(idx as u32 + 1) };
+                if self.free_q == Some(id) {
+                    self.free_q = self.headers[idx].next_seg();
+                }
+                self.unlink(id);
+                self.headers[idx].set_prev_seg(None);
+                self.headers[idx].set_next_seg(None);
+                self.headers[idx].set_accessible(false);
+                self.free -= 1;
+            }
+
+            // large items which start before the new end of the heap but
+            // extend beyond it are evicted, since the span would otherwise
+            // keep the segments beyond the new end mapped
+            if let Some(owner) = self.headers[segments].owner() {
+                self.retire(owner, ttl_buckets, hashtable);
+            }
+
+            self.unmap()?;
+        } else {
+            let mapped = self.headers.len();
+            if segments > mapped {
+                self.data
+                    .resize(segments * self.segment_size as usize)
+                    .map_err(|_| SegmentsError::ResizeFailure)?;
+
+                let mut headers = std::mem::take(&mut self.headers).into_vec();
+                headers.reserve_exact(segments - mapped);
+                for idx in mapped..segments {
+                    // safety: we are always adding 1 to the index
+                    headers.push(SegmentHeader::new(unsafe {
+                        NonZeroU32::new_unchecked(idx as u32 + 1)
+                    }));
+                }
+                self.headers = headers.into_boxed_slice();
+                self.evict.resize(segments);
+            }
+
+            // new segments, and segments which were retired by a previous
+            // shrink, are added to the free queue in order, so that they are
+            // used from the start of the new region
+            for idx in (cap..segments).rev() {
+                if !self.retired(idx) {
+                    continue;
+                }
+                // safety: we are always adding 1 to the index
+                let id = unsafe { NonZeroU32::new_unchecked(idx as u32 + 1) };
+                let begin = self.segment_size as usize * idx;
+                let end = begin + self.segment_size as usize;
+                Segment::from_raw_parts(
+                    &mut self.headers[idx],
+                    &mut self.data.as_mut_slice()[begin..end],
+                )
+                .init();
+                self.push_front(id, self.free_q);
+                self.free_q = Some(id);
+                self.free += 1;
+            }
+
+            self.cap = segments as u32;
+        }
+
+        #[cfg(feature = "metrics")]
+        {
+            SEGMENT_CURRENT.set(self.cap as _);
+            SEGMENT_FREE.set(self.free as _);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of segments which are mapped. This is larger than the
+    /// number of segments in the heap while the heap is shrinking.
+    pub(crate) fn mapped(&self) -> usize {
+        self.headers.len()
+    }
+
+    /// Returns true if the segment is beyond the end of the heap and is no
+    /// longer in use.
+    fn retired(&self, idx: usize) -> bool {
+        let header = &self.headers[idx];
+        idx >= self.cap as usize
+            && !header.accessible()
+            && !header.evictable()
+            && header.owner().is_none()
+    }
+
+    /// Evicts the items in a segment, which may be the head of a span, and
+    /// returns it to the free queue. Segments beyond the end of the heap are
+    /// retired instead.
+    fn retire(&mut self, id: NonZeroU32, ttl_buckets: &mut TtlBuckets, hashtable: &mut HashTable) {
+        let header = &self.headers[id.get() as usize - 1];
+        if !header.evictable() {
+            return;
+        }
+
+        #[cfg(feature = "metrics")]
+        SEGMENT_EVICT.increment();
+
+        ttl_buckets
+            .get_mut_bucket(header.namespace(), header.ttl())
+            .remove(id, header.prev_seg(), header.next_seg());
+
+        self.get_mut(id).unwrap().clear(hashtable, false);
+        self.push_free(id);
+    }
+
+    /// While the heap is shrinking, evicts the live segment which is furthest
+    /// beyond the new end of the heap, so that the datapool can be unmapped.
+    /// Returns false if there is no such segment.
+    fn retire_next(&mut self, ttl_buckets: &mut TtlBuckets, hashtable: &mut HashTable) -> bool {
+        for idx in (self.cap as usize..self.headers.len()).rev() {
+            let owner = self.headers[idx]
+                .owner()
+                .map(|id| id.get() as usize - 1)
+                .unwrap_or(idx);
+
+            if self.headers[owner].evictable() {
+                let id = self.headers[owner].id();
+                self.retire(id, ttl_buckets, hashtable);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Unmaps the retired segments at the end of the datapool.
+    fn unmap(&mut self) -> Result<(), SegmentsError> {
+        let mapped = self.headers.len();
+        let mut segments = mapped;
+        while segments > self.cap as usize && self.retired(segments - 1) {
+            segments -= 1;
+        }
+
+        if segments == mapped {
+            return Ok(());
+        }
+
+        debug!("unmapping {} segments", mapped - segments);
+
+        self.data
+            .resize(segments * self.segment_size as usize)
+            .map_err(|_| SegmentsError::ResizeFailure)?;
+
+        let mut headers = std::mem::take(&mut self.headers).into_vec();
+        headers.truncate(segments);
+        self.headers = headers.into_boxed_slice();
+        self.evict.resize(segments);
+
+        Ok(())
+    }
+
+    /// Returns the range of the data which is viewed by the segment at the
+    /// index. The view of a segment which holds a large item covers the whole
+    /// span of segments the item is stored across.
+    fn data_range(&self, idx: usize) -> std::ops::Range<usize> {
+        let begin = self.segment_size as usize * idx;
+        begin..(begin + self.segment_size as usize * (self.headers[idx].span() as usize + 1))
+    }
+
     /// Returns a mutable `Segment` view for the segment with the specified id
     pub(crate) fn get_mut(&mut self, id: NonZeroU32) -> Result<Segment, SegmentsError> {
         let id = id.get() as usize - 1;
         if id < self.headers.len() {
+            let range = self.data_range(id);
             let header = self.headers.get_mut(id).unwrap();
 
-            let seg_start = self.segment_size as usize * id;
-            let seg_end = self.segment_size as usize * (id + 1);
-
-            let seg_data = &mut self.data.as_mut_slice()[seg_start..seg_end];
+            let seg_data = &mut self.data.as_mut_slice()[range];
 
             let segment = Segment::from_raw_parts(header, seg_data);
             segment.check_magic();
@@ -302,7 +1517,8 @@ impl Segments {
     }
 
     /// Gets a mutable `Segment` view for two segments after making sure the
-    /// borrows are disjoint.
+    /// borrows are disjoint. This is an error if either segment holds a large
+    /// item which spans the other.
     pub(crate) fn get_mut_pair(
         &mut self,
         a: NonZeroU32,
@@ -316,37 +1532,28 @@ impl Segments {
             if a >= self.headers.len() || b >= self.headers.len() {
                 return Err(SegmentsError::BadSegmentId);
             }
-            // we have already guaranteed that 'a' and 'b' are not the same, so
-            // we know that they are disjoint borrows and can safely return
-            // mutable borrows to both the segments
+            let range_a = self.data_range(a);
+            let range_b = self.data_range(b);
+            if range_a.start < range_b.end && range_b.start < range_a.end {
+                return Err(SegmentsError::BadSegmentId);
+            }
+            // we have already guaranteed that 'a' and 'b' are not the same and
+            // that their data does not overlap, so we know that they are
+            // disjoint borrows and can safely return mutable borrows to both
+            // the segments
             unsafe {
-                let seg_size = self.segment_size() as usize;
-
                 let header_a = &mut self.headers[a] as *mut _;
                 let header_b = &mut self.headers[b] as *mut _;
 
                 let data = self.data.as_mut_slice();
 
-                // split the borrowed data
-                let split = (std::cmp::min(a, b) + 1) * seg_size;
-                let (first, second) = data.split_at_mut(split);
-
+                // split the borrowed data at the start of the later segment
                 let (data_a, data_b) = if a < b {
-                    let start_a = seg_size * a;
-                    let end_a = seg_size * (a + 1);
-
-                    let start_b = (seg_size * b) - first.len();
-                    let end_b = (seg_size * (b + 1)) - first.len();
-
-                    (&mut first[start_a..end_a], &mut second[start_b..end_b])
+                    let (first, second) = data.split_at_mut(range_b.start);
+                    (&mut first[range_a], &mut second[..range_b.len()])
                 } else {
-                    let start_a = (seg_size * a) - first.len();
-                    let end_a = (seg_size * (a + 1)) - first.len();
-
-                    let start_b = seg_size * b;
-                    let end_b = seg_size * (b + 1);
-
-                    (&mut second[start_a..end_a], &mut first[start_b..end_b])
+                    let (first, second) = data.split_at_mut(range_a.start);
+                    (&mut second[..range_a.len()], &mut first[range_b])
                 };
 
                 let segment_a = Segment::from_raw_parts(&mut *header_a, data_a);
@@ -390,28 +1597,162 @@ impl Segments {
     }
 
     /// Returns a segment to the free queue, to be used after clearing the
-    /// segment.
+    /// segment. If the segment is the head of a span, the segments holding the
+    /// remainder of the large item are also returned to the free queue. While
+    /// the heap is shrinking, segments beyond the new end of the heap are
+    /// retired instead and are unmapped once they reach the end of the
+    /// datapool.
     pub(crate) fn push_free(&mut self, id: NonZeroU32) {
+        self.release(id);
+
+        if self.headers.len() > self.cap as usize {
+            let _ = self.unmap();
+        }
+    }
+
+    fn release(&mut self, id: NonZeroU32) {
+        let id_idx = id.get() as usize - 1;
+        let retire = id_idx >= self.cap as usize;
+
         #[cfg(feature = "metrics")]
         {
             SEGMENT_RETURN.increment();
-            SEGMENT_FREE.increment();
+            if !retire {
+                SEGMENT_FREE.increment();
+            }
         }
 
         // unlinks the next segment
         self.unlink(id);
 
-        // relinks it as the free queue head
-        self.push_front(id, self.free_q);
-        self.free_q = Some(id);
+        if retire {
+            self.headers[id_idx].set_prev_seg(None);
+            self.headers[id_idx].set_next_seg(None);
+        } else {
+            // relinks it as the free queue head
+            self.push_front(id, self.free_q);
+            self.free_q = Some(id);
+        }
 
-        let id_idx = id.get() as usize - 1;
         assert!(!self.headers[id_idx].evictable());
         self.headers[id_idx].set_accessible(false);
 
+        let namespace = self.headers[id_idx].namespace();
+        if namespace != NO_NAMESPACE {
+            self.evict.namespace_segments[namespace as usize] -= self.headers[id_idx].span() + 1;
+            self.headers[id_idx].set_namespace(NO_NAMESPACE);
+        }
+
         self.headers[id_idx].reset();
 
-        self.free += 1;
+        if !retire {
+            self.free += 1;
+        }
+
+        let span = self.headers[id_idx].span();
+        if span > 0 {
+            self.headers[id_idx].set_span(0);
+
+            for i in 1..=span {
+                // safety: the span was validated to be within the heap
+                let id = unsafe { NonZeroU32::new_unchecked(id.get() + i) };
+
+                // the item data overwrote the magic bytes, so we restore them
+                // before the segment is no longer part of the span
+                self.get_mut(id).unwrap().write_magic();
+                self.headers[id.get() as usize - 1].set_owner(None);
+
+                self.release(id);
+            }
+        }
+    }
+
+    /// Returns a map of which segments are currently in the free queue.
+    fn free_map(&self) -> Vec<bool> {
+        let mut free = vec![false; self.headers.len()];
+
+        let mut next = self.free_q;
+        while let Some(id) = next {
+            let id_idx = id.get() as usize - 1;
+            free[id_idx] = true;
+            next = self.headers[id_idx].next_seg();
+        }
+
+        free
+    }
+
+    /// Helper function which removes a specific segment from the free queue.
+    fn take_free(&mut self, id: NonZeroU32) {
+        #[cfg(feature = "metrics")]
+        {
+            SEGMENT_REQUEST.increment();
+            SEGMENT_REQUEST_SUCCESS.increment();
+            SEGMENT_FREE.decrement();
+        }
+
+        let id_idx = id.get() as usize - 1;
+
+        if self.free_q == Some(id) {
+            self.free_q = self.headers[id_idx].next_seg();
+        }
+
+        self.unlink(id);
+        self.headers[id_idx].set_prev_seg(None);
+        self.headers[id_idx].set_next_seg(None);
+
+        self.free -= 1;
+    }
+
+    /// Try to take a run of adjacent segments from the free queue to hold an
+    /// item which is larger than a segment. The span is the number of segments
+    /// needed in addition to the head. Returns the id of the head of the run,
+    /// which must then be linked into a segment chain. The remaining segments
+    /// in the run are owned by the head and are not linked into any chain.
+    pub(crate) fn pop_free_span(&mut self, span: u32) -> Option<NonZeroU32> {
+        let len = span as usize + 1;
+
+        if (self.free as usize) < len {
+            return None;
+        }
+
+        let free = self.free_map();
+        let mut run = 0;
+
+        for (idx, free) in free.iter().enumerate() {
+            if !free {
+                run = 0;
+                continue;
+            }
+
+            run += 1;
+            if run < len {
+                continue;
+            }
+
+            let first = idx + 1 - len;
+
+            // safety: we are always adding 1 to the index
+            let head = unsafe { NonZeroU32::new_unchecked(first as u32 + 1) };
+
+            for id_idx in first..=idx {
+                // safety: we are always adding 1 to the index
+                self.take_free(unsafe { NonZeroU32::new_unchecked(id_idx as u32 + 1) });
+
+                if id_idx != first {
+                    self.headers[id_idx].set_accessible(false);
+                    self.headers[id_idx].set_owner(Some(head));
+                }
+            }
+
+            let header = &mut self.headers[first];
+            header.set_span(span);
+            header.mark_created();
+            header.mark_merged();
+
+            return Some(head);
+        }
+
+        None
     }
 
     /// Try to take a segment from the free queue. Returns the segment id which
@@ -466,10 +1807,12 @@ impl Segments {
     // TODO(bmartin): use a result here, not option
     /// Returns the least valuable segment based on the configured eviction
     /// policy. An eviction attempt should be made for the corresponding segment
-    /// before moving on to the next least valuable segment.
+    /// before moving on to the next least valuable segment. If a namespace is
+    /// provided, only segments belonging to that namespace are considered.
     pub(crate) fn least_valuable_seg(
         &mut self,
         ttl_buckets: &mut TtlBuckets,
+        namespace: Option<u16>,
     ) -> Option<NonZeroU32> {
         match self.evict.policy() {
             Policy::None => None,
@@ -480,7 +1823,9 @@ impl Segments {
 
                 for i in 0..self.cap {
                     let idx = (start + i) % self.cap;
-                    if self.headers[idx as usize].can_evict() {
+                    if self.headers[idx as usize].can_evict()
+                        && self.in_namespace(idx as usize, namespace)
+                    {
                         // safety: we are always adding 1 to the index
                         return Some(unsafe { NonZeroU32::new_unchecked(idx + 1) });
                     }
@@ -501,9 +1846,10 @@ impl Segments {
 
                 for i in 0..self.cap {
                     let idx = (start + i) % self.cap;
-                    if self.headers[idx as usize].accessible() {
-                        let ttl = self.headers[idx as usize].ttl();
-                        let ttl_bucket = ttl_buckets.get_mut_bucket(ttl);
+                    let header = &self.headers[idx as usize];
+                    if header.accessible() && self.in_namespace(idx as usize, namespace) {
+                        let ttl_bucket =
+                            ttl_buckets.get_mut_bucket(header.namespace(), header.ttl());
                         return ttl_bucket.head();
                     }
                 }
@@ -514,6 +1860,12 @@ impl Segments {
                 if self.evict.should_rerank() {
                     self.evict.rerank(&self.headers);
                 }
+                if let Some(namespace) = namespace {
+                    return self.evict.least_valuable_seg_matching(|id| {
+                        let header = &self.headers[id.get() as usize - 1];
+                        header.namespace() == namespace && header.can_evict()
+                    });
+                }
                 while let Some(id) = self.evict.least_valuable_seg() {
                     if let Ok(seg) = self.get_mut(id) {
                         if seg.can_evict() {
@@ -565,7 +1917,7 @@ impl Segments {
                 // if it's the head of a ttl bucket, we need to manually relink
                 // the bucket head while we have access to the ttl buckets
                 if segment.prev_seg().is_none() {
-                    let ttl_bucket = ttl_buckets.get_mut_bucket(segment.ttl());
+                    let ttl_bucket = ttl_buckets.get_mut_bucket(segment.namespace(), segment.ttl());
                     ttl_bucket.set_head(segment.next_seg());
                 }
                 self.push_free(seg_id);
@@ -614,7 +1966,8 @@ impl Segments {
                     let _ = self.merge_compact(seg_id, hashtable);
                     // we need to make sure the ttl bucket doesn't have a pointer to
                     // any of the segments we removed through merging.
-                    let ttl_bucket = ttl_buckets.get_mut_bucket(self.headers[id_idx].ttl());
+                    let header = &self.headers[id_idx];
+                    let ttl_bucket = ttl_buckets.get_mut_bucket(header.namespace(), header.ttl());
                     ttl_bucket.set_next_to_merge(None);
                 }
             }
@@ -627,7 +1980,7 @@ impl Segments {
     #[cfg(any(test, feature = "debug"))]
     pub(crate) fn items(&mut self) -> usize {
         let mut total = 0;
-        for id in 1..=self.cap {
+        for id in 1..=self.headers.len() as u32 {
             // this is safe because we start iterating from 1
             let segment = self
                 .get_mut(unsafe { NonZeroU32::new_unchecked(id) })
@@ -642,15 +1995,15 @@ impl Segments {
 
     #[cfg(test)]
     pub(crate) fn print_headers(&self) {
-        for id in 0..self.cap {
-            println!("segment header: {:?}", self.headers[id as usize]);
+        for id in 0..self.headers.len() {
+            println!("segment header: {:?}", self.headers[id]);
         }
     }
 
     #[cfg(feature = "debug")]
     pub(crate) fn check_integrity(&mut self, hashtable: &mut HashTable) -> bool {
         let mut integrity = true;
-        for id in 0..self.cap {
+        for id in 0..self.headers.len() as u32 {
             if !self
                 .get_mut(NonZeroU32::new(id + 1).unwrap())
                 .unwrap()
@@ -669,7 +2022,7 @@ impl Segments {
 
         while len < max {
             if let Ok(seg) = self.get_mut(id) {
-                if seg.can_evict() {
+                if seg.can_evict() && seg.span() == 0 {
                     len += 1;
                     match seg.next_seg() {
                         Some(i) => {
@@ -753,6 +2106,7 @@ impl Segments {
         let n_merge = self.evict.n_merge();
         let stop_ratio = self.evict.stop_ratio();
         let stop_bytes = (stop_ratio * self.segment_size() as f64) as i32;
+        let flush_at = self.flush_at;
 
         // dynamically set the target ratio based on the length of the merge chain
         let target_ratio = if chain_len < n_merge {
@@ -780,6 +2134,11 @@ impl Segments {
                 dst_new_size
             );
 
+            // items moved within the segment must be visited by the purge
+            if dst.purging() {
+                dst.set_scanned(false);
+            }
+
             dst.mark_merged();
             merged += 1;
         }
@@ -798,8 +2157,25 @@ impl Segments {
                 return Ok(None); // this causes the next_to_merge to reset
             }
 
+            if self.get_mut(src_id).map(|s| s.span() > 0).unwrap_or(false) {
+                trace!("stop merge: source segment holds a large item");
+                break;
+            }
+
             let (mut dst, mut src) = self.get_mut_pair(dst_id, src_id)?;
 
+            // items written before and after a purge started are kept apart
+            if dst.purging() != src.purging() {
+                trace!("stop merge: source segment is not part of the purge");
+                break;
+            }
+
+            // as are items written before and after a flush
+            if (dst.create_at() < flush_at) != (src.create_at() < flush_at) {
+                trace!("stop merge: source segment was created after a flush");
+                break;
+            }
+
             let dst_start_size = dst.live_bytes();
             let src_start_size = src.live_bytes();
 
@@ -871,6 +2247,7 @@ impl Segments {
         let max_merge = self.evict.max_merge();
         let stop_ratio = self.evict.stop_ratio();
         let stop_bytes = (stop_ratio * self.segment_size() as f64) as i32;
+        let flush_at = self.flush_at;
 
         // prune and compact target segment
         {
@@ -887,6 +2264,11 @@ impl Segments {
                 dst_new_size
             );
 
+            // items moved within the segment must be visited by the purge
+            if dst.purging() {
+                dst.set_scanned(false);
+            }
+
             dst.mark_merged();
             merged += 1;
         }
@@ -907,6 +2289,18 @@ impl Segments {
 
             let (mut dst, mut src) = self.get_mut_pair(dst_id, src_id)?;
 
+            // items written before and after a purge started are kept apart
+            if dst.purging() != src.purging() {
+                trace!("stop merge: source segment is not part of the purge");
+                break;
+            }
+
+            // as are items written before and after a flush
+            if (dst.create_at() < flush_at) != (src.create_at() < flush_at) {
+                trace!("stop merge: source segment was created after a flush");
+                break;
+            }
+
             let dst_start_size = dst.live_bytes();
             let src_start_size = src.live_bytes();
 
@@ -946,3 +2340,15 @@ impl Segments {
         Ok(next_id)
     }
 }
+
+impl Drop for Segments {
+    fn drop(&mut self) {
+        if !self.persistent() {
+            return;
+        }
+
+        if let Err(e) = self.flush() {
+            error!("failed to flush datapool: {}", e);
+        }
+    }
+}
@@ -0,0 +1,179 @@
+// Copyright 2023 Pelikan Foundation LLC.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Tags allow many items to be invalidated together.
+//!
+//! Items may carry tags, which are stored in the item following its optional
+//! data. Rather than scanning for the items with a tag, invalidation uses a
+//! generation counter. The cache keeps an epoch which is advanced by each
+//! invalidation, and records the epoch at which each tag was last invalidated.
+//! An item stores the epoch at which it was inserted along with the hashes of
+//! its tags, and it is invalid if any of its tags were invalidated after it
+//! was inserted. Invalid items are removed when they are next looked up.
+//!
+//! An invalidation is only kept while some item may have been inserted before
+//! it. Once every segment in the heap and the disk tier was created after an
+//! invalidation, every item was inserted after it and it is dropped.
+//!
+//! Tags are identified by a 32-bit hash, so a collision between two tags may
+//! cause items with the other tag to be invalidated, but never causes an
+//! invalidated item to be returned.
+//!
+//! Encoded tags:
+//! ```text
+//! ┌──────┬──────────────┬──────────────┬─────
+//! │COUNT │    EPOCH     │   TAG HASH   │ ...
+//! │      │              │              │
+//! │8 bit │    64 bit    │    32 bit    │
+//! └──────┴──────────────┴──────────────┴─────
+//! ```
+
+use crate::*;
+use ahash::RandomState;
+use std::collections::HashMap;
+
+/// The size of a serialized invalidation, the tag hash and its epoch.
+const INVALIDATION_SIZE: usize = std::mem::size_of::<u32>() + std::mem::size_of::<u64>();
+
+/// The maximum number of tags an item may carry.
+pub const MAX_TAGS: usize = u8::MAX as usize;
+
+/// Returns the length in bytes of the encoding of the given number of tags.
+pub(crate) const fn encoded_len(count: usize) -> usize {
+    1 + std::mem::size_of::<u64>() + std::mem::size_of::<u32>() * count
+}
+
+/// Tracks the invalidation of tags.
+pub(crate) struct Tags {
+    hash_builder: RandomState,
+    epoch: u64,
+    /// The epoch and the time at which each tag was last invalidated
+    invalidated: HashMap<u32, (u64, Instant)>,
+}
+
+impl Default for Tags {
+    fn default() -> Self {
+        Self {
+            // the hashes are stored in items, so the seeds are fixed to keep
+            // them stable across restarts
+            hash_builder: RandomState::with_seeds(
+                0x6a09e667f3bcc908,
+                0xbb67ae8584caa73b,
+                0x3c6ef372fe94f82b,
+                0xa54ff53a5f1d36f1,
+            ),
+            epoch: 0,
+            invalidated: HashMap::new(),
+        }
+    }
+}
+
+impl Tags {
+    fn hash(&self, tag: &[u8]) -> u32 {
+        let mut hasher = self.hash_builder.build_hasher();
+        hasher.write(tag);
+        hasher.finish() as u32
+    }
+
+    /// Encode the tags to be stored in an item. No tags are encoded as an
+    /// empty slice.
+    pub fn encode(&self, tags: &[&[u8]]) -> Result<Vec<u8>, SegcacheError> {
+        if tags.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        if tags.len() > MAX_TAGS {
+            return Err(SegcacheError::TooManyTags);
+        }
+
+        let mut encoded = Vec::with_capacity(encoded_len(tags.len()));
+        encoded.push(tags.len() as u8);
+        encoded.extend_from_slice(&self.epoch.to_le_bytes());
+        for tag in tags {
+            encoded.extend_from_slice(&self.hash(tag).to_le_bytes());
+        }
+
+        Ok(encoded)
+    }
+
+    /// Returns true if there have been any invalidations, otherwise every
+    /// item is valid and lookups do not need to be checked.
+    pub fn active(&self) -> bool {
+        !self.invalidated.is_empty()
+    }
+
+    /// Returns false if any of the encoded tags were invalidated after they
+    /// were encoded. Items without tags are always valid.
+    pub fn is_valid(&self, encoded: Option<&[u8]>) -> bool {
+        let encoded = match encoded {
+            Some(encoded) if self.active() => encoded,
+            _ => return true,
+        };
+
+        let epoch = u64::from_le_bytes(encoded[1..9].try_into().unwrap());
+        encoded[9..encoded_len(encoded[0] as usize)]
+            .chunks_exact(std::mem::size_of::<u32>())
+            .all(|hash| {
+                self.invalidated
+                    .get(&u32::from_le_bytes(hash.try_into().unwrap()))
+                    .map(|(invalidated, _)| *invalidated <= epoch)
+                    .unwrap_or(true)
+            })
+    }
+
+    /// Serialize the epoch and the invalidations so that they can be restored
+    /// along with the items which carry tags.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(
+            std::mem::size_of::<u64>() + INVALIDATION_SIZE * self.invalidated.len(),
+        );
+        bytes.extend_from_slice(&self.epoch.to_le_bytes());
+        for (hash, (epoch, _)) in self.invalidated.iter() {
+            bytes.extend_from_slice(&hash.to_le_bytes());
+            bytes.extend_from_slice(&epoch.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Restore the epoch and the invalidations which were serialized by
+    /// `to_bytes()`. Returns false, leaving the tags unchanged, if the bytes
+    /// are malformed. The time of each invalidation is not kept, so they are
+    /// treated as if they happened when they were restored.
+    pub fn load(&mut self, bytes: &[u8]) -> bool {
+        let epoch_size = std::mem::size_of::<u64>();
+        if bytes.len() < epoch_size || !(bytes.len() - epoch_size).is_multiple_of(INVALIDATION_SIZE)
+        {
+            return false;
+        }
+
+        let now = Instant::now();
+
+        self.epoch = u64::from_le_bytes(bytes[0..epoch_size].try_into().unwrap());
+        self.invalidated.clear();
+        for invalidation in bytes[epoch_size..].chunks_exact(INVALIDATION_SIZE) {
+            let hash = u32::from_le_bytes(invalidation[0..4].try_into().unwrap());
+            let epoch = u64::from_le_bytes(invalidation[4..].try_into().unwrap());
+            self.invalidated.insert(hash, (epoch, now));
+        }
+
+        true
+    }
+
+    /// Invalidate every item which currently carries the tag.
+    pub fn invalidate(&mut self, tag: &[u8]) {
+        self.epoch += 1;
+        let hash = self.hash(tag);
+        self.invalidated.insert(hash, (self.epoch, Instant::now()));
+    }
+
+    /// Drops the invalidations which happened before the provided instant.
+    /// This must be the creation time of the oldest segment which holds
+    /// items, since the invalidations may only be dropped once every item was
+    /// inserted after them.
+    pub fn prune(&mut self, oldest: Instant) {
+        // time has a resolution of one second, so an invalidation within the
+        // same second as the oldest segment may still apply to its items
+        self.invalidated.retain(|_, (_, at)| *at >= oldest);
+    }
+}
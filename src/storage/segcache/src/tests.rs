@@ -4,18 +4,21 @@
 
 use super::*;
 use crate::hashtable::HashBucket;
-use crate::item::ITEM_HDR_SIZE;
+use crate::item::{ITEM_HDR_SIZE, ITEM_MAX_OLEN};
 use core::num::NonZeroU32;
 
 use std::time::Duration;
 
 #[test]
 fn sizes() {
+    // the checksum adds four bytes to the item header
+    let checksum = if cfg!(feature = "checksum") { 4 } else { 0 };
+
     #[cfg(feature = "magic")]
-    assert_eq!(ITEM_HDR_SIZE, 9);
+    assert_eq!(ITEM_HDR_SIZE, 10 + checksum);
 
     #[cfg(not(feature = "magic"))]
-    assert_eq!(ITEM_HDR_SIZE, 5);
+    assert_eq!(ITEM_HDR_SIZE, 6 + checksum);
 
     assert_eq!(std::mem::size_of::<Segments>(), 64);
     assert_eq!(std::mem::size_of::<SegmentHeader>(), 64);
@@ -247,6 +250,74 @@ fn collisions() {
     assert_eq!(cache.items(), 7);
 }
 
+#[test]
+fn hash_expansion() {
+    let build = |threshold| {
+        Segcache::builder()
+            .segment_size(64 * 1024)
+            .heap_size(64 * 64 * 1024)
+            .hash_power(6)
+            .overflow_factor(1.0)
+            .hash_expand_threshold(threshold)
+            .build()
+            .expect("failed to create cache")
+    };
+
+    // without expansion, the hashtable only has room for a few hundred items
+    let mut cache = build(None);
+    let mut inserted = 0;
+    for i in 0..2000 {
+        let key = format!("{i}");
+        if cache
+            .insert(key.as_bytes(), b"value", None, Duration::ZERO)
+            .is_ok()
+        {
+            inserted += 1;
+        }
+    }
+    assert!(inserted < 200);
+    assert_eq!(cache.hashtable.power(), 6);
+
+    let mut cache = build(Some(0.75));
+    let mut keys = Vec::new();
+    for i in 0..2000 {
+        let key = format!("{i}");
+        if cache
+            .insert(key.as_bytes(), b"value", None, Duration::ZERO)
+            .is_ok()
+        {
+            keys.push(key);
+        }
+
+        // items can be found while the expansion is in progress
+        if i % 100 == 0 {
+            for key in &keys {
+                assert!(cache.get(key.as_bytes()).is_some());
+            }
+        }
+    }
+    assert!(keys.len() > 1900);
+    assert!(cache.hashtable.power() > 8);
+
+    while cache.hashtable.expanding() {
+        cache.expire();
+    }
+
+    for key in &keys {
+        let item = cache.get(key.as_bytes()).expect("item missing");
+        assert_eq!(item.value(), b"value");
+    }
+    assert_eq!(cache.hashtable.len() as usize, keys.len());
+    assert_eq!(cache.items(), keys.len());
+
+    // deletes and replacements keep the item count
+    assert!(cache.delete(keys[0].as_bytes()));
+    assert!(cache
+        .insert(keys[1].as_bytes(), b"other", None, Duration::ZERO)
+        .is_ok());
+    assert_eq!(cache.hashtable.len() as usize, keys.len() - 1);
+}
+
 #[test]
 fn full_cache_long() {
     let ttl = Duration::ZERO;
@@ -381,106 +452,1863 @@ fn expiration() {
 }
 
 #[test]
-fn clear() {
-    let ttl = Duration::ZERO;
-    let segment_size = 4096;
+fn expire_budget() {
+    let mut cache = Segcache::builder()
+        .segment_size(4096)
+        .heap_size(4096 * 64)
+        .hash_power(16)
+        .expire_budget(ExpireBudget::Segments(2))
+        .build()
+        .expect("failed to create cache");
+
+    // fill several segments with items which expire together
+    let value = [0x42; 100];
+    for i in 0..200 {
+        let key = format!("{i}");
+        assert!(cache
+            .insert(key.as_bytes(), &value[..], None, Duration::from_secs(1))
+            .is_ok());
+    }
+    let used = cache.segments.cap() - cache.segments.free();
+    assert!(used > 4);
+
+    std::thread::sleep(Duration::from_secs(2));
+
+    // each call expires at most two segments and resumes where the last call
+    // stopped
+    let mut expired = 0;
+    loop {
+        let n = cache.expire();
+        assert!(n <= 2);
+        if n == 0 {
+            break;
+        }
+        expired += n;
+        assert_eq!(
+            cache
+                .ttl_buckets
+                .expire_backlog(&mut cache.segments, cache.stale_grace),
+            used - expired
+        );
+    }
+    assert_eq!(expired, used);
+    assert_eq!(cache.segments.free(), cache.segments.cap());
+    assert_eq!(cache.items(), 0);
+}
+
+#[test]
+fn touch() {
     let segments = 64;
+    let segment_size = 2 * 1024;
     let heap_size = segments * segment_size as usize;
 
     let mut cache = Segcache::builder()
         .segment_size(segment_size)
         .heap_size(heap_size)
+        .hash_power(16)
         .build()
         .expect("failed to create cache");
-    assert_eq!(cache.items(), 0);
-    assert_eq!(cache.segments.free(), segments);
-    assert!(cache.get(b"coffee").is_none());
-    assert!(cache.insert(b"coffee", b"strong", None, ttl).is_ok());
-    assert_eq!(cache.segments.free(), segments - 1);
+
+    assert_eq!(
+        cache.touch(b"latte", Duration::from_secs(15)),
+        Err(SegcacheError::NotFound)
+    );
+    assert!(cache.ttl(b"latte").is_none());
+
+    assert!(cache
+        .insert(b"latte", b"foam", None, Duration::from_secs(5))
+        .is_ok());
+    let cas = cache.get(b"latte").unwrap().cas();
+    assert!(matches!(
+        cache.ttl(b"latte"),
+        Some(Expiry::After(ttl)) if ttl <= Duration::from_secs(5)
+    ));
+
+    // touching moves the item into a segment in the new ttl bucket
+    assert!(cache.touch(b"latte", Duration::from_secs(15)).is_ok());
     assert_eq!(cache.items(), 1);
-    assert!(cache.get(b"coffee").is_some());
+    assert_eq!(cache.segments.free(), segments - 2);
+    assert!(matches!(
+        cache.ttl(b"latte"),
+        Some(Expiry::After(ttl)) if ttl > Duration::from_secs(5)
+    ));
 
-    let item = cache.get(b"coffee").unwrap();
-    assert_eq!(item.value(), b"strong", "item is: {item:?}");
+    let item = cache.get(b"latte").unwrap();
+    assert_eq!(item.value(), b"foam", "item is: {item:?}");
+    assert_eq!(item.cas(), cas);
+
+    // the original segment expires without taking the item with it
+    std::thread::sleep(std::time::Duration::from_secs(5));
+    cache.expire();
+
+    assert!(cache.get(b"latte").is_some());
+    assert_eq!(cache.items(), 1);
+    assert_eq!(cache.segments.free(), segments - 1);
+
+    // an item touched without a TTL never expires, until it is given one
+    assert!(cache.touch(b"latte", Duration::ZERO).is_ok());
+    assert_eq!(cache.ttl(b"latte"), Some(Expiry::Never));
+    assert!(cache.touch(b"latte", Duration::from_secs(15)).is_ok());
+    assert!(matches!(cache.ttl(b"latte"), Some(Expiry::After(_))));
+}
+
+#[test]
+fn lease() {
+    let mut cache = Segcache::builder()
+        .segment_size(4096)
+        .heap_size(64 * 4096)
+        .hash_power(16)
+        .build()
+        .expect("failed to create cache");
+
+    let lease = |cache: &mut Segcache| cache.get_lease(b"mocha", Duration::from_secs(2));
+
+    // only the first miss is granted a lease
+    let token = match lease(&mut cache) {
+        Lease::Granted(token) => token,
+        lease => panic!("unexpected lease: {lease:?}"),
+    };
+    assert!(matches!(lease(&mut cache), Lease::Wait));
+
+    // a fill with the wrong token is rejected and leaves the lease in place
+    assert_eq!(
+        cache.insert_lease(b"mocha", b"cocoa", None, Duration::ZERO, token + 1),
+        Err(SegcacheError::InvalidLease)
+    );
+    assert!(matches!(lease(&mut cache), Lease::Wait));
+
+    // the lease holder fills the item, which releases the lease
+    assert!(cache
+        .insert_lease(b"mocha", b"cocoa", None, Duration::ZERO, token)
+        .is_ok());
+    match lease(&mut cache) {
+        Lease::Hit(item) => assert_eq!(item.value(), b"cocoa"),
+        lease => panic!("unexpected lease: {lease:?}"),
+    }
+    assert_eq!(
+        cache.insert_lease(b"mocha", b"cocoa", None, Duration::ZERO, token),
+        Err(SegcacheError::InvalidLease)
+    );
+
+    // a delete invalidates the lease, so a racing fill is rejected
+    assert!(cache.delete(b"mocha"));
+    let token = match lease(&mut cache) {
+        Lease::Granted(token) => token,
+        lease => panic!("unexpected lease: {lease:?}"),
+    };
+    assert!(!cache.delete(b"mocha"));
+    assert_eq!(
+        cache.insert_lease(b"mocha", b"stale", None, Duration::ZERO, token),
+        Err(SegcacheError::InvalidLease)
+    );
+
+    // an expired lease can not be used and is replaced by a new lease
+    let token = match lease(&mut cache) {
+        Lease::Granted(token) => token,
+        lease => panic!("unexpected lease: {lease:?}"),
+    };
+    std::thread::sleep(std::time::Duration::from_secs(3));
+    assert!(matches!(lease(&mut cache), Lease::Granted(t) if t != token));
+    assert_eq!(
+        cache.insert_lease(b"mocha", b"stale", None, Duration::ZERO, token),
+        Err(SegcacheError::InvalidLease)
+    );
+}
+
+#[test]
+fn lease_limits() {
+    let mut cache = Segcache::builder()
+        .segment_size(4096)
+        .heap_size(64 * 4096)
+        .hash_power(16)
+        .lease_max_duration(std::time::Duration::from_secs(2))
+        .lease_max_outstanding(2)
+        .build()
+        .expect("failed to create cache");
+
+    let day = std::time::Duration::from_secs(86400);
+
+    // once the limit is reached, misses are not granted a lease
+    let token = match cache.get_lease(b"mocha", day) {
+        Lease::Granted(token) => token,
+        lease => panic!("unexpected lease: {lease:?}"),
+    };
+    assert!(matches!(cache.get_lease(b"latte", day), Lease::Granted(_)));
+    assert!(matches!(cache.get_lease(b"mocha", day), Lease::Wait));
+    assert!(matches!(cache.get_lease(b"cocoa", day), Lease::Miss));
+
+    // releasing a lease makes room for another
+    assert!(cache
+        .insert_lease(b"mocha", b"cocoa", None, Duration::ZERO, token)
+        .is_ok());
+    assert!(matches!(cache.get_lease(b"cocoa", day), Lease::Granted(_)));
+    assert!(matches!(cache.get_lease(b"chai", day), Lease::Miss));
+
+    // the requested duration is capped, so the leases expire and are dropped
+    std::thread::sleep(std::time::Duration::from_secs(3));
+    cache.expire();
+    assert!(matches!(cache.get_lease(b"chai", day), Lease::Granted(_)));
+}
+
+#[test]
+fn stale() {
+    let mut cache = Segcache::builder()
+        .segment_size(4096)
+        .heap_size(64 * 4096)
+        .hash_power(16)
+        .stale_grace(std::time::Duration::from_secs(3))
+        .build()
+        .expect("failed to create cache");
+
+    assert!(cache
+        .insert(b"mocha", b"cocoa", None, Duration::from_secs(2))
+        .is_ok());
+    assert!(cache
+        .insert(b"latte", b"milk", None, Duration::from_secs(2))
+        .is_ok());
+    assert!(!cache.get(b"mocha").unwrap().is_stale());
+
+    // once the TTL passes, the items are kept and marked as stale
+    std::thread::sleep(std::time::Duration::from_secs(2));
+    cache.expire();
+    let item = cache.get_stale(b"mocha").expect("item missing");
+    assert!(item.is_stale());
+    assert_eq!(item.value(), b"cocoa");
+
+    // stale items are a miss unless they are asked for
+    assert!(cache.get(b"mocha").is_none());
+    assert!(cache.get_no_freq_incr(b"mocha").is_none());
+    assert_eq!(
+        cache.touch(b"latte", Duration::from_secs(2)),
+        Err(SegcacheError::NotFound)
+    );
+    let cas = cache.get_stale(b"latte").unwrap().cas();
+    assert_eq!(
+        cache.cas(b"latte", b"cream", None, Duration::from_secs(2), cas),
+        Err(SegcacheError::NotFound)
+    );
+    assert!(cache.get_stale(b"latte").unwrap().is_stale());
+
+    // only the first lookup is given the lease to refresh the item
+    let lease = |cache: &mut Segcache| cache.get_lease(b"mocha", Duration::from_secs(5));
+    let token = match lease(&mut cache) {
+        Lease::Stale(item, Some(token)) => {
+            assert_eq!(item.value(), b"cocoa");
+            token
+        }
+        lease => panic!("unexpected lease: {lease:?}"),
+    };
+    assert!(matches!(lease(&mut cache), Lease::Stale(_, None)));
+
+    // the refreshed item is not written into the stale segment
+    assert!(cache
+        .insert_lease(b"mocha", b"chocolate", None, Duration::from_secs(2), token)
+        .is_ok());
+    match lease(&mut cache) {
+        Lease::Hit(item) => assert_eq!(item.value(), b"chocolate"),
+        lease => panic!("unexpected lease: {lease:?}"),
+    }
+
+    // the stale item is removed after the grace period
+    std::thread::sleep(std::time::Duration::from_secs(3));
+    cache.expire();
+    assert!(cache.get_stale(b"latte").is_none());
+    assert!(cache.get_stale(b"mocha").unwrap().is_stale());
+}
+
+#[test]
+fn tags() {
+    let mut cache = Segcache::builder()
+        .segment_size(4096)
+        .heap_size(64 * 4096)
+        .hash_power(16)
+        .build()
+        .expect("failed to create cache");
+
+    let user: &[u8] = b"user:1";
+    let team: &[u8] = b"team:1";
+
+    assert!(cache
+        .insert_tagged(
+            b"profile",
+            b"alice",
+            Some(&[1, 2, 3, 4]),
+            Duration::ZERO,
+            &[user]
+        )
+        .is_ok());
+    assert!(cache
+        .insert_tagged(b"roster", b"alice,bob", None, Duration::ZERO, &[user, team])
+        .is_ok());
+    assert!(cache
+        .insert_tagged(b"standup", b"daily", None, Duration::ZERO, &[team])
+        .is_ok());
+    assert!(cache
+        .insert(b"motd", b"hello", None, Duration::ZERO)
+        .is_ok());
+
+    // the tags do not change the item
+    let item = cache.get(b"profile").expect("item missing");
+    assert_eq!(item.value(), b"alice");
+    assert_eq!(item.optional(), Some(&[1, 2, 3, 4][..]));
+
+    // every item with the tag is removed
+    cache.invalidate_tag(user);
+    assert!(cache.get(b"profile").is_none());
+    assert!(cache.get(b"roster").is_none());
+    assert!(cache.get(b"standup").is_some());
+    assert!(cache.get(b"motd").is_some());
+    assert_eq!(cache.items(), 2);
+
+    // items tagged after the invalidation are valid
+    assert!(cache
+        .insert_tagged(b"profile", b"alicia", None, Duration::ZERO, &[user])
+        .is_ok());
+    assert_eq!(cache.get(b"profile").unwrap().value(), b"alicia");
+
+    // invalidated items are not returned by a scan, even before a lookup
+    cache.invalidate_tag(team);
+    let (_, items) = cache.scan(0, 100);
+    let mut keys: Vec<&[u8]> = items.iter().map(|item| item.key()).collect();
+    keys.sort();
+    assert_eq!(keys, vec![&b"motd"[..], &b"profile"[..]]);
+
+    // tagged items keep the full optional data length
+    let optional = [0xFF; ITEM_MAX_OLEN];
+    assert!(cache
+        .insert_tagged(
+            b"long",
+            b"optional",
+            Some(&optional),
+            Duration::ZERO,
+            &[user]
+        )
+        .is_ok());
+    let item = cache.get(b"long").expect("item missing");
+    assert_eq!(item.value(), b"optional");
+    assert_eq!(item.optional(), Some(&optional[..]));
+
+    let tags: Vec<&[u8]> = vec![user; MAX_TAGS + 1];
+    assert_eq!(
+        cache.insert_tagged(b"many", b"tags", None, Duration::ZERO, &tags),
+        Err(SegcacheError::TooManyTags)
+    );
 
+    // invalidations are kept while items may have been inserted before them
+    cache.expire();
+    assert!(cache.tags.active());
+
+    // and dropped once every item was inserted after them
     cache.clear();
-    assert_eq!(cache.segments.free(), segments);
-    assert_eq!(cache.items(), 0);
-    assert!(cache.get(b"coffee").is_none());
+    std::thread::sleep(std::time::Duration::from_secs(2));
+    assert!(cache
+        .insert_tagged(b"profile", b"alicia", None, Duration::ZERO, &[user])
+        .is_ok());
+    cache.expire();
+    assert!(!cache.tags.active());
+    assert!(cache.get(b"profile").is_some());
 }
 
 #[test]
-fn wrapping_add() {
-    let ttl = Duration::ZERO;
-    let segment_size = 4096;
-    let segments = 64;
-    let heap_size = segments * segment_size as usize;
+fn delete_matching() {
+    let mut cache = Segcache::builder()
+        .segment_size(4096)
+        .heap_size(64 * 4096)
+        .hash_power(16)
+        .build()
+        .expect("failed to create cache");
+
+    assert!(cache.delete_matching_progress().is_none());
+
+    for i in 0..3000 {
+        let key = if i % 2 == 0 {
+            format!("session:{i}")
+        } else {
+            format!("user:{i}")
+        };
+        assert!(cache
+            .insert(key.as_bytes(), b"value", None, Duration::ZERO)
+            .is_ok());
+    }
+    assert_eq!(cache.items(), 3000);
+
+    // the purge does nothing until expire is called
+    cache.delete_matching(b"session:*");
+    let progress = cache.delete_matching_progress().unwrap();
+    assert_eq!(progress.segments_scanned, 0);
+    assert_eq!(progress.segments_total, 64);
+    assert!(!progress.done);
+    assert_eq!(cache.items(), 3000);
+
+    // each step visits a bounded number of items
+    cache.expire();
+    let progress = cache.delete_matching_progress().unwrap();
+    assert!(!progress.done);
+    assert!(progress.segments_scanned > 0);
+    assert!(progress.items_deleted > 0 && progress.items_deleted < 1500);
+
+    while !cache.delete_matching_progress().unwrap().done {
+        cache.expire();
+    }
+
+    let progress = cache.delete_matching_progress().unwrap();
+    assert_eq!(progress.segments_scanned, progress.segments_total);
+    assert_eq!(progress.items_deleted, 1500);
+    assert_eq!(cache.items(), 1500);
+    assert!(cache.get(b"session:0").is_none());
+    assert!(cache.get(b"user:1").is_some());
+
+    // a glob matches within the key and starting a purge replaces the last
+    cache.delete_matching(b"user:?9");
+    while !cache.delete_matching_progress().unwrap().done {
+        cache.expire();
+    }
+    assert_eq!(cache.delete_matching_progress().unwrap().items_deleted, 9);
+    assert!(cache.get(b"user:19").is_none());
+    assert!(cache.get(b"user:119").is_some());
+}
 
+#[test]
+fn delete_matching_start() {
     let mut cache = Segcache::builder()
-        .segment_size(segment_size)
-        .heap_size(heap_size)
+        .segment_size(4096)
+        .heap_size(64 * 4096)
+        .hash_power(16)
+        .eviction(Policy::Merge {
+            max: 8,
+            merge: 4,
+            compact: 2,
+        })
         .build()
         .expect("failed to create cache");
-    assert_eq!(cache.items(), 0);
-    assert_eq!(cache.segments.free(), 64);
-    assert!(cache.insert(b"coffee", 0, None, ttl).is_ok());
-    assert_eq!(cache.segments.free(), 63);
-    assert_eq!(cache.items(), 1);
-    assert!(cache.get(b"coffee").is_some());
 
-    let item = cache.get(b"coffee").unwrap();
-    assert_eq!(item.value(), 0, "item is: {item:?}");
-    cache
-        .wrapping_add(b"coffee", 1)
-        .expect("failed to increment");
-    assert_eq!(item.value(), 1, "item is: {item:?}");
-    cache
-        .wrapping_add(b"coffee", u64::MAX - 1)
-        .expect("failed to increment");
-    assert_eq!(item.value(), u64::MAX, "item is: {item:?}");
-    cache
-        .wrapping_add(b"coffee", 1)
-        .expect("failed to increment");
-    assert_eq!(item.value(), 0, "item is: {item:?}");
-    cache
-        .wrapping_add(b"coffee", 2)
-        .expect("failed to increment");
-    assert_eq!(item.value(), 2, "item is: {item:?}");
+    let value = [0x42; 100];
+    for i in 0..1800 {
+        let key = if i % 2 == 0 {
+            format!("session:{i}")
+        } else {
+            format!("user:{i}")
+        };
+        assert!(cache
+            .insert(key.as_bytes(), &value[..], None, Duration::ZERO)
+            .is_ok());
+    }
+
+    cache.delete_matching(b"session:*");
+
+    // matching items written before the purge are misses before it reaches them
+    assert!(cache.get(b"session:0").is_none());
+    assert_eq!(cache.delete_matching_progress().unwrap().items_deleted, 1);
+
+    // items written after the purge started are kept
+    assert!(cache
+        .insert(b"session:2", b"new", None, Duration::ZERO)
+        .is_ok());
+    assert_eq!(cache.get(b"session:2").unwrap().value(), b"new");
+
+    // merge eviction moves items while the purge is in progress
+    let mut inserted = 0;
+    while !cache.delete_matching_progress().unwrap().done {
+        for _ in 0..500 {
+            let key = format!("session:new:{inserted}");
+            assert!(cache
+                .insert(key.as_bytes(), &value[..], None, Duration::ZERO)
+                .is_ok());
+            inserted += 1;
+        }
+        cache.expire();
+    }
+    assert!(inserted > 500);
+
+    // none of the matching items written before the purge survive it
+    for i in (4..1800).step_by(2) {
+        assert!(cache
+            .hashtable
+            .get(
+                format!("session:{i}").as_bytes(),
+                cache.time,
+                &mut cache.segments
+            )
+            .is_none());
+    }
+    assert!(cache.delete_matching_progress().unwrap().items_deleted <= 900);
+
+    // and the recently written ones are not removed
+    for i in (inserted - 100)..inserted {
+        assert!(cache.get(format!("session:new:{i}").as_bytes()).is_some());
+    }
 }
 
 #[test]
-fn saturating_sub() {
-    let ttl = Duration::ZERO;
-    let segment_size = 4096;
-    let segments = 64;
-    let heap_size = segments * segment_size as usize;
+fn delete_matching_disk_tier() {
+    let segment_size = 4 * 1024;
+
+    let tempdir = tempfile::TempDir::new().expect("failed to generate tempdir");
 
     let mut cache = Segcache::builder()
         .segment_size(segment_size)
-        .heap_size(heap_size)
+        .heap_size(16 * segment_size as usize)
+        .hash_power(16)
+        .disk_path(Some(tempdir.path().join("segcache.disk")))
+        .disk_size(64 * segment_size as usize)
+        .disk_segment_size(segment_size)
         .build()
         .expect("failed to create cache");
-    assert_eq!(cache.items(), 0);
-    assert_eq!(cache.segments.free(), 64);
-    assert!(cache.insert(b"coffee", 3, None, ttl).is_ok());
-    assert_eq!(cache.segments.free(), 63);
-    assert_eq!(cache.items(), 1);
-    assert!(cache.get(b"coffee").is_some());
 
-    let item = cache.get(b"coffee").unwrap();
-    assert_eq!(item.value(), 3, "item is: {item:?}");
-    cache
-        .saturating_sub(b"coffee", 2)
-        .expect("failed to increment");
-    assert_eq!(item.value(), 1, "item is: {item:?}");
-    cache
-        .saturating_sub(b"coffee", 1)
-        .expect("failed to increment");
-    assert_eq!(item.value(), 0, "item is: {item:?}");
-    cache
-        .saturating_sub(b"coffee", 1)
-        .expect("failed to increment");
-    assert_eq!(item.value(), 0, "item is: {item:?}");
+    let key = |i: i32| {
+        if i % 2 == 0 {
+            format!("session:{i}")
+        } else {
+            format!("user:{i}")
+        }
+    };
+
+    // insert more items than the heap can hold, so that some are demoted
+    let value = [0x42; 100];
+    for i in 0..1024 {
+        assert!(cache
+            .insert(key(i).as_bytes(), &value[..], None, Duration::ZERO)
+            .is_ok());
+    }
+    assert!(cache
+        .hashtable
+        .get(b"session:0", cache.time, &mut cache.segments)
+        .is_none());
+
+    // the matching items in the disk tier are removed when the purge starts
+    cache.delete_matching(b"session:*");
+    let progress = cache.delete_matching_progress().unwrap();
+    assert_eq!(progress.segments_scanned, 0);
+    assert!(progress.items_deleted > 0);
+
+    // items evicted before the purge reaches them are not demoted
+    for i in 1024..2048 {
+        assert!(cache
+            .insert(
+                format!("user:{i}").as_bytes(),
+                &value[..],
+                None,
+                Duration::ZERO
+            )
+            .is_ok());
+    }
+
+    while !cache.delete_matching_progress().unwrap().done {
+        cache.expire();
+    }
+
+    // none of the matching items are promoted from the disk tier
+    for i in (0..1024).step_by(2) {
+        assert!(cache.get(key(i).as_bytes()).is_none());
+    }
+    assert!(cache.get(b"user:1").is_some());
+}
+
+#[cfg(feature = "checksum")]
+#[test]
+fn checksum() {
+    let mut cache = Segcache::builder()
+        .segment_size(4096)
+        .heap_size(64 * 4096)
+        .hash_power(16)
+        .build()
+        .expect("failed to create cache");
+
+    // flips a bit in the value of the item, in place
+    fn corrupt(cache: &mut Segcache, key: &[u8]) -> u64 {
+        let item_info = cache.hashtable.item_info(key, &mut cache.segments).unwrap();
+        let item = cache.segments.get_item(item_info).unwrap();
+        match item.value() {
+            Value::Bytes(value) => unsafe { *(value.as_ptr() as *mut u8) ^= 0x01 },
+            _ => panic!("unexpected value type"),
+        }
+        item_info
+    }
+
+    for key in [&b"coffee"[..], b"tea", b"juice"] {
+        assert!(cache.insert(key, b"value", None, Duration::ZERO).is_ok());
+    }
+    assert!(cache
+        .insert(b"counter", 1_u64, None, Duration::ZERO)
+        .is_ok());
+
+    // changes made by the cache keep the checksum up to date
+    assert!(cache.wrapping_add(b"counter", 1).is_ok());
+    assert_eq!(cache.get(b"counter").unwrap().value(), 2_u64);
+
+    // a corrupted item is dropped instead of being returned
+    corrupt(&mut cache, b"coffee");
+    assert!(cache.get(b"coffee").is_none());
+    assert!(cache.get(b"tea").is_some());
+    assert_eq!(cache.items(), 3);
+
+    // and a corrupted item is dropped when its segment is compacted
+    let item_info = corrupt(&mut cache, b"tea");
+    let seg_id = crate::hashtable::get_seg_id(item_info).unwrap();
+    cache
+        .segments
+        .get_mut(seg_id)
+        .unwrap()
+        .compact(&mut cache.hashtable)
+        .unwrap();
+    assert!(cache
+        .hashtable
+        .item_info(b"tea", &mut cache.segments)
+        .is_none());
+    assert!(cache.get(b"juice").is_some());
+    assert_eq!(cache.items(), 2);
+}
+
+#[test]
+fn large_item() {
+    let segments = 32;
+    let segment_size = 1024;
+    let heap_size = segments * segment_size as usize;
+
+    let mut cache = Segcache::builder()
+        .segment_size(segment_size)
+        .heap_size(heap_size)
+        .hash_power(16)
+        .build()
+        .expect("failed to create cache");
+
+    // the item is stored across a run of four segments
+    let value = vec![42; 3 * segment_size as usize + 512];
+    assert!(cache
+        .insert(b"large", &value, None, Duration::from_secs(2))
+        .is_ok());
+    assert_eq!(cache.items(), 1);
+    assert_eq!(cache.segments.free(), segments - 4);
+
+    let item = cache.get(b"large").unwrap();
+    assert_eq!(item.value(), value[..]);
+
+    // the segment view covers the whole run, so the item can be read in full
+    // by walking the segments
+    let (cursor, items) = cache.scan(0, 16);
+    assert_eq!(cursor, 0);
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].value(), value[..]);
+
+    // the whole run is freed when the item expires
+    std::thread::sleep(std::time::Duration::from_secs(2));
+    cache.expire();
+    assert!(cache.get(b"large").is_none());
+    assert_eq!(cache.items(), 0);
+    assert_eq!(cache.segments.free(), segments);
+
+    // items which can not fit in the heap are still rejected
+    let huge = vec![0; heap_size];
+    assert!(matches!(
+        cache.insert(b"huge", &huge, None, Duration::ZERO),
+        Err(SegcacheError::ItemOversized { .. })
+    ));
+
+    // fill the cache with small items, large items then evict a run of
+    // segments to make room
+    for i in 0..4096 {
+        let key = format!("small{i}");
+        assert!(cache
+            .insert(key.as_bytes(), b"coffee", None, Duration::ZERO)
+            .is_ok());
+    }
+    assert_eq!(cache.segments.free(), 0);
+
+    for i in 0..16 {
+        let key = format!("large{i}");
+        assert!(cache
+            .insert(key.as_bytes(), &value, None, Duration::ZERO)
+            .is_ok());
+
+        let item = cache.get(key.as_bytes()).unwrap();
+        assert_eq!(item.value(), value[..]);
+    }
+}
+
+#[test]
+fn resize_heap() {
+    let segment_size = 4096;
+    let mut cache = Segcache::builder()
+        .segment_size(segment_size)
+        .heap_size(8 * segment_size as usize)
+        .hash_power(16)
+        .build()
+        .expect("failed to create cache");
+
+    let value = [0x42; 100];
+    for i in 0..200 {
+        let key = format!("{i}");
+        assert!(cache
+            .insert(key.as_bytes(), &value[..], None, Duration::ZERO)
+            .is_ok());
+    }
+    let used = cache.segments.cap() - cache.segments.free();
+
+    // growing keeps all of the items and adds free segments
+    assert!(cache.resize_heap(16 * segment_size as usize).is_ok());
+    assert_eq!(cache.heap_size(), 16 * segment_size as usize);
+    assert_eq!(cache.segments.cap(), 16);
+    assert_eq!(cache.segments.free(), 16 - used);
+    assert_eq!(cache.items(), 200);
+
+    // the new segments are used for new items, including large items
+    for i in 200..300 {
+        let key = format!("{i}");
+        assert!(cache
+            .insert(key.as_bytes(), &value[..], None, Duration::ZERO)
+            .is_ok());
+    }
+    let large = vec![0x43; 2 * segment_size as usize];
+    assert!(cache
+        .insert(b"large", &large[..], None, Duration::ZERO)
+        .is_ok());
+    for i in 0..300 {
+        let key = format!("{i}");
+        assert!(cache.get(key.as_bytes()).is_some());
+    }
+
+    // shrinking keeps the items held beyond the new end of the heap until the
+    // segments which hold them are evicted
+    assert!(cache.resize_heap(4 * segment_size as usize).is_ok());
+    assert_eq!(cache.heap_size(), 4 * segment_size as usize);
+    assert_eq!(cache.segments.cap(), 4);
+    assert!(cache.segments.mapped() > 4);
+    assert_eq!(cache.items(), 301);
+
+    // growing while the heap is shrinking keeps the items as well
+    assert!(cache.resize_heap(6 * segment_size as usize).is_ok());
+    assert_eq!(cache.segments.cap(), 6);
+    assert_eq!(cache.items(), 301);
+    assert!(cache.resize_heap(4 * segment_size as usize).is_ok());
+
+    // writes evict the segments beyond the new end of the heap first, which
+    // are then unmapped
+    for i in 400..800 {
+        let key = format!("{i}");
+        assert!(cache
+            .insert(key.as_bytes(), &value[..], None, Duration::ZERO)
+            .is_ok());
+    }
+    assert_eq!(cache.segments.mapped(), 4);
+    assert!(cache.get(b"799").is_some());
+    assert!(cache.get(b"large").is_none());
+    let mut hits = 0;
+    for i in (0..300).chain(400..800) {
+        let key = format!("{i}");
+        if let Some(item) = cache.get(key.as_bytes()) {
+            assert_eq!(item.value(), value);
+            hits += 1;
+        }
+    }
+    assert!(hits > 0 && hits < 700);
+    assert_eq!(cache.items(), hits);
+
+    assert_eq!(cache.resize_heap(0), Err(SegcacheError::InvalidHeapSize));
+    assert_eq!(cache.segments.cap(), 4);
+}
+
+#[test]
+fn compression() {
+    let segments = 16;
+    let segment_size = 4 * 1024;
+    let heap_size = segments * segment_size as usize;
+
+    let mut cache = Segcache::builder()
+        .segment_size(segment_size)
+        .heap_size(heap_size)
+        .hash_power(16)
+        .compression(Compression::Zstd {
+            level: 3,
+            threshold: 128,
+        })
+        .build()
+        .expect("failed to create cache");
+
+    // the values are much larger than the heap in total, but compress well
+    // enough that they all fit
+    let mut values = Vec::new();
+    for i in 0..256 {
+        let value = format!("{{\"id\":{i},\"drink\":\"coffee\"}}").repeat(64);
+        let key = format!("{i}");
+        assert!(cache
+            .insert(key.as_bytes(), value.as_bytes(), None, Duration::ZERO)
+            .is_ok());
+        values.push(value);
+    }
+    assert!(values.iter().map(|v| v.len()).sum::<usize>() > heap_size);
+
+    for (i, value) in values.iter().enumerate() {
+        let key = format!("{i}");
+        let item = cache.get(key.as_bytes()).unwrap();
+        assert_eq!(item.value(), *value.as_bytes());
+    }
+
+    // values below the threshold are stored as provided
+    assert!(cache
+        .insert(b"small", b"coffee", None, Duration::ZERO)
+        .is_ok());
+    let item = cache.get(b"small").unwrap();
+    assert_eq!(item.value(), b"coffee");
+
+    // optional data does not spill into the compressed flag, and optional
+    // data which is too long is refused
+    let optional = [0xFF; ITEM_MAX_OLEN];
+    assert!(cache
+        .insert(b"flags", b"coffee", Some(&optional), Duration::ZERO)
+        .is_ok());
+    let item = cache.get(b"flags").unwrap();
+    assert_eq!(item.value(), b"coffee");
+    assert_eq!(item.optional(), Some(&optional[..]));
+    assert!(matches!(
+        cache.insert(
+            b"flags",
+            b"coffee",
+            Some(&[0xFF; ITEM_MAX_OLEN + 1]),
+            Duration::ZERO
+        ),
+        Err(SegcacheError::ItemOversized { .. })
+    ));
+
+    // a value which cannot be decompressed is removed and treated as a miss
+    let item_info = cache
+        .hashtable
+        .item_info(b"0", &mut cache.segments)
+        .unwrap();
+    let mut item = cache.segments.get_item(item_info).unwrap();
+    match item.value() {
+        Value::Bytes(value) => unsafe { *(value.as_ptr() as *mut u8) ^= 0xFF },
+        _ => panic!("unexpected value type"),
+    }
+    item.set_checksum();
+    assert!(cache.get(b"0").is_none());
+    assert!(cache
+        .hashtable
+        .item_info(b"0", &mut cache.segments)
+        .is_none());
+    assert!(cache.get(b"1").is_some());
+}
+
+#[test]
+fn admission() {
+    let segments = 16;
+    let segment_size = 4 * 1024;
+    let heap_size = segments * segment_size as usize;
+
+    let mut cache = Segcache::builder()
+        .segment_size(segment_size)
+        .heap_size(heap_size)
+        .hash_power(16)
+        .admission(Admission::TinyLfu {
+            counters: 1024,
+            threshold: 2,
+        })
+        .build()
+        .expect("failed to create cache");
+
+    // writes are admitted while there are free segments
+    let value = [0x42; 100];
+    let mut i = 0;
+    while cache.segments.free() > 0 {
+        let key = format!("{i}");
+        assert!(cache
+            .insert(key.as_bytes(), &value[..], None, Duration::ZERO)
+            .is_ok());
+        assert!(cache.get_no_freq_incr(key.as_bytes()).is_some());
+        i += 1;
+    }
+
+    // once the heap is full, a key which is only written once is rejected
+    assert_eq!(
+        cache.insert(b"scan", &value[..], None, Duration::ZERO),
+        Err(SegcacheError::NotAdmitted)
+    );
+    assert!(cache.get_no_freq_incr(b"scan").is_none());
+
+    // but a key which was read before it is written is admitted
+    assert!(cache.get(b"coffee").is_none());
+    assert!(cache
+        .insert(b"coffee", &value[..], None, Duration::ZERO)
+        .is_ok());
+    assert!(cache.get_no_freq_incr(b"coffee").is_some());
+
+    // keys which are already in the cache can always be updated
+    assert!(cache
+        .insert(b"coffee", b"strong", None, Duration::ZERO)
+        .is_ok());
+    let item = cache.get(b"coffee").unwrap();
+    assert_eq!(item.value(), b"strong");
+}
+
+#[test]
+fn miss_ratio_curve() {
+    let mut cache = Segcache::builder()
+        .segment_size(4096)
+        .heap_size(4096 * 64)
+        .hash_power(16)
+        .miss_ratio_curve(MissRatioCurve::Shards {
+            sample_rate: 1.0,
+            max_keys: 1024,
+        })
+        .build()
+        .expect("failed to create cache");
+
+    // the first lookup of each key is a miss, then the key is written and
+    // every later lookup is a hit for every heap size
+    for _ in 0..10 {
+        for i in 0..10 {
+            let key = format!("{i}");
+            if cache.get(key.as_bytes()).is_none() {
+                assert!(cache
+                    .insert(key.as_bytes(), b"value", None, Duration::ZERO)
+                    .is_ok());
+            }
+        }
+    }
+
+    let curve = cache.miss_ratio_curve().unwrap();
+    assert_eq!(curve.len(), crate::mrc::HEAP_MULTIPLES.len());
+    for (_, miss_ratio) in curve {
+        assert!((miss_ratio - 0.1).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn namespaces() {
+    let segments = 32;
+    let segment_size = 4 * 1024;
+    let heap_size = segments * segment_size as usize;
+
+    let mut cache = Segcache::builder()
+        .segment_size(segment_size)
+        .heap_size(heap_size)
+        .hash_power(16)
+        .namespace(Namespace::new("a").prefix(b"a:").share(0.25))
+        .namespace(Namespace::new("b").prefix(b"b:").share(0.25))
+        .build()
+        .expect("failed to create cache");
+
+    let value = [0x42; 100];
+
+    // namespace a fills a few segments, which is within its share
+    for i in 0..128 {
+        let key = format!("a:{i}");
+        assert!(cache
+            .insert(key.as_bytes(), &value[..], None, Duration::ZERO)
+            .is_ok());
+    }
+    let a = cache.segments.namespace_segments(1);
+    assert!(a > 1 && a <= segments / 4);
+
+    // namespace b writes many times the size of the heap, it may use all of
+    // the free segments but is then limited to evicting its own segments
+    for i in 0..4096 {
+        let key = format!("b:{i}");
+        assert!(cache
+            .insert(key.as_bytes(), &value[..], None, Duration::ZERO)
+            .is_ok());
+    }
+    assert_eq!(cache.segments.free(), 0);
+    assert_eq!(cache.segments.namespace_segments(1), a);
+    assert_eq!(cache.segments.namespace_segments(2), segments - a);
+
+    // none of the items in namespace a were evicted
+    for i in 0..128 {
+        let key = format!("a:{i}");
+        assert!(cache.get(key.as_bytes()).is_some());
+    }
+
+    // keys which match no prefix use the default namespace, which takes
+    // segments from namespace b since it is over its share
+    for i in 0..512 {
+        let key = format!("{i}");
+        assert!(cache
+            .insert(key.as_bytes(), &value[..], None, Duration::ZERO)
+            .is_ok());
+    }
+    assert!(cache.segments.namespace_segments(0) > 1);
+    assert_eq!(cache.segments.namespace_segments(1), a);
+
+    // items can be inserted into a namespace explicitly
+    assert!(cache
+        .insert_namespace(1, b"coffee", b"strong", None, Duration::ZERO)
+        .is_ok());
+    assert!(cache.get(b"coffee").is_some());
+    assert_eq!(
+        cache.insert_namespace(3, b"tea", b"green", None, Duration::ZERO),
+        Err(SegcacheError::UnknownNamespace)
+    );
+}
+
+#[test]
+fn custom_eviction() {
+    use std::sync::{Arc, Mutex};
+
+    // evicts the evictable segment with the lowest id, or nothing once
+    // disabled
+    struct LowestId {
+        enabled: Arc<Mutex<bool>>,
+        chosen: Arc<Mutex<Vec<NonZeroU32>>>,
+    }
+
+    impl EvictionPolicy for LowestId {
+        fn choose(
+            &mut self,
+            headers: &[SegmentHeader],
+            _namespace: Option<u16>,
+        ) -> Option<Decision> {
+            if !*self.enabled.lock().unwrap() {
+                return None;
+            }
+            let id = headers.iter().find(|h| h.can_evict())?.id();
+            self.chosen.lock().unwrap().push(id);
+            Some(Decision::Evict(id))
+        }
+    }
+
+    let segments = 16;
+    let segment_size = 4 * 1024;
+    let heap_size = segments * segment_size as usize;
+
+    let enabled = Arc::new(Mutex::new(true));
+    let chosen = Arc::new(Mutex::new(Vec::new()));
+
+    let mut cache = Segcache::builder()
+        .segment_size(segment_size)
+        .heap_size(heap_size)
+        .hash_power(16)
+        .custom_eviction(LowestId {
+            enabled: enabled.clone(),
+            chosen: chosen.clone(),
+        })
+        .build()
+        .expect("failed to create cache");
+
+    let value = [0x42; 100];
+    for i in 0..1024 {
+        let key = format!("{i}");
+        assert!(cache
+            .insert(key.as_bytes(), &value[..], None, Duration::ZERO)
+            .is_ok());
+    }
+
+    // the policy was used and the first segment written was evicted first
+    let chosen = chosen.lock().unwrap().clone();
+    assert!(!chosen.is_empty());
+    assert_eq!(chosen[0].get(), 1);
+    assert!(cache.get(b"0").is_none());
+    assert!(cache.get(b"1023").is_some());
+
+    // if the policy chooses nothing, inserts fail once the heap is full
+    *enabled.lock().unwrap() = false;
+    let mut i = 1024;
+    let result = loop {
+        let key = format!("{i}");
+        let result = cache.insert(key.as_bytes(), &value[..], None, Duration::ZERO);
+        if result.is_err() {
+            break result;
+        }
+        i += 1;
+    };
+    assert_eq!(result, Err(SegcacheError::NoFreeSegments));
+}
+
+#[test]
+fn disk_tier() {
+    let segments = 16;
+    let segment_size = 4 * 1024;
+    let heap_size = segments * segment_size as usize;
+
+    let tempdir = tempfile::TempDir::new().expect("failed to generate tempdir");
+
+    let mut cache = Segcache::builder()
+        .segment_size(segment_size)
+        .heap_size(heap_size)
+        .hash_power(16)
+        .disk_path(Some(tempdir.path().join("segcache.disk")))
+        .disk_size(64 * segment_size as usize)
+        .disk_segment_size(segment_size)
+        .build()
+        .expect("failed to create cache");
+
+    // insert more items than the heap can hold
+    let value = [0x42; 100];
+    for i in 0..1024 {
+        let key = format!("{i}");
+        assert!(cache
+            .insert(key.as_bytes(), &value[..], None, Duration::ZERO)
+            .is_ok());
+    }
+    assert!(cache.items() < 1024);
+
+    // the first item was evicted from the heap, but is promoted from the disk
+    // tier when it is read
+    assert!(cache
+        .hashtable
+        .get(b"0", cache.time, &mut cache.segments)
+        .is_none());
+    let item = cache.get(b"0").expect("item was not promoted");
+    assert_eq!(item.value(), value[..]);
+    assert!(cache
+        .hashtable
+        .get(b"0", cache.time, &mut cache.segments)
+        .is_some());
+
+    // every item is still retrievable
+    for i in 0..1024 {
+        let key = format!("{i}");
+        let item = cache.get(key.as_bytes()).expect("item was lost");
+        assert_eq!(item.value(), value[..]);
+    }
+
+    // deleting an item which is only held on disk removes it
+    let demoted = (0..1024)
+        .map(|i| format!("{i}"))
+        .find(|key| {
+            cache
+                .hashtable
+                .get(key.as_bytes(), cache.time, &mut cache.segments)
+                .is_none()
+        })
+        .expect("no items on disk");
+    assert!(cache.delete(demoted.as_bytes()));
+    assert!(cache.get(demoted.as_bytes()).is_none());
+
+    // clearing the cache also clears the disk tier
+    cache.clear();
+    for i in 0..1024 {
+        let key = format!("{i}");
+        assert!(cache.get(key.as_bytes()).is_none());
+    }
+}
+
+#[test]
+fn clear() {
+    let ttl = Duration::ZERO;
+    let segment_size = 4096;
+    let segments = 64;
+    let heap_size = segments * segment_size as usize;
+
+    let mut cache = Segcache::builder()
+        .segment_size(segment_size)
+        .heap_size(heap_size)
+        .build()
+        .expect("failed to create cache");
+    assert_eq!(cache.items(), 0);
+    assert_eq!(cache.segments.free(), segments);
+    assert!(cache.get(b"coffee").is_none());
+    assert!(cache.insert(b"coffee", b"strong", None, ttl).is_ok());
+    assert_eq!(cache.segments.free(), segments - 1);
+    assert_eq!(cache.items(), 1);
+    assert!(cache.get(b"coffee").is_some());
+
+    let item = cache.get(b"coffee").unwrap();
+    assert_eq!(item.value(), b"strong", "item is: {item:?}");
+
+    cache.clear();
+    assert_eq!(cache.segments.free(), segments);
+    assert_eq!(cache.items(), 0);
+    assert!(cache.get(b"coffee").is_none());
+}
+
+#[test]
+fn clear_after() {
+    let ttl = Duration::ZERO;
+    let segment_size = 4096;
+    let segments = 64;
+    let heap_size = segments * segment_size as usize;
+
+    let mut cache = Segcache::builder()
+        .segment_size(segment_size)
+        .heap_size(heap_size)
+        .build()
+        .expect("failed to create cache");
+    assert!(cache.insert(b"coffee", b"strong", None, ttl).is_ok());
+
+    cache.clear_after(Duration::from_secs(2));
+    assert_eq!(cache.expire(), 0);
+    assert!(cache.get(b"coffee").is_some());
+
+    // items stored before the delay has passed are also cleared, and are
+    // hidden before they are expired
+    assert!(cache.insert(b"tea", b"green", None, ttl).is_ok());
+    std::thread::sleep(std::time::Duration::from_secs(3));
+    assert!(cache.get(b"coffee").is_none());
+
+    // items stored afterwards are kept
+    assert!(cache.insert(b"latte", b"", None, ttl).is_ok());
+    cache.expire();
+    assert_eq!(cache.items(), 1);
+    assert_eq!(cache.segments.free(), segments - 1);
+    assert!(cache.get(b"tea").is_none());
+    assert!(cache.get(b"latte").is_some());
+
+    // a zero delay clears immediately
+    cache.clear_after(Duration::ZERO);
+    assert!(cache.get(b"latte").is_none());
+}
+
+#[test]
+fn scan() {
+    let ttl = Duration::ZERO;
+    let segment_size = 4096;
+    let segments = 64;
+    let heap_size = segments * segment_size as usize;
+
+    let mut cache = Segcache::builder()
+        .segment_size(segment_size)
+        .heap_size(heap_size)
+        .build()
+        .expect("failed to create cache");
+
+    // an empty cache completes the scan immediately
+    let (cursor, items) = cache.scan(0, 10);
+    assert_eq!(cursor, 0);
+    assert!(items.is_empty());
+
+    // insert enough items to span several segments
+    for i in 0..1024 {
+        let key = format!("{i}");
+        assert!(cache.insert(key.as_bytes(), b"value", None, ttl).is_ok());
+    }
+    assert!(cache.segments.free() < segments - 1);
+
+    // overwritten and deleted items are not returned
+    assert!(cache.insert(b"0", b"other", None, ttl).is_ok());
+    assert!(cache.delete(b"1"));
+
+    let mut seen = std::collections::HashMap::new();
+    let mut cursor = 0;
+    loop {
+        let (next, items) = cache.scan(cursor, 7);
+        assert!(items.len() <= 7);
+        for item in items {
+            let key = std::str::from_utf8(item.key()).unwrap().to_owned();
+            assert!(seen.insert(key, item.value().to_owned()).is_none());
+        }
+
+        // mutations between calls do not invalidate the cursor
+        assert!(cache.insert(b"extra", b"value", None, ttl).is_ok());
+
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+
+    assert!(!seen.contains_key("1"));
+    assert_eq!(seen.get("0").unwrap().as_value(), b"other");
+    for i in 2..1024 {
+        assert_eq!(seen.get(&format!("{i}")).unwrap().as_value(), b"value");
+    }
+}
+
+#[test]
+fn restore() {
+    let segments = 64;
+    let segment_size = 4096;
+    let heap_size = segments * segment_size as usize;
+
+    let tempdir = tempfile::TempDir::new().expect("failed to generate tempdir");
+    let mut path = tempdir.path().to_owned();
+    path.push("segcache.data");
+
+    {
+        let mut cache = Segcache::builder()
+            .segment_size(segment_size)
+            .heap_size(heap_size)
+            .datapool_path(Some(&path))
+            .build()
+            .expect("failed to create cache");
+
+        assert!(cache
+            .insert(b"coffee", b"strong", None, Duration::ZERO)
+            .is_ok());
+        assert!(cache
+            .insert(b"drink", b"coffee", None, Duration::ZERO)
+            .is_ok());
+        assert!(cache
+            .insert(b"drink", b"whisky", None, Duration::ZERO)
+            .is_ok());
+        assert!(cache.insert(b"tea", b"green", None, Duration::ZERO).is_ok());
+        assert!(cache.delete(b"tea"));
+        assert!(cache.insert(b"count", 42_u64, None, Duration::ZERO).is_ok());
+        assert!(cache
+            .insert(b"latte", b"", None, Duration::from_secs(1))
+            .is_ok());
+        assert_eq!(cache.items(), 4);
+        assert_eq!(cache.segments.free(), segments - 2);
+    }
+
+    // wait long enough for the short ttl item to expire while the cache is
+    // not running
+    std::thread::sleep(std::time::Duration::from_secs(2));
+
+    let mut cache = Segcache::builder()
+        .segment_size(segment_size)
+        .heap_size(heap_size)
+        .datapool_path(Some(&path))
+        .build()
+        .expect("failed to restore cache");
+
+    assert_eq!(cache.items(), 3);
+    assert_eq!(cache.segments.free(), segments - 1);
+
+    let item = cache.get(b"coffee").expect("didn't get item back");
+    assert_eq!(item.value(), b"strong", "item is: {item:?}");
+    let item = cache.get(b"drink").expect("didn't get item back");
+    assert_eq!(item.value(), b"whisky", "item is: {item:?}");
+    let item = cache.get(b"count").expect("didn't get item back");
+    assert_eq!(item.value(), 42_u64, "item is: {item:?}");
+    assert!(cache.get(b"tea").is_none());
+    assert!(cache.get(b"latte").is_none());
+
+    // the restored cache is fully usable
+    assert!(cache.insert(b"tea", b"black", None, Duration::ZERO).is_ok());
+    assert!(cache.delete(b"coffee"));
+    assert_eq!(cache.items(), 3);
+
+    // a datapool with a different layout is replaced and the cache starts cold
+    drop(cache);
+    let mut cache = Segcache::builder()
+        .segment_size(segment_size)
+        .heap_size(2 * heap_size)
+        .datapool_path(Some(&path))
+        .build()
+        .expect("failed to create cache");
+    assert_eq!(cache.items(), 0);
+    assert_eq!(cache.segments.free(), 2 * segments);
+    assert!(cache.get(b"drink").is_none());
+}
+
+#[test]
+fn restore_corrupted() {
+    let segment_size = 4096;
+    let heap_size = 64 * segment_size as usize;
+
+    let tempdir = tempfile::TempDir::new().expect("failed to generate tempdir");
+    let path = tempdir.path().join("segcache.data");
+
+    let builder = || {
+        Segcache::builder()
+            .segment_size(segment_size)
+            .heap_size(heap_size)
+            .datapool_path(Some(&path))
+    };
+
+    {
+        let mut cache = builder().build().expect("failed to create cache");
+        assert!(cache
+            .insert(b"coffee", b"strong", None, Duration::ZERO)
+            .is_ok());
+    }
+
+    // modify the datapool as if it was not flushed before the process exited
+    let mut contents = std::fs::read(&path).expect("failed to read datapool");
+    let offset = contents.len() - 1;
+    contents[offset] ^= 0x01;
+    std::fs::write(&path, contents).expect("failed to write datapool");
+
+    // the datapool fails validation and the cache starts cold
+    let mut cache = builder().build().expect("failed to create cache");
+    assert_eq!(cache.items(), 0);
+    assert!(cache.get(b"coffee").is_none());
+
+    // the replacement datapool is restored by the next process
+    assert!(cache.insert(b"tea", b"green", None, Duration::ZERO).is_ok());
+    drop(cache);
+    let mut cache = builder().build().expect("failed to restore cache");
+    let item = cache.get(b"tea").expect("didn't get item back");
+    assert_eq!(item.value(), b"green", "item is: {item:?}");
+
+    // a datapool which was never flushed is also replaced
+    drop(cache);
+    std::fs::write(&path, []).expect("failed to truncate datapool");
+    let mut cache = builder().build().expect("failed to create cache");
+    assert!(cache.get(b"tea").is_none());
+}
+
+#[test]
+fn restore_encrypted() {
+    let segment_size = 4096;
+    let heap_size = 64 * segment_size as usize;
+
+    let tempdir = tempfile::TempDir::new().expect("failed to generate tempdir");
+    let path = tempdir.path().join("segcache.data");
+    let key = tempdir.path().join("segcache.key");
+    std::fs::write(&key, b"0123456789abcdef0123456789abcdef").expect("failed to write key");
+
+    let builder = || {
+        Segcache::builder()
+            .segment_size(segment_size)
+            .heap_size(heap_size)
+            .datapool_path(Some(&path))
+    };
+
+    {
+        let mut cache = builder()
+            .datapool_key_path(Some(&key))
+            .build()
+            .expect("failed to create cache");
+        assert!(cache
+            .insert(b"coffee", b"strong", None, Duration::ZERO)
+            .is_ok());
+    }
+
+    // the plaintext is not written to the datapool file
+    let contents = std::fs::read(&path).expect("failed to read datapool");
+    assert!(!contents.windows(6).any(|w| w == b"coffee"));
+
+    // the datapool is refused without a key or with a different key
+    assert!(builder().build().is_err());
+    let wrong = tempdir.path().join("wrong.key");
+    std::fs::write(&wrong, [0; 32]).expect("failed to write key");
+    assert!(builder().datapool_key_path(Some(&wrong)).build().is_err());
+
+    let mut cache = builder()
+        .datapool_key_path(Some(&key))
+        .build()
+        .expect("failed to restore cache");
+    let item = cache.get(b"coffee").expect("didn't get item back");
+    assert_eq!(item.value(), b"strong", "item is: {item:?}");
+
+    // the checkpoint is encrypted with the same key
+    let checkpoint = tempdir.path().join("segcache.checkpoint");
+    assert!(cache.checkpoint(&checkpoint).is_ok());
+    drop(cache);
+    let contents = std::fs::read(&checkpoint).expect("failed to read checkpoint");
+    assert!(!contents.windows(7).any(|w| w == b"SEGCKPT"));
+
+    let mut cache = builder()
+        .datapool_key_path(Some(&key))
+        .checkpoint_path(Some(&checkpoint))
+        .build()
+        .expect("failed to restore cache");
+    let item = cache.get(b"coffee").expect("didn't get item back");
+    assert_eq!(item.value(), b"strong", "item is: {item:?}");
+    drop(cache);
+
+    // evicted items would be written to the disk tier in plaintext
+    assert!(builder()
+        .datapool_key_path(Some(&key))
+        .disk_path(Some(tempdir.path().join("segcache.disk")))
+        .build()
+        .is_err());
+}
+
+#[test]
+fn restore_tags() {
+    let segment_size = 4096;
+    let heap_size = 64 * segment_size as usize;
+
+    let tempdir = tempfile::TempDir::new().expect("failed to generate tempdir");
+    let path = tempdir.path().join("segcache.data");
+
+    let builder = || {
+        Segcache::builder()
+            .segment_size(segment_size)
+            .heap_size(heap_size)
+            .datapool_path(Some(&path))
+    };
+
+    {
+        let mut cache = builder().build().expect("failed to create cache");
+        for (key, tag) in [(&b"latte"[..], &b"milk"[..]), (b"espresso", b"beans")] {
+            assert!(cache
+                .insert_tagged(key, b"coffee", None, Duration::ZERO, &[tag])
+                .is_ok());
+        }
+        assert!(cache.insert(b"tea", b"green", None, Duration::ZERO).is_ok());
+        cache.invalidate_tag(b"milk");
+    }
+
+    // the invalidations are restored, so items with tags which were not
+    // invalidated are kept
+    let mut cache = builder().build().expect("failed to restore cache");
+    assert!(cache.get(b"latte").is_none());
+    assert!(cache.get(b"espresso").is_some());
+    assert!(cache.get(b"tea").is_some());
+
+    // invalidations continue from the restored epoch
+    assert!(cache
+        .insert_tagged(b"cortado", b"coffee", None, Duration::ZERO, &[b"milk"])
+        .is_ok());
+    cache.invalidate_tag(b"beans");
+    assert!(cache.get(b"espresso").is_none());
+    assert!(cache.get(b"cortado").is_some());
+    drop(cache);
+
+    // without the invalidations, every item with tags is dropped
+    let mut tags = path.as_os_str().to_owned();
+    tags.push(".tags");
+    std::fs::remove_file(&tags).expect("failed to remove tags");
+    let mut cache = builder().build().expect("failed to restore cache");
+    assert!(cache.get(b"cortado").is_none());
+    assert!(cache.get(b"tea").is_some());
+    drop(cache);
+
+    // invalidations which do not match the datapool are not restored
+    let mut cache = builder().build().expect("failed to restore cache");
+    assert!(cache
+        .insert_tagged(b"mocha", b"coffee", None, Duration::ZERO, &[b"cocoa"])
+        .is_ok());
+    drop(cache);
+    let stale = std::fs::read(&tags).expect("failed to read tags");
+    let mut cache = builder().build().expect("failed to restore cache");
+    assert!(cache
+        .insert(b"chai", b"spiced", None, Duration::ZERO)
+        .is_ok());
+    drop(cache);
+    std::fs::write(&tags, stale).expect("failed to write tags");
+    let mut cache = builder().build().expect("failed to restore cache");
+    assert!(cache.get(b"mocha").is_none());
+    assert!(cache.get(b"chai").is_some());
+}
+
+#[test]
+fn checkpoint() {
+    let segments = 64;
+    let segment_size = 4096;
+    let heap_size = segments * segment_size as usize;
+
+    let tempdir = tempfile::TempDir::new().expect("failed to generate tempdir");
+    let datapool = tempdir.path().join("segcache.data");
+    let checkpoint = tempdir.path().join("segcache.checkpoint");
+
+    let builder = || {
+        Segcache::builder()
+            .segment_size(segment_size)
+            .heap_size(heap_size)
+            .datapool_path(Some(&datapool))
+            .checkpoint_path(Some(&checkpoint))
+    };
+
+    // checkpoints require a file backed datapool
+    let mut cache = Segcache::builder()
+        .segment_size(segment_size)
+        .heap_size(heap_size)
+        .build()
+        .expect("failed to create cache");
+    assert!(cache.checkpoint(&checkpoint).is_err());
+    assert!(!checkpoint.exists());
+
+    {
+        let mut cache = builder().build().expect("failed to create cache");
+
+        assert!(cache
+            .insert(b"coffee", b"strong", None, Duration::ZERO)
+            .is_ok());
+        assert!(cache.insert(b"tea", b"green", None, Duration::ZERO).is_ok());
+        assert!(cache.delete(b"tea"));
+        assert!(cache.insert(b"count", 42_u64, None, Duration::ZERO).is_ok());
+        assert!(cache
+            .insert(b"latte", b"", None, Duration::from_secs(1))
+            .is_ok());
+        assert!(cache.checkpoint(&checkpoint).is_ok());
+    }
+
+    // wait long enough for the short ttl item to expire while the cache is
+    // not running
+    std::thread::sleep(std::time::Duration::from_secs(2));
+
+    {
+        let mut cache = builder().build().expect("failed to restore cache");
+
+        assert_eq!(cache.items(), 2);
+        assert_eq!(cache.segments.free(), segments - 1);
+
+        let item = cache.get(b"coffee").expect("didn't get item back");
+        assert_eq!(item.value(), b"strong", "item is: {item:?}");
+        let item = cache.get(b"count").expect("didn't get item back");
+        assert_eq!(item.value(), 42_u64, "item is: {item:?}");
+        assert!(cache.get(b"tea").is_none());
+        assert!(cache.get(b"latte").is_none());
+
+        // the restored cache is fully usable, but these changes are not
+        // reflected in the checkpoint
+        assert!(cache.insert(b"tea", b"black", None, Duration::ZERO).is_ok());
+        assert!(cache.delete(b"coffee"));
+        assert_eq!(cache.items(), 2);
+    }
+
+    // a stale checkpoint is ignored and the segments are scanned instead
+    let mut cache = builder().build().expect("failed to restore cache");
+    assert_eq!(cache.items(), 2);
+    let item = cache.get(b"tea").expect("didn't get item back");
+    assert_eq!(item.value(), b"black", "item is: {item:?}");
+    assert!(cache.get(b"coffee").is_none());
+}
+
+#[test]
+fn checkpoint_expanded() {
+    let tempdir = tempfile::TempDir::new().expect("failed to generate tempdir");
+    let datapool = tempdir.path().join("segcache.data");
+    let checkpoint = tempdir.path().join("segcache.checkpoint");
+
+    let builder = || {
+        Segcache::builder()
+            .segment_size(64 * 1024)
+            .heap_size(64 * 64 * 1024)
+            .hash_power(6)
+            .overflow_factor(1.0)
+            .hash_expand_threshold(Some(0.75))
+            .datapool_path(Some(&datapool))
+            .checkpoint_path(Some(&checkpoint))
+    };
+
+    let power = {
+        let mut cache = builder().build().expect("failed to create cache");
+        for i in 0..2000 {
+            let key = format!("{i}");
+            assert!(cache
+                .insert(key.as_bytes(), b"value", None, Duration::ZERO)
+                .is_ok());
+        }
+        assert!(cache.hashtable.power() > 6);
+        assert!(cache.checkpoint(&checkpoint).is_ok());
+        cache.hashtable.power()
+    };
+
+    // the hashtable is restored from the checkpoint at its expanded size,
+    // rather than being rebuilt by scanning the segments
+    let mut cache = builder().build().expect("failed to restore cache");
+    assert_eq!(cache.hashtable.power(), power);
+    assert!(!cache.hashtable.expanding());
+    assert_eq!(cache.items(), 2000);
+    for i in 0..2000 {
+        let key = format!("{i}");
+        let item = cache.get(key.as_bytes()).expect("item missing");
+        assert_eq!(item.value(), b"value");
+    }
+}
+
+#[test]
+fn wrapping_add() {
+    let ttl = Duration::ZERO;
+    let segment_size = 4096;
+    let segments = 64;
+    let heap_size = segments * segment_size as usize;
+
+    let mut cache = Segcache::builder()
+        .segment_size(segment_size)
+        .heap_size(heap_size)
+        .build()
+        .expect("failed to create cache");
+    assert_eq!(cache.items(), 0);
+    assert_eq!(cache.segments.free(), 64);
+    assert!(cache.insert(b"coffee", 0, None, ttl).is_ok());
+    assert_eq!(cache.segments.free(), 63);
+    assert_eq!(cache.items(), 1);
+    assert!(cache.get(b"coffee").is_some());
+
+    let item = cache.get(b"coffee").unwrap();
+    assert_eq!(item.value(), 0, "item is: {item:?}");
+    cache
+        .wrapping_add(b"coffee", 1)
+        .expect("failed to increment");
+    assert_eq!(item.value(), 1, "item is: {item:?}");
+    cache
+        .wrapping_add(b"coffee", u64::MAX - 1)
+        .expect("failed to increment");
+    assert_eq!(item.value(), u64::MAX, "item is: {item:?}");
+    cache
+        .wrapping_add(b"coffee", 1)
+        .expect("failed to increment");
+    assert_eq!(item.value(), 0, "item is: {item:?}");
+    cache
+        .wrapping_add(b"coffee", 2)
+        .expect("failed to increment");
+    assert_eq!(item.value(), 2, "item is: {item:?}");
+}
+
+#[test]
+fn saturating_sub() {
+    let ttl = Duration::ZERO;
+    let segment_size = 4096;
+    let segments = 64;
+    let heap_size = segments * segment_size as usize;
+
+    let mut cache = Segcache::builder()
+        .segment_size(segment_size)
+        .heap_size(heap_size)
+        .build()
+        .expect("failed to create cache");
+    assert_eq!(cache.items(), 0);
+    assert_eq!(cache.segments.free(), 64);
+    assert!(cache.insert(b"coffee", 3, None, ttl).is_ok());
+    assert_eq!(cache.segments.free(), 63);
+    assert_eq!(cache.items(), 1);
+    assert!(cache.get(b"coffee").is_some());
+
+    let item = cache.get(b"coffee").unwrap();
+    assert_eq!(item.value(), 3, "item is: {item:?}");
+    cache
+        .saturating_sub(b"coffee", 2)
+        .expect("failed to increment");
+    assert_eq!(item.value(), 1, "item is: {item:?}");
+    cache
+        .saturating_sub(b"coffee", 1)
+        .expect("failed to increment");
+    assert_eq!(item.value(), 0, "item is: {item:?}");
+    cache
+        .saturating_sub(b"coffee", 1)
+        .expect("failed to increment");
+    assert_eq!(item.value(), 0, "item is: {item:?}");
+}
+
+#[test]
+fn checked_add() {
+    let ttl = Duration::ZERO;
+    let mut cache = Segcache::builder()
+        .segment_size(4096)
+        .heap_size(64 * 4096)
+        .build()
+        .expect("failed to create cache");
+
+    assert!(cache.insert(b"coffee", Value::I64(-3), None, ttl).is_ok());
+    let item = cache.get(b"coffee").unwrap();
+    assert_eq!(item.value(), Value::I64(-3), "item is: {item:?}");
+
+    cache
+        .checked_add(b"coffee", 5)
+        .expect("failed to increment");
+    assert_eq!(item.value(), Value::I64(2), "item is: {item:?}");
+    cache
+        .checked_add(b"coffee", i64::MAX - 2)
+        .expect("failed to increment");
+    assert_eq!(item.value(), Value::I64(i64::MAX), "item is: {item:?}");
+
+    // overflow is an error and leaves the value unchanged
+    assert_eq!(
+        cache.checked_add(b"coffee", 1).err(),
+        Some(SegcacheError::Overflow)
+    );
+    assert_eq!(item.value(), Value::I64(i64::MAX), "item is: {item:?}");
+    cache
+        .checked_add(b"coffee", i64::MIN)
+        .expect("failed to decrement");
+    assert_eq!(item.value(), Value::I64(-1), "item is: {item:?}");
+
+    // unsigned values are stored as signed afterwards
+    assert!(cache.insert(b"tea", 5, None, ttl).is_ok());
+    cache.checked_add(b"tea", -10).expect("failed to decrement");
+    assert_eq!(cache.get(b"tea").unwrap().value(), Value::I64(-5));
+
+    // unsigned values which are outside the signed range overflow
+    assert!(cache.insert(b"chai", u64::MAX, None, ttl).is_ok());
+    assert_eq!(
+        cache.checked_add(b"chai", -1).err(),
+        Some(SegcacheError::Overflow)
+    );
+    assert_eq!(cache.get(b"chai").unwrap().value(), u64::MAX);
+
+    // floats and bytes are not integers
+    assert!(cache.insert(b"latte", Value::F64(1.5), None, ttl).is_ok());
+    assert_eq!(
+        cache.checked_add(b"latte", 1).err(),
+        Some(SegcacheError::NotInteger)
+    );
+    assert!(cache.insert(b"mocha", b"1", None, ttl).is_ok());
+    assert_eq!(
+        cache.checked_add(b"mocha", 1).err(),
+        Some(SegcacheError::NotNumeric)
+    );
+    assert_eq!(
+        cache.checked_add(b"water", 1).err(),
+        Some(SegcacheError::NotFound)
+    );
+}
+
+#[test]
+fn checked_add_float() {
+    let ttl = Duration::ZERO;
+    let mut cache = Segcache::builder()
+        .segment_size(4096)
+        .heap_size(64 * 4096)
+        .build()
+        .expect("failed to create cache");
+
+    assert!(cache.insert(b"coffee", Value::F64(10.5), None, ttl).is_ok());
+    let item = cache
+        .checked_add_float(b"coffee", 0.1)
+        .expect("failed to increment");
+    assert_eq!(item.value(), Value::F64(10.6), "item is: {item:?}");
+    let item = cache
+        .checked_add_float(b"coffee", -5e3)
+        .expect("failed to decrement");
+    assert_eq!(item.value(), Value::F64(-4989.4), "item is: {item:?}");
+
+    // results which are not finite are an error and leave the value unchanged
+    assert_eq!(
+        cache.checked_add_float(b"coffee", f64::INFINITY).err(),
+        Some(SegcacheError::NotFinite)
+    );
+    assert_eq!(
+        cache.checked_add_float(b"coffee", f64::NAN).err(),
+        Some(SegcacheError::NotFinite)
+    );
+    assert_eq!(cache.get(b"coffee").unwrap().value(), Value::F64(-4989.4));
+
+    // integers are converted to floats
+    assert!(cache.insert(b"tea", Value::I64(-3), None, ttl).is_ok());
+    let item = cache
+        .checked_add_float(b"tea", 0.5)
+        .expect("failed to increment");
+    assert_eq!(item.value(), Value::F64(-2.5), "item is: {item:?}");
+    assert!(cache.insert(b"latte", 3, None, ttl).is_ok());
+    let item = cache
+        .checked_add_float(b"latte", 0.25)
+        .expect("failed to increment");
+    assert_eq!(item.value(), Value::F64(3.25), "item is: {item:?}");
+
+    assert!(cache.insert(b"mocha", b"1.5", None, ttl).is_ok());
+    assert_eq!(
+        cache.checked_add_float(b"mocha", 1.0).err(),
+        Some(SegcacheError::NotNumeric)
+    );
+}
+
+#[test]
+fn top_keys() {
+    let ttl = Duration::ZERO;
+    let cache = Segcache::builder()
+        .segment_size(4096)
+        .heap_size(64 * 4096)
+        .build()
+        .expect("failed to create cache");
+    assert!(cache.top_keys(10).is_none());
+
+    let mut cache = Segcache::builder()
+        .segment_size(4096)
+        .heap_size(64 * 4096)
+        .top_keys(16)
+        .build()
+        .expect("failed to create cache");
+    assert_eq!(cache.top_keys(10), Some(Vec::new()));
+
+    assert!(cache.insert(b"coffee", b"strong", None, ttl).is_ok());
+    assert!(cache.insert(b"tea", b"green", None, ttl).is_ok());
+    for _ in 0..5 {
+        assert!(cache.get(b"coffee").is_some());
+    }
+    assert!(cache.get(b"tea").is_some());
+
+    // misses and peeks are not counted
+    assert!(cache.get(b"water").is_none());
+    assert!(cache.get_no_freq_incr(b"tea").is_some());
+
+    let top = cache.top_keys(10).unwrap();
+    assert_eq!(top.len(), 2);
+    assert_eq!(&*top[0].key, b"coffee");
+    assert_eq!(top[0].hits, 5);
+    assert_eq!(top[0].error, 0);
+    assert_eq!(
+        top[0].size,
+        cache.get_no_freq_incr(b"coffee").unwrap().size()
+    );
+    assert_eq!(&*top[1].key, b"tea");
+    assert_eq!(top[1].hits, 1);
+
+    assert_eq!(cache.top_keys(1).unwrap().len(), 1);
 }
 
 #[test]
@@ -0,0 +1,325 @@
+// Copyright 2023 Pelikan Foundation LLC.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! A second storage tier on local disk which holds items that were evicted
+//! from the heap.
+//!
+//! The tier is a circular log of fixed-size segments in a file-backed
+//! datapool. Items which are evicted from the heap are demoted by copying
+//! them from the heap segment straight into the current segment of the tier,
+//! as part of the eviction. Once the current segment is full, another
+//! segment is chosen according to the `DiskPolicy` and any items it holds are
+//! dropped from the tier. Items are promoted back into the heap when they are
+//! read, which removes them from the tier.
+//!
+//! The index of the items held in the tier is kept in memory and is keyed by
+//! the 64-bit hash of the item key, rather than the key itself, to keep the
+//! index small. The key is stored with the item in the tier, and every lookup
+//! compares it against the requested key, so an item is never returned for a
+//! different key. If two keys have the same hash, only the most recently
+//! demoted item is kept and the older one is dropped from the tier as if it
+//! had been evicted. A lookup of the older key is then a miss.
+
+use crate::*;
+use ahash::RandomState;
+use datatier::*;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The policy used to choose which segment of the disk tier is reused once
+/// the current segment is full.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DiskPolicy {
+    /// Segments are reused in the order they were written, which drops the
+    /// oldest demoted items first.
+    #[default]
+    Fifo,
+    /// Segments are reused in a random order.
+    Random,
+}
+
+/// The location of an item within the disk tier.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Location {
+    seg: u32,
+    offset: u32,
+    /// The creation time of the heap segment the item was evicted from
+    created: Instant,
+    expire_at: Instant,
+}
+
+/// A segment-structured log in a file-backed datapool which holds items that
+/// were demoted from the heap.
+pub(crate) struct DiskTier {
+    data: Box<dyn Datapool>,
+    segment_size: usize,
+    /// The number of bytes written into each segment
+    written: Box<[u32]>,
+    /// The oldest creation time of the heap segments which the items written
+    /// into each segment were evicted from
+    created: Box<[Instant]>,
+    /// The segment currently being written
+    current: usize,
+    policy: DiskPolicy,
+    rng: Box<Random>,
+    hash_builder: Box<RandomState>,
+    /// The location of each item, keyed by the hash of its key. Holds one
+    /// item per hash, see the module documentation.
+    index: HashMap<u64, Location>,
+    /// The pattern of a purge in progress, matching items are not demoted
+    purge: Option<Pattern>,
+}
+
+impl DiskTier {
+    /// Creates a new disk tier backed by a file at the given path. The tier is
+    /// not persistent, so the contents of any existing file at the path are
+    /// discarded.
+    ///
+    /// The file is memory mapped, which allows the tier to be larger than the
+    /// available memory. The operating system pages in the parts of the tier
+    /// which are accessed.
+    pub fn new<T: AsRef<Path>>(
+        path: T,
+        size: usize,
+        segment_size: i32,
+        policy: DiskPolicy,
+    ) -> Result<Self, std::io::Error> {
+        let segment_size = segment_size as usize;
+        let segments = size / segment_size.max(1);
+
+        if segment_size == 0 || segments == 0 || segments > u32::MAX as usize {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "disk tier size must hold at least one segment",
+            ));
+        }
+
+        let path = path.as_ref();
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+
+        let data = MmapFile::create(path, segments * segment_size, crate::VERSION)?;
+
+        Ok(Self {
+            data: Box::new(data),
+            segment_size,
+            written: vec![0; segments].into_boxed_slice(),
+            created: vec![Instant::now(); segments].into_boxed_slice(),
+            current: 0,
+            policy,
+            rng: Box::new(rng()),
+            hash_builder: Box::new(RandomState::with_seeds(
+                0xbb8c484891ec6c86,
+                0x0522a25ae9c769f9,
+                0xeed2797b9571bc75,
+                0x4feb29c1fbbd59d0,
+            )),
+            index: HashMap::new(),
+            purge: None,
+        })
+    }
+
+    fn hash(&self, key: &[u8]) -> u64 {
+        let mut hasher = self.hash_builder.build_hasher();
+        hasher.write(key);
+        hasher.finish()
+    }
+
+    /// Returns the item stored at the given location.
+    fn item_at(&mut self, seg: usize, offset: usize) -> RawItem {
+        let start = seg * self.segment_size + offset;
+        RawItem::from_ptr(self.data.as_mut_slice()[start..].as_mut_ptr())
+    }
+
+    /// Demote an item into the tier. The item is copied directly from the heap
+    /// segment it was evicted from, which was created at `created`. Items
+    /// which are larger than a segment of the tier are dropped, as are items
+    /// which match a purge in progress, since the purge may not have reached
+    /// them before they were evicted.
+    pub fn insert(&mut self, item: &RawItem, created: Instant, expire_at: Instant) {
+        let bytes = item.as_bytes();
+        if bytes.len() > self.segment_size {
+            return;
+        }
+
+        if let Some(purge) = self.purge.as_ref() {
+            if purge.matches(item.key()) {
+                return;
+            }
+        }
+
+        if self.written[self.current] as usize + bytes.len() > self.segment_size {
+            self.advance();
+        }
+
+        let seg = self.current;
+        let offset = self.written[seg] as usize;
+        if offset == 0 || created < self.created[seg] {
+            self.created[seg] = created;
+        }
+        let start = seg * self.segment_size + offset;
+
+        self.data.as_mut_slice()[start..(start + bytes.len())].copy_from_slice(bytes);
+        self.written[seg] += bytes.len() as u32;
+
+        let hash = self.hash(item.key());
+        let location = Location {
+            seg: seg as u32,
+            offset: offset as u32,
+            created,
+            expire_at,
+        };
+
+        #[allow(unused_variables)]
+        let previous = self.index.insert(hash, location);
+
+        // an item with a different key but the same hash is dropped
+        #[cfg(feature = "metrics")]
+        match previous {
+            None => {
+                DISK_ITEM_CURRENT.increment();
+            }
+            Some(previous) => {
+                if self
+                    .item_at(previous.seg as usize, previous.offset as usize)
+                    .key()
+                    != item.key()
+                {
+                    DISK_EVICT.increment();
+                }
+            }
+        }
+
+        #[cfg(feature = "metrics")]
+        DISK_DEMOTE.increment();
+    }
+
+    /// Moves to the next segment to be written, dropping any items which it
+    /// holds from the tier.
+    fn advance(&mut self) {
+        let segments = self.written.len();
+
+        let next = match self.policy {
+            DiskPolicy::Fifo => (self.current + 1) % segments,
+            DiskPolicy::Random => self.rng.gen::<u32>() as usize % segments,
+        };
+
+        let mut offset = 0;
+        while offset < self.written[next] as usize {
+            let item = self.item_at(next, offset);
+            let hash = self.hash(item.key());
+
+            if let Some(location) = self.index.get(&hash) {
+                if location.seg as usize == next && location.offset as usize == offset {
+                    self.index.remove(&hash);
+
+                    #[cfg(feature = "metrics")]
+                    {
+                        DISK_EVICT.increment();
+                        DISK_ITEM_CURRENT.decrement();
+                    }
+                }
+            }
+
+            offset += item.size();
+        }
+
+        self.written[next] = 0;
+        self.current = next;
+    }
+
+    /// Looks up the location of the item with the provided key. Returns `None`
+    /// if the index entry for the hash of the key belongs to another key.
+    fn locate(&mut self, key: &[u8]) -> Option<(u64, Location)> {
+        let hash = self.hash(key);
+        let location = *self.index.get(&hash)?;
+
+        if self
+            .item_at(location.seg as usize, location.offset as usize)
+            .key()
+            != key
+        {
+            return None;
+        }
+
+        Some((hash, location))
+    }
+
+    /// Removes the item with the provided key from the tier. If the item has
+    /// not expired, the raw bytes of the item are returned along with the
+    /// creation time of the heap segment it was evicted from and its remaining
+    /// TTL, so that it can be promoted into the heap.
+    pub fn remove(&mut self, key: &[u8]) -> Option<(Vec<u8>, Instant, Duration)> {
+        let (hash, location) = self.locate(key)?;
+        self.index.remove(&hash);
+
+        #[cfg(feature = "metrics")]
+        DISK_ITEM_CURRENT.decrement();
+
+        let now = Instant::now();
+        if location.expire_at <= now {
+            return None;
+        }
+
+        let item = self.item_at(location.seg as usize, location.offset as usize);
+
+        // the ttl has a resolution of one second, so the item may live for up
+        // to a second longer than it would have in the heap
+        let ttl = std::cmp::max(Duration::from_secs(1), location.expire_at - now);
+
+        Some((item.as_bytes().to_vec(), location.created, ttl))
+    }
+
+    /// Removes every item with a key which matches the pattern from the tier.
+    /// Returns the number of items which were removed.
+    pub fn remove_matching(&mut self, pattern: &Pattern) -> usize {
+        let matching: Vec<u64> = self
+            .index
+            .iter()
+            .filter(|(_, location)| {
+                let start = location.seg as usize * self.segment_size + location.offset as usize;
+                let item = RawItem::from_ptr(self.data.as_slice()[start..].as_ptr() as *mut u8);
+                pattern.matches(item.key())
+            })
+            .map(|(hash, _)| *hash)
+            .collect();
+
+        for hash in matching.iter() {
+            self.index.remove(hash);
+        }
+
+        #[cfg(feature = "metrics")]
+        DISK_ITEM_CURRENT.sub(matching.len() as _);
+
+        matching.len()
+    }
+
+    /// Stops items which match the pattern from being demoted while a purge
+    /// is in progress. Passing `None` allows them to be demoted again.
+    pub fn set_purge(&mut self, pattern: Option<Pattern>) {
+        self.purge = pattern;
+    }
+
+    /// Returns the creation time of the oldest heap segment which any item
+    /// held in the tier was evicted from, if the tier holds items.
+    pub fn oldest(&self) -> Option<Instant> {
+        self.written
+            .iter()
+            .zip(self.created.iter())
+            .filter(|(written, _)| **written > 0)
+            .map(|(_, created)| *created)
+            .min()
+    }
+
+    /// Drops all items from the tier.
+    pub fn clear(&mut self) {
+        #[cfg(feature = "metrics")]
+        DISK_ITEM_CURRENT.sub(self.index.len() as _);
+
+        self.index.clear();
+        self.written.fill(0);
+        self.current = 0;
+    }
+}
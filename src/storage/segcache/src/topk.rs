@@ -0,0 +1,185 @@
+// Copyright 2023 Pelikan Foundation LLC.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Approximate top-K keys by hit count using the Space-Saving algorithm from
+//! "Efficient Computation of Frequent and Top-k Elements in Data Streams" by
+//! Metwally, Agrawal, and El Abbadi.
+//!
+//! A fixed number of counters are kept. A hit for a key which has a counter
+//! increments it. A hit for any other key takes over the counter with the
+//! smallest count, and the new key starts from that count plus one. The count
+//! of a key therefore never underestimates its hits, and overestimates them by
+//! at most the error recorded when the key took over the counter. Any key with
+//! more hits than the total number of hits divided by the number of counters
+//! is guaranteed to hold a counter.
+//!
+//! The counters are kept in a binary min-heap so that the smallest count can
+//! be found in constant time and updated in logarithmic time.
+
+use std::collections::HashMap;
+
+/// A key reported by [`Segcache::top_keys`](crate::Segcache::top_keys).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TopKey {
+    /// The key
+    pub key: Box<[u8]>,
+    /// The estimated number of hits, which may be an overestimate
+    pub hits: u64,
+    /// The most the number of hits may be overestimated by
+    pub error: u64,
+    /// The size of the item in bytes when it was last hit
+    pub size: usize,
+}
+
+struct Counter {
+    key: Box<[u8]>,
+    hits: u64,
+    error: u64,
+    size: usize,
+}
+
+/// Tracks the keys with the most hits using a bounded number of counters.
+pub(crate) struct TopKeys {
+    capacity: usize,
+    /// A min-heap of the counters, ordered by hits
+    heap: Vec<Counter>,
+    /// The position of each key in the heap
+    index: HashMap<Box<[u8]>, usize>,
+}
+
+impl TopKeys {
+    /// Returns a new estimator with the given number of counters, or `None`
+    /// if the capacity is zero.
+    pub fn new(capacity: usize) -> Option<Self> {
+        if capacity == 0 {
+            return None;
+        }
+
+        Some(Self {
+            capacity,
+            heap: Vec::with_capacity(capacity),
+            index: HashMap::with_capacity(capacity),
+        })
+    }
+
+    /// Record a hit for a key, along with the size of the item.
+    pub fn record(&mut self, key: &[u8], size: usize) {
+        if let Some(&position) = self.index.get(key) {
+            let counter = &mut self.heap[position];
+            counter.hits += 1;
+            counter.size = size;
+            self.sift_down(position);
+        } else if self.heap.len() < self.capacity {
+            self.index.insert(key.into(), self.heap.len());
+            self.heap.push(Counter {
+                key: key.into(),
+                hits: 1,
+                error: 0,
+                size,
+            });
+            self.sift_up(self.heap.len() - 1);
+        } else {
+            // the key takes over the counter with the fewest hits
+            let counter = &mut self.heap[0];
+            self.index.remove(&counter.key);
+            counter.key = key.into();
+            counter.error = counter.hits;
+            counter.hits += 1;
+            counter.size = size;
+            self.index.insert(key.into(), 0);
+            self.sift_down(0);
+        }
+    }
+
+    /// Returns up to `k` keys with the most hits, from most to fewest hits.
+    pub fn top(&self, k: usize) -> Vec<TopKey> {
+        let mut counters: Vec<&Counter> = self.heap.iter().collect();
+        counters.sort_by(|a, b| b.hits.cmp(&a.hits).then_with(|| a.key.cmp(&b.key)));
+        counters
+            .iter()
+            .take(k)
+            .map(|counter| TopKey {
+                key: counter.key.clone(),
+                hits: counter.hits,
+                error: counter.error,
+                size: counter.size,
+            })
+            .collect()
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        *self.index.get_mut(&self.heap[a].key).unwrap() = a;
+        *self.index.get_mut(&self.heap[b].key).unwrap() = b;
+    }
+
+    fn sift_up(&mut self, mut position: usize) {
+        while position > 0 {
+            let parent = (position - 1) / 2;
+            if self.heap[parent].hits <= self.heap[position].hits {
+                return;
+            }
+            self.swap(parent, position);
+            position = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut position: usize) {
+        loop {
+            let left = 2 * position + 1;
+            let right = left + 1;
+
+            let mut smallest = position;
+            if left < self.heap.len() && self.heap[left].hits < self.heap[smallest].hits {
+                smallest = left;
+            }
+            if right < self.heap.len() && self.heap[right].hits < self.heap[smallest].hits {
+                smallest = right;
+            }
+
+            if smallest == position {
+                return;
+            }
+            self.swap(smallest, position);
+            position = smallest;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn space_saving() {
+        let mut topk = TopKeys::new(8).unwrap();
+
+        // a few heavy hitters mixed with a long tail of keys which are only
+        // hit once
+        for i in 0..1000 {
+            topk.record(b"hot", 10);
+            if i % 2 == 0 {
+                topk.record(b"warm", 20);
+            }
+            topk.record(format!("cold:{i}").as_bytes(), 30);
+        }
+
+        let top = topk.top(2);
+        assert_eq!(top.len(), 2);
+        assert_eq!(&*top[0].key, b"hot");
+        assert_eq!(top[0].size, 10);
+        assert_eq!(&*top[1].key, b"warm");
+        assert_eq!(top[1].size, 20);
+
+        // the counts are never underestimated and are within the error
+        for key in &top {
+            let hits = if &*key.key == b"hot" { 1000 } else { 500 };
+            assert!(key.hits >= hits);
+            assert!(key.hits - key.error <= hits);
+        }
+
+        assert_eq!(topk.top(16).len(), 8);
+        assert!(TopKeys::new(0).is_none());
+    }
+}
@@ -0,0 +1,57 @@
+// Copyright 2023 Pelikan Foundation LLC.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Budgets which bound the amount of work done by a single expiration pass.
+
+use clocksource::precise::Instant;
+
+/// Limits the work done by a single call to `Segcache::expire()`. Once the
+/// budget is exhausted, expiration stops and the next call resumes from the
+/// `TtlBucket` where it stopped. At least one expired segment is cleared by
+/// each call, so expiration always makes progress.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ExpireBudget {
+    /// Every expired segment is cleared in a single call.
+    #[default]
+    Unbounded,
+    /// Clear at most this many expired segments in a single call.
+    Segments(usize),
+    /// Stop clearing expired segments once this much time has passed.
+    Time(std::time::Duration),
+}
+
+/// Tracks the work done during an expiration pass against the budget.
+pub(crate) struct ExpireLimit {
+    budget: ExpireBudget,
+    start: Instant,
+    expired: usize,
+}
+
+impl ExpireLimit {
+    pub fn new(budget: ExpireBudget) -> Self {
+        Self {
+            budget,
+            start: Instant::now(),
+            expired: 0,
+        }
+    }
+
+    /// Record that a segment was expired.
+    pub fn record(&mut self) {
+        self.expired += 1;
+    }
+
+    /// Returns true if no more segments should be expired during this pass.
+    pub fn exhausted(&self) -> bool {
+        if self.expired == 0 {
+            return false;
+        }
+
+        match self.budget {
+            ExpireBudget::Unbounded => false,
+            ExpireBudget::Segments(segments) => self.expired >= segments,
+            ExpireBudget::Time(duration) => Instant::now() >= self.start + duration,
+        }
+    }
+}
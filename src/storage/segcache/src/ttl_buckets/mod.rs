@@ -12,6 +12,7 @@
 //! <https://twitter.github.io/pelikan/2021/segcache.html>
 //!
 
+mod budget;
 mod error;
 mod ttl_bucket;
 #[allow(clippy::module_inception)]
@@ -20,6 +21,9 @@ mod ttl_buckets;
 #[cfg(test)]
 mod tests;
 
+pub use budget::ExpireBudget;
 pub use error::TtlBucketsError;
 pub use ttl_bucket::TtlBucket;
 pub use ttl_buckets::TtlBuckets;
+
+pub(crate) use budget::ExpireLimit;
@@ -11,11 +11,11 @@
 //! │   HEAD SEG   │   TAIL SEG   │     TTL     │     NSEG     │
 //! │              │              │             │              │
 //! │    32 bit    │    32 bit    │    32 bit   │    32 bit    │
-//! ├──────────────┼──────────────┴─────────────┴──────────────┤
-//! │  NEXT MERGE  │                  PADDING                  │
-//! │              │                                           │
-//! │    32 bit    │                  96 bit                   │
-//! ├──────────────┴───────────────────────────────────────────┤
+//! ├──────────────┼────────┬─────┴─────────────┴──────────────┤
+//! │  NEXT MERGE  │   NS   │             PADDING              │
+//! │              │        │                                  │
+//! │    32 bit    │ 16 bit │             80 bit               │
+//! ├──────────────┴────────┴──────────────────────────────────┤
 //! │                         PADDING                          │
 //! │                                                          │
 //! │                         128 bit                          │
@@ -39,19 +39,22 @@ pub struct TtlBucket {
     ttl: i32,
     nseg: i32,
     next_to_merge: Option<NonZeroU32>,
-    _pad: [u8; 44],
+    namespace: u16,
+    _pad: [u8; 42],
 }
 
 impl TtlBucket {
-    /// Create a new `TtlBucket` which will hold items with the provided TTL.
-    pub(super) fn new(ttl: i32) -> Self {
+    /// Create a new `TtlBucket` which will hold items in the namespace with
+    /// the provided TTL.
+    pub(super) fn new(namespace: u16, ttl: i32) -> Self {
         Self {
             head: None,
             tail: None,
             ttl,
             nseg: 0,
             next_to_merge: None,
-            _pad: [0; 44],
+            namespace,
+            _pad: [0; 42],
         }
     }
 
@@ -65,6 +68,15 @@ impl TtlBucket {
         self.head = id;
     }
 
+    /// Replace the segment chain for this `TtlBucket`. Used when the chain has
+    /// been restored from an existing datapool.
+    pub(crate) fn restore(&mut self, head: NonZeroU32, tail: NonZeroU32, nseg: i32) {
+        self.head = Some(head);
+        self.tail = Some(tail);
+        self.nseg = nseg;
+        self.next_to_merge = None;
+    }
+
     /// Returns the segment ID of the next segment to merge within the
     /// `TtlBucket`.
     pub fn next_to_merge(&self) -> Option<NonZeroU32> {
@@ -76,9 +88,38 @@ impl TtlBucket {
         self.next_to_merge = next;
     }
 
+    /// Removes a segment from the segment chain for this `TtlBucket`, given the
+    /// segments before and after it in the chain. The segment itself must be
+    /// unlinked from its neighbors by the caller.
+    pub(crate) fn remove(
+        &mut self,
+        id: NonZeroU32,
+        prev: Option<NonZeroU32>,
+        next: Option<NonZeroU32>,
+    ) {
+        if self.head == Some(id) {
+            self.head = next;
+        }
+        if self.tail == Some(id) {
+            self.tail = prev;
+        }
+        if self.next_to_merge == Some(id) {
+            self.next_to_merge = None;
+        }
+        self.nseg -= 1;
+    }
+
     /// Expire segments from this TtlBucket, returns the number of segments
-    /// expired.
-    pub(super) fn expire(&mut self, hashtable: &mut HashTable, segments: &mut Segments) -> usize {
+    /// expired. Stops early once the limit is exhausted. Segments which have
+    /// passed their TTL are kept for the grace period and marked as stale, they
+    /// are only cleared once the grace period has also passed.
+    pub(super) fn expire(
+        &mut self,
+        hashtable: &mut HashTable,
+        segments: &mut Segments,
+        limit: &mut ExpireLimit,
+        grace: Duration,
+    ) -> usize {
         if self.head.is_none() {
             return 0;
         }
@@ -87,11 +128,17 @@ impl TtlBucket {
         let ts = Instant::now();
 
         loop {
+            if limit.exhausted() {
+                return expired;
+            }
+
             let seg_id = self.head;
             if let Some(seg_id) = seg_id {
                 let flush_at = segments.flush_at();
                 let mut segment = segments.get_mut(seg_id).unwrap();
-                if segment.create_at() + segment.ttl() <= ts || segment.create_at() < flush_at {
+                if segment.create_at() + segment.ttl() + grace <= ts
+                    || segment.create_at() < flush_at
+                {
                     if let Some(next) = segment.next_seg() {
                         self.head = Some(next);
                     } else {
@@ -104,14 +151,63 @@ impl TtlBucket {
                     #[cfg(feature = "metrics")]
                     SEGMENT_EXPIRE.increment();
 
+                    limit.record();
                     expired += 1;
                 } else {
-                    return expired;
+                    break;
                 }
             } else {
                 return expired;
             }
         }
+
+        if grace.as_secs() > 0 {
+            self.mark_stale(segments, ts);
+        }
+
+        expired
+    }
+
+    /// Marks the segments at the head of this TtlBucket which have passed
+    /// their TTL, but not the grace period, as stale.
+    fn mark_stale(&mut self, segments: &mut Segments, ts: Instant) {
+        let mut next = self.head;
+        while let Some(seg_id) = next {
+            let mut segment = segments.get_mut(seg_id).unwrap();
+            if segment.create_at() + segment.ttl() > ts {
+                return;
+            }
+
+            if !segment.stale() {
+                segment.set_stale(true);
+
+                #[cfg(feature = "metrics")]
+                SEGMENT_STALE.increment();
+            }
+
+            next = segment.next_seg();
+        }
+    }
+
+    /// Returns the number of segments at the head of this TtlBucket which have
+    /// expired but have not yet been cleared.
+    #[cfg(any(test, feature = "metrics"))]
+    pub(super) fn expired_segments(&self, segments: &mut Segments, grace: Duration) -> usize {
+        let ts = Instant::now();
+        let flush_at = segments.flush_at();
+
+        let mut expired = 0;
+        let mut next = self.head;
+        while let Some(seg_id) = next {
+            let segment = segments.get_mut(seg_id).unwrap();
+            if segment.create_at() + segment.ttl() + grace <= ts || segment.create_at() < flush_at {
+                expired += 1;
+                next = segment.next_seg();
+            } else {
+                break;
+            }
+        }
+        expired
     }
 
     /// Clear segments from this TtlBucket, returns the number of segments
@@ -151,36 +247,68 @@ impl TtlBucket {
     /// return and error. It is up to the caller to handle the error and retry.
     fn try_expand(&mut self, segments: &mut Segments) -> Result<(), TtlBucketsError> {
         if let Some(id) = segments.pop_free() {
-            {
-                if let Some(tail_id) = self.tail {
-                    let mut tail = segments.get_mut(tail_id).unwrap();
-                    tail.set_next_seg(Some(id));
-                }
-            }
-
-            let mut segment = segments.get_mut(id).unwrap();
-            segment.set_prev_seg(self.tail);
-            segment.set_next_seg(None);
-            segment.set_ttl(Duration::from_secs(self.ttl as u32));
-            if self.head.is_none() {
-                debug_assert!(self.tail.is_none());
-                self.head = Some(id);
-            }
-            self.tail = Some(id);
-            self.nseg += 1;
-            debug_assert!(!segment.evictable(), "segment should not be evictable");
-            segment.set_evictable(true);
-            segment.set_accessible(true);
+            self.push_back(id, segments);
             Ok(())
         } else {
             Err(TtlBucketsError::NoFreeSegments)
         }
     }
 
+    /// Links a segment which was taken from the free queue onto the end of the
+    /// segment chain for this `TtlBucket`.
+    fn push_back(&mut self, id: NonZeroU32, segments: &mut Segments) {
+        {
+            if let Some(tail_id) = self.tail {
+                let mut tail = segments.get_mut(tail_id).unwrap();
+                tail.set_next_seg(Some(id));
+            }
+        }
+
+        segments.assign_namespace(id, self.namespace);
+
+        let mut segment = segments.get_mut(id).unwrap();
+        segment.set_prev_seg(self.tail);
+        segment.set_next_seg(None);
+        segment.set_ttl(Duration::from_secs(self.ttl as u32));
+        if self.head.is_none() {
+            debug_assert!(self.tail.is_none());
+            self.head = Some(id);
+        }
+        self.tail = Some(id);
+        self.nseg += 1;
+        debug_assert!(!segment.evictable(), "segment should not be evictable");
+        segment.set_evictable(true);
+        segment.set_accessible(true);
+    }
+
+    /// Reserve space for an item which is larger than a segment by taking a
+    /// run of adjacent segments from the free queue. The head of the run is
+    /// linked into the segment chain and the item is written starting in the
+    /// head and continuing through the rest of the run. No other items are
+    /// written into the run.
+    fn reserve_span(
+        &mut self,
+        size: usize,
+        span: u32,
+        segments: &mut Segments,
+    ) -> Result<ReservedItem, TtlBucketsError> {
+        let id = segments
+            .pop_free_span(span)
+            .ok_or(TtlBucketsError::NoFreeSegments)?;
+
+        self.push_back(id, segments);
+
+        let mut segment = segments.get_mut(id).unwrap();
+        let offset = segment.write_offset() as usize;
+        let item = segment.alloc_item(size as i32);
+        Ok(ReservedItem::new(item, segment.id(), offset))
+    }
+
     /// Reserve space in this `TtlBucket` for an item with the specified size in
     /// bytes. This function will return an error if the item is oversized, or
     /// if there is no space in the `TtlBucket` for the item and the `TtlBucket`
-    /// could not be expanded with a segment from the free queue.
+    /// could not be expanded with a segment from the free queue. Items which
+    /// are larger than a segment are stored across a run of segments.
     pub(crate) fn reserve(
         &mut self,
         size: usize,
@@ -190,17 +318,32 @@ impl TtlBucket {
 
         let seg_size = segments.segment_size() as usize;
 
-        if size > seg_size {
-            debug!("item is oversized");
-            return Err(TtlBucketsError::ItemOversized { size });
+        match segments.span(size) {
+            None => {
+                debug!("item is oversized");
+                return Err(TtlBucketsError::ItemOversized { size });
+            }
+            Some(0) => {}
+            Some(span) => {
+                return self.reserve_span(size, span, segments);
+            }
         }
 
+        let flush_at = segments.flush_at();
+
         loop {
             if let Some(id) = self.tail {
                 if let Ok(mut segment) = segments.get_mut(id) {
                     if !segment.accessible() {
                         continue;
                     }
+                    // a segment which has passed its TTL is not written to,
+                    // nor is one which must only hold items older than the
+                    // current purge or the last flush
+                    if segment.stale() || segment.purging() || segment.create_at() < flush_at {
+                        self.try_expand(segments)?;
+                        continue;
+                    }
                     let offset = segment.write_offset() as usize;
                     trace!("offset: {}", offset);
                     if offset + size <= seg_size {
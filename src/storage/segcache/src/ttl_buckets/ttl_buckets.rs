@@ -16,11 +16,15 @@
 //! * TTLs beyond 8_388_608s (~97 days) and TTLs of 0 are all treated as the max
 //!   TTL.
 //!
+//! Each namespace has its own set of 1024 buckets, so that segments only hold
+//! items from a single namespace.
+//!
 //! See the
 //! [Segcache paper](https://www.usenix.org/system/files/nsdi21-yang.pdf) for
 //! more detail.
 
 use crate::*;
+use core::num::NonZeroU32;
 
 const N_BUCKET_PER_STEP_N_BIT: usize = 8;
 const N_BUCKET_PER_STEP: usize = 1 << N_BUCKET_PER_STEP_N_BIT;
@@ -45,12 +49,21 @@ const MAX_TTL_BUCKET_IDX: usize = MAX_N_TTL_BUCKET - 1;
 pub struct TtlBuckets {
     pub(crate) buckets: Box<[TtlBucket]>,
     pub(crate) last_expired: Instant,
+    /// One more than the index of the `TtlBucket` where an expiration pass
+    /// stopped because its budget was exhausted
+    expire_cursor: Option<NonZeroU32>,
 }
 
 impl TtlBuckets {
     /// Create a new set of `TtlBuckets` which cover the full range of TTLs. See
     /// the module-level documentation for how the range of TTLs are stored.
     pub fn new() -> Self {
+        Self::with_namespaces(1)
+    }
+
+    /// Create a new set of `TtlBuckets` which cover the full range of TTLs for
+    /// each of the provided number of namespaces.
+    pub fn with_namespaces(namespaces: u16) -> Self {
         let intervals = [
             TTL_BUCKET_INTERVAL_1,
             TTL_BUCKET_INTERVAL_2,
@@ -59,13 +72,15 @@ impl TtlBuckets {
         ];
 
         let mut buckets = Vec::with_capacity(0);
-        buckets.reserve_exact(intervals.len() * N_BUCKET_PER_STEP);
-
-        for interval in &intervals {
-            for j in 0..N_BUCKET_PER_STEP {
-                let ttl = interval * j + 1;
-                let bucket = TtlBucket::new(ttl as i32);
-                buckets.push(bucket);
+        buckets.reserve_exact(namespaces as usize * MAX_N_TTL_BUCKET);
+
+        for namespace in 0..namespaces {
+            for interval in &intervals {
+                for j in 0..N_BUCKET_PER_STEP {
+                    let ttl = interval * j + 1;
+                    let bucket = TtlBucket::new(namespace, ttl as i32);
+                    buckets.push(bucket);
+                }
             }
         }
 
@@ -75,14 +90,26 @@ impl TtlBuckets {
         Self {
             buckets,
             last_expired,
+            expire_cursor: None,
         }
     }
 
-    /// Get the index of the `TtlBucket` for the given TTL.
+    /// Returns the number of namespaces which have their own `TtlBucket`s.
+    pub(crate) fn namespaces(&self) -> u16 {
+        (self.buckets.len() / MAX_N_TTL_BUCKET) as u16
+    }
+
+    /// Returns the range of `TtlBucket` indices which belong to the namespace.
+    pub(crate) fn namespace_range(&self, namespace: u16) -> std::ops::Range<usize> {
+        let start = namespace as usize * MAX_N_TTL_BUCKET;
+        start..(start + MAX_N_TTL_BUCKET)
+    }
+
+    /// Get the index of the `TtlBucket` for the given TTL within a namespace.
     pub(crate) fn get_bucket_index(&self, ttl: Duration) -> usize {
         let ttl = ttl.as_secs() as i32;
         if ttl <= 0 {
-            self.buckets.len() - 1
+            MAX_TTL_BUCKET_IDX
         } else if ttl & !(TTL_BOUNDARY_1 - 1) == 0 {
             (ttl >> TTL_BUCKET_INTERVAL_N_BIT_1) as usize
         } else if ttl & !(TTL_BOUNDARY_2 - 1) == 0 {
@@ -100,28 +127,51 @@ impl TtlBuckets {
     }
 
     // TODO(bmartin): confirm handling for negative TTLs here...
-    /// Get a mutable reference to the `TtlBucket` for the given TTL.
-    pub(crate) fn get_mut_bucket(&mut self, ttl: Duration) -> &mut TtlBucket {
-        let index = self.get_bucket_index(ttl);
+    /// Get a mutable reference to the `TtlBucket` for the given TTL in the
+    /// namespace.
+    pub(crate) fn get_mut_bucket(&mut self, namespace: u16, ttl: Duration) -> &mut TtlBucket {
+        let index = namespace as usize * MAX_N_TTL_BUCKET + self.get_bucket_index(ttl);
 
-        // NOTE: since get_bucket_index() must return an index within the slice,
-        // we do not need to worry about UB here.
-        unsafe { self.buckets.get_unchecked_mut(index) }
+        &mut self.buckets[index]
     }
 
-    pub(crate) fn expire(&mut self, hashtable: &mut HashTable, segments: &mut Segments) -> usize {
+    /// Expire segments from the `TtlBuckets` until the budget is exhausted,
+    /// returning the number of segments expired. An expiration pass which was
+    /// stopped by its budget is resumed by the next call, otherwise a pass is
+    /// only started once per second. Segments are kept for the grace period
+    /// after their TTL, during which their items are stale.
+    pub(crate) fn expire(
+        &mut self,
+        hashtable: &mut HashTable,
+        segments: &mut Segments,
+        budget: ExpireBudget,
+        grace: Duration,
+    ) -> usize {
         let now = Instant::now();
 
-        if now == self.last_expired {
+        if now == self.last_expired && self.expire_cursor.is_none() {
             return 0;
         } else {
             self.last_expired = now;
         }
 
+        let first = self
+            .expire_cursor
+            .take()
+            .map(|cursor| cursor.get() as usize - 1)
+            .unwrap_or(0);
+
         let start = Instant::now();
+        let mut limit = ExpireLimit::new(budget);
         let mut expired = 0;
-        for bucket in self.buckets.iter_mut() {
-            expired += bucket.expire(hashtable, segments);
+        for i in 0..self.buckets.len() {
+            let idx = (first + i) % self.buckets.len();
+            expired += self.buckets[idx].expire(hashtable, segments, &mut limit, grace);
+
+            if limit.exhausted() {
+                self.expire_cursor = NonZeroU32::new(idx as u32 + 1);
+                break;
+            }
         }
         let duration = start.elapsed();
         debug!("expired: {} segments in {:?}", expired, duration);
@@ -132,6 +182,20 @@ impl TtlBuckets {
         expired
     }
 
+    /// Returns the number of expired segments which have not yet been cleared
+    /// because an expiration pass was stopped by its budget.
+    #[cfg(any(test, feature = "metrics"))]
+    pub(crate) fn expire_backlog(&self, segments: &mut Segments, grace: Duration) -> usize {
+        if self.expire_cursor.is_none() {
+            return 0;
+        }
+
+        self.buckets
+            .iter()
+            .map(|bucket| bucket.expired_segments(segments, grace))
+            .sum()
+    }
+
     pub(crate) fn clear(&mut self, hashtable: &mut HashTable, segments: &mut Segments) -> usize {
         let start = Instant::now();
         let mut cleared = 0;
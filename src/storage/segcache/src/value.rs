@@ -2,16 +2,20 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
-#[derive(PartialEq, Eq)]
+// NOTE: floats are compared by their bit representation so that values are
+// `Eq`. A stored NaN is equal to itself and `0.0` is not equal to `-0.0`.
 pub enum Value<'a> {
     Bytes(&'a [u8]),
     U64(u64),
+    I64(i64),
+    F64(f64),
 }
 
-#[derive(PartialEq, Eq)]
 pub enum OwnedValue {
     Bytes(Box<[u8]>),
     U64(u64),
+    I64(i64),
+    F64(f64),
 }
 
 impl<'a> Value<'a> {
@@ -19,6 +23,8 @@ impl<'a> Value<'a> {
         match self {
             Self::Bytes(v) => OwnedValue::Bytes(v.to_vec().into_boxed_slice()),
             Self::U64(v) => OwnedValue::U64(*v),
+            Self::I64(v) => OwnedValue::I64(*v),
+            Self::F64(v) => OwnedValue::F64(*v),
         }
     }
 }
@@ -28,10 +34,14 @@ impl<'a> OwnedValue {
         match self {
             Self::Bytes(v) => Value::Bytes(v.as_ref()),
             Self::U64(v) => Value::U64(*v),
+            Self::I64(v) => Value::I64(*v),
+            Self::F64(v) => Value::F64(*v),
         }
     }
 }
 
+// NOTE: signed and float values are constructed explicitly, eg `Value::I64`,
+// so that integer literals continue to be inferred as `u64`.
 impl From<u64> for Value<'_> {
     fn from(value: u64) -> Self {
         Self::U64(value)
@@ -68,15 +78,39 @@ impl<'a> Value<'a> {
         match self {
             Value::Bytes(v) => v.len(),
             Value::U64(_) => core::mem::size_of::<u64>(),
+            Value::I64(_) => core::mem::size_of::<i64>(),
+            Value::F64(_) => core::mem::size_of::<f64>(),
         }
     }
 }
 
+impl<'a> PartialEq for Value<'a> {
+    fn eq(&self, rhs: &Value<'a>) -> bool {
+        match (self, rhs) {
+            (Value::Bytes(a), Value::Bytes(b)) => a == b,
+            (Value::U64(a), Value::U64(b)) => a == b,
+            (Value::I64(a), Value::I64(b)) => a == b,
+            (Value::F64(a), Value::F64(b)) => a.to_bits() == b.to_bits(),
+            _ => false,
+        }
+    }
+}
+
+impl<'a> Eq for Value<'a> {}
+
+impl PartialEq for OwnedValue {
+    fn eq(&self, rhs: &OwnedValue) -> bool {
+        self.as_value() == rhs.as_value()
+    }
+}
+
+impl Eq for OwnedValue {}
+
 impl<'a, const N: usize> PartialEq<&[u8; N]> for Value<'a> {
     fn eq(&self, rhs: &&[u8; N]) -> bool {
         match self {
             Value::Bytes(v) => v == *rhs,
-            Value::U64(_) => false,
+            _ => false,
         }
     }
 }
@@ -85,7 +119,7 @@ impl<'a, const N: usize> PartialEq<[u8; N]> for Value<'a> {
     fn eq(&self, rhs: &[u8; N]) -> bool {
         match self {
             Value::Bytes(v) => v == rhs,
-            Value::U64(_) => false,
+            _ => false,
         }
     }
 }
@@ -94,7 +128,7 @@ impl<'a> PartialEq<[u8]> for Value<'a> {
     fn eq(&self, rhs: &[u8]) -> bool {
         match self {
             Value::Bytes(v) => *v == rhs,
-            Value::U64(_) => false,
+            _ => false,
         }
     }
 }
@@ -102,8 +136,8 @@ impl<'a> PartialEq<[u8]> for Value<'a> {
 impl<'a> PartialEq<u64> for Value<'a> {
     fn eq(&self, rhs: &u64) -> bool {
         match self {
-            Value::Bytes(_) => false,
             Value::U64(v) => *v == *rhs,
+            _ => false,
         }
     }
 }
@@ -113,6 +147,8 @@ impl<'a> core::fmt::Debug for Value<'a> {
         match &self {
             Value::Bytes(v) => write!(f, "{v:?}"),
             Value::U64(v) => write!(f, "{v}"),
+            Value::I64(v) => write!(f, "{v}"),
+            Value::F64(v) => write!(f, "{v}"),
         }
     }
 }
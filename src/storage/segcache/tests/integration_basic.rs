@@ -69,13 +69,23 @@ fn integration_basic() {
     assert_eq!(cache.get(b"e").map(|v| v.value().len()), Some(42));
     assert_eq!(cache.get(b"f").map(|v| v.value().len()), Some(27));
 
-    #[cfg(feature = "magic")]
+    // the item header is larger with magic bytes or checksums
+    #[cfg(all(feature = "magic", feature = "checksum"))]
+    {
+        let _ = cache.insert(b"g", b"Out, out", None, ttl);
+        assert_eq!(cache.get(b"g").map(|v| v.value().len()), Some(8));
+    }
+
+    #[cfg(all(
+        any(feature = "magic", feature = "checksum"),
+        not(all(feature = "magic", feature = "checksum"))
+    ))]
     {
         let _ = cache.insert(b"g", b"Et tu, Brute?", None, ttl);
         assert_eq!(cache.get(b"g").map(|v| v.value().len()), Some(13));
     }
 
-    #[cfg(not(feature = "magic"))]
+    #[cfg(not(any(feature = "magic", feature = "checksum")))]
     {
         let _ = cache.insert(
             b"g",